@@ -6,17 +6,34 @@
 //! the LiveKit Rust SDK for publishing audio tracks.
 
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 pub struct NowPlaying {
     pub track: String,
     pub artist: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
+    /// Full length of the track, when the source reports it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queued_by: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
 pub struct SharedNowPlaying {
     pub title: String,
     pub url: String,
+    #[serde(default)]
+    pub artist: Option<String>,
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+    #[serde(default)]
+    pub queued_by: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -26,6 +43,18 @@ pub struct SharedQueueItem {
     pub title: Option<String>,
     pub id: u64,
     pub queued_by: Option<String>,
+    /// Uploader or channel name, when known.
+    #[serde(default)]
+    pub artist: Option<String>,
+    /// Artwork URL, when the source provides one.
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+    /// Unix seconds when the track was queued; absent for older events.
+    #[serde(default)]
+    pub queued_at: Option<u64>,
+    /// Start and end seconds when only part of the track is played.
+    #[serde(default)]
+    pub clip: Option<(u64, u64)>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -34,6 +63,10 @@ pub struct SharedHistoryItem {
     pub url: String,
     pub title: Option<String>,
     pub queued_by: Option<String>,
+    #[serde(default)]
+    pub artist: Option<String>,
+    #[serde(default)]
+    pub thumbnail: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -42,13 +75,101 @@ pub struct SharedQueueSnapshot {
     pub queue: Vec<SharedQueueItem>,
     pub now_playing: Option<SharedNowPlaying>,
     pub history: Vec<SharedHistoryItem>,
+    /// Whether the host has paused playback for the whole room.
+    #[serde(default)]
+    pub paused: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum DjStatus {
     Idle,
     Loading,
+    /// Waiting for a Spotify client to connect and start playback.
+    WaitingForSpotify,
     Playing(NowPlaying),
+    /// Holding the current track because the room was paused.
+    Paused(NowPlaying),
+}
+
+/// Returned by `play_previous` when there is nothing to go back to.
+pub const NO_HISTORY: &str = "No previously played track";
+
+/// Returned by room-wide controls when there is no shared queue to coordinate through.
+pub const NO_SHARED_QUEUE: &str = "Room controls need a shared queue";
+
+/// Returned by `seek` when the current track is streaming live rather than from the cache.
+pub const SEEK_NEEDS_CACHE: &str = "Seeking requires a cached track";
+
+/// Chunks of PCM a pipeline buffers for the LiveKit publisher. Everything buffered still
+/// plays after a skip unless the publisher flushes it, so smaller is snappier.
+pub const DEFAULT_PCM_CHANNEL_CHUNKS: usize = 1024;
+pub const MIN_PCM_CHANNEL_CHUNKS: usize = 16;
+pub const MAX_PCM_CHANNEL_CHUNKS: usize = 4096;
+
+/// Clamp a configured PCM channel capacity to the supported range.
+pub fn clamp_pcm_channel_chunks(chunks: usize) -> usize {
+    chunks.clamp(MIN_PCM_CHANNEL_CHUNKS, MAX_PCM_CHANNEL_CHUNKS)
+}
+
+/// Longest attack or release accepted for ducking.
+pub const MAX_DUCK_RAMP_MS: u64 = 5_000;
+
+/// Lowers the music sent to the room while someone is talking.
+pub struct Ducking {
+    /// How far to lower the music, in percent; `0` turns ducking off.
+    amount_pct: AtomicU8,
+    attack_ms: AtomicU64,
+    release_ms: AtomicU64,
+    /// Leave the music alone when the only speaker is the local participant.
+    ignore_self: AtomicBool,
+    speaking: AtomicBool,
+}
+
+impl Default for Ducking {
+    fn default() -> Self {
+        Self {
+            amount_pct: AtomicU8::new(0),
+            attack_ms: AtomicU64::new(100),
+            release_ms: AtomicU64::new(600),
+            ignore_self: AtomicBool::new(false),
+            speaking: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Ducking {
+    /// Apply new settings, returning the amount, attack and release actually used.
+    pub fn configure(&self, amount_pct: u8, attack_ms: u64, release_ms: u64, ignore_self: bool) -> (u8, u64, u64) {
+        let applied = (
+            amount_pct.min(100),
+            attack_ms.min(MAX_DUCK_RAMP_MS),
+            release_ms.min(MAX_DUCK_RAMP_MS),
+        );
+        self.amount_pct.store(applied.0, Ordering::Relaxed);
+        self.attack_ms.store(applied.1, Ordering::Relaxed);
+        self.release_ms.store(applied.2, Ordering::Relaxed);
+        self.ignore_self.store(ignore_self, Ordering::Relaxed);
+        applied
+    }
+
+    /// Update from the identities LiveKit reports as speaking. Speaker detection is per
+    /// participant, so while `local_publishes_music` the local identity is always left out,
+    /// or the music would duck itself.
+    pub fn set_speakers(&self, speakers: &[String], local_identity: &str, local_publishes_music: bool) {
+        let ignore_self = self.ignore_self.load(Ordering::Relaxed) || local_publishes_music;
+        let speaking = speakers.iter().any(|identity| !(ignore_self && identity == local_identity));
+        self.speaking.store(speaking, Ordering::Relaxed);
+    }
+
+    /// Gain the music should move towards, and how many ms a full-range ramp takes.
+    pub fn target(&self) -> (f32, u64) {
+        let amount = self.amount_pct.load(Ordering::Relaxed);
+        if amount > 0 && self.speaking.load(Ordering::Relaxed) {
+            (1.0 - f32::from(amount) / 100.0, self.attack_ms.load(Ordering::Relaxed))
+        } else {
+            (1.0, self.release_ms.load(Ordering::Relaxed))
+        }
+    }
 }
 
 pub trait AudioPipeline: Send + Sync {
@@ -67,40 +188,168 @@ pub trait AudioPipeline: Send + Sync {
     /// Get the current volume (0-100).
     fn volume(&self) -> u8;
 
-    /// Add a URL to the playback queue.
-    fn queue_track(&self, url: String, queued_by: Option<String>) -> Result<(), String>;
+    /// Add a URL to the playback queue, optionally only its `(start, end)` seconds.
+    fn queue_track(&self, url: String, queued_by: Option<String>, clip: Option<(u64, u64)>) -> Result<(), String>;
+
+    /// Add a URL ahead of everything else still to play.
+    fn queue_track_next(
+        &self,
+        _url: String,
+        _queued_by: Option<String>,
+        _clip: Option<(u64, u64)>,
+    ) -> Result<(), String> {
+        Err("This backend can't queue tracks up next".to_string())
+    }
 
     /// Skip the currently playing track.
     fn skip_track(&self) -> Result<(), String>;
 
-    /// Get the current queue (list of URLs/titles).
-    fn get_queue(&self) -> Vec<String>;
+    /// URLs still to play, from the shared queue when one is configured.
+    fn get_queue(&self) -> Result<Vec<String>, String>;
 
-    /// Get shared queue if configured.
-    fn shared_queue(&self) -> Option<Vec<String>> {
-        None
+    /// Get shared queue snapshot (queue + now playing); `None` when none is configured.
+    fn shared_queue_snapshot(&self) -> Result<Option<SharedQueueSnapshot>, String> {
+        Ok(None)
     }
 
-    /// Get shared queue snapshot (queue + now playing) if configured.
-    fn shared_queue_snapshot(&self) -> Option<SharedQueueSnapshot> {
+    /// The local queue, used when no shared queue is configured. Ids are what `move_track`
+    /// takes; backends without moves can leave them as positions.
+    fn local_queue(&self) -> Result<Vec<SharedQueueItem>, String> {
+        Ok(self
+            .get_queue()?
+            .into_iter()
+            .enumerate()
+            .map(|(i, url)| SharedQueueItem {
+                url,
+                title: None,
+                id: i as u64,
+                queued_by: None,
+                artist: None,
+                thumbnail: None,
+                queued_at: None,
+                clip: None,
+            })
+            .collect())
+    }
+
+    /// Track playing from the local queue, used when no shared queue is configured.
+    fn local_now_playing(&self) -> Option<SharedNowPlaying> {
         None
     }
 
+    /// Shared queue event types this version ignores because a newer client wrote them.
+    fn unknown_event_types(&self) -> Result<Vec<String>, String> {
+        Ok(Vec::new())
+    }
+
+    /// Raw NDJSON event lines of the shared queue, for debugging. Empty if not configured.
+    fn shared_queue_raw(&self) -> Result<Vec<String>, String> {
+        Ok(Vec::new())
+    }
+
+    /// Re-queue the most recently played track at the front and skip to it.
+    fn play_previous(&self) -> Result<(), String> {
+        Err(NO_HISTORY.to_string())
+    }
+
+    /// Create the shared queue file if the repo doesn't have it yet. Returns whether it was created.
+    fn initialize_shared_queue(&self) -> Result<bool, String> {
+        Err(NO_SHARED_QUEUE.to_string())
+    }
+
     /// Clear the queue (shared if configured).
     fn clear_shared_queue(&self) -> Result<(), String> {
         Ok(())
     }
 
+    /// Clear upcoming tracks but let the current one finish.
+    fn clear_upcoming(&self) -> Result<(), String> {
+        Ok(())
+    }
+
     /// Reorder queue items by their IDs.
     fn reorder_queue(&self, _order: Vec<u64>) -> Result<(), String> {
         Ok(())
     }
 
+    /// Pause playback for everyone reading the shared queue.
+    fn pause_room(&self) -> Result<(), String> {
+        Err(NO_SHARED_QUEUE.to_string())
+    }
+
+    /// Resume playback after `pause_room`.
+    fn resume_room(&self) -> Result<(), String> {
+        Err(NO_SHARED_QUEUE.to_string())
+    }
+
+    /// Jump to `secs` into the current track.
+    fn seek(&self, _secs: u64) -> Result<(), String> {
+        Err("Seeking isn't supported by this backend".to_string())
+    }
+
+    /// Seconds into the current track, if one is playing.
+    fn playback_position(&self) -> Option<u64> {
+        None
+    }
+
+    /// Move the queued track with id `queued_id`, as listed in the queue snapshot, to
+    /// `new_index` (clamped to the end of the queue).
+    fn move_track(&self, _queued_id: u64, _new_index: usize) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Point the shared queue at another repo/file; blank values disable it.
+    fn reconfigure_shared_queue(&self, _repo: String, _path: String, _gh_path: String) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Whether this pipeline manages its own queue (false when controlled externally).
+    fn supports_queue(&self) -> bool {
+        true
+    }
+
     /// Take the PCM receiver for LiveKit publishing (can only be called once).
     fn take_pcm_receiver(&self) -> Option<tokio::sync::mpsc::Receiver<Vec<u8>>>;
 
+    /// Channels in the interleaved PCM sent to the receiver, so LiveKit can publish mono sources as mono.
+    fn pcm_channels(&self) -> u16 {
+        2
+    }
+
     /// Disable/enable local speaker playback.
     fn set_local_playback(&self, _enabled: bool) {}
+
+    /// Record the local participant identity used for DJ claims.
+    fn set_local_identity(&self, _identity: Option<String>) {}
+
+    /// Work that records a DJ claim where other clients see it. It may block on the
+    /// network, so callers run it off the async runtime before `claim_dj`.
+    fn dj_claim_writer(&self, _identity: &str) -> Option<Box<dyn FnOnce() -> Result<(), String> + Send>> {
+        None
+    }
+
+    /// Claim DJ duty for the room so this client becomes the one streaming.
+    fn claim_dj(&self, _identity: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Identity of the DJ elected through the shared queue, if any.
+    fn current_dj(&self) -> Option<String> {
+        None
+    }
+
+    /// Like `current_dj`, but read from the shared queue now instead of the last sync.
+    fn elected_dj(&self) -> Result<Option<String>, String> {
+        Ok(self.current_dj())
+    }
+
+    /// Record who is in the LiveKit room, or `None` once disconnected.
+    fn set_room_participants(&self, _identities: Option<Vec<String>>) {}
+
+    /// Receive every status change, for backends that publish them.
+    fn subscribe_status(&self) -> Option<tokio::sync::watch::Receiver<DjStatus>> {
+        None
+    }
 }
 
 /// Stub implementation for development/testing without real Spotify or LiveKit.
@@ -144,7 +393,7 @@ impl AudioPipeline for StubAudioPipeline {
         *self.volume.lock().unwrap_or_else(|e| e.into_inner())
     }
 
-    fn queue_track(&self, _url: String, _queued_by: Option<String>) -> Result<(), String> {
+    fn queue_track(&self, _url: String, _queued_by: Option<String>, _clip: Option<(u64, u64)>) -> Result<(), String> {
         Ok(())
     }
 
@@ -152,8 +401,8 @@ impl AudioPipeline for StubAudioPipeline {
         Ok(())
     }
 
-    fn get_queue(&self) -> Vec<String> {
-        vec![]
+    fn get_queue(&self) -> Result<Vec<String>, String> {
+        Ok(vec![])
     }
 
     fn take_pcm_receiver(&self) -> Option<tokio::sync::mpsc::Receiver<Vec<u8>>> {
@@ -161,10 +410,157 @@ impl AudioPipeline for StubAudioPipeline {
     }
 }
 
+/// A call made on `MockAudioPipeline`.
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum PipelineCall {
+    Start,
+    Stop,
+    QueueTrack(String),
+    Skip,
+    SetLocalPlayback(bool),
+}
+
+/// Scripted pipeline for tests: reports a fixed sequence of statuses and records calls.
+#[cfg(test)]
+pub struct MockAudioPipeline {
+    /// Remaining statuses; each `status()` moves on to the next, repeating the last one.
+    statuses: std::sync::Mutex<std::collections::VecDeque<DjStatus>>,
+    calls: std::sync::Mutex<Vec<PipelineCall>>,
+    queue: std::sync::Mutex<Vec<String>>,
+    volume: std::sync::Mutex<u8>,
+    pcm_receiver: std::sync::Mutex<Option<tokio::sync::mpsc::Receiver<Vec<u8>>>>,
+    pcm_channels: u16,
+    current_dj: Option<String>,
+}
+
+#[cfg(test)]
+impl MockAudioPipeline {
+    pub fn new(statuses: Vec<DjStatus>) -> Self {
+        Self {
+            statuses: std::sync::Mutex::new(statuses.into()),
+            calls: std::sync::Mutex::new(Vec::new()),
+            queue: std::sync::Mutex::new(Vec::new()),
+            volume: std::sync::Mutex::new(50),
+            pcm_receiver: std::sync::Mutex::new(None),
+            pcm_channels: 2,
+            current_dj: None,
+        }
+    }
+
+    /// Report `identity` as the DJ the shared queue elected.
+    pub fn with_current_dj(mut self, identity: &str) -> Self {
+        self.current_dj = Some(identity.to_string());
+        self
+    }
+
+    /// Hand out `rx` as the PCM receiver, reporting `channels` channels.
+    pub fn with_pcm(mut self, rx: tokio::sync::mpsc::Receiver<Vec<u8>>, channels: u16) -> Self {
+        self.pcm_receiver = std::sync::Mutex::new(Some(rx));
+        self.pcm_channels = channels;
+        self
+    }
+
+    /// Calls made so far, in order.
+    pub fn calls(&self) -> Vec<PipelineCall> {
+        self.calls.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn record(&self, call: PipelineCall) {
+        self.calls.lock().unwrap_or_else(|e| e.into_inner()).push(call);
+    }
+}
+
+#[cfg(test)]
+impl AudioPipeline for MockAudioPipeline {
+    fn start(&self) -> Result<(), String> {
+        self.record(PipelineCall::Start);
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), String> {
+        self.record(PipelineCall::Stop);
+        Ok(())
+    }
+
+    fn status(&self) -> DjStatus {
+        let mut statuses = self.statuses.lock().unwrap_or_else(|e| e.into_inner());
+        if statuses.len() > 1 {
+            statuses.pop_front().unwrap_or(DjStatus::Idle)
+        } else {
+            statuses.front().cloned().unwrap_or(DjStatus::Idle)
+        }
+    }
+
+    fn set_volume(&self, volume: u8) -> Result<(), String> {
+        *self.volume.lock().map_err(|e| e.to_string())? = volume.min(100);
+        Ok(())
+    }
+
+    fn volume(&self) -> u8 {
+        *self.volume.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn queue_track(&self, url: String, _queued_by: Option<String>, _clip: Option<(u64, u64)>) -> Result<(), String> {
+        self.record(PipelineCall::QueueTrack(url.clone()));
+        self.queue.lock().map_err(|e| e.to_string())?.push(url);
+        Ok(())
+    }
+
+    fn skip_track(&self) -> Result<(), String> {
+        self.record(PipelineCall::Skip);
+        let mut queue = self.queue.lock().map_err(|e| e.to_string())?;
+        if !queue.is_empty() {
+            queue.remove(0);
+        }
+        Ok(())
+    }
+
+    fn get_queue(&self) -> Result<Vec<String>, String> {
+        Ok(self.queue.lock().unwrap_or_else(|e| e.into_inner()).clone())
+    }
+
+    fn take_pcm_receiver(&self) -> Option<tokio::sync::mpsc::Receiver<Vec<u8>>> {
+        self.pcm_receiver.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+
+    fn pcm_channels(&self) -> u16 {
+        self.pcm_channels
+    }
+
+    fn set_local_playback(&self, enabled: bool) {
+        self.record(PipelineCall::SetLocalPlayback(enabled));
+    }
+
+    fn current_dj(&self) -> Option<String> {
+        self.current_dj.clone()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn mock_steps_through_scripted_statuses() {
+        let now = NowPlaying {
+            track: "Song".to_string(),
+            ..Default::default()
+        };
+        let pipeline = MockAudioPipeline::new(vec![DjStatus::Loading, DjStatus::Playing(now.clone())]);
+        assert_eq!(pipeline.status(), DjStatus::Loading);
+        assert_eq!(pipeline.status(), DjStatus::Playing(now.clone()));
+        assert_eq!(pipeline.status(), DjStatus::Playing(now));
+
+        assert!(pipeline.queue_track("https://youtu.be/abc".to_string(), None, None).is_ok());
+        assert!(pipeline.skip_track().is_ok());
+        assert_eq!(pipeline.get_queue(), Ok(Vec::new()));
+        assert_eq!(
+            pipeline.calls(),
+            vec![PipelineCall::QueueTrack("https://youtu.be/abc".to_string()), PipelineCall::Skip]
+        );
+    }
+
     #[test]
     fn stub_starts_in_idle() {
         let pipeline = StubAudioPipeline::new();
@@ -186,6 +582,74 @@ mod tests {
         assert_eq!(pipeline.status(), DjStatus::Idle);
     }
 
+    #[test]
+    fn dj_status_serializes_existing_variants_unchanged() {
+        let idle = serde_json::to_string(&DjStatus::Idle).unwrap_or_default();
+        let loading = serde_json::to_string(&DjStatus::Loading).unwrap_or_default();
+        let playing = serde_json::to_string(&DjStatus::Playing(NowPlaying {
+            track: "Song".to_string(),
+            artist: "Artist".to_string(),
+            ..Default::default()
+        }))
+        .unwrap_or_default();
+        let waiting = serde_json::to_string(&DjStatus::WaitingForSpotify).unwrap_or_default();
+
+        assert_eq!(idle, r#""Idle""#);
+        assert_eq!(loading, r#""Loading""#);
+        assert_eq!(playing, r#"{"Playing":{"track":"Song","artist":"Artist"}}"#);
+        assert_eq!(waiting, r#""WaitingForSpotify""#);
+    }
+
+    #[test]
+    fn now_playing_details_round_trip() {
+        let now = NowPlaying {
+            track: "Song".to_string(),
+            artist: "Artist".to_string(),
+            url: Some("https://youtube.com/watch?v=abc".to_string()),
+            thumbnail: None,
+            duration_secs: Some(215),
+            queued_by: Some("alex".to_string()),
+        };
+        let json = serde_json::to_string(&now).unwrap_or_default();
+        assert!(!json.contains("thumbnail"));
+        let parsed: NowPlaying = serde_json::from_str(&json).unwrap_or_default();
+        assert_eq!(parsed, now);
+
+        let old: NowPlaying = serde_json::from_str(r#"{"track":"Song","artist":"Artist"}"#).unwrap_or_default();
+        assert_eq!(old.track, "Song");
+        assert_eq!(old.duration_secs, None);
+    }
+
+    #[test]
+    fn ducking_follows_speakers_unless_only_self_is_talking() {
+        let ducking = Ducking::default();
+        ducking.set_speakers(&["alice".to_string()], "me", false);
+        assert_eq!(ducking.target().0, 1.0);
+
+        assert_eq!(ducking.configure(150, 50, 9_000, true), (100, 50, MAX_DUCK_RAMP_MS));
+        ducking.configure(60, 50, 400, true);
+        ducking.set_speakers(&["alice".to_string()], "me", false);
+        assert_eq!(ducking.target(), (0.4, 50));
+        ducking.set_speakers(&["me".to_string()], "me", false);
+        assert_eq!(ducking.target(), (1.0, 400));
+    }
+
+    #[test]
+    fn the_djs_own_music_never_ducks_itself() {
+        let ducking = Ducking::default();
+        ducking.configure(60, 50, 400, false);
+        ducking.set_speakers(&["me".to_string()], "me", true);
+        assert_eq!(ducking.target(), (1.0, 400));
+        ducking.set_speakers(&["me".to_string(), "alice".to_string()], "me", true);
+        assert_eq!(ducking.target(), (0.4, 50));
+    }
+
+    #[test]
+    fn pipelines_support_queue_by_default() {
+        let pipeline = StubAudioPipeline::new();
+        assert!(pipeline.supports_queue());
+    }
+
     #[test]
     fn stub_default_volume_is_50() {
         let pipeline = StubAudioPipeline::new();
@@ -0,0 +1,103 @@
+//! Track URLs that should never be streamed, persisted as JSON in the config dir.
+
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+#[derive(Debug, Default)]
+pub struct Blacklist {
+    /// Where the list is saved; `None` keeps it in memory only.
+    path: Option<PathBuf>,
+    urls: Mutex<Vec<String>>,
+}
+
+impl Blacklist {
+    /// Load the blacklist from `path`, starting empty if it is missing or unreadable.
+    pub fn load(path: PathBuf) -> Self {
+        let urls = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|err| {
+                crate::dlog!("[DJ] Ignoring unreadable blacklist {}: {err}", path.display());
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        Self {
+            path: Some(path),
+            urls: Mutex::new(urls),
+        }
+    }
+
+    pub fn contains(&self, url: &str) -> bool {
+        self.lock().iter().any(|u| u == url)
+    }
+
+    pub fn urls(&self) -> Vec<String> {
+        self.lock().clone()
+    }
+
+    pub fn add(&self, url: String) -> Result<()> {
+        let mut urls = self.lock();
+        if !urls.contains(&url) {
+            urls.push(url);
+        }
+        self.save(&urls)
+    }
+
+    pub fn remove(&self, url: &str) -> Result<()> {
+        let mut urls = self.lock();
+        urls.retain(|u| u != url);
+        self.save(&urls)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<String>> {
+        self.urls.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn save(&self, urls: &[String]) -> Result<()> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        let content = serde_json::to_string_pretty(urls).context("Failed to serialize blacklist")?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create blacklist dir: {}", parent.display()))?;
+        }
+        std::fs::write(path, content)
+            .with_context(|| format!("Failed to write blacklist file: {}", path.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_and_remove_persist_across_loads() {
+        let dir = match tempfile::tempdir() {
+            Ok(dir) => dir,
+            Err(err) => panic!("tempdir failed: {err}"),
+        };
+        let path = dir.path().join("blacklist.json");
+
+        let blacklist = Blacklist::load(path.clone());
+        assert!(blacklist.add("https://a".to_string()).is_ok());
+        assert!(blacklist.add("https://a".to_string()).is_ok());
+        assert!(blacklist.add("https://b".to_string()).is_ok());
+        assert!(blacklist.remove("https://b").is_ok());
+
+        let reloaded = Blacklist::load(path);
+        assert_eq!(reloaded.urls(), vec!["https://a".to_string()]);
+        assert!(reloaded.contains("https://a"));
+        assert!(!reloaded.contains("https://b"));
+    }
+
+    #[test]
+    fn missing_file_loads_empty() {
+        let dir = match tempfile::tempdir() {
+            Ok(dir) => dir,
+            Err(err) => panic!("tempdir failed: {err}"),
+        };
+        assert!(Blacklist::load(dir.path().join("missing.json")).urls().is_empty());
+    }
+}
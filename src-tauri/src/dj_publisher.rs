@@ -3,6 +3,7 @@
 
 use std::borrow::Cow;
 use std::sync::Arc;
+use std::time::Duration;
 
 use livekit::prelude::*;
 use livekit::options::TrackPublishOptions;
@@ -12,18 +13,85 @@ use livekit::webrtc::audio_source::{AudioSourceOptions, RtcAudioSource};
 use tokio::sync::mpsc;
 
 const SAMPLE_RATE: u32 = 48000;
-const NUM_CHANNELS: u32 = 2;
+/// Stereo, used when a pipeline reports a channel count LiveKit can't publish.
+pub const DEFAULT_NUM_CHANNELS: u32 = 2;
 // 10ms of audio per frame (LiveKit requires 10ms frames for unbuffered mode)
 const SAMPLES_PER_CHANNEL: u32 = SAMPLE_RATE / 100; // 480
 
+/// Name of the track the DJ's music is published on.
+pub const MUSIC_TRACK_NAME: &str = "music";
+
+/// Default publisher buffer. Larger buffers ride out scheduling hiccups
+/// (smoother music) at the cost of extra delay for listeners.
+pub const DEFAULT_LATENCY_MS: u32 = 100;
+pub const MIN_LATENCY_MS: u32 = 40;
+pub const MAX_LATENCY_MS: u32 = 200;
+
+/// How long the pipeline must stay quiet before a drain is considered complete.
+const DRAIN_IDLE: Duration = Duration::from_millis(100);
+
+/// How the publisher should stop when signalled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopMode {
+    /// Stop right away, dropping anything still buffered.
+    Immediate,
+    /// Send whatever PCM is still queued, flush the partial frame, then stop.
+    Drain,
+}
+
+/// Returns `ms` if it is within the supported range, otherwise the default.
+pub fn validate_latency_ms(ms: u32) -> u32 {
+    if (MIN_LATENCY_MS..=MAX_LATENCY_MS).contains(&ms) {
+        ms
+    } else {
+        DEFAULT_LATENCY_MS
+    }
+}
+
+/// Returns `channels` if it is mono or stereo, otherwise stereo.
+pub fn validate_num_channels(channels: u32) -> u32 {
+    if (1..=2).contains(&channels) {
+        channels
+    } else {
+        DEFAULT_NUM_CHANNELS
+    }
+}
+
+/// Bytes of interleaved i16 PCM in one 10ms frame.
+fn frame_size_bytes(num_channels: u32) -> usize {
+    (SAMPLES_PER_CHANNEL * num_channels) as usize * 2 // i16 = 2 bytes
+}
+
+/// Throw away chunks already queued in `pcm_rx` up to and including chunk number
+/// `drop_through`, without waiting. `received` counts chunks taken off the channel;
+/// returns how many were dropped.
+fn discard_through(pcm_rx: &mut mpsc::Receiver<Vec<u8>>, received: &mut u64, drop_through: u64) -> usize {
+    let mut dropped = 0;
+    while *received < drop_through && pcm_rx.try_recv().is_ok() {
+        *received += 1;
+        dropped += 1;
+    }
+    dropped
+}
+
 /// Publishes PCM audio from a channel as a LiveKit audio track.
+/// `num_channels` is the layout of the incoming PCM; it is published as-is.
+/// Whenever the pipeline signals `flush_rx` (on a skip) with the number of chunks it had sent,
+/// those chunks are dropped here, in the channel and in the audio source at once, so the whole
+/// room hears the skip. Chunks sent after the skip, like the track's faded tail, still play.
 /// Returns a JoinHandle that can be aborted to stop publishing.
 pub fn spawn_audio_publisher(
     room: Arc<Room>,
     mut pcm_rx: mpsc::Receiver<Vec<u8>>,
-    mut shutdown_rx: tokio::sync::oneshot::Receiver<()>,
+    mut shutdown_rx: tokio::sync::oneshot::Receiver<StopMode>,
+    latency_ms: u32,
+    num_channels: u32,
+    mut flush_rx: tokio::sync::watch::Receiver<u64>,
 ) -> tokio::task::JoinHandle<()> {
+    let latency_ms = validate_latency_ms(latency_ms);
+    let num_channels = validate_num_channels(num_channels);
     tokio::spawn(async move {
+        crate::dlog!("Starting audio publisher with {latency_ms}ms buffer, {num_channels} channel(s)");
         let source = NativeAudioSource::new(
             AudioSourceOptions {
                 echo_cancellation: false,
@@ -31,13 +99,13 @@ pub fn spawn_audio_publisher(
                 auto_gain_control: false,
             },
             SAMPLE_RATE,
-            NUM_CHANNELS,
-            // Use buffered mode (100ms buffer) for smoother playback
-            100,
+            num_channels,
+            // Buffered mode: trades latency for smoother playback
+            latency_ms,
         );
 
         let rtc_source = RtcAudioSource::Native(source.clone());
-        let track = LocalAudioTrack::create_audio_track("music", rtc_source);
+        let track = LocalAudioTrack::create_audio_track(MUSIC_TRACK_NAME, rtc_source);
 
         let publish_options = TrackPublishOptions {
             dtx: false, // Disable discontinuous transmission — we're streaming music, not voice
@@ -62,49 +130,55 @@ pub fn spawn_audio_publisher(
         crate::dlog!("Published music audio track to LiveKit room");
 
         // Buffer to accumulate PCM samples into 10ms frames
-        let frame_size_samples = (SAMPLES_PER_CHANNEL * NUM_CHANNELS) as usize;
-        let frame_size_bytes = frame_size_samples * 2; // i16 = 2 bytes
+        let frame_size_bytes = frame_size_bytes(num_channels);
         let mut buffer: Vec<u8> = Vec::with_capacity(frame_size_bytes * 2);
         let mut frames_sent: u64 = 0;
+        // Chunks taken off `pcm_rx`, numbered like the pipeline's count of chunks sent
+        let mut received: u64 = 0;
+        let mut drop_through: u64 = 0;
+        flush_rx.mark_unchanged();
 
         loop {
             tokio::select! {
-                _ = &mut shutdown_rx => {
+                stop = &mut shutdown_rx => {
+                    if matches!(stop, Ok(StopMode::Drain)) {
+                        // Keep sending until the pipeline goes quiet, then pad out the last frame
+                        while let Ok(Some(bytes)) = tokio::time::timeout(DRAIN_IDLE, pcm_rx.recv()).await {
+                            received += 1;
+                            if received <= drop_through {
+                                continue;
+                            }
+                            buffer.extend_from_slice(&bytes);
+                            send_complete_frames(&source, num_channels, &mut buffer, &mut frames_sent).await;
+                        }
+                        if !buffer.is_empty() {
+                            buffer.resize(frame_size_bytes, 0);
+                            send_complete_frames(&source, num_channels, &mut buffer, &mut frames_sent).await;
+                        }
+                        // Give the source time to play out its own buffer before unpublishing
+                        tokio::time::sleep(Duration::from_millis(latency_ms as u64)).await;
+                        crate::dlog!("Drained audio publisher");
+                    }
                     crate::dlog!("Stopping audio publisher (sent {} frames)", frames_sent);
                     break;
                 }
+                Ok(()) = flush_rx.changed() => {
+                    drop_through = *flush_rx.borrow_and_update();
+                    let dropped = discard_through(&mut pcm_rx, &mut received, drop_through);
+                    buffer.clear();
+                    source.clear_buffer();
+                    crate::dlog!("Skipped: dropped {dropped} buffered PCM chunks");
+                }
                 data = pcm_rx.recv() => {
                     match data {
                         Some(bytes) => {
-                            buffer.extend_from_slice(&bytes);
-
-                            // Process complete 10ms frames from the buffer
-                            while buffer.len() >= frame_size_bytes {
-                                let frame_bytes: Vec<u8> = buffer.drain(..frame_size_bytes).collect();
-
-                                // Convert bytes back to i16 samples
-                                let samples: Vec<i16> = frame_bytes
-                                    .chunks_exact(2)
-                                    .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]))
-                                    .collect();
-
-                                let frame = AudioFrame {
-                                    data: Cow::Borrowed(&samples),
-                                    sample_rate: SAMPLE_RATE,
-                                    num_channels: NUM_CHANNELS,
-                                    samples_per_channel: SAMPLES_PER_CHANNEL,
-                                };
-
-                                if let Err(e) = source.capture_frame(&frame).await {
-                                    crate::dlog!("Failed to capture audio frame: {e}");
-                                }
-                                frames_sent += 1;
-                                if frames_sent == 1 {
-                                    crate::dlog!("First audio frame captured and sent to LiveKit");
-                                } else if frames_sent % 1000 == 0 {
-                                    crate::dlog!("Audio frames sent: {} (~{}s)", frames_sent, frames_sent / 100);
-                                }
+                            received += 1;
+                            // Sent before the last skip but only arriving now
+                            if received <= drop_through {
+                                continue;
                             }
+                            buffer.extend_from_slice(&bytes);
+                            send_complete_frames(&source, num_channels, &mut buffer, &mut frames_sent).await;
                         }
                         None => {
                             crate::dlog!("PCM channel closed, stopping publisher (sent {} frames)", frames_sent);
@@ -121,6 +195,42 @@ pub fn spawn_audio_publisher(
     })
 }
 
+/// Send every complete 10ms frame in `buffer` to LiveKit, leaving any remainder.
+async fn send_complete_frames(
+    source: &NativeAudioSource,
+    num_channels: u32,
+    buffer: &mut Vec<u8>,
+    frames_sent: &mut u64,
+) {
+    let frame_size_bytes = frame_size_bytes(num_channels);
+    while buffer.len() >= frame_size_bytes {
+        let frame_bytes: Vec<u8> = buffer.drain(..frame_size_bytes).collect();
+
+        // Convert bytes back to i16 samples
+        let samples: Vec<i16> = frame_bytes
+            .chunks_exact(2)
+            .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]))
+            .collect();
+
+        let frame = AudioFrame {
+            data: Cow::Borrowed(&samples),
+            sample_rate: SAMPLE_RATE,
+            num_channels,
+            samples_per_channel: SAMPLES_PER_CHANNEL,
+        };
+
+        if let Err(e) = source.capture_frame(&frame).await {
+            crate::dlog!("Failed to capture audio frame: {e}");
+        }
+        *frames_sent += 1;
+        if *frames_sent == 1 {
+            crate::dlog!("First audio frame captured and sent to LiveKit");
+        } else if *frames_sent % 1000 == 0 {
+            crate::dlog!("Audio frames sent: {} (~{}s)", frames_sent, *frames_sent / 100);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -129,7 +239,39 @@ mod tests {
     fn constants_are_correct() {
         // 48000 Hz / 100 = 480 samples per 10ms frame
         assert_eq!(SAMPLES_PER_CHANNEL, 480);
-        // Stereo: 480 * 2 = 960 samples per frame
-        assert_eq!(SAMPLES_PER_CHANNEL * NUM_CHANNELS, 960);
+    }
+
+    #[test]
+    fn frame_size_follows_channel_count() {
+        // Mono: 480 samples of i16 per 10ms frame
+        assert_eq!(frame_size_bytes(1), 960);
+        // Stereo: 480 * 2 interleaved samples of i16
+        assert_eq!(frame_size_bytes(2), 1920);
+        assert_eq!(validate_num_channels(1), 1);
+        assert_eq!(validate_num_channels(0), DEFAULT_NUM_CHANNELS);
+        assert_eq!(validate_num_channels(6), DEFAULT_NUM_CHANNELS);
+    }
+
+    #[test]
+    fn discard_keeps_chunks_sent_after_the_skip() {
+        let (tx, mut rx) = mpsc::channel(4);
+        for chunk in [vec![1], vec![2], vec![3]] {
+            assert!(tx.try_send(chunk).is_ok());
+        }
+        let mut received = 0;
+        // The skip came after two chunks; the third is the faded tail
+        assert_eq!(discard_through(&mut rx, &mut received, 2), 2);
+        assert_eq!(received, 2);
+        assert_eq!(discard_through(&mut rx, &mut received, 2), 0);
+        assert_eq!(rx.try_recv().ok(), Some(vec![3]));
+    }
+
+    #[test]
+    fn latency_outside_range_falls_back_to_default() {
+        assert_eq!(validate_latency_ms(40), 40);
+        assert_eq!(validate_latency_ms(200), 200);
+        assert_eq!(validate_latency_ms(0), DEFAULT_LATENCY_MS);
+        assert_eq!(validate_latency_ms(39), DEFAULT_LATENCY_MS);
+        assert_eq!(validate_latency_ms(1000), DEFAULT_LATENCY_MS);
     }
 }
@@ -0,0 +1,112 @@
+//! Tracks the user keeps coming back to, persisted as JSON in the config dir.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Favorite {
+    pub url: String,
+    pub title: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Favorites {
+    /// Where the list is saved; `None` keeps it in memory only.
+    path: Option<PathBuf>,
+    items: Mutex<Vec<Favorite>>,
+}
+
+impl Favorites {
+    /// Load favorites from `path`, starting empty if it is missing or unreadable.
+    pub fn load(path: PathBuf) -> Self {
+        let items = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|err| {
+                crate::dlog!("[DJ] Ignoring unreadable favorites {}: {err}", path.display());
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        Self {
+            path: Some(path),
+            items: Mutex::new(items),
+        }
+    }
+
+    pub fn list(&self) -> Vec<Favorite> {
+        self.lock().clone()
+    }
+
+    /// Add `url`, or update its title if it is already a favorite.
+    pub fn add(&self, url: String, title: String) -> Result<()> {
+        let mut items = self.lock();
+        match items.iter_mut().find(|f| f.url == url) {
+            Some(existing) => existing.title = title,
+            None => items.push(Favorite { url, title }),
+        }
+        self.save(&items)
+    }
+
+    pub fn remove(&self, url: &str) -> Result<()> {
+        let mut items = self.lock();
+        items.retain(|f| f.url != url);
+        self.save(&items)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Favorite>> {
+        self.items.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn save(&self, items: &[Favorite]) -> Result<()> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        let content = serde_json::to_string_pretty(items).context("Failed to serialize favorites")?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create favorites dir: {}", parent.display()))?;
+        }
+        std::fs::write(path, content)
+            .with_context(|| format!("Failed to write favorites file: {}", path.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_and_remove_persist_across_loads() {
+        let dir = match tempfile::tempdir() {
+            Ok(dir) => dir,
+            Err(err) => panic!("tempdir failed: {err}"),
+        };
+        let path = dir.path().join("favorites.json");
+
+        let favorites = Favorites::load(path.clone());
+        assert!(favorites.add("https://a".to_string(), "A".to_string()).is_ok());
+        assert!(favorites.add("https://b".to_string(), "B".to_string()).is_ok());
+        assert!(favorites.add("https://a".to_string(), "A (live)".to_string()).is_ok());
+        assert!(favorites.remove("https://b").is_ok());
+
+        let reloaded = Favorites::load(path);
+        assert_eq!(
+            reloaded.list(),
+            vec![Favorite { url: "https://a".to_string(), title: "A (live)".to_string() }]
+        );
+    }
+
+    #[test]
+    fn corrupt_file_loads_empty() {
+        let dir = match tempfile::tempdir() {
+            Ok(dir) => dir,
+            Err(err) => panic!("tempdir failed: {err}"),
+        };
+        let path = dir.path().join("favorites.json");
+        assert!(std::fs::write(&path, "{not json").is_ok());
+        assert!(Favorites::load(path).list().is_empty());
+        assert!(Favorites::load(dir.path().join("missing.json")).list().is_empty());
+    }
+}
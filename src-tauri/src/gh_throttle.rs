@@ -0,0 +1,242 @@
+//! Shared throttle for `gh api` calls.
+//!
+//! Every call to the GitHub API goes through one gate that spaces requests out,
+//! and stops issuing them for a while once GitHub reports a rate limit.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::process::Output;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+
+/// Minimum gap between two `gh api` calls from this app.
+const MIN_INTERVAL: Duration = Duration::from_millis(100);
+/// Cooldown used when GitHub reports a rate limit without saying when it lifts.
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(60);
+
+static GATE: Mutex<Gate> = Mutex::new(Gate::new());
+
+struct Gate {
+    next_slot: Option<Instant>,
+    cooldown_until: Option<Instant>,
+}
+
+impl Gate {
+    const fn new() -> Self {
+        Self { next_slot: None, cooldown_until: None }
+    }
+
+    /// Claim the next call slot and return how long to wait for it, or the
+    /// remaining cooldown when calls are paused.
+    fn reserve(&mut self, now: Instant) -> Result<Duration, Duration> {
+        if let Some(until) = self.cooldown_until {
+            if until > now {
+                return Err(until - now);
+            }
+            self.cooldown_until = None;
+        }
+        let slot = self.next_slot.map_or(now, |slot| slot.max(now));
+        self.next_slot = Some(slot + MIN_INTERVAL);
+        Ok(slot - now)
+    }
+
+    /// Pause calls for `wait`; returns true when this starts a new cooldown.
+    fn cool_down(&mut self, now: Instant, wait: Duration) -> bool {
+        let until = now + wait;
+        let started = self.cooldown_until.is_none_or(|current| current <= now);
+        self.cooldown_until = Some(self.cooldown_until.map_or(until, |current| current.max(until)));
+        started
+    }
+}
+
+fn lock_gate() -> std::sync::MutexGuard<'static, Gate> {
+    GATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn rate_limited_error(remaining: Duration) -> String {
+    format!(
+        "GitHub API rate limit reached; retrying in {}s",
+        remaining.as_secs().max(1)
+    )
+}
+
+/// Whether a `gh api` error is GitHub refusing the call because of a rate limit.
+pub fn is_rate_limited(stderr: &str) -> bool {
+    let lower = stderr.to_ascii_lowercase();
+    lower.contains("rate limit")
+        || lower.contains("http 429")
+        || lower.contains("x-ratelimit-remaining: 0")
+}
+
+#[derive(Debug, Deserialize)]
+struct RateLimitResponse {
+    resources: HashMap<String, RateLimitResource>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RateLimitResource {
+    remaining: u64,
+    reset: u64,
+}
+
+/// Unix time the exhausted limits in `gh api rate_limit` output lift, if any are exhausted.
+fn exhausted_reset(rate_limit: &str) -> Option<u64> {
+    let response: RateLimitResponse = serde_json::from_str(rate_limit).ok()?;
+    response
+        .resources
+        .values()
+        .filter(|resource| resource.remaining == 0)
+        .map(|resource| resource.reset)
+        .max()
+}
+
+/// How long to stop calling the API after `stderr`, if it reports a rate limit.
+/// `gh api` doesn't print response headers on failure, so the reset time comes from
+/// `rate_limit`, the output of `gh api rate_limit`. Secondary limits exhaust nothing there
+/// and get the default cooldown.
+fn rate_limit_backoff(stderr: &str, rate_limit: Option<&str>, now_unix: u64) -> Option<Duration> {
+    if !is_rate_limited(stderr) {
+        return None;
+    }
+    Some(rate_limit.and_then(exhausted_reset).map_or(DEFAULT_COOLDOWN, |reset| {
+        Duration::from_secs(reset.saturating_sub(now_unix).max(1))
+    }))
+}
+
+/// Wait for a call slot, or fail straight away while a rate-limit cooldown is active.
+fn permit() -> Result<Duration, String> {
+    lock_gate().reserve(Instant::now()).map_err(rate_limited_error)
+}
+
+/// Whether a finished call was rejected for hitting the rate limit.
+fn hit_rate_limit(output: &Output) -> bool {
+    !output.status.success() && is_rate_limited(&String::from_utf8_lossy(&output.stderr))
+}
+
+/// `gh api rate_limit` stdout, when it runs. Querying it doesn't count against the limit.
+fn rate_limit_text(output: std::io::Result<Output>) -> Option<String> {
+    output
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Start a cooldown for a call that was rejected for hitting the rate limit.
+fn record(output: &Output, rate_limit: Option<&str>) {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let now_unix = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let Some(wait) = rate_limit_backoff(&stderr, rate_limit, now_unix) else {
+        return;
+    };
+    if lock_gate().cool_down(Instant::now(), wait) {
+        tracing::warn!(event = "gh_rate_limited", cooldown_secs = wait.as_secs());
+        crate::dlog!("[Queue] GitHub rate limit hit, pausing gh api calls for {}s", wait.as_secs());
+        crate::emit_event("rate-limited", wait.as_secs());
+    }
+}
+
+/// Run blocking work, letting the runtime move other tasks off this thread first when it
+/// is an async worker.
+fn run_blocking<T>(f: impl FnOnce() -> T) -> T {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
+
+/// Run a blocking `gh api` command through the throttle.
+pub fn output(command: &mut std::process::Command) -> Result<Output, String> {
+    let wait = permit()?;
+    run_blocking(|| {
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+        let output = command.output().map_err(|e| format!("Failed to run gh api: {e}"))?;
+        if hit_rate_limit(&output) {
+            let rate_limit = rate_limit_text(
+                std::process::Command::new(command.get_program())
+                    .args(["api", "rate_limit"])
+                    .output(),
+            );
+            record(&output, rate_limit.as_deref());
+        }
+        Ok(output)
+    })
+}
+
+/// Run an async `gh api` command through the throttle.
+pub async fn output_async(command: &mut tokio::process::Command) -> Result<Output, String> {
+    let wait = permit()?;
+    if !wait.is_zero() {
+        tokio::time::sleep(wait).await;
+    }
+    let output = command
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run gh api: {e}"))?;
+    if hit_rate_limit(&output) {
+        let gh: &OsStr = command.as_std().get_program();
+        let rate_limit = rate_limit_text(
+            tokio::process::Command::new(gh)
+                .args(["api", "rate_limit"])
+                .output()
+                .await,
+        );
+        record(&output, rate_limit.as_deref());
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserve_spaces_calls_by_min_interval() {
+        let mut gate = Gate::new();
+        let now = Instant::now();
+        assert_eq!(gate.reserve(now), Ok(Duration::ZERO));
+        assert_eq!(gate.reserve(now), Ok(MIN_INTERVAL));
+        assert_eq!(gate.reserve(now), Ok(MIN_INTERVAL * 2));
+        assert_eq!(gate.reserve(now + MIN_INTERVAL * 10), Ok(Duration::ZERO));
+    }
+
+    #[test]
+    fn cooldown_rejects_calls_until_it_lifts() {
+        let mut gate = Gate::new();
+        let now = Instant::now();
+        assert!(gate.cool_down(now, Duration::from_secs(30)));
+        assert!(!gate.cool_down(now, Duration::from_secs(10)));
+        assert_eq!(
+            gate.reserve(now + Duration::from_secs(5)),
+            Err(Duration::from_secs(25))
+        );
+        assert_eq!(gate.reserve(now + Duration::from_secs(30)), Ok(Duration::ZERO));
+    }
+
+    #[test]
+    fn backoff_waits_for_the_exhausted_limit_to_reset() {
+        let stderr = "gh: API rate limit exceeded for user ID 1. (HTTP 403)";
+        let exhausted = r#"{"resources":{"core":{"limit":5000,"used":5000,"remaining":0,"reset":1090},"search":{"limit":30,"used":0,"remaining":30,"reset":1010}},"rate":{"limit":5000,"used":5000,"remaining":0,"reset":1090}}"#;
+        assert_eq!(rate_limit_backoff(stderr, Some(exhausted), 1_000), Some(Duration::from_secs(90)));
+
+        // Secondary limits leave the primary ones untouched
+        let secondary = r#"{"resources":{"core":{"limit":5000,"used":12,"remaining":4988,"reset":1090}}}"#;
+        let abuse = "gh: You have exceeded a secondary rate limit. (HTTP 403)";
+        assert_eq!(rate_limit_backoff(abuse, Some(secondary), 1_000), Some(DEFAULT_COOLDOWN));
+        assert_eq!(rate_limit_backoff(stderr, None, 1_000), Some(DEFAULT_COOLDOWN));
+    }
+
+    #[test]
+    fn other_errors_do_not_back_off() {
+        assert_eq!(rate_limit_backoff("HTTP 404: Not Found", None, 0), None);
+        assert_eq!(rate_limit_backoff("HTTP 403: Must have admin rights", None, 0), None);
+    }
+}
@@ -1,6 +1,13 @@
 mod audio;
+mod blacklist;
 mod dj_publisher;
+mod favorites;
+mod gh_throttle;
+#[cfg(feature = "spotify")]
+mod librespot_pipeline;
 mod livekit_room;
+mod queue_access;
+mod resampler;
 mod room;
 mod settings;
 mod shared_queue_webhook;
@@ -9,27 +16,147 @@ mod youtube_pipeline;
 
 use audio::{AudioPipeline, DjStatus, SharedQueueSnapshot};
 use livekit_room::LiveKitRoom;
-use room::RoomState;
+use room::{RoomSnapshot, RoomState};
 use settings::Settings;
 use serde::Serialize;
 use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
-use tauri::{AppHandle, Manager, State};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use tauri::{AppHandle, Emitter, Manager, RunEvent, State};
 use tracing_subscriber::EnvFilter;
 use tokio::sync::{broadcast, Mutex as TokioMutex};
 
 struct SettingsPath(std::path::PathBuf);
+/// False when settings live in a temporary fallback directory.
+struct SettingsPersistent(bool);
 struct PlaybackVolume(Arc<AtomicU8>);
 struct MicLevel(Arc<AtomicU8>);
+/// Level of the mic input while it's published, before LiveKit's voice processing.
+struct PublishedInputLevel(Arc<AtomicU8>);
 struct QueueUpdatesTx(broadcast::Sender<()>);
 struct WebhookStarted(Arc<AtomicBool>);
+/// The running queue webhook listener, kept so it can be restarted against another repo.
+struct RunningWebhook {
+    secret: String,
+    gh_path: String,
+    task: tauri::async_runtime::JoinHandle<()>,
+}
+struct WebhookListener(Mutex<Option<RunningWebhook>>);
+/// Whether the queue webhook websocket is currently connected.
+struct WebhookConnected(Arc<AtomicBool>);
+struct ActiveWebhook(shared_queue_webhook::ActiveWebhook);
+struct QueueSync(Arc<youtube_pipeline::QueueSyncMode>);
+/// Upcoming tracks to download ahead of playback.
+struct PrefetchDepth(Arc<AtomicUsize>);
+/// Prefetch downloads allowed to run at once.
+struct PrefetchConcurrency(Arc<AtomicUsize>);
+/// Set to stop a running `prefetch_all`.
+struct PrefetchCancel(AtomicBool);
+struct TrackBlacklist(Arc<blacklist::Blacklist>);
+struct FavoriteTracks(favorites::Favorites);
+/// Seconds a stalled yt-dlp may hang before its track is failed.
+struct StreamTimeout(Arc<AtomicU64>);
+/// Extra attempts at loading a track before it is failed.
+struct LoadRetries(Arc<AtomicUsize>);
+/// PCM chunks buffered for the LiveKit publisher; used when the pipeline is next built.
+struct PcmChannelChunks(Arc<AtomicUsize>);
+/// Signalled by the pipeline on skip so the publisher drops stale buffered audio.
+struct PcmFlush(Arc<tokio::sync::watch::Sender<u64>>);
+/// Seconds an empty queue may idle before the DJ loop stops; `0` never stops.
+struct IdleTimeout(Arc<AtomicU64>);
+/// yt-dlp audio quality used for the next track.
+struct AudioQualitySetting(Arc<Mutex<youtube_pipeline::AudioQuality>>);
+/// Where new shared queue events are written.
+struct ShardStrategySetting(Arc<Mutex<youtube_pipeline::ShardStrategy>>);
+/// Cookies file passed to yt-dlp, if one is configured and exists.
+struct CookiesPath(Arc<Mutex<Option<std::path::PathBuf>>>);
+/// Explicit local monitoring choice; `None` follows the LiveKit connection.
+struct LocalMonitoring(Mutex<Option<bool>>);
+/// When set, music never plays on local speakers, whatever the LiveKit state.
+struct DjOnlyMode(AtomicBool);
+/// When set, this client only listens and refuses to publish mic or music.
+struct ListenerMode(AtomicBool);
+/// Average bytes/sec the current track is arriving at.
+struct StreamThroughput(Arc<AtomicU64>);
+/// Music ducking while people talk, shared by LiveKit and the pipeline.
+struct MusicDucking(Arc<audio::Ducking>);
+/// Track previews looked up recently, by URL.
+struct TrackPreviews(youtube_pipeline::TrackPreviewCache);
+/// Where recent tracks were loaded from.
+struct TrackDiagnostics(Arc<youtube_pipeline::TrackDiagnosticLog>);
+
+/// Everything needed to (re)build an audio pipeline backend.
+struct PipelineFactory {
+    cache_dir: Option<std::path::PathBuf>,
+    shared_state: Option<std::path::PathBuf>,
+    /// Repo, file and gh path; updated when the shared queue is reconfigured.
+    shared_queue: Mutex<(String, String, String)>,
+    queue_updates_tx: broadcast::Sender<()>,
+    sync_mode: Arc<youtube_pipeline::QueueSyncMode>,
+    webhook_connected: Arc<AtomicBool>,
+    prefetch_depth: Arc<AtomicUsize>,
+    prefetch_concurrency: Arc<AtomicUsize>,
+    blacklist: Arc<blacklist::Blacklist>,
+    stream_timeout_secs: Arc<AtomicU64>,
+    load_retries: Arc<AtomicUsize>,
+    idle_timeout_secs: Arc<AtomicU64>,
+    audio_quality: Arc<Mutex<youtube_pipeline::AudioQuality>>,
+    cookies_path: Arc<Mutex<Option<std::path::PathBuf>>>,
+    stream_throughput: Arc<AtomicU64>,
+    track_diagnostics: Arc<youtube_pipeline::TrackDiagnosticLog>,
+    ducking: Arc<audio::Ducking>,
+    shard_strategy: Arc<Mutex<youtube_pipeline::ShardStrategy>>,
+    pcm_channel_chunks: Arc<AtomicUsize>,
+    pcm_flush: Arc<tokio::sync::watch::Sender<u64>>,
+}
+
+impl PipelineFactory {
+    fn build(&self, backend: &str) -> Result<DynAudioPipeline, String> {
+        match backend {
+            "youtube" => Ok(Box::new(
+                youtube_pipeline::YouTubePipeline::with_cache_dir_and_state(
+                    self.cache_dir.clone(),
+                    self.shared_state.clone(),
+                    Some(self.shared_queue.lock().unwrap_or_else(|e| e.into_inner()).clone()),
+                    Some(self.queue_updates_tx.clone()),
+                )
+                .with_sync_mode(self.sync_mode.clone())
+                .with_webhook_connected(self.webhook_connected.clone())
+                .with_prefetch_depth(self.prefetch_depth.clone())
+                .with_prefetch_concurrency(self.prefetch_concurrency.clone())
+                .with_blacklist(self.blacklist.clone())
+                .with_stream_timeout(self.stream_timeout_secs.clone())
+                .with_load_retries(self.load_retries.clone())
+                .with_idle_timeout(self.idle_timeout_secs.clone())
+                .with_audio_quality(self.audio_quality.clone())
+                .with_cookies_path(self.cookies_path.clone())
+                .with_stream_throughput(self.stream_throughput.clone())
+                .with_track_diagnostics(self.track_diagnostics.clone())
+                .with_ducking(self.ducking.clone())
+                .with_shard_strategy(self.shard_strategy.clone())
+                .with_pcm_capacity(self.pcm_channel_chunks.load(Ordering::Relaxed))
+                .with_pcm_flush(self.pcm_flush.clone()),
+            )),
+            #[cfg(feature = "spotify")]
+            "spotify" => Ok(Box::new(
+                librespot_pipeline::LibrespotPipeline::new()
+                    .with_pcm_capacity(self.pcm_channel_chunks.load(Ordering::Relaxed)),
+            )),
+            #[cfg(not(feature = "spotify"))]
+            "spotify" => Err("Spotify support is not enabled in this build".to_string()),
+            other => Err(format!("Unknown pipeline backend: {other}")),
+        }
+    }
+}
 
 /// Holds the DJ publisher shutdown handle.
 struct DjPublisherHandle {
-    shutdown_tx: Option<tokio::sync::oneshot::Sender<()>>,
+    shutdown_tx: Option<tokio::sync::oneshot::Sender<dj_publisher::StopMode>>,
     task: Option<tokio::task::JoinHandle<()>>,
 }
 
+/// Upper bound on how long `stop_dj_audio` waits for the publisher to drain.
+const PUBLISHER_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
 struct VoiceChatHandle {
     inner: voice_chat::VoiceChatHandle,
 }
@@ -40,43 +167,107 @@ struct MicTestHandle {
 
 /// Shared debug log buffer accessible from frontend.
 pub struct DebugLogBuffer {
-    logs: Mutex<Vec<String>>,
+    started: std::time::Instant,
+    logs: Mutex<Vec<(std::time::Duration, String)>>,
 }
 
 impl DebugLogBuffer {
     pub fn new() -> Self {
-        Self { logs: Mutex::new(Vec::new()) }
+        Self {
+            started: std::time::Instant::now(),
+            logs: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Time since the buffer was created, used to timestamp log lines.
+    pub fn elapsed(&self) -> std::time::Duration {
+        self.started.elapsed()
     }
 
     pub fn push(&self, msg: String) {
+        let elapsed = self.elapsed();
         if let Ok(mut logs) = self.logs.lock() {
             if logs.len() > 500 {
                 let drain_to = logs.len() - 250;
                 logs.drain(..drain_to);
             }
-            logs.push(msg);
+            logs.push((elapsed, msg));
         }
     }
 
     pub fn drain(&self) -> Vec<String> {
         if let Ok(mut logs) = self.logs.lock() {
-            logs.drain(..).collect()
+            logs.drain(..)
+                .map(|(elapsed, msg)| format!("{} {}", format_elapsed(elapsed), msg))
+                .collect()
         } else {
             vec![]
         }
     }
 }
 
+/// Format a duration as `HH:MM:SS.mmm`.
+fn format_elapsed(elapsed: std::time::Duration) -> String {
+    let total_ms = elapsed.as_millis();
+    let hours = total_ms / 3_600_000;
+    let minutes = (total_ms / 60_000) % 60;
+    let seconds = (total_ms / 1000) % 60;
+    let millis = total_ms % 1000;
+    format!("{hours:02}:{minutes:02}:{seconds:02}.{millis:03}")
+}
+
 /// Global debug log buffer.
 static DEBUG_LOG: std::sync::OnceLock<DebugLogBuffer> = std::sync::OnceLock::new();
 
 pub fn debug_log(msg: String) {
-    tracing::info!(event = "app_log", message = %msg);
-    if let Some(buf) = DEBUG_LOG.get() {
-        buf.push(msg);
+    match DEBUG_LOG.get() {
+        Some(buf) => {
+            let elapsed = format_elapsed(buf.elapsed());
+            tracing::info!(event = "app_log", elapsed = %elapsed, message = %msg);
+            buf.push(msg);
+        }
+        None => tracing::info!(event = "app_log", message = %msg),
     }
 }
 
+/// App handle for emitting events from background threads.
+static APP_HANDLE: std::sync::OnceLock<AppHandle> = std::sync::OnceLock::new();
+
+/// Emit a Tauri event to the frontend from anywhere in the backend.
+pub fn emit_event<S: Serialize + Clone>(event: &str, payload: S) {
+    if let Some(app) = APP_HANDLE.get() {
+        if let Err(err) = app.emit(event, payload) {
+            tracing::warn!(event = "emit_failed", name = %event, error = %err);
+        }
+    }
+}
+
+/// Forward the pipeline's status changes as `dj-status-changed` events until it is dropped.
+fn spawn_status_bridge(pipeline: &DynAudioPipeline) {
+    let Some(mut rx) = pipeline.subscribe_status() else {
+        return;
+    };
+    tauri::async_runtime::spawn(async move {
+        while rx.changed().await.is_ok() {
+            let status = rx.borrow_and_update().clone();
+            emit_event("dj-status-changed", status);
+        }
+    });
+}
+
+/// Payload of the `backend-error` event: a non-fatal failure in a background task.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendError {
+    /// Subsystem that failed: `playback`, `webhook`, `voice` or `dj`.
+    pub category: &'static str,
+    pub message: String,
+}
+
+/// Surface a non-fatal background failure to the UI.
+pub fn emit_backend_error(category: &'static str, message: impl Into<String>) {
+    emit_event("backend-error", BackendError { category, message: message.into() });
+}
+
 /// Macro for debug logging from anywhere.
 #[macro_export]
 macro_rules! dlog {
@@ -96,6 +287,59 @@ struct UpdateCheckResult {
     dmg_url: Option<String>,
 }
 
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DependencyCheck {
+    name: String,
+    available: bool,
+}
+
+/// Snapshot of every subsystem, for troubleshooting and bug reports.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Diagnostics {
+    app_version: String,
+    livekit_connected: bool,
+    voice_active: bool,
+    mic_test_active: bool,
+    dj_status: DjStatus,
+    webhook_connected: bool,
+    queue_polling: bool,
+    dependencies: Vec<DependencyCheck>,
+    cache_bytes: u64,
+    /// Scopes of the token `gh` is logged in with, when it reports them.
+    gh_token_scopes: Option<Vec<String>>,
+}
+
+/// Whether `program` runs at all, giving up after a few seconds.
+async fn binary_available(program: &str, version_arg: &str) -> bool {
+    matches!(
+        tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            tokio::process::Command::new(program)
+                .arg(version_arg)
+                .kill_on_drop(true)
+                .output(),
+        )
+        .await,
+        Ok(Ok(output)) if output.status.success()
+    )
+}
+
+/// Total size of the files directly inside `dir`.
+fn dir_size(dir: &std::path::Path) -> u64 {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| entry.metadata().ok())
+                .filter(|meta| meta.is_file())
+                .map(|meta| meta.len())
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
 fn normalize_version(tag: &str) -> String {
     let trimmed = tag.trim_start_matches('v');
     trimmed.split('-').next().unwrap_or(trimmed).to_string()
@@ -137,55 +381,212 @@ fn is_newer_version(latest: &str, current: &str) -> bool {
     false
 }
 
-#[tauri::command]
-fn join_room(state: State<'_, Mutex<RoomState>>) -> Result<Vec<String>, String> {
+/// Resolve an app directory, falling back to a temp dir so the app still runs.
+/// Returns the directory and whether it is the real, persistent location.
+fn app_dir_or_temp(kind: &str, dir: tauri::Result<std::path::PathBuf>) -> (std::path::PathBuf, bool) {
+    match dir {
+        Ok(dir) => (dir, true),
+        Err(e) => {
+            let fallback = std::env::temp_dir().join("gezellig").join(kind);
+            dlog!(
+                "WARNING: app {kind} dir unavailable ({e}), using {} instead; data will not persist across restarts",
+                fallback.display()
+            );
+            (fallback, false)
+        }
+    }
+}
+
+fn load_settings_or_default(settings_path: &SettingsPath) -> Settings {
+    Settings::load(&settings_path.0).unwrap_or_default()
+}
+
+/// The configured `gh` binary, or `gh` from PATH when none is set.
+fn gh_path_or_default(settings: &Settings) -> String {
+    if settings.gh_path.trim().is_empty() {
+        "gh".to_string()
+    } else {
+        settings.gh_path.clone()
+    }
+}
+
+/// Apply a change to the persisted settings and return the updated values.
+fn update_settings<F>(settings_path: &SettingsPath, apply: F) -> Result<Settings, String>
+where
+    F: FnOnce(&mut Settings),
+{
+    let mut settings = load_settings_or_default(settings_path);
+    apply(&mut settings);
+    settings.save(&settings_path.0).map_err(|e| e.to_string())?;
+    Ok(settings)
+}
+
+fn mic_options(settings_path: &SettingsPath) -> voice_chat::MicOptions {
+    let settings = load_settings_or_default(settings_path);
+    voice_chat::MicOptions {
+        input_channel: settings.input_channel,
+        stereo: settings.stereo_input,
+    }
+}
+
+/// Refresh `RoomState` from the live LiveKit participant list.
+async fn sync_room_state(
+    state: &Mutex<RoomState>,
+    lk_room: &TokioMutex<Option<LiveKitRoom>>,
+) -> Result<RoomSnapshot, String> {
+    let participants = match lk_room.lock().await.as_ref() {
+        Some(room) => room.participants().await,
+        None => vec![],
+    };
     let mut room = state.lock().map_err(|e| e.to_string())?;
-    room.join("You".to_string());
-    Ok(room.participants().to_vec())
+    room.sync(participants);
+    Ok(room.snapshot())
+}
+
+/// LiveKit identity of the local participant.
+async fn local_room_identity(lk_room: &TokioMutex<Option<LiveKitRoom>>) -> Result<String, String> {
+    let identity = match lk_room.lock().await.as_ref() {
+        Some(room) => room.local_identity().await,
+        None => None,
+    };
+    identity.ok_or_else(|| "LiveKit not connected".to_string())
+}
+
+#[tauri::command]
+async fn join_room(
+    state: State<'_, Mutex<RoomState>>,
+    lk_room: State<'_, TokioMutex<Option<LiveKitRoom>>>,
+) -> Result<RoomSnapshot, String> {
+    local_room_identity(&lk_room).await?;
+    sync_room_state(&state, &lk_room).await
 }
 
 #[tauri::command]
-fn leave_room(state: State<'_, Mutex<RoomState>>) -> Result<Vec<String>, String> {
+async fn leave_room(
+    state: State<'_, Mutex<RoomState>>,
+    lk_room: State<'_, TokioMutex<Option<LiveKitRoom>>>,
+) -> Result<RoomSnapshot, String> {
+    let identity = local_room_identity(&lk_room).await.ok();
     let mut room = state.lock().map_err(|e| e.to_string())?;
-    room.leave("You");
-    Ok(room.participants().to_vec())
+    if let Some(identity) = identity {
+        room.stop_dj(&identity);
+    }
+    room.sync(vec![]);
+    Ok(room.snapshot())
+}
+
+#[tauri::command]
+async fn get_room_participants(
+    state: State<'_, Mutex<RoomState>>,
+    lk_room: State<'_, TokioMutex<Option<LiveKitRoom>>>,
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
+) -> Result<Vec<livekit_room::Participant>, String> {
+    let participants = sync_room_state(&state, &lk_room).await?.participants;
+    let connected = lk_room.lock().await.is_some();
+    pipeline
+        .lock()
+        .map_err(|e| e.to_string())?
+        .set_room_participants(connected.then(|| participant_identities(&participants)));
+    Ok(participants)
+}
+
+fn participant_identities(participants: &[livekit_room::Participant]) -> Vec<String> {
+    participants.iter().map(|p| p.identity.clone()).collect()
 }
 
+/// The elected DJ from the shared queue, falling back to the local room state.
 #[tauri::command]
-fn get_room_participants(state: State<'_, Mutex<RoomState>>) -> Result<Vec<String>, String> {
+fn get_current_dj(
+    state: State<'_, Mutex<RoomState>>,
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
+) -> Result<Option<String>, String> {
+    let elected = pipeline.lock().map_err(|e| e.to_string())?.current_dj();
     let room = state.lock().map_err(|e| e.to_string())?;
-    Ok(room.participants().to_vec())
+    Ok(elected.or_else(|| room.current_dj().map(|s| s.to_string())))
 }
 
 #[tauri::command]
-fn become_dj(state: State<'_, Mutex<RoomState>>) -> Result<Option<String>, String> {
-    let mut room = state.lock().map_err(|e| e.to_string())?;
-    room.become_dj("You".to_string())?;
+async fn become_dj(
+    state: State<'_, Mutex<RoomState>>,
+    lk_room: State<'_, TokioMutex<Option<LiveKitRoom>>>,
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
+) -> Result<Option<String>, String> {
+    let identity = local_room_identity(&lk_room).await?;
+    sync_room_state(&state, &lk_room).await?;
+    state.lock().map_err(|e| e.to_string())?.become_dj(identity.clone())?;
+    // Record the claim in the shared queue so other clients stop streaming
+    claim_dj_with(&pipeline, &identity).await?;
+    let room = state.lock().map_err(|e| e.to_string())?;
     Ok(room.current_dj().map(|s| s.to_string()))
 }
 
+/// Claim DJ duty, writing the claim on a blocking thread. The pipeline is only locked to
+/// hand out that work and to record the result, never while it runs.
+async fn claim_dj_with(pipeline: &Mutex<DynAudioPipeline>, identity: &str) -> Result<(), String> {
+    let writer = pipeline.lock().map_err(|e| e.to_string())?.dj_claim_writer(identity);
+    if let Some(writer) = writer {
+        tokio::task::spawn_blocking(writer)
+            .await
+            .map_err(|e| format!("DJ claim failed: {e}"))??;
+    }
+    pipeline.lock().map_err(|e| e.to_string())?.claim_dj(identity)
+}
+
 #[tauri::command]
-fn stop_dj(state: State<'_, Mutex<RoomState>>) -> Result<(), String> {
+async fn stop_dj(
+    state: State<'_, Mutex<RoomState>>,
+    lk_room: State<'_, TokioMutex<Option<LiveKitRoom>>>,
+) -> Result<(), String> {
+    let identity = local_room_identity(&lk_room).await?;
     let mut room = state.lock().map_err(|e| e.to_string())?;
-    room.stop_dj("You");
+    room.stop_dj(&identity);
     Ok(())
 }
 
+/// Save connection settings. Returns whether they will persist across restarts.
 #[tauri::command]
 fn save_settings(
     settings_path: State<'_, SettingsPath>,
+    persistent: State<'_, SettingsPersistent>,
     livekit_url: String,
     shared_queue_repo: String,
     shared_queue_file: String,
     gh_path: String,
+    livekit_url_fallback: Option<String>,
+) -> Result<bool, String> {
+    update_settings(&settings_path, |settings| {
+        settings.livekit_url = livekit_url;
+        if let Some(fallback) = livekit_url_fallback {
+            settings.livekit_url_fallback = fallback.trim().to_string();
+        }
+        settings.shared_queue_repo = shared_queue_repo;
+        settings.shared_queue_file = shared_queue_file;
+        settings.gh_path = gh_path;
+    })?;
+    if !persistent.0 {
+        dlog!("WARNING: settings saved to a temporary directory and will be lost on restart");
+    }
+    Ok(persistent.0)
+}
+
+#[tauri::command]
+async fn set_display_name(
+    settings_path: State<'_, SettingsPath>,
+    lk_room: State<'_, TokioMutex<Option<LiveKitRoom>>>,
+    name: String,
 ) -> Result<(), String> {
-    let settings = Settings {
-        livekit_url,
-        shared_queue_repo,
-        shared_queue_file,
-        gh_path,
-    };
-    settings.save(&settings_path.0).map_err(|e| e.to_string())
+    let name = name.trim().to_string();
+    update_settings(&settings_path, |settings| settings.display_name = name.clone())?;
+    if name.is_empty() {
+        return Ok(());
+    }
+    let guard = lk_room.lock().await;
+    if let Some(room) = guard.as_ref() {
+        if room.is_connected().await {
+            room.set_display_name(&name).await?;
+        }
+    }
+    Ok(())
 }
 
 #[tauri::command]
@@ -207,12 +608,7 @@ struct ReleaseInfo {
 #[tauri::command]
 async fn check_for_update(settings_path: State<'_, SettingsPath>) -> Result<UpdateCheckResult, String> {
     let current_version = env!("CARGO_PKG_VERSION").to_string();
-    let settings = Settings::load(&settings_path.0).unwrap_or_default();
-    let gh_path = if settings.gh_path.trim().is_empty() {
-        "gh".to_string()
-    } else {
-        settings.gh_path
-    };
+    let gh_path = gh_path_or_default(&load_settings_or_default(&settings_path));
 
     let output = match tokio::time::timeout(
         std::time::Duration::from_secs(5),
@@ -281,12 +677,49 @@ async fn check_for_update(settings_path: State<'_, SettingsPath>) -> Result<Upda
     })
 }
 
+/// PCM receiver taken from a pipeline, with its channel count.
+type PcmSource = (tokio::sync::mpsc::Receiver<Vec<u8>>, u32);
+
+/// Decide whether the DJ hears the music locally, start the pipeline and return its
+/// status along with the PCM to publish.
+fn start_pipeline(
+    p: &dyn AudioPipeline,
+    dj_only: bool,
+    monitoring: Option<bool>,
+    has_livekit: bool,
+) -> Result<(String, Option<PcmSource>), String> {
+    if dj_only {
+        p.set_local_playback(false);
+        crate::dlog!("[DJ] DJ-only mode, local playback disabled");
+    } else if let Some(enabled) = monitoring {
+        p.set_local_playback(enabled);
+        crate::dlog!("[DJ] Local monitoring {}", if enabled { "on" } else { "off" });
+    } else if has_livekit {
+        p.set_local_playback(false);
+        crate::dlog!("[DJ] LiveKit connected, local playback disabled");
+    } else {
+        p.set_local_playback(true);
+        crate::dlog!("[DJ] No LiveKit, local playback enabled");
+    }
+    p.start()?;
+    let status = format!("{:?}", p.status());
+    let rx = p.take_pcm_receiver().map(|rx| (rx, u32::from(p.pcm_channels())));
+    Ok((status, rx))
+}
+
 #[tauri::command]
 async fn start_dj_audio(
     pipeline: State<'_, Mutex<DynAudioPipeline>>,
     lk_room: State<'_, TokioMutex<Option<LiveKitRoom>>>,
     publisher_handle: State<'_, TokioMutex<Option<DjPublisherHandle>>>,
+    local_monitoring: State<'_, LocalMonitoring>,
+    dj_only: State<'_, DjOnlyMode>,
+    listener: State<'_, ListenerMode>,
+    pcm_flush: State<'_, PcmFlush>,
+    settings_path: State<'_, SettingsPath>,
 ) -> Result<String, String> {
+    ensure_can_publish(&listener)?;
+    let latency_ms = load_settings_or_default(&settings_path).dj_latency_ms;
     // Check if connected to LiveKit — if so, disable local playback before starting
     let has_livekit = {
         let room_guard = lk_room.lock().await;
@@ -297,19 +730,10 @@ async fn start_dj_audio(
         }
     };
 
+    let monitoring = *local_monitoring.0.lock().map_err(|e| e.to_string())?;
     let (status_str, pcm_receiver) = {
         let p = pipeline.lock().map_err(|e| e.to_string())?;
-        if has_livekit {
-            p.set_local_playback(false);
-            crate::dlog!("[DJ] LiveKit connected, local playback disabled");
-        } else {
-            p.set_local_playback(true);
-            crate::dlog!("[DJ] No LiveKit, local playback enabled");
-        }
-        p.start()?;
-        let status = format!("{:?}", p.status());
-        let rx = p.take_pcm_receiver();
-        (status, rx)
+        start_pipeline(&**p, dj_only.0.load(Ordering::Relaxed), monitoring, has_livekit)?
     };
 
     // If connected to LiveKit, spawn the publisher
@@ -317,9 +741,17 @@ async fn start_dj_audio(
         let room_guard = lk_room.lock().await;
         if let Some(lk) = room_guard.as_ref() {
             if let Some(room) = lk.get_room().await {
-                if let Some(rx) = pcm_receiver {
+                if let Some((rx, num_channels)) = pcm_receiver {
                     let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
-                    let task = dj_publisher::spawn_audio_publisher(room, rx, shutdown_rx);
+                    let task =
+                        dj_publisher::spawn_audio_publisher(
+                            room,
+                            rx,
+                            shutdown_rx,
+                            latency_ms,
+                            num_channels,
+                            pcm_flush.0.subscribe(),
+                        );
                     *publisher_handle.lock().await = Some(DjPublisherHandle {
                         shutdown_tx: Some(shutdown_tx),
                         task: Some(task),
@@ -337,186 +769,1045 @@ async fn start_dj_audio(
 async fn stop_dj_audio(
     pipeline: State<'_, Mutex<DynAudioPipeline>>,
     publisher_handle: State<'_, TokioMutex<Option<DjPublisherHandle>>>,
+    dj_only: State<'_, DjOnlyMode>,
+    stop_after_current: Option<bool>,
 ) -> Result<(), String> {
+    let local_playback = !dj_only.0.load(Ordering::Relaxed);
+    if stop_after_current.unwrap_or(false) {
+        // Stop the pipeline first so it sends its faded tail, then let the publisher drain it
+        pipeline.lock().map_err(|e| e.to_string())?.stop()?;
+        stop_publisher(&publisher_handle, dj_publisher::StopMode::Drain).await;
+        pipeline.lock().map_err(|e| e.to_string())?.set_local_playback(local_playback);
+        return Ok(());
+    }
+
     // Stop the publisher first
+    stop_publisher(&publisher_handle, dj_publisher::StopMode::Immediate).await;
+
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    p.set_local_playback(local_playback);
+    p.stop()
+}
+
+async fn stop_publisher(
+    publisher_handle: &TokioMutex<Option<DjPublisherHandle>>,
+    mode: dj_publisher::StopMode,
+) {
     let mut handle = publisher_handle.lock().await;
     if let Some(mut h) = handle.take() {
         if let Some(tx) = h.shutdown_tx.take() {
-            let _ = tx.send(());
+            let _ = tx.send(mode);
         }
-        if let Some(task) = h.task.take() {
-            let _ = task.await;
+        if let Some(mut task) = h.task.take() {
+            if mode == dj_publisher::StopMode::Drain {
+                if tokio::time::timeout(PUBLISHER_DRAIN_TIMEOUT, &mut task).await.is_err() {
+                    crate::dlog!("[DJ] Publisher drain timed out, stopping immediately");
+                    task.abort();
+                }
+            } else {
+                let _ = task.await;
+            }
         }
         crate::dlog!("[DJ] LiveKit audio publisher stopped");
     }
+}
 
-    let p = pipeline.lock().map_err(|e| e.to_string())?;
-    p.set_local_playback(true);
-    p.stop()
+#[tauri::command]
+async fn switch_pipeline(
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
+    factory: State<'_, PipelineFactory>,
+    lk_room: State<'_, TokioMutex<Option<LiveKitRoom>>>,
+    publisher_handle: State<'_, TokioMutex<Option<DjPublisherHandle>>>,
+    dj_only: State<'_, DjOnlyMode>,
+    settings_path: State<'_, SettingsPath>,
+    backend: String,
+) -> Result<String, String> {
+    let replacement = factory.build(&backend)?;
+    if dj_only.0.load(Ordering::Relaxed) {
+        replacement.set_local_playback(false);
+    }
+    stop_publisher(&publisher_handle, dj_publisher::StopMode::Immediate).await;
+
+    let local_identity = {
+        let guard = lk_room.lock().await;
+        match guard.as_ref() {
+            Some(lk) => lk.local_identity().await,
+            None => None,
+        }
+    };
+    replacement.set_local_identity(local_identity);
+    spawn_status_bridge(&replacement);
+
+    let previous = {
+        let mut p = pipeline.lock().map_err(|e| e.to_string())?;
+        std::mem::replace(&mut *p, replacement)
+    };
+    if let Err(err) = previous.stop() {
+        crate::dlog!("[DJ] Failed to stop previous pipeline: {err}");
+    }
+
+    update_settings(&settings_path, |settings| {
+        settings.pipeline_backend = backend.clone();
+    })?;
+    crate::dlog!("[DJ] Switched audio pipeline to {backend}");
+    Ok(backend)
 }
 
+/// Turn local speaker monitoring on or off while DJing, including mid-track.
 #[tauri::command]
-fn get_dj_status(pipeline: State<'_, Mutex<DynAudioPipeline>>) -> Result<DjStatus, String> {
+fn set_local_monitoring(
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
+    local_monitoring: State<'_, LocalMonitoring>,
+    dj_only: State<'_, DjOnlyMode>,
+    enabled: bool,
+) -> Result<(), String> {
+    *local_monitoring.0.lock().map_err(|e| e.to_string())? = Some(enabled);
     let p = pipeline.lock().map_err(|e| e.to_string())?;
-    Ok(p.status())
+    p.set_local_playback(enabled && !dj_only.0.load(Ordering::Relaxed));
+    Ok(())
 }
 
+/// Keep music off the local speakers entirely, including between DJ sessions.
 #[tauri::command]
-fn set_music_volume(
+fn set_dj_only_mode(
     pipeline: State<'_, Mutex<DynAudioPipeline>>,
-    playback_volume: State<'_, PlaybackVolume>,
-    volume: u8,
+    local_monitoring: State<'_, LocalMonitoring>,
+    dj_only: State<'_, DjOnlyMode>,
+    settings_path: State<'_, SettingsPath>,
+    enabled: bool,
 ) -> Result<(), String> {
+    dj_only.0.store(enabled, Ordering::Relaxed);
+    update_settings(&settings_path, |settings| settings.dj_only_mode = enabled)?;
     let p = pipeline.lock().map_err(|e| e.to_string())?;
-    p.set_volume(volume)?;
-    let clamped = p.volume();
-    playback_volume.0.store(clamped, Ordering::Relaxed);
+    if enabled {
+        p.set_local_playback(false);
+    } else if let Some(monitoring) = *local_monitoring.0.lock().map_err(|e| e.to_string())? {
+        p.set_local_playback(monitoring);
+    }
+    crate::dlog!("[DJ] DJ-only mode {}", if enabled { "on" } else { "off" });
     Ok(())
 }
 
-#[tauri::command]
-fn get_music_volume(playback_volume: State<'_, PlaybackVolume>) -> Result<u8, String> {
-    Ok(playback_volume.0.load(Ordering::Relaxed))
+fn ensure_can_publish(listener: &ListenerMode) -> Result<(), String> {
+    if listener.0.load(Ordering::Relaxed) {
+        return Err("Listener mode is on; turn it off in settings to talk or DJ".to_string());
+    }
+    Ok(())
 }
 
+/// Only listen: stop publishing the mic and music now, and refuse to start either again.
 #[tauri::command]
-async fn start_voice_chat(
-    lk_room: State<'_, TokioMutex<Option<LiveKitRoom>>>,
+async fn set_listener_mode(
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
+    publisher_handle: State<'_, TokioMutex<Option<DjPublisherHandle>>>,
+    dj_only: State<'_, DjOnlyMode>,
     voice_handle: State<'_, TokioMutex<Option<VoiceChatHandle>>>,
-    mic_test: State<'_, TokioMutex<Option<MicTestHandle>>>,
-    mic_level: State<'_, MicLevel>,
+    listener: State<'_, ListenerMode>,
+    settings_path: State<'_, SettingsPath>,
+    enabled: bool,
 ) -> Result<(), String> {
-    let room = {
-        let guard = lk_room.lock().await;
-        match guard.as_ref() {
-            Some(lk) => lk.get_room().await.ok_or("LiveKit not connected")?,
-            None => return Err("LiveKit not connected".into()),
+    listener.0.store(enabled, Ordering::Relaxed);
+    update_settings(&settings_path, |settings| settings.listener_mode = enabled)?;
+    if enabled {
+        if let Some(handle) = voice_handle.lock().await.take() {
+            voice_chat::stop_voice_chat(handle.inner).await;
+        }
+        if publisher_handle.lock().await.is_some() {
+            stop_dj_audio(pipeline, publisher_handle, dj_only, None).await?;
         }
-    };
-
-    if voice_handle.lock().await.is_some() {
-        return Ok(());
-    }
-
-    if let Some(handle) = mic_test.lock().await.take() {
-        voice_chat::stop_mic_test(handle.inner);
     }
-
-    let handle = voice_chat::start_voice_chat(room, mic_level.0.clone())
-        .await
-        .map_err(|e| e.to_string())?;
-    *voice_handle.lock().await = Some(VoiceChatHandle { inner: handle });
+    crate::dlog!("[LK] Listener mode {}", if enabled { "on" } else { "off" });
     Ok(())
 }
 
+/// Become DJ automatically after connecting to LiveKit.
 #[tauri::command]
-async fn stop_voice_chat(
-    voice_handle: State<'_, TokioMutex<Option<VoiceChatHandle>>>,
-) -> Result<(), String> {
-    if let Some(handle) = voice_handle.lock().await.take() {
-        voice_chat::stop_voice_chat(handle.inner).await;
-    }
+fn set_auto_dj(settings_path: State<'_, SettingsPath>, enabled: bool) -> Result<(), String> {
+    update_settings(&settings_path, |settings| settings.auto_dj = enabled)?;
     Ok(())
 }
 
+/// Let each person's first track since the last clear play next.
 #[tauri::command]
-async fn start_mic_test(
-    voice_handle: State<'_, TokioMutex<Option<VoiceChatHandle>>>,
-    mic_test: State<'_, TokioMutex<Option<MicTestHandle>>>,
-    mic_level: State<'_, MicLevel>,
-) -> Result<(), String> {
-    if voice_handle.lock().await.is_some() {
-        return Ok(());
-    }
-    if mic_test.lock().await.is_some() {
-        return Ok(());
-    }
-    let handle = voice_chat::start_mic_test(mic_level.0.clone()).map_err(|e| e.to_string())?;
-    *mic_test.lock().await = Some(MicTestHandle { inner: handle });
+fn set_first_song_jumps_queue(settings_path: State<'_, SettingsPath>, enabled: bool) -> Result<(), String> {
+    update_settings(&settings_path, |settings| settings.first_song_jumps_queue = enabled)?;
     Ok(())
 }
 
+/// Refuse to queue the same URL twice in a row.
 #[tauri::command]
-async fn stop_mic_test(
-    mic_test: State<'_, TokioMutex<Option<MicTestHandle>>>,
-) -> Result<(), String> {
-    if let Some(handle) = mic_test.lock().await.take() {
-        voice_chat::stop_mic_test(handle.inner);
-    }
+fn set_reject_duplicates(settings_path: State<'_, SettingsPath>, enabled: bool) -> Result<(), String> {
+    update_settings(&settings_path, |settings| settings.reject_duplicates = enabled)?;
     Ok(())
 }
 
+/// Set how often the shared queue is refetched when webhooks are unavailable.
 #[tauri::command]
-fn get_mic_level(mic_level: State<'_, MicLevel>) -> Result<u8, String> {
-    Ok(mic_level.0.load(Ordering::Relaxed))
+fn set_queue_poll_interval(
+    settings_path: State<'_, SettingsPath>,
+    queue_sync: State<'_, QueueSync>,
+    secs: u64,
+) -> Result<u64, String> {
+    let applied = queue_sync.0.set_poll_interval_secs(secs);
+    update_settings(&settings_path, |settings| settings.queue_poll_interval_secs = applied)?;
+    Ok(applied)
 }
 
+/// Set how often a streaming DJ polls for remote skips when webhooks are unavailable.
 #[tauri::command]
-fn queue_track(
-    pipeline: State<'_, Mutex<DynAudioPipeline>>,
-    url: String,
-    queued_by: Option<String>,
-) -> Result<(), String> {
-    let p = pipeline.lock().map_err(|e| e.to_string())?;
-    p.queue_track(url, queued_by)
+fn set_skip_check_interval(
+    settings_path: State<'_, SettingsPath>,
+    queue_sync: State<'_, QueueSync>,
+    secs: u64,
+) -> Result<u64, String> {
+    let applied = queue_sync.0.set_skip_check_interval_secs(secs);
+    update_settings(&settings_path, |settings| settings.skip_check_interval_secs = applied)?;
+    Ok(applied)
 }
 
+/// Set how often the webhook websocket is pinged; applies from the next connection.
 #[tauri::command]
-fn skip_track(pipeline: State<'_, Mutex<DynAudioPipeline>>) -> Result<(), String> {
-    let p = pipeline.lock().map_err(|e| e.to_string())?;
-    p.skip_track()
+fn set_webhook_ping_interval(
+    settings_path: State<'_, SettingsPath>,
+    queue_sync: State<'_, QueueSync>,
+    secs: u64,
+) -> Result<u64, String> {
+    let applied = queue_sync.0.set_ping_interval_secs(secs);
+    update_settings(&settings_path, |settings| settings.webhook_ping_interval_secs = applied)?;
+    Ok(applied)
 }
 
+/// Set how many upcoming tracks to prefetch (`0` disables it). Returns the value applied.
 #[tauri::command]
-fn get_queue(pipeline: State<'_, Mutex<DynAudioPipeline>>) -> Result<Vec<String>, String> {
-    let p = pipeline.lock().map_err(|e| e.to_string())?;
-    Ok(p.get_queue())
+fn set_prefetch_depth(
+    settings_path: State<'_, SettingsPath>,
+    prefetch_depth: State<'_, PrefetchDepth>,
+    depth: usize,
+) -> Result<usize, String> {
+    let applied = youtube_pipeline::clamp_prefetch_depth(depth);
+    prefetch_depth.0.store(applied, Ordering::Relaxed);
+    update_settings(&settings_path, |settings| settings.prefetch_depth = applied)?;
+    Ok(applied)
 }
 
+/// Set how many prefetch downloads may run at once (1–4). Returns the value applied.
 #[tauri::command]
-fn get_shared_queue(pipeline: State<'_, Mutex<DynAudioPipeline>>) -> Result<Vec<String>, String> {
-    let p = pipeline.lock().map_err(|e| e.to_string())?;
-    if let Some(queue) = p.shared_queue() {
-        Ok(queue)
-    } else {
-        Ok(p.get_queue())
-    }
+fn set_prefetch_concurrency(
+    settings_path: State<'_, SettingsPath>,
+    prefetch_concurrency: State<'_, PrefetchConcurrency>,
+    concurrency: usize,
+) -> Result<usize, String> {
+    let applied = youtube_pipeline::clamp_prefetch_concurrency(concurrency);
+    prefetch_concurrency.0.store(applied, Ordering::Relaxed);
+    update_settings(&settings_path, |settings| settings.prefetch_concurrency = applied)?;
+    Ok(applied)
 }
 
+/// Download the whole upcoming queue ahead of time, emitting `prefetch-progress` as items finish.
+/// Returns how many items were handled.
 #[tauri::command]
-fn get_shared_queue_state(
+async fn prefetch_all(
     pipeline: State<'_, Mutex<DynAudioPipeline>>,
-) -> Result<SharedQueueSnapshot, String> {
-    let p = pipeline.lock().map_err(|e| e.to_string())?;
-    if let Some(snapshot) = p.shared_queue_snapshot() {
-        Ok(snapshot)
-    } else {
-        Ok(SharedQueueSnapshot {
-            queue: p.get_queue().into_iter().enumerate().map(|(i, url)| {
-                crate::audio::SharedQueueItem { url, title: None, id: i as u64, queued_by: None }
-            }).collect(),
-            now_playing: None,
-            history: Vec::new(),
-        })
+    factory: State<'_, PipelineFactory>,
+    prefetch_concurrency: State<'_, PrefetchConcurrency>,
+    prefetch_cancel: State<'_, PrefetchCancel>,
+) -> Result<usize, String> {
+    let urls = {
+        let p = pipeline.lock().map_err(|e| e.to_string())?;
+        p.get_queue()?
+    };
+    if urls.len() > youtube_pipeline::CACHE_LIMIT {
+        emit_backend_error(
+            "playback",
+            format!(
+                "The queue has {} tracks but only {} stay cached; later prefetches will evict the oldest",
+                urls.len(),
+                youtube_pipeline::CACHE_LIMIT
+            ),
+        );
     }
+    let quality = *factory.audio_quality.lock().map_err(|e| e.to_string())?;
+    let cookies = factory.cookies_path.lock().map_err(|e| e.to_string())?.clone();
+    let source = youtube_pipeline::YtDlpSource::new(factory.cache_dir.clone())
+        .with_quality(quality)
+        .with_cookies(cookies);
+    prefetch_cancel.0.store(false, Ordering::Relaxed);
+    let done = youtube_pipeline::prefetch_all(
+        &source,
+        urls,
+        prefetch_concurrency.0.load(Ordering::Relaxed),
+        &prefetch_cancel.0,
+        |progress| emit_event("prefetch-progress", progress),
+    )
+    .await;
+    Ok(done)
 }
 
+/// Stop a running `prefetch_all`; downloads already in flight finish.
 #[tauri::command]
-fn clear_shared_queue(pipeline: State<'_, Mutex<DynAudioPipeline>>) -> Result<(), String> {
-    let p = pipeline.lock().map_err(|e| e.to_string())?;
-    p.clear_shared_queue()
+fn cancel_prefetch(prefetch_cancel: State<'_, PrefetchCancel>) -> Result<(), String> {
+    prefetch_cancel.0.store(true, Ordering::Relaxed);
+    Ok(())
 }
 
+/// Set how long yt-dlp may stall before a track is failed. Returns the value applied.
 #[tauri::command]
-fn reorder_queue(pipeline: State<'_, Mutex<DynAudioPipeline>>, order: Vec<u64>) -> Result<(), String> {
-    let p = pipeline.lock().map_err(|e| e.to_string())?;
-    p.reorder_queue(order)
+fn set_stream_timeout_secs(
+    settings_path: State<'_, SettingsPath>,
+    stream_timeout: State<'_, StreamTimeout>,
+    secs: u64,
+) -> Result<u64, String> {
+    let applied = youtube_pipeline::clamp_stream_timeout_secs(secs);
+    stream_timeout.0.store(applied, Ordering::Relaxed);
+    update_settings(&settings_path, |settings| settings.stream_timeout_secs = applied)?;
+    Ok(applied)
 }
 
+/// Lower the music by `amount_pct` while anyone talks, fading over `attack_ms` and
+/// `release_ms`. `0` turns ducking off.
 #[tauri::command]
-fn get_backend_logs() -> Vec<String> {
-    if let Some(buf) = DEBUG_LOG.get() {
-        buf.drain()
+fn set_music_ducking(
+    settings_path: State<'_, SettingsPath>,
+    ducking: State<'_, MusicDucking>,
+    amount_pct: u8,
+    attack_ms: u64,
+    release_ms: u64,
+    ignore_self: bool,
+) -> Result<(), String> {
+    let (amount_pct, attack_ms, release_ms) = ducking.0.configure(amount_pct, attack_ms, release_ms, ignore_self);
+    update_settings(&settings_path, |settings| {
+        settings.duck_amount_pct = amount_pct;
+        settings.duck_attack_ms = attack_ms;
+        settings.duck_release_ms = release_ms;
+        settings.duck_ignore_self = ignore_self;
+    })?;
+    Ok(())
+}
+
+/// Set how many times a track that fails to load is retried (0–5). Returns the value applied.
+#[tauri::command]
+fn set_load_retries(
+    settings_path: State<'_, SettingsPath>,
+    load_retries: State<'_, LoadRetries>,
+    retries: usize,
+) -> Result<usize, String> {
+    let applied = youtube_pipeline::clamp_load_retries(retries);
+    load_retries.0.store(applied, Ordering::Relaxed);
+    update_settings(&settings_path, |settings| settings.load_retries = applied)?;
+    Ok(applied)
+}
+
+/// Set how many PCM chunks are buffered for LiveKit. Returns the value applied, which takes
+/// effect the next time the music backend is created (switching backend or restarting).
+#[tauri::command]
+fn set_pcm_channel_chunks(
+    settings_path: State<'_, SettingsPath>,
+    pcm_channel_chunks: State<'_, PcmChannelChunks>,
+    chunks: usize,
+) -> Result<usize, String> {
+    let applied = audio::clamp_pcm_channel_chunks(chunks);
+    pcm_channel_chunks.0.store(applied, Ordering::Relaxed);
+    update_settings(&settings_path, |settings| settings.pcm_channel_chunks = applied)?;
+    Ok(applied)
+}
+
+/// Stop the DJ loop after the queue has been empty for `secs`; `0` never stops it.
+#[tauri::command]
+fn set_idle_timeout_secs(
+    settings_path: State<'_, SettingsPath>,
+    idle_timeout: State<'_, IdleTimeout>,
+    secs: u64,
+) -> Result<(), String> {
+    idle_timeout.0.store(secs, Ordering::Relaxed);
+    update_settings(&settings_path, |settings| settings.idle_timeout_secs = secs)?;
+    Ok(())
+}
+
+/// Set the yt-dlp audio quality (`best`, `medium` or `low`); applies from the next track.
+#[tauri::command]
+fn set_audio_quality(
+    settings_path: State<'_, SettingsPath>,
+    audio_quality: State<'_, AudioQualitySetting>,
+    quality: String,
+) -> Result<(), String> {
+    let parsed = youtube_pipeline::AudioQuality::from_setting(&quality)
+        .ok_or_else(|| format!("Unknown audio quality: {quality}"))?;
+    *audio_quality.0.lock().map_err(|e| e.to_string())? = parsed;
+    update_settings(&settings_path, |settings| settings.audio_quality = parsed.as_setting().to_string())?;
+    Ok(())
+}
+
+/// Set how shared queue events are spread over files (`single` or `buckets`); applies from the next write.
+#[tauri::command]
+fn set_shared_queue_sharding(
+    settings_path: State<'_, SettingsPath>,
+    shard_strategy: State<'_, ShardStrategySetting>,
+    sharding: String,
+) -> Result<(), String> {
+    let parsed = youtube_pipeline::ShardStrategy::from_setting(&sharding)
+        .ok_or_else(|| format!("Unknown shared queue sharding: {sharding}"))?;
+    *shard_strategy.0.lock().map_err(|e| e.to_string())? = parsed;
+    update_settings(&settings_path, |settings| {
+        settings.shared_queue_sharding = parsed.as_setting().to_string()
+    })?;
+    Ok(())
+}
+
+/// Set the cookies.txt yt-dlp uses for restricted videos; `None` or blank clears it.
+#[tauri::command]
+fn set_cookies_path(
+    settings_path: State<'_, SettingsPath>,
+    cookies_path: State<'_, CookiesPath>,
+    path: Option<String>,
+) -> Result<(), String> {
+    let path = path.map(|p| p.trim().to_string()).filter(|p| !p.is_empty());
+    if let Some(p) = path.as_deref() {
+        if !std::path::Path::new(p).is_file() {
+            return Err(format!("Cookies file not found: {p}"));
+        }
+    }
+    *cookies_path.0.lock().map_err(|e| e.to_string())? = path.as_ref().map(std::path::PathBuf::from);
+    update_settings(&settings_path, |settings| settings.cookies_path = path)?;
+    Ok(())
+}
+
+/// Tracks in the audio cache, most recently used first.
+#[tauri::command]
+fn list_cached_tracks(factory: State<'_, PipelineFactory>) -> Vec<youtube_pipeline::CachedTrack> {
+    factory
+        .cache_dir
+        .as_deref()
+        .map(youtube_pipeline::list_cached_tracks)
+        .unwrap_or_default()
+}
+
+/// Save a cached track to `dest_path` as WAV, or FLAC if the path ends in `.flac`.
+#[tauri::command]
+async fn export_track(
+    factory: State<'_, PipelineFactory>,
+    url: String,
+    dest_path: String,
+) -> Result<(), String> {
+    let quality = *factory.audio_quality.lock().map_err(|e| e.to_string())?;
+    let source = youtube_pipeline::YtDlpSource::new(factory.cache_dir.clone()).with_quality(quality);
+    source.export_track(&url, std::path::Path::new(&dest_path)).await
+}
+
+/// Set the DJ publisher buffer; takes effect the next time DJ audio starts.
+#[tauri::command]
+fn set_dj_latency_ms(settings_path: State<'_, SettingsPath>, ms: u32) -> Result<u32, String> {
+    let applied = dj_publisher::validate_latency_ms(ms);
+    if applied != ms {
+        crate::dlog!("[DJ] Latency {ms}ms out of range, using {applied}ms");
+    }
+    update_settings(&settings_path, |settings| settings.dj_latency_ms = applied)?;
+    Ok(applied)
+}
+
+#[tauri::command]
+fn pipeline_supports_queue(pipeline: State<'_, Mutex<DynAudioPipeline>>) -> Result<bool, String> {
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    Ok(p.supports_queue())
+}
+
+#[tauri::command]
+async fn get_diagnostics(
+    settings_path: State<'_, SettingsPath>,
+    lk_room: State<'_, TokioMutex<Option<LiveKitRoom>>>,
+    voice_handle: State<'_, TokioMutex<Option<VoiceChatHandle>>>,
+    mic_test: State<'_, TokioMutex<Option<MicTestHandle>>>,
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
+    webhook_connected: State<'_, WebhookConnected>,
+    queue_sync: State<'_, QueueSync>,
+    factory: State<'_, PipelineFactory>,
+) -> Result<Diagnostics, String> {
+    let dj_status = pipeline.lock().map_err(|e| e.to_string())?.status();
+    let livekit_connected = match lk_room.lock().await.as_ref() {
+        Some(room) => room.is_connected().await,
+        None => false,
+    };
+    let voice_active = voice_handle.lock().await.is_some();
+    let mic_test_active = mic_test.lock().await.is_some();
+
+    let gh_path = gh_path_or_default(&load_settings_or_default(&settings_path));
+    let mut dependencies = Vec::new();
+    for (name, version_arg) in [("yt-dlp", "--version"), ("ffmpeg", "-version"), (gh_path.as_str(), "--version")] {
+        dependencies.push(DependencyCheck {
+            name: name.to_string(),
+            available: binary_available(name, version_arg).await,
+        });
+    }
+    let gh_token_scopes = queue_access::token_scopes(&gh_path).await;
+
+    Ok(Diagnostics {
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        livekit_connected,
+        voice_active,
+        mic_test_active,
+        dj_status,
+        webhook_connected: webhook_connected.0.load(Ordering::Relaxed),
+        queue_polling: queue_sync.0.is_polling(),
+        dependencies,
+        cache_bytes: factory.cache_dir.as_deref().map(dir_size).unwrap_or(0),
+        gh_token_scopes,
+    })
+}
+
+#[tauri::command]
+fn get_dj_status(pipeline: State<'_, Mutex<DynAudioPipeline>>) -> Result<DjStatus, String> {
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    Ok(p.status())
+}
+
+#[tauri::command]
+fn set_music_volume(
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
+    playback_volume: State<'_, PlaybackVolume>,
+    volume: u8,
+) -> Result<(), String> {
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    p.set_volume(volume)?;
+    let clamped = p.volume();
+    playback_volume.0.store(clamped, Ordering::Relaxed);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_music_volume(playback_volume: State<'_, PlaybackVolume>) -> Result<u8, String> {
+    Ok(playback_volume.0.load(Ordering::Relaxed))
+}
+
+#[tauri::command]
+async fn start_voice_chat(
+    lk_room: State<'_, TokioMutex<Option<LiveKitRoom>>>,
+    voice_handle: State<'_, TokioMutex<Option<VoiceChatHandle>>>,
+    mic_test: State<'_, TokioMutex<Option<MicTestHandle>>>,
+    mic_level: State<'_, MicLevel>,
+    published_input_level: State<'_, PublishedInputLevel>,
+    listener: State<'_, ListenerMode>,
+    settings_path: State<'_, SettingsPath>,
+) -> Result<(), String> {
+    ensure_can_publish(&listener)?;
+    let room = {
+        let guard = lk_room.lock().await;
+        match guard.as_ref() {
+            Some(lk) => lk.get_room().await.ok_or("LiveKit not connected")?,
+            None => return Err("LiveKit not connected".into()),
+        }
+    };
+
+    if voice_handle.lock().await.is_some() {
+        return Ok(());
+    }
+
+    if let Some(handle) = mic_test.lock().await.take() {
+        voice_chat::stop_mic_test(handle.inner);
+    }
+
+    let handle = voice_chat::start_voice_chat(
+        room,
+        mic_level.0.clone(),
+        published_input_level.0.clone(),
+        mic_options(&settings_path),
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    *voice_handle.lock().await = Some(VoiceChatHandle { inner: handle });
+    Ok(())
+}
+
+#[tauri::command]
+async fn stop_voice_chat(
+    voice_handle: State<'_, TokioMutex<Option<VoiceChatHandle>>>,
+) -> Result<(), String> {
+    if let Some(handle) = voice_handle.lock().await.take() {
+        voice_chat::stop_voice_chat(handle.inner).await;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn start_mic_test(
+    voice_handle: State<'_, TokioMutex<Option<VoiceChatHandle>>>,
+    mic_test: State<'_, TokioMutex<Option<MicTestHandle>>>,
+    mic_level: State<'_, MicLevel>,
+    settings_path: State<'_, SettingsPath>,
+    loopback: Option<bool>,
+) -> Result<(), String> {
+    if voice_handle.lock().await.is_some() {
+        return Ok(());
+    }
+    if mic_test.lock().await.is_some() {
+        return Ok(());
+    }
+    let handle = voice_chat::start_mic_test(
+        mic_level.0.clone(),
+        mic_options(&settings_path),
+        loopback.unwrap_or(false),
+    )
+    .map_err(|e| e.to_string())?;
+    *mic_test.lock().await = Some(MicTestHandle { inner: handle });
+    Ok(())
+}
+
+/// Default mic name and supported formats, for reporting capture problems.
+#[tauri::command]
+fn get_input_device_info() -> Result<voice_chat::InputDeviceInfo, String> {
+    voice_chat::input_device_info().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn stop_mic_test(
+    mic_test: State<'_, TokioMutex<Option<MicTestHandle>>>,
+) -> Result<(), String> {
+    if let Some(handle) = mic_test.lock().await.take() {
+        voice_chat::stop_mic_test(handle.inner);
+    }
+    Ok(())
+}
+
+/// Select a single mic input channel (zero-based), or `None` to average all channels.
+/// Takes effect the next time the mic is opened.
+#[tauri::command]
+fn set_input_channel(
+    settings_path: State<'_, SettingsPath>,
+    channel: Option<usize>,
+) -> Result<(), String> {
+    update_settings(&settings_path, |settings| settings.input_channel = channel).map(|_| ())
+}
+
+/// Capture the mic in stereo for line-in sources; takes effect the next time the mic is opened.
+#[tauri::command]
+fn set_stereo_input(settings_path: State<'_, SettingsPath>, enabled: bool) -> Result<(), String> {
+    update_settings(&settings_path, |settings| settings.stereo_input = enabled).map(|_| ())
+}
+
+#[tauri::command]
+fn get_mic_level(mic_level: State<'_, MicLevel>) -> Result<u8, String> {
+    Ok(mic_level.0.load(Ordering::Relaxed))
+}
+
+#[tauri::command]
+fn get_published_input_level(published_input_level: State<'_, PublishedInputLevel>) -> Result<u8, String> {
+    Ok(published_input_level.0.load(Ordering::Relaxed))
+}
+
+/// Who a track is attributed to: an explicit `queued_by`, else the saved display name.
+fn queued_by_or_default(queued_by: Option<String>, settings: &Settings) -> Option<String> {
+    queued_by
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .or_else(|| Some(settings.display_name.trim().to_string()).filter(|name| !name.is_empty()))
+}
+
+/// Queue `url`, attributed to `queued_by` or, when that is missing, the saved display name.
+#[tauri::command]
+fn queue_track(
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
+    settings_path: State<'_, SettingsPath>,
+    url: String,
+    queued_by: Option<String>,
+) -> Result<(), String> {
+    let settings = load_settings_or_default(&settings_path);
+    if let Some(pattern) = settings.banned_pattern_for(&url) {
+        return Err(format!("This URL is banned in this room (matches \"{pattern}\")"));
+    }
+    let queued_by = queued_by_or_default(queued_by, &settings);
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    queue_with_policy(&**p, &settings, url, queued_by, None)
+}
+
+/// Queue a track, applying the room's duplicate and first-song settings.
+fn queue_with_policy(
+    p: &dyn AudioPipeline,
+    settings: &Settings,
+    url: String,
+    queued_by: Option<String>,
+    clip: Option<(u64, u64)>,
+) -> Result<(), String> {
+    if settings.reject_duplicates {
+        check_not_repeated(p, &url)?;
+    }
+    // Only the shared queue knows who queued what
+    let first_song = match queued_by.as_deref() {
+        Some(by) if settings.first_song_jumps_queue => {
+            p.shared_queue_snapshot()?.is_some_and(|snapshot| is_first_song(&snapshot, by))
+        }
+        _ => false,
+    };
+    if !first_song {
+        return p.queue_track(url, queued_by, clip);
+    }
+    p.queue_track_next(url, queued_by.clone(), clip)?;
+    crate::dlog!("[Queue] First track from {} queued up next", queued_by.unwrap_or_default());
+    Ok(())
+}
+
+/// Whether `queued_by` has nothing waiting, playing or played since the queue was last cleared.
+fn is_first_song(snapshot: &SharedQueueSnapshot, queued_by: &str) -> bool {
+    let theirs = |by: &Option<String>| by.as_deref() == Some(queued_by);
+    !snapshot.queue.iter().any(|t| theirs(&t.queued_by))
+        && !snapshot.now_playing.as_ref().is_some_and(|now| theirs(&now.queued_by))
+        && !snapshot.history.iter().any(|t| theirs(&t.queued_by))
+}
+
+/// Fail if `url` is already the last unplayed track, so a double-click doesn't queue it twice.
+fn check_not_repeated(p: &dyn AudioPipeline, url: &str) -> Result<(), String> {
+    let url = url.trim();
+    if queue_snapshot(p)?.queue.last().is_some_and(|last| last.url == url) {
+        return Err("That track is already at the end of the queue".to_string());
+    }
+    Ok(())
+}
+
+/// Queue only seconds `start_secs` to `end_secs` of a track; every client plays the same clip.
+#[tauri::command]
+fn queue_track_clip(
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
+    settings_path: State<'_, SettingsPath>,
+    url: String,
+    queued_by: Option<String>,
+    start_secs: u64,
+    end_secs: u64,
+) -> Result<(), String> {
+    let settings = load_settings_or_default(&settings_path);
+    if let Some(pattern) = settings.banned_pattern_for(&url) {
+        return Err(format!("This URL is banned in this room (matches \"{pattern}\")"));
+    }
+    let queued_by = queued_by_or_default(queued_by, &settings);
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    queue_with_policy(&**p, &settings, url, queued_by, Some((start_secs, end_secs)))
+}
+
+/// Average bytes/sec the current track is downloading at; real-time playback needs 192000.
+#[tauri::command]
+fn get_stream_throughput(throughput: State<'_, StreamThroughput>) -> u64 {
+    throughput.0.load(Ordering::Relaxed)
+}
+
+/// Source and fetch time of the most recent tracks, newest first, including failed loads.
+#[tauri::command]
+fn get_track_diagnostics(diagnostics: State<'_, TrackDiagnostics>) -> Vec<youtube_pipeline::TrackDiagnostic> {
+    diagnostics.0.recent()
+}
+
+/// Jump to `secs` into the current track; only tracks playing from the cache can seek.
+#[tauri::command]
+fn seek(pipeline: State<'_, Mutex<DynAudioPipeline>>, secs: u64) -> Result<(), String> {
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    p.seek(secs)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PlaybackPosition {
+    position_secs: u64,
+    duration_secs: Option<u64>,
+}
+
+/// How far into the current track playback is, for the seek bar.
+#[tauri::command]
+fn get_playback_position(
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
+) -> Result<Option<PlaybackPosition>, String> {
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    let duration_secs = match p.status() {
+        DjStatus::Playing(now) | DjStatus::Paused(now) => now.duration_secs,
+        _ => None,
+    };
+    Ok(p.playback_position().map(|position_secs| PlaybackPosition { position_secs, duration_secs }))
+}
+
+#[tauri::command]
+fn skip_track(pipeline: State<'_, Mutex<DynAudioPipeline>>) -> Result<(), String> {
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    p.skip_track()
+}
+
+/// Go back to the previously played track.
+#[tauri::command]
+fn play_previous(pipeline: State<'_, Mutex<DynAudioPipeline>>) -> Result<(), String> {
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    p.play_previous()
+}
+
+/// URLs still to play. Reads the shared queue when one is configured, so this and
+/// `get_shared_queue` always agree.
+#[tauri::command]
+fn get_queue(pipeline: State<'_, Mutex<DynAudioPipeline>>) -> Result<Vec<String>, String> {
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    p.get_queue()
+}
+
+/// Same as `get_queue`; kept for callers written before `get_queue` read the shared queue.
+#[tauri::command]
+fn get_shared_queue(pipeline: State<'_, Mutex<DynAudioPipeline>>) -> Result<Vec<String>, String> {
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    p.get_queue()
+}
+
+/// The shared queue, or the local queue dressed up as one when none is configured.
+fn queue_snapshot(p: &dyn AudioPipeline) -> Result<SharedQueueSnapshot, String> {
+    if let Some(snapshot) = p.shared_queue_snapshot()? {
+        return Ok(snapshot);
+    }
+    Ok(SharedQueueSnapshot {
+        queue: p.local_queue()?,
+        now_playing: p.local_now_playing(),
+        history: Vec::new(),
+        paused: false,
+    })
+}
+
+#[tauri::command]
+fn get_shared_queue_state(
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
+) -> Result<SharedQueueSnapshot, String> {
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    queue_snapshot(&**p)
+}
+
+/// Shared queue history, `recent` (most recent first) or `chronological`.
+#[tauri::command]
+fn get_history(
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
+    order: String,
+) -> Result<Vec<crate::audio::SharedHistoryItem>, String> {
+    let mut history = queue_snapshot(&**pipeline.lock().map_err(|e| e.to_string())?)?.history;
+    match order.as_str() {
+        "recent" => {}
+        "chronological" => history.reverse(),
+        other => return Err(format!("Unknown history order \"{other}\"; use recent or chronological")),
+    }
+    Ok(history)
+}
+
+/// Render the session in play order: history oldest first, the current track, then the queue.
+/// `format` is `m3u` (with `#EXTINF` titles) or `txt` (one `title - url` per line).
+fn session_playlist(snapshot: &SharedQueueSnapshot, format: &str) -> Result<String, String> {
+    let tracks = snapshot
+        .history
+        .iter()
+        .rev()
+        .map(|item| (item.title.as_deref(), item.url.as_str()))
+        .chain(snapshot.now_playing.iter().map(|now| (Some(now.title.as_str()), now.url.as_str())))
+        .chain(snapshot.queue.iter().map(|item| (item.title.as_deref(), item.url.as_str())));
+    let one_line = |title: &str| title.replace(['\r', '\n'], " ");
+    let mut out = String::new();
+    match format {
+        "m3u" => {
+            out.push_str("#EXTM3U\n");
+            for (title, url) in tracks {
+                out.push_str(&format!("#EXTINF:-1,{}\n{url}\n", one_line(title.unwrap_or(url))));
+            }
+        }
+        "txt" => {
+            for (title, url) in tracks {
+                match title {
+                    Some(title) => out.push_str(&format!("{} - {url}\n", one_line(title))),
+                    None => out.push_str(&format!("{url}\n")),
+                }
+            }
+        }
+        other => return Err(format!("Unknown playlist format \"{other}\"; use m3u or txt")),
+    }
+    Ok(out)
+}
+
+/// Write the session's setlist to `dest_path` as an M3U or plain text playlist.
+#[tauri::command]
+fn export_session_playlist(
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
+    dest_path: String,
+    format: String,
+) -> Result<(), String> {
+    let snapshot = queue_snapshot(&**pipeline.lock().map_err(|e| e.to_string())?)?;
+    let playlist = session_playlist(&snapshot, &format.to_ascii_lowercase())?;
+    std::fs::write(&dest_path, playlist).map_err(|e| format!("Failed to write {dest_path}: {e}"))
+}
+
+/// Raw shared queue event log, one NDJSON line per event.
+#[tauri::command]
+fn get_shared_queue_raw(pipeline: State<'_, Mutex<DynAudioPipeline>>) -> Result<Vec<String>, String> {
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    p.shared_queue_raw()
+}
+
+/// Shared queue event types this client doesn't understand; non-empty means it is out of date.
+#[tauri::command]
+fn get_unknown_event_types(pipeline: State<'_, Mutex<DynAudioPipeline>>) -> Result<Vec<String>, String> {
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    p.unknown_event_types()
+}
+
+/// Create an empty shared queue file if the repo doesn't have one. Returns whether it was created.
+#[tauri::command]
+fn initialize_shared_queue(pipeline: State<'_, Mutex<DynAudioPipeline>>) -> Result<bool, String> {
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    p.initialize_shared_queue()
+}
+
+/// Check that `gh` can read the configured shared queue repo, flagging a private repo the
+/// token lacks the `repo` scope for.
+#[tauri::command]
+async fn validate_shared_queue(settings_path: State<'_, SettingsPath>) -> Result<queue_access::QueueAccess, String> {
+    let settings = load_settings_or_default(&settings_path);
+    let repo = settings.shared_queue_repo.trim().to_string();
+    if repo.is_empty() {
+        return Err("No shared queue repo configured".to_string());
+    }
+    let gh_path = gh_path_or_default(&settings);
+    let access = queue_access::check_queue_access(&gh_path, &repo).await;
+    if let Some(problem) = &access.problem {
+        crate::dlog!("[Queue] Shared queue {repo} not usable: {problem}");
+    }
+    Ok(access)
+}
+
+#[tauri::command]
+fn clear_shared_queue(pipeline: State<'_, Mutex<DynAudioPipeline>>) -> Result<(), String> {
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    p.clear_shared_queue()
+}
+
+/// Clear the queue without skipping the current track.
+#[tauri::command]
+fn clear_upcoming(pipeline: State<'_, Mutex<DynAudioPipeline>>) -> Result<(), String> {
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    p.clear_upcoming()
+}
+
+/// Pause playback for everyone in the room until `resume_room`.
+#[tauri::command]
+fn pause_room(pipeline: State<'_, Mutex<DynAudioPipeline>>) -> Result<(), String> {
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    p.pause_room()
+}
+
+#[tauri::command]
+fn resume_room(pipeline: State<'_, Mutex<DynAudioPipeline>>) -> Result<(), String> {
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    p.resume_room()
+}
+
+#[tauri::command]
+fn reorder_queue(pipeline: State<'_, Mutex<DynAudioPipeline>>, order: Vec<u64>) -> Result<(), String> {
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    p.reorder_queue(order)
+}
+
+/// Never stream `url` again; queued copies are marked failed when they come up.
+#[tauri::command]
+fn blacklist_track(blacklist: State<'_, TrackBlacklist>, url: String) -> Result<(), String> {
+    blacklist.0.add(url).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn unblacklist_track(blacklist: State<'_, TrackBlacklist>, url: String) -> Result<(), String> {
+    blacklist.0.remove(&url).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_blacklist(blacklist: State<'_, TrackBlacklist>) -> Vec<String> {
+    blacklist.0.urls()
+}
+
+/// Save `url` as a favorite, looking its title up with yt-dlp when none is given.
+#[tauri::command]
+async fn add_favorite(
+    favorites: State<'_, FavoriteTracks>,
+    factory: State<'_, PipelineFactory>,
+    url: String,
+    title: Option<String>,
+) -> Result<(), String> {
+    let url = url.trim().to_string();
+    if !youtube_pipeline::is_plausible_media_url(&url) {
+        return Err(format!("Not a playable URL: {url}"));
+    }
+    let title = match title.map(|t| t.trim().to_string()).filter(|t| !t.is_empty()) {
+        Some(title) => title,
+        None => {
+            let cookies = factory.cookies_path.lock().map_err(|e| e.to_string())?.clone();
+            let source = youtube_pipeline::YtDlpSource::new(None).with_cookies(cookies);
+            source.fetch_title(&url, std::time::Duration::from_secs(15)).await?.0
+        }
+    };
+    favorites.0.add(url, title).map_err(|e| e.to_string())
+}
+
+/// Title, duration, uploader and thumbnail for `url`, without queueing or downloading it.
+#[tauri::command]
+async fn fetch_track_info(
+    previews: State<'_, TrackPreviews>,
+    factory: State<'_, PipelineFactory>,
+    url: String,
+) -> Result<youtube_pipeline::TrackPreview, String> {
+    let url = url.trim().to_string();
+    if !youtube_pipeline::is_plausible_media_url(&url) {
+        return Err(format!("Not a playable URL: {url}"));
+    }
+    if let Some(preview) = previews.0.get(&url, std::time::Instant::now()) {
+        return Ok(preview);
+    }
+    let cookies = factory.cookies_path.lock().map_err(|e| e.to_string())?.clone();
+    let source = youtube_pipeline::YtDlpSource::new(None).with_cookies(cookies);
+    let preview = source.fetch_preview(&url, std::time::Duration::from_secs(15)).await?;
+    previews.0.insert(url, preview.clone(), std::time::Instant::now());
+    Ok(preview)
+}
+
+#[tauri::command]
+fn remove_favorite(favorites: State<'_, FavoriteTracks>, url: String) -> Result<(), String> {
+    favorites.0.remove(&url).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_favorites(favorites: State<'_, FavoriteTracks>) -> Vec<favorites::Favorite> {
+    favorites.0.list()
+}
+
+/// Refuse to queue URLs matching `pattern` (a substring, or a glob using `*`).
+#[tauri::command]
+fn add_banned_pattern(settings_path: State<'_, SettingsPath>, pattern: String) -> Result<Vec<String>, String> {
+    let pattern = pattern.trim().to_string();
+    if pattern.is_empty() {
+        return Err("Pattern must not be empty".to_string());
+    }
+    let settings = update_settings(&settings_path, |settings| {
+        if !settings.banned_patterns.contains(&pattern) {
+            settings.banned_patterns.push(pattern);
+        }
+    })?;
+    Ok(settings.banned_patterns)
+}
+
+#[tauri::command]
+fn remove_banned_pattern(settings_path: State<'_, SettingsPath>, pattern: String) -> Result<Vec<String>, String> {
+    let settings = update_settings(&settings_path, |settings| {
+        settings.banned_patterns.retain(|p| p != &pattern);
+    })?;
+    Ok(settings.banned_patterns)
+}
+
+#[tauri::command]
+fn get_banned_patterns(settings_path: State<'_, SettingsPath>) -> Vec<String> {
+    load_settings_or_default(&settings_path).banned_patterns
+}
+
+#[tauri::command]
+fn move_track(
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
+    queued_id: u64,
+    new_index: usize,
+) -> Result<(), String> {
+    let p = pipeline.lock().map_err(|e| e.to_string())?;
+    p.move_track(queued_id, new_index)
+}
+
+#[tauri::command]
+fn get_backend_logs() -> Vec<String> {
+    if let Some(buf) = DEBUG_LOG.get() {
+        buf.drain()
     } else {
         vec![]
     }
@@ -551,6 +1842,11 @@ fn start_queue_webhook(
     app: AppHandle,
     updates_tx: State<'_, QueueUpdatesTx>,
     started: State<'_, WebhookStarted>,
+    factory: State<'_, PipelineFactory>,
+    active_webhook: State<'_, ActiveWebhook>,
+    queue_sync: State<'_, QueueSync>,
+    webhook_connected: State<'_, WebhookConnected>,
+    webhook_listener: State<'_, WebhookListener>,
     repo: String,
     path: String,
     gh_path: String,
@@ -569,40 +1865,333 @@ fn start_queue_webhook(
         path = %path,
         secret_len = secret.len()
     );
-    shared_queue_webhook::spawn_shared_queue_webhook(
+    let task = shared_queue_webhook::spawn_shared_queue_webhook(
         app,
         repo,
         path,
-        gh_path,
-        secret,
+        gh_path.clone(),
+        secret.clone(),
         hook_id,
         Some(updates_tx.0.clone()),
+        factory.shared_state.clone(),
+        active_webhook.0.clone(),
+        queue_sync.0.clone(),
+        webhook_connected.0.clone(),
     );
+    *webhook_listener.0.lock().map_err(|e| e.to_string())? = Some(RunningWebhook { secret, gh_path, task });
     Ok(hook_id.unwrap_or(0))
 }
 
+/// Resync the shared queue now, exactly as if a webhook push had arrived.
+#[tauri::command]
+fn refresh_shared_queue(updates_tx: State<'_, QueueUpdatesTx>) -> Result<(), String> {
+    if updates_tx.0.send(()).is_err() {
+        crate::dlog!("[Queue] Refresh requested but no sync task is running");
+    }
+    Ok(())
+}
+
+/// Point the shared queue at another repo/file without restarting, moving the webhook with it.
+#[tauri::command]
+async fn reconfigure_shared_queue(
+    app: AppHandle,
+    settings_path: State<'_, SettingsPath>,
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
+    factory: State<'_, PipelineFactory>,
+    updates_tx: State<'_, QueueUpdatesTx>,
+    active_webhook: State<'_, ActiveWebhook>,
+    queue_sync: State<'_, QueueSync>,
+    webhook_connected: State<'_, WebhookConnected>,
+    webhook_listener: State<'_, WebhookListener>,
+    repo: String,
+    file: String,
+) -> Result<(), String> {
+    let repo = repo.trim().to_string();
+    let file = file.trim().to_string();
+    let gh_path = load_settings_or_default(&settings_path).gh_path;
+    pipeline
+        .lock()
+        .map_err(|e| e.to_string())?
+        .reconfigure_shared_queue(repo.clone(), file.clone(), gh_path.clone())?;
+    *factory.shared_queue.lock().map_err(|e| e.to_string())? = (repo.clone(), file.clone(), gh_path);
+    update_settings(&settings_path, |settings| {
+        settings.shared_queue_repo = repo.clone();
+        settings.shared_queue_file = file.clone();
+    })?;
+
+    let running = webhook_listener.0.lock().map_err(|e| e.to_string())?.take();
+    let Some(running) = running else {
+        return Ok(());
+    };
+    running.task.abort();
+    shared_queue_webhook::set_connected(&webhook_connected.0, &queue_sync.0, false);
+    let registration = active_webhook.0.lock().ok().and_then(|mut hook| hook.take());
+    if let Some(hook) = registration {
+        if let Err(err) = shared_queue_webhook::delete_webhook(&hook.gh_path, &hook.repo, hook.hook_id).await {
+            tracing::warn!(event = "queue_webhook_delete_failed", hook_id = hook.hook_id, error = %err);
+        }
+    }
+    if repo.is_empty() || file.is_empty() {
+        return Ok(());
+    }
+    tracing::info!(event = "queue_webhook_reconfigured", repo = %repo, path = %file);
+    let task = shared_queue_webhook::spawn_shared_queue_webhook(
+        app,
+        repo,
+        file,
+        running.gh_path.clone(),
+        running.secret.clone(),
+        None,
+        Some(updates_tx.0.clone()),
+        factory.shared_state.clone(),
+        active_webhook.0.clone(),
+        queue_sync.0.clone(),
+        webhook_connected.0.clone(),
+    );
+    *webhook_listener.0.lock().map_err(|e| e.to_string())? = Some(RunningWebhook { task, ..running });
+    Ok(())
+}
+
+/// Saved profile names, sorted.
+#[tauri::command]
+fn list_profiles(settings_path: State<'_, SettingsPath>) -> Vec<String> {
+    load_settings_or_default(&settings_path).profile_names()
+}
+
+/// Save the current connection settings under `name` and make it the active profile.
+#[tauri::command]
+fn save_profile(settings_path: State<'_, SettingsPath>, name: String) -> Result<Vec<String>, String> {
+    let mut settings = load_settings_or_default(&settings_path);
+    settings.save_profile(&name).map_err(|e| e.to_string())?;
+    settings.save(&settings_path.0).map_err(|e| e.to_string())?;
+    Ok(settings.profile_names())
+}
+
+/// Load a saved profile and point the shared queue and webhook at its repo.
+#[tauri::command]
+async fn switch_profile(
+    app: AppHandle,
+    settings_path: State<'_, SettingsPath>,
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
+    factory: State<'_, PipelineFactory>,
+    updates_tx: State<'_, QueueUpdatesTx>,
+    active_webhook: State<'_, ActiveWebhook>,
+    queue_sync: State<'_, QueueSync>,
+    webhook_connected: State<'_, WebhookConnected>,
+    webhook_listener: State<'_, WebhookListener>,
+    name: String,
+) -> Result<Settings, String> {
+    let mut settings = load_settings_or_default(&settings_path);
+    settings.switch_profile(&name).map_err(|e| e.to_string())?;
+    settings.save(&settings_path.0).map_err(|e| e.to_string())?;
+    let (repo, file) = (settings.shared_queue_repo.clone(), settings.shared_queue_file.clone());
+    reconfigure_shared_queue(
+        app,
+        settings_path,
+        pipeline,
+        factory,
+        updates_tx,
+        active_webhook,
+        queue_sync,
+        webhook_connected,
+        webhook_listener,
+        repo,
+        file,
+    )
+    .await?;
+    tracing::info!(event = "profile_switched", name = %name);
+    Ok(settings)
+}
+
+#[tauri::command]
+async fn claim_dj(
+    lk_room: State<'_, TokioMutex<Option<LiveKitRoom>>>,
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
+) -> Result<String, String> {
+    let identity = {
+        let guard = lk_room.lock().await;
+        match guard.as_ref() {
+            Some(lk) => lk.local_identity().await.ok_or("LiveKit not connected")?,
+            None => return Err("LiveKit not connected".into()),
+        }
+    };
+    claim_dj_with(&pipeline, &identity).await?;
+    Ok(identity)
+}
+
+/// Who auto-DJ would take over from. Nothing has synced the shared queue yet at connect
+/// time, so the elected DJ is read from it directly.
+fn auto_dj_blocked_by(
+    p: &dyn AudioPipeline,
+    identity: &str,
+    participants: &[livekit_room::Participant],
+) -> Option<String> {
+    let elected = p.elected_dj().unwrap_or_else(|err| {
+        crate::dlog!("[DJ] Couldn't read the elected DJ: {err}");
+        p.current_dj()
+    });
+    other_active_dj(elected, identity, participants)
+}
+
+/// Someone other than `identity` who is DJing and still in the room.
+fn other_active_dj(
+    elected: Option<String>,
+    identity: &str,
+    participants: &[livekit_room::Participant],
+) -> Option<String> {
+    elected.filter(|dj| dj != identity && participants.iter().any(|p| &p.identity == dj))
+}
+
 #[tauri::command]
 async fn livekit_connect(
     lk_room: State<'_, TokioMutex<Option<LiveKitRoom>>>,
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
     playback_volume: State<'_, PlaybackVolume>,
+    ducking: State<'_, MusicDucking>,
+    settings_path: State<'_, SettingsPath>,
+    room_state: State<'_, Mutex<RoomState>>,
+    publisher_handle: State<'_, TokioMutex<Option<DjPublisherHandle>>>,
+    local_monitoring: State<'_, LocalMonitoring>,
+    dj_only: State<'_, DjOnlyMode>,
+    listener: State<'_, ListenerMode>,
+    pcm_flush: State<'_, PcmFlush>,
+    voice_handle: State<'_, TokioMutex<Option<VoiceChatHandle>>>,
+    mic_test: State<'_, TokioMutex<Option<MicTestHandle>>>,
+    mic_level: State<'_, MicLevel>,
+    published_input_level: State<'_, PublishedInputLevel>,
     url: String,
     token: String,
+    fallback_token: Option<String>,
+    display_name: Option<String>,
 ) -> Result<Vec<livekit_room::Participant>, String> {
-    let room = LiveKitRoom::new(url, token, playback_volume.0.clone());
-    room.connect().await?;
+    let settings = load_settings_or_default(&settings_path);
+    let display_name = display_name
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or(settings.display_name);
+    let primary_url = url.clone();
+    let targets = livekit_room::connect_targets(url, token, Some(settings.livekit_url_fallback), fallback_token);
+    let (room, target) = livekit_room::connect_first(targets, |target| {
+        let room = LiveKitRoom::new(target.url, target.token, playback_volume.0.clone(), ducking.0.clone());
+        async move { room.connect().await.map(|()| room) }
+    })
+    .await?;
+    if target.url != primary_url {
+        crate::dlog!("[LK] Connected to fallback server {}", target.url);
+        emit_event("livekit-fallback", target.url);
+    }
+    if !display_name.trim().is_empty() {
+        if let Err(err) = room.set_display_name(display_name.trim()).await {
+            crate::dlog!("[LK] {err}");
+        }
+    }
     let participants = room.participants().await;
+    let identity = room.local_identity().await;
+    {
+        let p = pipeline.lock().map_err(|e| e.to_string())?;
+        p.set_local_identity(identity.clone());
+        p.set_room_participants(Some(participant_identities(&participants)));
+    }
+    room_state
+        .lock()
+        .map_err(|e| e.to_string())?
+        .sync(participants.clone());
+    let new_room = room.get_room().await;
     *lk_room.lock().await = Some(room);
+    if let Some(new_room) = new_room {
+        republish_voice(new_room, &voice_handle, &mic_test, &mic_level, &published_input_level, &settings_path).await;
+    }
+
+    if listener.0.load(Ordering::Relaxed) && settings.auto_dj {
+        crate::dlog!("[DJ] Auto-DJ skipped in listener mode");
+    } else if let (true, Some(identity)) = (settings.auto_dj, identity) {
+        let blocked_by = auto_dj_blocked_by(&**pipeline.lock().map_err(|e| e.to_string())?, &identity, &participants);
+        if let Some(other) = blocked_by {
+            crate::dlog!("[DJ] Auto-DJ skipped, {other} is already DJing");
+            emit_event("auto-dj-skipped", other);
+        } else {
+            let result = async {
+                room_state.lock().map_err(|e| e.to_string())?.become_dj(identity.clone())?;
+                claim_dj_with(&pipeline, &identity).await?;
+                start_dj_audio(
+                    pipeline,
+                    lk_room,
+                    publisher_handle,
+                    local_monitoring,
+                    dj_only,
+                    listener,
+                    pcm_flush,
+                    settings_path,
+                )
+                .await
+            }
+            .await;
+            match result {
+                Ok(_) => {
+                    crate::dlog!("[DJ] Auto-DJ started as {identity}");
+                    emit_event("auto-dj-started", identity);
+                }
+                Err(err) => emit_backend_error("dj", format!("Couldn't become DJ automatically: {err}")),
+            }
+        }
+    }
     Ok(participants)
 }
 
+/// Move a running voice chat onto a newly connected room; its track died with the old one.
+/// The voice handle stays locked throughout, so a mic test can't start mid-switch.
+async fn republish_voice(
+    room: Arc<livekit::prelude::Room>,
+    voice_handle: &TokioMutex<Option<VoiceChatHandle>>,
+    mic_test: &TokioMutex<Option<MicTestHandle>>,
+    mic_level: &MicLevel,
+    published_input_level: &PublishedInputLevel,
+    settings_path: &SettingsPath,
+) {
+    let mut voice = voice_handle.lock().await;
+    let Some(old) = voice.take() else {
+        return;
+    };
+    voice_chat::stop_voice_chat(old.inner).await;
+    if let Some(handle) = mic_test.lock().await.take() {
+        voice_chat::stop_mic_test(handle.inner);
+    }
+    match voice_chat::start_voice_chat(
+        room,
+        mic_level.0.clone(),
+        published_input_level.0.clone(),
+        mic_options(settings_path),
+    )
+    .await
+    {
+        Ok(handle) => {
+            *voice = Some(VoiceChatHandle { inner: handle });
+            crate::dlog!("[VC] Voice chat republished after reconnect");
+            emit_event("voice-republished", ());
+        }
+        Err(err) => emit_backend_error("voice-republish", format!("Voice chat stopped after reconnecting: {err}")),
+    }
+}
+
+/// Send an emoji reaction to the room; everyone, including us, gets `reaction-received`.
+#[tauri::command]
+async fn send_reaction(lk_room: State<'_, TokioMutex<Option<LiveKitRoom>>>, emoji: String) -> Result<(), String> {
+    let guard = lk_room.lock().await;
+    let room = guard.as_ref().ok_or("LiveKit not connected")?;
+    room.send_reaction(&emoji).await
+}
+
 #[tauri::command]
 async fn livekit_disconnect(
     lk_room: State<'_, TokioMutex<Option<LiveKitRoom>>>,
+    room_state: State<'_, Mutex<RoomState>>,
+    pipeline: State<'_, Mutex<DynAudioPipeline>>,
 ) -> Result<(), String> {
     let mut guard = lk_room.lock().await;
     if let Some(room) = guard.take() {
         room.disconnect().await?;
     }
+    room_state.lock().map_err(|e| e.to_string())?.sync(vec![]);
+    pipeline.lock().map_err(|e| e.to_string())?.set_room_participants(None);
     Ok(())
 }
 
@@ -617,6 +2206,15 @@ async fn livekit_participants(
     }
 }
 
+/// Identities currently speaking; updates arrive as `active-speakers` events.
+#[tauri::command]
+async fn get_active_speakers(
+    lk_room: State<'_, TokioMutex<Option<LiveKitRoom>>>,
+) -> Result<Vec<String>, String> {
+    let guard = lk_room.lock().await;
+    Ok(guard.as_ref().map(|room| room.active_speakers()).unwrap_or_default())
+}
+
 #[tauri::command]
 async fn livekit_is_connected(
     lk_room: State<'_, TokioMutex<Option<LiveKitRoom>>>,
@@ -640,6 +2238,7 @@ pub fn run() {
 
     let playback_volume = Arc::new(AtomicU8::new(50));
     let mic_level = Arc::new(AtomicU8::new(0));
+    let published_input_level = Arc::new(AtomicU8::new(0));
     let result = tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .manage(Mutex::new(RoomState::new()))
@@ -647,13 +2246,18 @@ pub fn run() {
         .manage(TokioMutex::new(None::<DjPublisherHandle>))
         .manage(PlaybackVolume(playback_volume))
         .manage(MicLevel(mic_level))
+        .manage(PublishedInputLevel(published_input_level))
+        .manage(LocalMonitoring(Mutex::new(None)))
+        .manage(ActiveWebhook(Arc::new(Mutex::new(None))))
         .manage(TokioMutex::new(None::<VoiceChatHandle>))
         .manage(TokioMutex::new(None::<MicTestHandle>))
         .setup(|app| {
-            let app_dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+            let _ = APP_HANDLE.set(app.handle().clone());
+            let (app_dir, persistent) = app_dir_or_temp("config", app.path().app_config_dir());
             let settings_path = app_dir.join("settings.json");
             let settings = Settings::load(&settings_path).unwrap_or_default();
             app.manage(SettingsPath(settings_path));
+            app.manage(SettingsPersistent(persistent));
             let shared_queue_repo =
                 std::env::var("GEZELLIG_SHARED_QUEUE_REPO").unwrap_or(settings.shared_queue_repo);
             let shared_queue_file =
@@ -661,22 +2265,115 @@ pub fn run() {
             let gh_path = std::env::var("GEZELLIG_GH_PATH").unwrap_or(settings.gh_path);
             let webhook_started = Arc::new(AtomicBool::new(false));
 
-            let cache_dir = app.path().app_cache_dir().ok().map(|d| d.join("audio"));
+            let (cache_root, _) = app_dir_or_temp("cache", app.path().app_cache_dir());
+            let cache_dir = Some(cache_root.join("audio"));
             let shared_state = app_dir.join("shared_queue_state.json");
+            let blacklist = Arc::new(blacklist::Blacklist::load(app_dir.join("blacklist.json")));
+            let stream_timeout_secs = Arc::new(AtomicU64::new(
+                youtube_pipeline::clamp_stream_timeout_secs(settings.stream_timeout_secs),
+            ));
+            let load_retries = Arc::new(AtomicUsize::new(
+                youtube_pipeline::clamp_load_retries(settings.load_retries),
+            ));
+            let idle_timeout_secs = Arc::new(AtomicU64::new(settings.idle_timeout_secs));
+            let pcm_channel_chunks = Arc::new(AtomicUsize::new(audio::clamp_pcm_channel_chunks(
+                settings.pcm_channel_chunks,
+            )));
+            let pcm_flush = Arc::new(tokio::sync::watch::Sender::new(0));
+            let audio_quality = Arc::new(Mutex::new(
+                youtube_pipeline::AudioQuality::from_setting(&settings.audio_quality).unwrap_or_default(),
+            ));
+            let cookies_path = Arc::new(Mutex::new(
+                youtube_pipeline::existing_cookies_path(settings.cookies_path.as_deref()),
+            ));
+            let shard_strategy = Arc::new(Mutex::new(
+                youtube_pipeline::ShardStrategy::from_setting(&settings.shared_queue_sharding)
+                    .unwrap_or_default(),
+            ));
             let (queue_updates_tx, _) = broadcast::channel(16);
-            let pipeline = youtube_pipeline::YouTubePipeline::with_cache_dir_and_state(
+            let sync_mode = Arc::new(youtube_pipeline::QueueSyncMode::default());
+            sync_mode.set_poll_interval_secs(settings.queue_poll_interval_secs);
+            sync_mode.set_skip_check_interval_secs(settings.skip_check_interval_secs);
+            sync_mode.set_ping_interval_secs(settings.webhook_ping_interval_secs);
+            let webhook_connected = Arc::new(AtomicBool::new(false));
+            let prefetch_depth = Arc::new(AtomicUsize::new(
+                youtube_pipeline::clamp_prefetch_depth(settings.prefetch_depth),
+            ));
+            let prefetch_concurrency = Arc::new(AtomicUsize::new(
+                youtube_pipeline::clamp_prefetch_concurrency(settings.prefetch_concurrency),
+            ));
+            let stream_throughput = Arc::new(AtomicU64::new(0));
+            let track_diagnostics = Arc::new(youtube_pipeline::TrackDiagnosticLog::default());
+            let ducking = Arc::new(audio::Ducking::default());
+            ducking.configure(
+                settings.duck_amount_pct,
+                settings.duck_attack_ms,
+                settings.duck_release_ms,
+                settings.duck_ignore_self,
+            );
+            let factory = PipelineFactory {
                 cache_dir,
-                Some(shared_state),
-                Some((
+                shared_state: Some(shared_state),
+                shared_queue: Mutex::new((
                     shared_queue_repo.clone(),
                     shared_queue_file.clone(),
                     gh_path.clone(),
                 )),
-                Some(queue_updates_tx.clone()),
-            );
-            app.manage(Mutex::new(Box::new(pipeline) as DynAudioPipeline));
+                queue_updates_tx: queue_updates_tx.clone(),
+                sync_mode: sync_mode.clone(),
+                webhook_connected: webhook_connected.clone(),
+                prefetch_depth: prefetch_depth.clone(),
+                prefetch_concurrency: prefetch_concurrency.clone(),
+                blacklist: blacklist.clone(),
+                stream_timeout_secs: stream_timeout_secs.clone(),
+                load_retries: load_retries.clone(),
+                idle_timeout_secs: idle_timeout_secs.clone(),
+                audio_quality: audio_quality.clone(),
+                cookies_path: cookies_path.clone(),
+                stream_throughput: stream_throughput.clone(),
+                track_diagnostics: track_diagnostics.clone(),
+                ducking: ducking.clone(),
+                shard_strategy: shard_strategy.clone(),
+                pcm_channel_chunks: pcm_channel_chunks.clone(),
+                pcm_flush: pcm_flush.clone(),
+            };
+            let pipeline = match factory.build(&settings.pipeline_backend) {
+                Ok(pipeline) => pipeline,
+                Err(err) => {
+                    tracing::warn!(error = %err, "Falling back to YouTube pipeline");
+                    factory.build("youtube")?
+                }
+            };
+            if settings.dj_only_mode {
+                pipeline.set_local_playback(false);
+            }
+            spawn_status_bridge(&pipeline);
+            app.manage(Mutex::new(pipeline));
+            app.manage(DjOnlyMode(AtomicBool::new(settings.dj_only_mode)));
+            app.manage(ListenerMode(AtomicBool::new(settings.listener_mode)));
+            app.manage(factory);
             app.manage(QueueUpdatesTx(queue_updates_tx));
+            app.manage(QueueSync(sync_mode));
+            app.manage(PrefetchDepth(prefetch_depth));
+            app.manage(PrefetchConcurrency(prefetch_concurrency));
+            app.manage(PrefetchCancel(AtomicBool::new(false)));
+            app.manage(TrackBlacklist(blacklist));
+            app.manage(FavoriteTracks(favorites::Favorites::load(app_dir.join("favorites.json"))));
+            app.manage(StreamTimeout(stream_timeout_secs));
+            app.manage(LoadRetries(load_retries));
+            app.manage(IdleTimeout(idle_timeout_secs));
+            app.manage(PcmChannelChunks(pcm_channel_chunks));
+            app.manage(PcmFlush(pcm_flush));
+            app.manage(AudioQualitySetting(audio_quality));
+            app.manage(CookiesPath(cookies_path));
+            app.manage(ShardStrategySetting(shard_strategy));
+            app.manage(StreamThroughput(stream_throughput));
+            app.manage(TrackDiagnostics(track_diagnostics));
+            app.manage(TrackPreviews(youtube_pipeline::TrackPreviewCache::default()));
+            app.manage(MusicDucking(ducking));
             app.manage(WebhookStarted(webhook_started));
+            app.manage(WebhookConnected(webhook_connected));
+            app.manage(WebhookListener(Mutex::new(None)));
 
             Ok(())
         })
@@ -684,46 +2381,211 @@ pub fn run() {
             join_room,
             leave_room,
             get_room_participants,
+            get_current_dj,
             become_dj,
             stop_dj,
             save_settings,
             load_settings,
+            set_display_name,
             check_for_update,
             start_dj_audio,
             stop_dj_audio,
             get_dj_status,
+            switch_pipeline,
+            set_local_monitoring,
+            set_dj_only_mode,
+            set_auto_dj,
+            set_listener_mode,
+            set_reject_duplicates,
+            set_first_song_jumps_queue,
+            set_queue_poll_interval,
+            set_skip_check_interval,
+            set_webhook_ping_interval,
+            set_dj_latency_ms,
+            set_prefetch_depth,
+            set_prefetch_concurrency,
+            prefetch_all,
+            cancel_prefetch,
+            set_stream_timeout_secs,
+            set_load_retries,
+            set_pcm_channel_chunks,
+            set_idle_timeout_secs,
+            set_music_ducking,
+            set_audio_quality,
+            set_shared_queue_sharding,
+            set_cookies_path,
+            list_profiles,
+            save_profile,
+            switch_profile,
+            export_track,
+            list_cached_tracks,
+            pipeline_supports_queue,
             set_music_volume,
             get_music_volume,
             start_voice_chat,
             stop_voice_chat,
             start_mic_test,
             stop_mic_test,
+            get_input_device_info,
             get_mic_level,
+            get_published_input_level,
+            set_input_channel,
+            set_stereo_input,
             queue_track,
+            queue_track_clip,
             skip_track,
+            play_previous,
             get_queue,
             get_shared_queue,
             get_shared_queue_state,
+            get_history,
+            export_session_playlist,
+            get_shared_queue_raw,
+            get_diagnostics,
+            initialize_shared_queue,
+            validate_shared_queue,
             clear_shared_queue,
+            clear_upcoming,
+            pause_room,
+            resume_room,
             reorder_queue,
+            move_track,
+            blacklist_track,
+            unblacklist_track,
+            get_blacklist,
+            add_favorite,
+            remove_favorite,
+            list_favorites,
+            fetch_track_info,
+            add_banned_pattern,
+            remove_banned_pattern,
+            get_banned_patterns,
+            get_stream_throughput,
+            get_track_diagnostics,
+            get_unknown_event_types,
+            seek,
+            get_playback_position,
+            claim_dj,
             livekit_connect,
             livekit_disconnect,
+            send_reaction,
             livekit_participants,
+            get_active_speakers,
             livekit_is_connected,
             get_backend_logs,
             get_env_config,
             start_queue_webhook,
+            reconfigure_shared_queue,
+            refresh_shared_queue,
         ])
-        .run(tauri::generate_context!())
-        ;
-    if let Err(e) = result {
-        tracing::error!(error = %e, "error while running tauri application");
+        .build(tauri::generate_context!());
+    match result {
+        Ok(app) => app.run(|app, event| {
+            if let RunEvent::Exit = event {
+                remove_active_webhook(app);
+            }
+        }),
+        Err(e) => tracing::error!(error = %e, "error while running tauri application"),
+    }
+}
+
+/// Delete the webhook this instance registered so hooks don't pile up on the repo.
+fn remove_active_webhook(app: &AppHandle) {
+    let registration = app
+        .try_state::<ActiveWebhook>()
+        .and_then(|active| active.0.lock().ok().and_then(|mut hook| hook.take()));
+    let Some(hook) = registration else {
+        return;
+    };
+    let result = tauri::async_runtime::block_on(shared_queue_webhook::delete_webhook(
+        &hook.gh_path,
+        &hook.repo,
+        hook.hook_id,
+    ));
+    match result {
+        Ok(()) => tracing::info!(event = "queue_webhook_deleted", hook_id = hook.hook_id),
+        Err(err) => tracing::warn!(event = "queue_webhook_delete_failed", hook_id = hook.hook_id, error = %err),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{is_newer_version, normalize_version};
+    use super::{
+        app_dir_or_temp, auto_dj_blocked_by, check_not_repeated, format_elapsed, is_first_song, is_newer_version, normalize_version, other_active_dj, queued_by_or_default,
+        session_playlist, start_pipeline, DebugLogBuffer,
+    };
+    use crate::audio::{AudioPipeline, DjStatus, MockAudioPipeline, PipelineCall};
+    use crate::livekit_room::Participant;
+    use std::time::Duration;
+
+    #[test]
+    fn first_song_counts_both_waiting_and_played_tracks() {
+        use crate::audio::{SharedHistoryItem, SharedNowPlaying, SharedQueueItem, SharedQueueSnapshot};
+        let snapshot = SharedQueueSnapshot {
+            queue: vec![SharedQueueItem {
+                url: "https://a".to_string(),
+                title: None,
+                id: 1,
+                queued_by: Some("alex".to_string()),
+                artist: None,
+                thumbnail: None,
+                queued_at: None,
+                clip: None,
+            }],
+            now_playing: Some(SharedNowPlaying {
+                title: "C".to_string(),
+                url: "https://c".to_string(),
+                artist: None,
+                thumbnail: None,
+                queued_by: Some("kim".to_string()),
+            }),
+            history: vec![SharedHistoryItem {
+                url: "https://b".to_string(),
+                title: None,
+                queued_by: Some("sam".to_string()),
+                artist: None,
+                thumbnail: None,
+            }],
+            paused: false,
+        };
+        assert!(!is_first_song(&snapshot, "alex"));
+        assert!(!is_first_song(&snapshot, "sam"));
+        assert!(!is_first_song(&snapshot, "kim"));
+        assert!(is_first_song(&snapshot, "robin"));
+    }
+
+    #[test]
+    fn check_not_repeated_only_rejects_the_last_queued_url() {
+        let pipeline = MockAudioPipeline::new(vec![DjStatus::Idle]);
+        assert!(check_not_repeated(&pipeline, "https://a").is_ok());
+        assert!(pipeline.queue_track("https://a".to_string(), None, None).is_ok());
+        assert!(pipeline.queue_track("https://b".to_string(), None, None).is_ok());
+        assert!(check_not_repeated(&pipeline, " https://b ").is_err());
+        assert!(check_not_repeated(&pipeline, "https://a").is_ok());
+    }
+
+    #[test]
+    fn start_pipeline_keeps_music_off_speakers_in_dj_only_mode() {
+        let pipeline = MockAudioPipeline::new(vec![DjStatus::Loading]);
+        let result = start_pipeline(&pipeline, true, Some(true), false);
+        assert_eq!(result.map(|(status, rx)| (status, rx.is_none())), Ok(("Loading".to_string(), true)));
+        assert_eq!(pipeline.calls(), vec![PipelineCall::SetLocalPlayback(false), PipelineCall::Start]);
+    }
+
+    #[test]
+    fn start_pipeline_plays_locally_only_without_livekit() {
+        let pipeline = MockAudioPipeline::new(vec![DjStatus::Idle]);
+        assert!(start_pipeline(&pipeline, false, None, false).is_ok());
+        let (_tx, rx) = tokio::sync::mpsc::channel(1);
+        let publishing = MockAudioPipeline::new(vec![DjStatus::Idle]).with_pcm(rx, 1);
+        let channels = start_pipeline(&publishing, false, None, true)
+            .ok()
+            .and_then(|(_, pcm)| pcm.map(|(_, channels)| channels));
+
+        assert_eq!(pipeline.calls(), vec![PipelineCall::SetLocalPlayback(true), PipelineCall::Start]);
+        assert_eq!(publishing.calls(), vec![PipelineCall::SetLocalPlayback(false), PipelineCall::Start]);
+        assert_eq!(channels, Some(1));
+    }
 
     #[test]
     fn normalize_version_strips_v_and_suffix() {
@@ -738,4 +2600,113 @@ mod tests {
         assert!(!is_newer_version("0.0.6", "0.0.6"));
         assert!(!is_newer_version("0.0.5", "0.0.6"));
     }
+
+    #[test]
+    fn format_elapsed_pads_components() {
+        assert_eq!(format_elapsed(Duration::from_millis(0)), "00:00:00.000");
+        assert_eq!(format_elapsed(Duration::from_millis(61_005)), "00:01:01.005");
+        assert_eq!(format_elapsed(Duration::from_millis(3_723_456)), "01:02:03.456");
+    }
+
+    #[test]
+    fn auto_dj_defers_only_to_a_dj_still_in_the_room() {
+        let participants = vec![
+            Participant { identity: "me".to_string(), name: String::new() },
+            Participant { identity: "bob".to_string(), name: String::new() },
+        ];
+        assert_eq!(other_active_dj(None, "me", &participants), None);
+        assert_eq!(other_active_dj(Some("me".to_string()), "me", &participants), None);
+        assert_eq!(other_active_dj(Some("alice".to_string()), "me", &participants), None);
+        assert_eq!(
+            other_active_dj(Some("bob".to_string()), "me", &participants),
+            Some("bob".to_string())
+        );
+    }
+
+    #[test]
+    fn auto_dj_reads_the_dj_elected_before_connecting() {
+        let participants = vec![
+            Participant { identity: "me".to_string(), name: String::new() },
+            Participant { identity: "bob".to_string(), name: String::new() },
+        ];
+        let idle = MockAudioPipeline::new(vec![DjStatus::Idle]);
+        assert_eq!(auto_dj_blocked_by(&idle, "me", &participants), None);
+        let djing = MockAudioPipeline::new(vec![DjStatus::Idle]).with_current_dj("bob");
+        assert_eq!(auto_dj_blocked_by(&djing, "me", &participants), Some("bob".to_string()));
+    }
+
+    #[test]
+    fn missing_app_dir_falls_back_to_temp() {
+        let (dir, persistent) = app_dir_or_temp("config", Err(tauri::Error::UnknownPath));
+        assert!(!persistent);
+        assert!(dir.starts_with(std::env::temp_dir()));
+        assert!(dir.ends_with("gezellig/config"));
+
+        let real = std::path::PathBuf::from("/tmp/real");
+        assert_eq!(app_dir_or_temp("config", Ok(real.clone())), (real, true));
+    }
+
+    #[test]
+    fn queued_by_falls_back_to_the_saved_display_name() {
+        let mut settings = crate::settings::Settings::default();
+        assert_eq!(queued_by_or_default(None, &settings), None);
+
+        settings.display_name = " Alex ".to_string();
+        assert_eq!(queued_by_or_default(None, &settings), Some("Alex".to_string()));
+        assert_eq!(queued_by_or_default(Some("  ".to_string()), &settings), Some("Alex".to_string()));
+        assert_eq!(queued_by_or_default(Some("Sam".to_string()), &settings), Some("Sam".to_string()));
+    }
+
+    #[test]
+    fn session_playlist_is_in_play_order() {
+        use crate::audio::{SharedHistoryItem, SharedNowPlaying, SharedQueueItem, SharedQueueSnapshot};
+        let history = |url: &str, title: Option<&str>| SharedHistoryItem {
+            url: url.to_string(),
+            title: title.map(str::to_string),
+            queued_by: None,
+            artist: None,
+            thumbnail: None,
+        };
+        let snapshot = SharedQueueSnapshot {
+            // Most recent first, as the shared queue reports it
+            history: vec![history("https://b", Some("B")), history("https://a", None)],
+            now_playing: Some(SharedNowPlaying {
+                title: "C\nlive".to_string(),
+                url: "https://c".to_string(),
+                artist: None,
+                thumbnail: None,
+                queued_by: None,
+            }),
+            queue: vec![SharedQueueItem {
+                url: "https://d".to_string(),
+                title: Some("D".to_string()),
+                id: 4,
+                queued_by: None,
+                artist: None,
+                thumbnail: None,
+                queued_at: None,
+                clip: None,
+            }],
+            paused: false,
+        };
+
+        assert_eq!(
+            session_playlist(&snapshot, "txt"),
+            Ok("https://a\nB - https://b\nC live - https://c\nD - https://d\n".to_string())
+        );
+        let m3u = session_playlist(&snapshot, "m3u").unwrap_or_default();
+        assert!(m3u.starts_with("#EXTM3U\n#EXTINF:-1,https://a\nhttps://a\n#EXTINF:-1,B\nhttps://b\n"));
+        assert!(session_playlist(&snapshot, "pls").is_err());
+    }
+
+    #[test]
+    fn drained_logs_are_timestamped() {
+        let buf = DebugLogBuffer::new();
+        buf.push("hello".to_string());
+        let logs = buf.drain();
+        assert_eq!(logs.len(), 1);
+        assert!(logs[0].ends_with(" hello"));
+        assert_eq!(logs[0].find(' '), Some(12));
+        assert!(buf.drain().is_empty());
+    }
 }
@@ -2,6 +2,8 @@
 //!
 //! Implements a custom audio sink that captures PCM samples from librespot
 //! and sends them through a channel for LiveKit publishing.
+//!
+//! Only compiled with the `spotify` feature.
 
 use std::sync::{
     atomic::{AtomicU8, Ordering},
@@ -24,20 +26,31 @@ use librespot::playback::{
 };
 use tokio::sync::mpsc;
 
-use crate::audio::{AudioPipeline, DjStatus, NowPlaying};
+use crate::audio::{clamp_pcm_channel_chunks, AudioPipeline, DjStatus, NowPlaying, DEFAULT_PCM_CHANNEL_CHUNKS};
+use crate::resampler::LinearResampler;
 
 const DEVICE_NAME: &str = "Gezellig DJ";
+const QUEUE_UNSUPPORTED: &str = "Queue is controlled from your Spotify app";
+/// librespot always decodes to 44.1kHz stereo.
+const SPOTIFY_SAMPLE_RATE: f64 = 44_100.0;
+/// The LiveKit publisher expects 48kHz stereo.
+const PUBLISH_SAMPLE_RATE: f64 = 48_000.0;
 
-/// A librespot audio sink that sends PCM bytes through a channel.
+/// A librespot audio sink that sends 48kHz PCM bytes through a channel.
 pub struct ChannelSink {
     sender: mpsc::Sender<Vec<u8>>,
     #[allow(dead_code)]
     format: AudioFormat,
+    resampler: LinearResampler,
 }
 
 impl ChannelSink {
     pub fn new(sender: mpsc::Sender<Vec<u8>>, format: AudioFormat) -> Self {
-        Self { sender, format }
+        Self {
+            sender,
+            format,
+            resampler: LinearResampler::new(SPOTIFY_SAMPLE_RATE, PUBLISH_SAMPLE_RATE, 2),
+        }
     }
 }
 
@@ -54,7 +67,8 @@ impl Sink for ChannelSink {
         use zerocopy::IntoBytes;
         let bytes = match packet {
             AudioPacket::Samples(samples) => {
-                let samples_i16 = converter.f64_to_s16(&samples);
+                let resampled = self.resampler.process(&samples);
+                let samples_i16 = converter.f64_to_s16(&resampled);
                 samples_i16.as_bytes().to_vec()
             }
             AudioPacket::Raw(data) => data,
@@ -77,7 +91,7 @@ pub struct LibrespotPipeline {
 
 impl LibrespotPipeline {
     pub fn new() -> Self {
-        let (tx, rx) = mpsc::channel(1024);
+        let (tx, rx) = mpsc::channel(DEFAULT_PCM_CHANNEL_CHUNKS);
         Self {
             status: Arc::new(Mutex::new(DjStatus::Idle)),
             volume: Arc::new(AtomicU8::new(50)),
@@ -87,11 +101,12 @@ impl LibrespotPipeline {
         }
     }
 
-    /// Take the PCM receiver (can only be called once).
-    /// Used by the LiveKit audio publisher to consume PCM data.
-    #[allow(dead_code)]
-    pub fn take_pcm_receiver(&self) -> Option<mpsc::Receiver<Vec<u8>>> {
-        self.pcm_receiver.lock().ok()?.take()
+    /// Buffer `chunks` of PCM for the publisher instead of the default.
+    pub fn with_pcm_capacity(mut self, chunks: usize) -> Self {
+        let (tx, rx) = mpsc::channel(clamp_pcm_channel_chunks(chunks));
+        self.pcm_sender = tx;
+        self.pcm_receiver = Mutex::new(Some(rx));
+        self
     }
 
     /// Get a clone of the PCM sender for creating sinks.
@@ -127,7 +142,7 @@ impl AudioPipeline for LibrespotPipeline {
 
             tokio::spawn(async move {
                 if let Err(e) = run_librespot(pcm_sender, status.clone(), &mut shutdown_rx).await {
-                    log::error!("Librespot error: {e}");
+                    crate::dlog!("[DJ] Librespot error: {e}");
                     update_status(&status, DjStatus::Idle);
                 }
             });
@@ -161,6 +176,46 @@ impl AudioPipeline for LibrespotPipeline {
     fn volume(&self) -> u8 {
         self.volume.load(Ordering::Relaxed)
     }
+
+    fn queue_track(&self, _url: String, _queued_by: Option<String>, _clip: Option<(u64, u64)>) -> Result<(), String> {
+        Err(QUEUE_UNSUPPORTED.to_string())
+    }
+
+    fn skip_track(&self) -> Result<(), String> {
+        Err(QUEUE_UNSUPPORTED.to_string())
+    }
+
+    fn get_queue(&self) -> Result<Vec<String>, String> {
+        Ok(Vec::new())
+    }
+
+    fn clear_shared_queue(&self) -> Result<(), String> {
+        Err(QUEUE_UNSUPPORTED.to_string())
+    }
+
+    fn clear_upcoming(&self) -> Result<(), String> {
+        Err(QUEUE_UNSUPPORTED.to_string())
+    }
+
+    fn play_previous(&self) -> Result<(), String> {
+        Err(QUEUE_UNSUPPORTED.to_string())
+    }
+
+    fn reorder_queue(&self, _order: Vec<u64>) -> Result<(), String> {
+        Err(QUEUE_UNSUPPORTED.to_string())
+    }
+
+    fn move_track(&self, _queued_id: u64, _new_index: usize) -> Result<(), String> {
+        Err(QUEUE_UNSUPPORTED.to_string())
+    }
+
+    fn supports_queue(&self) -> bool {
+        false
+    }
+
+    fn take_pcm_receiver(&self) -> Option<mpsc::Receiver<Vec<u8>>> {
+        self.pcm_receiver.lock().ok()?.take()
+    }
 }
 
 /// Run the librespot Zeroconf discovery + player loop.
@@ -173,33 +228,33 @@ async fn run_librespot(
     let device_id = session_config.device_id.clone();
     let client_id = session_config.client_id.clone();
 
-    log::info!("[DJ] Starting Zeroconf discovery for '{DEVICE_NAME}' (device_id={device_id})");
+    crate::dlog!("[DJ] Starting Zeroconf discovery for '{DEVICE_NAME}' (device_id={device_id})");
 
     let mut discovery = Discovery::builder(device_id, client_id)
         .name(DEVICE_NAME)
         .launch()
         .map_err(|e| {
-            log::error!("[DJ] Failed to start Zeroconf discovery: {e}");
+            crate::dlog!("[DJ] Failed to start Zeroconf discovery: {e}");
             format!("Failed to start Zeroconf discovery: {e}")
         })?;
 
-    log::info!("[DJ] Spotify Connect device '{DEVICE_NAME}' is now discoverable via mDNS");
+    crate::dlog!("[DJ] Spotify Connect device '{DEVICE_NAME}' is now discoverable via mDNS");
 
     // Wait for Spotify to connect and provide credentials
     let credentials = loop {
         tokio::select! {
             _ = &mut *shutdown_rx => {
-                log::info!("[DJ] Librespot shutdown requested during discovery");
+                crate::dlog!("[DJ] Librespot shutdown requested during discovery");
                 return Ok(());
             }
             item = discovery.next() => {
                 match item {
                     Some(creds) => {
-                        log::info!("[DJ] Spotify credentials received!");
+                        crate::dlog!("[DJ] Spotify credentials received!");
                         break creds;
                     }
                     None => {
-                        log::error!("[DJ] Discovery stream ended without credentials");
+                        crate::dlog!("[DJ] Discovery stream ended without credentials");
                         return Err("Discovery stream ended without credentials".to_string());
                     }
                 }
@@ -207,7 +262,7 @@ async fn run_librespot(
         }
     };
 
-    log::info!("Spotify credentials received, connecting session...");
+    crate::dlog!("[DJ] Spotify credentials received, connecting session...");
 
     let session = Session::new(SessionConfig::default(), None);
     session
@@ -215,7 +270,7 @@ async fn run_librespot(
         .await
         .map_err(|e| format!("Failed to connect session: {e}"))?;
 
-    log::info!("Spotify session connected, starting player...");
+    crate::dlog!("[DJ] Spotify session connected, starting player...");
 
     let player_config = PlayerConfig::default();
     let sender = pcm_sender.clone();
@@ -232,14 +287,14 @@ async fn run_librespot(
     loop {
         tokio::select! {
             _ = &mut *shutdown_rx => {
-                log::info!("Librespot shutdown requested");
+                crate::dlog!("[DJ] Librespot shutdown requested");
                 break;
             }
             event = event_channel.recv() => {
                 match event {
                     Some(event) => handle_player_event(&event, &status),
                     None => {
-                        log::info!("Player event channel closed");
+                        crate::dlog!("[DJ] Player event channel closed");
                         break;
                     }
                 }
@@ -281,6 +336,9 @@ pub fn handle_player_event(
                 DjStatus::Playing(NowPlaying {
                     track: audio_item.name.clone(),
                     artist,
+                    url: Some(audio_item.uri.clone()),
+                    duration_secs: Some(u64::from(audio_item.duration_ms) / 1000),
+                    ..Default::default()
                 }),
             );
         }
@@ -340,6 +398,18 @@ mod tests {
         assert_eq!(pipeline.volume(), 100);
     }
 
+    #[test]
+    fn queue_operations_report_spotify_control() {
+        let pipeline = LibrespotPipeline::new();
+        assert!(!pipeline.supports_queue());
+        assert_eq!(
+            pipeline.queue_track("https://example.com".to_string(), None, None),
+            Err(QUEUE_UNSUPPORTED.to_string())
+        );
+        assert_eq!(pipeline.skip_track(), Err(QUEUE_UNSUPPORTED.to_string()));
+        assert_eq!(pipeline.get_queue(), Ok(Vec::new()));
+    }
+
     #[test]
     fn can_take_pcm_receiver_once() {
         let pipeline = LibrespotPipeline::new();
@@ -367,7 +437,7 @@ mod tests {
                 Some(received) => received,
                 None => panic!("failed to receive pcm bytes"),
             };
-            // Should have received i16 bytes (4 samples × 2 bytes each = 8 bytes)
+            // Two stereo frames resample to two 48kHz frames of i16 (8 bytes)
             assert_eq!(received.len(), 8);
         });
     }
@@ -380,6 +450,7 @@ mod tests {
             DjStatus::Playing(NowPlaying {
                 track: "Test Song".to_string(),
                 artist: "Test Artist".to_string(),
+                ..Default::default()
             }),
         );
         let status_value = match status.lock() {
@@ -391,6 +462,7 @@ mod tests {
             DjStatus::Playing(NowPlaying {
                 track: "Test Song".to_string(),
                 artist: "Test Artist".to_string(),
+                ..Default::default()
             })
         );
     }
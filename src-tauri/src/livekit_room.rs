@@ -3,34 +3,138 @@
 //! Handles connecting to a LiveKit room, tracking participants,
 //! and publishing/subscribing to audio tracks.
 
+use crate::audio::Ducking;
 use livekit::prelude::*;
 use livekit::webrtc::audio_stream::native::NativeAudioStream;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex as TokioMutex;
 use futures_util::StreamExt;
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
 pub struct Participant {
     pub identity: String,
     pub name: String,
 }
 
+/// Data channel topic reactions are published on.
+const REACTION_TOPIC: &str = "reaction";
+/// Reactions each participant may send per [`REACTION_WINDOW`]; extras are dropped.
+const MAX_REACTIONS_PER_WINDOW: usize = 5;
+const REACTION_WINDOW: Duration = Duration::from_secs(10);
+/// Longest reaction accepted, in bytes; enough for an emoji with modifiers.
+const MAX_REACTION_BYTES: usize = 32;
+
+/// An emoji reaction, as sent over the data channel and emitted as `reaction-received`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Reaction {
+    pub emoji: String,
+    /// Identity of whoever sent it; filled in from LiveKit, never trusted from the payload.
+    #[serde(default)]
+    pub sender: String,
+}
+
+/// Keeps each participant to [`MAX_REACTIONS_PER_WINDOW`] reactions per [`REACTION_WINDOW`].
+#[derive(Debug, Default)]
+struct ReactionLimiter {
+    recent: HashMap<String, VecDeque<Instant>>,
+}
+
+impl ReactionLimiter {
+    /// Record a reaction from `sender` at `now`, or refuse it if they are over the limit.
+    fn allow(&mut self, sender: &str, now: Instant) -> bool {
+        let sent = self.recent.entry(sender.to_string()).or_default();
+        while sent.front().is_some_and(|at| now.duration_since(*at) >= REACTION_WINDOW) {
+            sent.pop_front();
+        }
+        if sent.len() >= MAX_REACTIONS_PER_WINDOW {
+            return false;
+        }
+        sent.push_back(now);
+        true
+    }
+}
+
+fn check_reaction(emoji: &str) -> Result<&str, String> {
+    let emoji = emoji.trim();
+    if emoji.is_empty() || emoji.len() > MAX_REACTION_BYTES {
+        return Err("Reactions must be a single emoji".to_string());
+    }
+    Ok(emoji)
+}
+
+/// A LiveKit server to try, with a token that is valid on it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectTarget {
+    pub url: String,
+    pub token: String,
+}
+
+/// The primary server, then the fallback if one is set. Tokens are usually signed for
+/// one server, but the fallback reuses the primary token when it has none of its own.
+pub fn connect_targets(
+    url: String,
+    token: String,
+    fallback_url: Option<String>,
+    fallback_token: Option<String>,
+) -> Vec<ConnectTarget> {
+    let fallback_url = fallback_url
+        .map(|u| u.trim().to_string())
+        .filter(|u| !u.is_empty() && *u != url.trim());
+    let fallback_token = fallback_token
+        .filter(|t| !t.trim().is_empty())
+        .unwrap_or_else(|| token.clone());
+    let mut targets = vec![ConnectTarget { url, token }];
+    if let Some(url) = fallback_url {
+        targets.push(ConnectTarget { url, token: fallback_token });
+    }
+    targets
+}
+
+/// Try each target in order and return the first connection with the target it used.
+/// Fails with every target's error when none connect.
+pub async fn connect_first<T, F, Fut>(targets: Vec<ConnectTarget>, mut connect: F) -> Result<(T, ConnectTarget), String>
+where
+    F: FnMut(ConnectTarget) -> Fut,
+    Fut: std::future::Future<Output = Result<T, String>>,
+{
+    let mut errors = Vec::new();
+    for target in targets {
+        match connect(target.clone()).await {
+            Ok(connection) => return Ok((connection, target)),
+            Err(err) => {
+                crate::dlog!("[LK] {} unreachable: {err}", target.url);
+                errors.push(format!("{}: {err}", target.url));
+            }
+        }
+    }
+    Err(errors.join("; "))
+}
+
 /// Manages a connection to a LiveKit room.
 pub struct LiveKitRoom {
     room: Arc<TokioMutex<Option<Arc<Room>>>>,
     url: String,
     token: String,
     playback_volume: Arc<AtomicU8>,
+    /// Identities of participants currently speaking, as reported by LiveKit.
+    active_speakers: Arc<std::sync::Mutex<Vec<String>>>,
+    ducking: Arc<Ducking>,
+    reactions: Arc<std::sync::Mutex<ReactionLimiter>>,
 }
 
 impl LiveKitRoom {
-    pub fn new(url: String, token: String, playback_volume: Arc<AtomicU8>) -> Self {
+    pub fn new(url: String, token: String, playback_volume: Arc<AtomicU8>, ducking: Arc<Ducking>) -> Self {
         Self {
             room: Arc::new(TokioMutex::new(None)),
             url: url.split_whitespace().collect::<Vec<_>>().join(""),
             token: token.split_whitespace().collect::<Vec<_>>().join(""),
             playback_volume,
+            active_speakers: Arc::new(std::sync::Mutex::new(Vec::new())),
+            ducking,
+            reactions: Arc::new(std::sync::Mutex::new(ReactionLimiter::default())),
         }
     }
 
@@ -56,6 +160,9 @@ impl LiveKitRoom {
         // Spawn event handler
         let room_clone = room.clone();
         let playback_volume = self.playback_volume.clone();
+        let active_speakers = self.active_speakers.clone();
+        let ducking = self.ducking.clone();
+        let reactions = self.reactions.clone();
         tokio::spawn(async move {
             while let Some(event) = events.recv().await {
                 match event {
@@ -74,6 +181,37 @@ impl LiveKitRoom {
                             Self::spawn_audio_playback(audio_track, playback_volume.clone());
                         }
                     }
+                    RoomEvent::ActiveSpeakersChanged { speakers } => {
+                        let identities: Vec<String> =
+                            speakers.iter().map(|p| p.identity().to_string()).collect();
+                        let local = room_clone.local_participant();
+                        let publishes_music = local
+                            .track_publications()
+                            .values()
+                            .any(|publication| publication.name() == crate::dj_publisher::MUSIC_TRACK_NAME);
+                        ducking.set_speakers(&identities, &local.identity().to_string(), publishes_music);
+                        *active_speakers.lock().unwrap_or_else(|e| e.into_inner()) = identities.clone();
+                        crate::emit_event("active-speakers", identities);
+                    }
+                    RoomEvent::DataReceived { payload, topic, participant, .. }
+                        if topic.as_deref() == Some(REACTION_TOPIC) =>
+                    {
+                        let Some(sender) = participant.map(|p| p.identity().to_string()) else {
+                            continue;
+                        };
+                        let Ok(mut reaction) = serde_json::from_slice::<Reaction>(&payload) else {
+                            crate::dlog!("[LK] Ignoring malformed reaction from {sender}");
+                            continue;
+                        };
+                        let allowed = reactions
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .allow(&sender, Instant::now());
+                        if allowed && check_reaction(&reaction.emoji).is_ok() {
+                            reaction.sender = sender;
+                            crate::emit_event("reaction-received", reaction);
+                        }
+                    }
                     RoomEvent::Disconnected { reason } => {
                         crate::dlog!("[LK] Disconnected from room: {reason:?}");
                         break;
@@ -81,6 +219,9 @@ impl LiveKitRoom {
                     _ => {}
                 }
             }
+            active_speakers.lock().unwrap_or_else(|e| e.into_inner()).clear();
+            ducking.set_speakers(&[], "", false);
+            crate::emit_event("active-speakers", Vec::<String>::new());
             drop(room_clone);
         });
 
@@ -123,6 +264,62 @@ impl LiveKitRoom {
         participants
     }
 
+    /// Set the name other participants see for the local participant.
+    pub async fn set_display_name(&self, name: &str) -> Result<(), String> {
+        let room_guard = self.room.lock().await;
+        let Some(room) = room_guard.as_ref() else {
+            return Err("LiveKit not connected".to_string());
+        };
+        room.local_participant()
+            .set_name(name.to_string())
+            .await
+            .map_err(|e| format!("Failed to set display name: {e}"))
+    }
+
+    /// Identity of the local participant, if connected.
+    pub async fn local_identity(&self) -> Option<String> {
+        let room_guard = self.room.lock().await;
+        room_guard
+            .as_ref()
+            .map(|room| room.local_participant().identity().to_string())
+    }
+
+    /// Send an emoji reaction to everyone in the room, including ourselves.
+    pub async fn send_reaction(&self, emoji: &str) -> Result<(), String> {
+        let emoji = check_reaction(emoji)?;
+        let Some(room) = self.get_room().await else {
+            return Err("LiveKit not connected".to_string());
+        };
+        let local = room.local_participant();
+        let sender = local.identity().to_string();
+        let allowed = self
+            .reactions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .allow(&sender, Instant::now());
+        if !allowed {
+            return Err("Slow down, too many reactions".to_string());
+        }
+        let reaction = Reaction { emoji: emoji.to_string(), sender };
+        let payload = serde_json::to_vec(&reaction).map_err(|e| e.to_string())?;
+        local
+            .publish_data(DataPacket {
+                payload,
+                topic: Some(REACTION_TOPIC.to_string()),
+                reliable: true,
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| format!("Failed to send reaction: {e}"))?;
+        crate::emit_event("reaction-received", reaction);
+        Ok(())
+    }
+
+    /// Identities of participants who are speaking right now.
+    pub fn active_speakers(&self) -> Vec<String> {
+        self.active_speakers.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
     /// Check if currently connected.
     pub async fn is_connected(&self) -> bool {
         let room_guard = self.room.lock().await;
@@ -194,6 +391,63 @@ impl LiveKitRoom {
 mod tests {
     use super::*;
 
+    #[test]
+    fn fallback_is_tried_after_the_primary_fails() {
+        let targets = connect_targets(
+            "wss://primary".to_string(),
+            "primary-token".to_string(),
+            Some("wss://backup".to_string()),
+            None,
+        );
+        assert_eq!(targets[1], ConnectTarget { url: "wss://backup".to_string(), token: "primary-token".to_string() });
+
+        let rt = match tokio::runtime::Runtime::new() {
+            Ok(rt) => rt,
+            Err(err) => panic!("failed to create runtime: {err}"),
+        };
+        let mut tried = Vec::new();
+        let result = rt.block_on(connect_first(targets.clone(), |target| {
+            tried.push(target.url.clone());
+            async move {
+                if target.url == "wss://primary" { Err("refused".to_string()) } else { Ok(target.url) }
+            }
+        }));
+        assert_eq!(tried, vec!["wss://primary", "wss://backup"]);
+        assert_eq!(result.map(|(_, target)| target.url), Ok("wss://backup".to_string()));
+
+        let failed = rt.block_on(connect_first(targets, |_| async { Err::<(), _>("down".to_string()) }));
+        assert_eq!(failed.err().as_deref(), Some("wss://primary: down; wss://backup: down"));
+    }
+
+    #[test]
+    fn blank_or_duplicate_fallback_is_skipped() {
+        let url = "wss://primary".to_string();
+        let token = "t".to_string();
+        assert_eq!(connect_targets(url.clone(), token.clone(), Some(" ".to_string()), None).len(), 1);
+        assert_eq!(connect_targets(url.clone(), token.clone(), Some(url.clone()), None).len(), 1);
+        let targets = connect_targets(url, token, Some("wss://backup".to_string()), Some("backup-token".to_string()));
+        assert_eq!(targets[1].token, "backup-token");
+    }
+
+    #[test]
+    fn reactions_are_rate_limited_per_sender() {
+        let mut limiter = ReactionLimiter::default();
+        let start = Instant::now();
+        for _ in 0..MAX_REACTIONS_PER_WINDOW {
+            assert!(limiter.allow("alice", start));
+        }
+        assert!(!limiter.allow("alice", start + Duration::from_secs(1)));
+        assert!(limiter.allow("bob", start + Duration::from_secs(1)));
+        assert!(limiter.allow("alice", start + REACTION_WINDOW));
+    }
+
+    #[test]
+    fn reactions_must_be_short() {
+        assert_eq!(check_reaction(" 🔥 "), Ok("🔥"));
+        assert!(check_reaction("").is_err());
+        assert!(check_reaction(&"🎉".repeat(20)).is_err());
+    }
+
     #[test]
     fn new_room_is_not_connected() {
         let rt = match tokio::runtime::Runtime::new() {
@@ -206,9 +460,11 @@ mod tests {
                 "wss://test.livekit.cloud".to_string(),
                 "test-token".to_string(),
                 playback_volume,
+                Arc::new(Ducking::default()),
             );
             assert!(!room.is_connected().await);
             assert!(room.participants().await.is_empty());
+            assert!(room.active_speakers().is_empty());
         });
     }
 }
@@ -0,0 +1,133 @@
+//! Checks that `gh` can read the shared queue repo, and explains why when it can't.
+//!
+//! A private queue repo read with a token that lacks the `repo` scope just looks like a
+//! 404, so the token's scopes are checked alongside a probe of the repo itself.
+
+use serde::{Deserialize, Serialize};
+
+/// What `validate_shared_queue` found out about the configured repo.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueAccess {
+    pub repo: String,
+    /// `None` when the repo couldn't be read.
+    pub private: Option<bool>,
+    /// Scopes `gh auth status` reports for the token; `None` when it doesn't list any
+    /// (fine-grained tokens and `GH_TOKEN` overrides don't).
+    pub token_scopes: Option<Vec<String>>,
+    /// Why the queue won't work, if it won't.
+    pub problem: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RepoResponse {
+    private: bool,
+}
+
+/// Scopes from the `Token scopes: 'gist', 'repo'` line of `gh auth status`.
+pub fn parse_token_scopes(status: &str) -> Option<Vec<String>> {
+    let line = status.lines().find_map(|line| line.split_once("Token scopes:"))?.1;
+    Some(
+        line.split(',')
+            .map(|scope| scope.trim().trim_matches(|c| c == '\'' || c == '"').to_string())
+            .filter(|scope| !scope.is_empty() && scope != "none")
+            .collect(),
+    )
+}
+
+fn has_repo_scope(scopes: &[String]) -> bool {
+    scopes.iter().any(|scope| scope == "repo")
+}
+
+/// Turn the probe result into an actionable message. `probe` is the repo's visibility,
+/// or the `gh api` error.
+fn access_problem(repo: &str, probe: &Result<bool, String>, token_scopes: Option<&[String]>) -> Option<String> {
+    let missing_repo_scope = token_scopes.is_some_and(|scopes| !has_repo_scope(scopes));
+    match probe {
+        Ok(true) if missing_repo_scope => Some("token missing 'repo' scope for private queue".to_string()),
+        Ok(_) => None,
+        Err(err) if crate::youtube_pipeline::is_not_found(err) => Some(if missing_repo_scope {
+            format!("{repo} not found; if it is private, the token is missing the 'repo' scope")
+        } else {
+            format!("{repo} not found, or this GitHub account can't see it")
+        }),
+        Err(err) if err.contains("403") => Some(format!("GitHub refused access to {repo}: {}", err.trim())),
+        Err(err) => Some(err.trim().to_string()),
+    }
+}
+
+/// Scopes of the token `gh` is logged in with, if it reports them.
+pub async fn token_scopes(gh_path: &str) -> Option<Vec<String>> {
+    let output = tokio::time::timeout(
+        std::time::Duration::from_secs(10),
+        tokio::process::Command::new(gh_path)
+            .args(["auth", "status", "--hostname", "github.com"])
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .ok()?
+    .ok()?;
+    // Older gh prints the status on stderr, newer on stdout
+    let text = format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    parse_token_scopes(&text)
+}
+
+async fn repo_is_private(gh_path: &str, repo: &str) -> Result<bool, String> {
+    let output = crate::gh_throttle::output_async(
+        tokio::process::Command::new(gh_path).args(["api", &format!("repos/{repo}")]),
+    )
+    .await?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).to_string());
+    }
+    serde_json::from_slice::<RepoResponse>(&output.stdout)
+        .map(|response| response.private)
+        .map_err(|e| format!("Invalid repo response: {e}"))
+}
+
+/// Probe `repo` and the token's scopes.
+pub async fn check_queue_access(gh_path: &str, repo: &str) -> QueueAccess {
+    let token_scopes = token_scopes(gh_path).await;
+    let probe = repo_is_private(gh_path, repo).await;
+    let problem = access_problem(repo, &probe, token_scopes.as_deref());
+    QueueAccess {
+        repo: repo.to_string(),
+        private: probe.ok(),
+        token_scopes,
+        problem,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scopes_are_read_from_gh_auth_status() {
+        let status = "github.com\n  ✓ Logged in to github.com account alex (keyring)\n  - Active account: true\n  - Token scopes: 'gist', 'read:org', 'repo'\n";
+        assert_eq!(
+            parse_token_scopes(status),
+            Some(vec!["gist".to_string(), "read:org".to_string(), "repo".to_string()])
+        );
+        assert_eq!(parse_token_scopes("  - Token scopes: none\n"), Some(Vec::new()));
+        assert_eq!(parse_token_scopes("  ✓ Logged in to github.com account alex (GH_TOKEN)\n"), None);
+    }
+
+    #[test]
+    fn private_repo_without_repo_scope_is_called_out() {
+        let public_only = vec!["public_repo".to_string()];
+        assert_eq!(
+            access_problem("a/b", &Ok(true), Some(&public_only)),
+            Some("token missing 'repo' scope for private queue".to_string())
+        );
+        assert_eq!(access_problem("a/b", &Ok(false), Some(&public_only)), None);
+        assert_eq!(access_problem("a/b", &Ok(true), None), None);
+        let not_found = access_problem("a/b", &Err("gh: Not Found (HTTP 404)".to_string()), Some(&public_only));
+        assert!(not_found.is_some_and(|msg| msg.contains("'repo' scope")));
+    }
+}
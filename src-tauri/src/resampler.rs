@@ -0,0 +1,118 @@
+//! Linear resampling for interleaved audio.
+//!
+//! Shared by the mic capture and the Spotify sink, which both publish 48kHz audio
+//! from sources running at other rates.
+
+/// A sample type the resampler can interpolate.
+pub trait Sample: Copy {
+    fn to_f64(self) -> f64;
+    fn from_f64(value: f64) -> Self;
+}
+
+impl Sample for f32 {
+    fn to_f64(self) -> f64 {
+        f64::from(self)
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+}
+
+impl Sample for f64 {
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+}
+
+/// Linear resampler for interleaved samples that keeps state across buffers.
+pub struct LinearResampler {
+    channels: usize,
+    step: f64,
+    pos: f64,
+    /// Last frame of the previous buffer, interpolated from at the start of the next.
+    prev: Vec<f64>,
+}
+
+impl LinearResampler {
+    pub fn new(from_rate: f64, to_rate: f64, channels: usize) -> Self {
+        let channels = channels.max(1);
+        Self {
+            channels,
+            step: from_rate / to_rate,
+            pos: 0.0,
+            prev: vec![0.0; channels],
+        }
+    }
+
+    /// Resample one buffer of interleaved samples; a trailing partial frame is ignored.
+    pub fn process<T: Sample>(&mut self, input: &[T]) -> Vec<T> {
+        let channels = self.channels;
+        let frames = input.len() / channels;
+        if frames == 0 {
+            return Vec::new();
+        }
+        let prev = &self.prev;
+        let sample = |frame: isize, channel: usize| -> f64 {
+            if frame < 0 {
+                prev[channel]
+            } else {
+                input[frame as usize * channels + channel].to_f64()
+            }
+        };
+        let mut output = Vec::with_capacity(((frames as f64 / self.step) as usize + 1) * channels);
+        while self.pos < (frames - 1) as f64 {
+            let base = self.pos.floor();
+            let frac = self.pos - base;
+            for channel in 0..channels {
+                let a = sample(base as isize, channel);
+                let b = sample(base as isize + 1, channel);
+                output.push(T::from_f64(a + (b - a) * frac));
+            }
+            self.pos += self.step;
+        }
+        self.pos -= frames as f64;
+        let last = (frames - 1) * channels;
+        self.prev = input[last..last + channels].iter().map(|s| s.to_f64()).collect();
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolates_between_samples() {
+        let mut resampler = LinearResampler::new(1.0, 2.0, 1);
+        let output = resampler.process(&[0.0_f32, 1.0]);
+        assert_eq!(output, vec![0.0, 0.5]);
+    }
+
+    #[test]
+    fn produces_48khz_frame_count() {
+        let mut resampler = LinearResampler::new(44_100.0, 48_000.0, 2);
+        let input = vec![0.25_f64; 441 * 2];
+        let mut total = 0;
+        for _ in 0..10 {
+            total += resampler.process(&input).len() / 2;
+        }
+        // 100ms of 44.1kHz audio should become ~4800 frames.
+        assert!((4798..=4802).contains(&total), "got {total} frames");
+    }
+
+    #[test]
+    fn keeps_channels_apart() {
+        let mut resampler = LinearResampler::new(44_100.0, 48_000.0, 2);
+        let _ = resampler.process(&[0.5_f64, -0.5, 0.5, -0.5]);
+        let output = resampler.process(&[0.5_f64, -0.5, 0.5, -0.5, 0.5, -0.5]);
+        for frame in output.chunks(2) {
+            assert!((frame[0] - 0.5).abs() < 1e-9);
+            assert!((frame[1] + 0.5).abs() < 1e-9);
+        }
+    }
+}
@@ -1,5 +1,16 @@
+use crate::livekit_room::Participant;
+
+/// Who is in the room and who is DJing, as reported to the frontend.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomSnapshot {
+    pub participants: Vec<Participant>,
+    pub current_dj: Option<String>,
+}
+
+/// Room membership mirrored from LiveKit, plus the DJ election keyed by identity.
 pub struct RoomState {
-    participants: Vec<String>,
+    participants: Vec<Participant>,
     dj: Option<String>,
 }
 
@@ -11,33 +22,30 @@ impl RoomState {
         }
     }
 
-    pub fn join(&mut self, name: String) {
-        if !self.participants.contains(&name) {
-            self.participants.push(name);
+    /// Replace the participant list with the live one; a DJ who left loses the role.
+    pub fn sync(&mut self, participants: Vec<Participant>) {
+        self.participants = participants;
+        if let Some(dj) = self.dj.as_deref() {
+            if !self.contains(dj) {
+                self.dj = None;
+            }
         }
     }
 
-    pub fn leave(&mut self, name: &str) {
-        self.participants.retain(|p| p != name);
-        if self.dj.as_deref() == Some(name) {
-            self.dj = None;
-        }
-    }
-
-    pub fn participants(&self) -> &[String] {
+    pub fn participants(&self) -> &[Participant] {
         &self.participants
     }
 
-    pub fn become_dj(&mut self, name: String) -> Result<(), String> {
-        if !self.participants.contains(&name) {
+    pub fn become_dj(&mut self, identity: String) -> Result<(), String> {
+        if !self.contains(&identity) {
             return Err("Must be in the room to become DJ".to_string());
         }
-        self.dj = Some(name);
+        self.dj = Some(identity);
         Ok(())
     }
 
-    pub fn stop_dj(&mut self, name: &str) {
-        if self.dj.as_deref() == Some(name) {
+    pub fn stop_dj(&mut self, identity: &str) {
+        if self.dj.as_deref() == Some(identity) {
             self.dj = None;
         }
     }
@@ -45,56 +53,47 @@ impl RoomState {
     pub fn current_dj(&self) -> Option<&str> {
         self.dj.as_deref()
     }
+
+    pub fn snapshot(&self) -> RoomSnapshot {
+        RoomSnapshot {
+            participants: self.participants.clone(),
+            current_dj: self.dj.clone(),
+        }
+    }
+
+    fn contains(&self, identity: &str) -> bool {
+        self.participants.iter().any(|p| p.identity == identity)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn new_room_has_no_participants() {
-        let room = RoomState::new();
-        assert!(room.participants().is_empty());
-    }
-
-    #[test]
-    fn join_adds_participant() {
-        let mut room = RoomState::new();
-        room.join("Alice".to_string());
-        assert_eq!(room.participants(), &["Alice"]);
+    fn participant(identity: &str) -> Participant {
+        Participant {
+            identity: identity.to_string(),
+            name: String::new(),
+        }
     }
 
-    #[test]
-    fn join_is_idempotent() {
-        let mut room = RoomState::new();
-        room.join("Alice".to_string());
-        room.join("Alice".to_string());
-        assert_eq!(room.participants(), &["Alice"]);
+    fn identities(room: &RoomState) -> Vec<&str> {
+        room.participants().iter().map(|p| p.identity.as_str()).collect()
     }
 
     #[test]
-    fn leave_removes_participant() {
-        let mut room = RoomState::new();
-        room.join("Alice".to_string());
-        room.leave("Alice");
-        assert!(room.participants().is_empty());
-    }
-
-    #[test]
-    fn leave_nonexistent_is_noop() {
-        let mut room = RoomState::new();
-        room.leave("Nobody");
+    fn new_room_has_no_participants() {
+        let room = RoomState::new();
         assert!(room.participants().is_empty());
     }
 
     #[test]
-    fn multiple_participants() {
+    fn sync_replaces_participants() {
         let mut room = RoomState::new();
-        room.join("Alice".to_string());
-        room.join("Bob".to_string());
-        assert_eq!(room.participants(), &["Alice", "Bob"]);
-        room.leave("Alice");
-        assert_eq!(room.participants(), &["Bob"]);
+        room.sync(vec![participant("alice"), participant("bob")]);
+        assert_eq!(identities(&room), vec!["alice", "bob"]);
+        room.sync(vec![participant("bob")]);
+        assert_eq!(identities(&room), vec!["bob"]);
     }
 
     #[test]
@@ -106,42 +105,55 @@ mod tests {
     #[test]
     fn become_dj() {
         let mut room = RoomState::new();
-        room.join("Alice".to_string());
-        assert!(room.become_dj("Alice".to_string()).is_ok());
-        assert_eq!(room.current_dj(), Some("Alice"));
+        room.sync(vec![participant("alice")]);
+        assert!(room.become_dj("alice".to_string()).is_ok());
+        assert_eq!(room.current_dj(), Some("alice"));
     }
 
     #[test]
     fn cannot_become_dj_without_joining() {
         let mut room = RoomState::new();
-        assert!(room.become_dj("Alice".to_string()).is_err());
+        assert!(room.become_dj("alice".to_string()).is_err());
     }
 
     #[test]
     fn stop_dj() {
         let mut room = RoomState::new();
-        room.join("Alice".to_string());
-        assert!(room.become_dj("Alice".to_string()).is_ok());
-        room.stop_dj("Alice");
+        room.sync(vec![participant("alice")]);
+        assert!(room.become_dj("alice".to_string()).is_ok());
+        room.stop_dj("alice");
         assert!(room.current_dj().is_none());
     }
 
     #[test]
     fn leaving_clears_dj() {
         let mut room = RoomState::new();
-        room.join("Alice".to_string());
-        assert!(room.become_dj("Alice".to_string()).is_ok());
-        room.leave("Alice");
+        room.sync(vec![participant("alice"), participant("bob")]);
+        assert!(room.become_dj("alice".to_string()).is_ok());
+        room.sync(vec![participant("bob")]);
         assert!(room.current_dj().is_none());
     }
 
     #[test]
     fn new_dj_replaces_old() {
         let mut room = RoomState::new();
-        room.join("Alice".to_string());
-        room.join("Bob".to_string());
-        assert!(room.become_dj("Alice".to_string()).is_ok());
-        assert!(room.become_dj("Bob".to_string()).is_ok());
-        assert_eq!(room.current_dj(), Some("Bob"));
+        room.sync(vec![participant("alice"), participant("bob")]);
+        assert!(room.become_dj("alice".to_string()).is_ok());
+        assert!(room.become_dj("bob".to_string()).is_ok());
+        assert_eq!(room.current_dj(), Some("bob"));
+    }
+
+    #[test]
+    fn snapshot_includes_current_dj() {
+        let mut room = RoomState::new();
+        room.sync(vec![participant("alice"), participant("bob")]);
+        assert!(room.become_dj("alice".to_string()).is_ok());
+        assert_eq!(
+            room.snapshot(),
+            RoomSnapshot {
+                participants: vec![participant("alice"), participant("bob")],
+                current_dj: Some("alice".to_string()),
+            }
+        );
     }
 }
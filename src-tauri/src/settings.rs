@@ -1,17 +1,121 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use anyhow::{Context, Result};
 
+/// Profile holding the top-level connection fields when no other profile is active.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Per-room connection settings that can be saved and switched between.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct ProfileSettings {
+    pub livekit_url: String,
+    pub shared_queue_repo: String,
+    pub shared_queue_file: String,
+    #[serde(default)]
+    pub display_name: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Settings {
     #[serde(default = "default_livekit_url")]
     pub livekit_url: String,
+    /// Backup LiveKit server tried when `livekit_url` can't be reached; empty disables it.
+    #[serde(default)]
+    pub livekit_url_fallback: String,
     #[serde(default = "default_shared_queue_repo")]
     pub shared_queue_repo: String,
     #[serde(default = "default_shared_queue_file")]
     pub shared_queue_file: String,
     #[serde(default = "default_gh_path")]
     pub gh_path: String,
+    /// Name shown to other participants; falls back to the token identity when empty.
+    #[serde(default)]
+    pub display_name: String,
+    /// Audio pipeline backend: `youtube` or `spotify`.
+    #[serde(default = "default_pipeline_backend")]
+    pub pipeline_backend: String,
+    /// Zero-based mic input channel; `None` averages all channels.
+    #[serde(default)]
+    pub input_channel: Option<usize>,
+    /// Capture the input in stereo (for line-in instruments) instead of mono voice.
+    #[serde(default)]
+    pub stereo_input: bool,
+    /// Seconds between shared queue refetches when webhooks are unavailable.
+    #[serde(default = "default_queue_poll_interval_secs")]
+    pub queue_poll_interval_secs: u64,
+    /// Seconds between remote skip checks while streaming without webhooks.
+    #[serde(default = "default_skip_check_interval_secs")]
+    pub skip_check_interval_secs: u64,
+    /// Seconds between webhook pings; two silent intervals force a reconnect.
+    #[serde(default = "default_webhook_ping_interval_secs")]
+    pub webhook_ping_interval_secs: u64,
+    /// DJ publisher buffer in milliseconds (40–200); lower is snappier, higher is smoother.
+    #[serde(default = "default_dj_latency_ms")]
+    pub dj_latency_ms: u32,
+    /// Upcoming tracks downloaded ahead of playback; `0` disables prefetching.
+    #[serde(default = "default_prefetch_depth")]
+    pub prefetch_depth: usize,
+    /// Prefetch downloads allowed to run at the same time (1–4).
+    #[serde(default = "default_prefetch_concurrency")]
+    pub prefetch_concurrency: usize,
+    /// Seconds yt-dlp may stall on a title lookup or stream before the track is failed.
+    #[serde(default = "default_stream_timeout_secs")]
+    pub stream_timeout_secs: u64,
+    /// Extra attempts at starting a track after a load error before it is failed (0–5).
+    #[serde(default = "default_load_retries")]
+    pub load_retries: usize,
+    /// Seconds the DJ loop may sit with an empty queue before it stops itself; `0` never stops.
+    #[serde(default)]
+    pub idle_timeout_secs: u64,
+    /// Chunks of music buffered for LiveKit (16–4096); fewer means less delay but less slack.
+    #[serde(default = "default_pcm_channel_chunks")]
+    pub pcm_channel_chunks: usize,
+    /// yt-dlp audio quality: `best`, `medium` (≤128kbps) or `low` (≤64kbps).
+    #[serde(default = "default_audio_quality")]
+    pub audio_quality: String,
+    /// Percent the music is lowered while someone talks; `0` disables ducking.
+    #[serde(default)]
+    pub duck_amount_pct: u8,
+    /// Milliseconds to fade the music down once someone starts talking.
+    #[serde(default = "default_duck_attack_ms")]
+    pub duck_attack_ms: u64,
+    /// Milliseconds to bring the music back up after the talking stops.
+    #[serde(default = "default_duck_release_ms")]
+    pub duck_release_ms: u64,
+    /// Don't duck for the local participant's own voice.
+    #[serde(default)]
+    pub duck_ignore_self: bool,
+    /// Never play music locally, for a dedicated box that only streams to LiveKit.
+    #[serde(default)]
+    pub dj_only_mode: bool,
+    /// Only listen: never publish the mic or music to LiveKit.
+    #[serde(default)]
+    pub listener_mode: bool,
+    /// URL patterns the host refuses to queue: a substring, or a glob using `*`.
+    #[serde(default)]
+    pub banned_patterns: Vec<String>,
+    /// Become DJ and start the music as soon as LiveKit connects, unless someone else is DJing.
+    #[serde(default)]
+    pub auto_dj: bool,
+    /// Refuse to queue a URL that is already at the end of the queue, e.g. from a double-click.
+    #[serde(default)]
+    pub reject_duplicates: bool,
+    /// Someone's first track since the queue was cleared plays next instead of waiting its turn.
+    #[serde(default)]
+    pub first_song_jumps_queue: bool,
+    /// Where new shared queue events go: `single` file or spread over `buckets`.
+    #[serde(default = "default_shared_queue_sharding")]
+    pub shared_queue_sharding: String,
+    /// Netscape cookies.txt handed to yt-dlp so login-only videos can play.
+    #[serde(default)]
+    pub cookies_path: Option<String>,
+    /// Saved per-room profiles by name; the top-level fields are the active one.
+    #[serde(default)]
+    pub profiles: HashMap<String, ProfileSettings>,
+    /// Name of the profile the top-level fields belong to; `None` means [`DEFAULT_PROFILE`].
+    #[serde(default)]
+    pub active_profile: Option<String>,
 }
 
 fn default_livekit_url() -> String {
@@ -30,13 +134,99 @@ fn default_gh_path() -> String {
     "gh".to_string()
 }
 
+fn default_pipeline_backend() -> String {
+    "youtube".to_string()
+}
+
+fn default_queue_poll_interval_secs() -> u64 {
+    10
+}
+
+fn default_skip_check_interval_secs() -> u64 {
+    2
+}
+
+fn default_webhook_ping_interval_secs() -> u64 {
+    30
+}
+
+fn default_dj_latency_ms() -> u32 {
+    100
+}
+
+fn default_prefetch_depth() -> usize {
+    2
+}
+
+fn default_prefetch_concurrency() -> usize {
+    2
+}
+
+fn default_stream_timeout_secs() -> u64 {
+    30
+}
+
+fn default_load_retries() -> usize {
+    2
+}
+
+fn default_pcm_channel_chunks() -> usize {
+    1024
+}
+
+fn default_duck_attack_ms() -> u64 {
+    100
+}
+
+fn default_duck_release_ms() -> u64 {
+    600
+}
+
+fn default_audio_quality() -> String {
+    "best".to_string()
+}
+
+fn default_shared_queue_sharding() -> String {
+    "single".to_string()
+}
+
 impl Default for Settings {
     fn default() -> Self {
         Self {
             livekit_url: default_livekit_url(),
+            livekit_url_fallback: String::new(),
             shared_queue_repo: default_shared_queue_repo(),
             shared_queue_file: default_shared_queue_file(),
             gh_path: default_gh_path(),
+            display_name: String::new(),
+            pipeline_backend: default_pipeline_backend(),
+            input_channel: None,
+            stereo_input: false,
+            queue_poll_interval_secs: default_queue_poll_interval_secs(),
+            skip_check_interval_secs: default_skip_check_interval_secs(),
+            webhook_ping_interval_secs: default_webhook_ping_interval_secs(),
+            dj_latency_ms: default_dj_latency_ms(),
+            prefetch_depth: default_prefetch_depth(),
+            prefetch_concurrency: default_prefetch_concurrency(),
+            stream_timeout_secs: default_stream_timeout_secs(),
+            load_retries: default_load_retries(),
+            idle_timeout_secs: 0,
+            pcm_channel_chunks: default_pcm_channel_chunks(),
+            audio_quality: default_audio_quality(),
+            duck_amount_pct: 0,
+            duck_attack_ms: default_duck_attack_ms(),
+            duck_release_ms: default_duck_release_ms(),
+            duck_ignore_self: false,
+            dj_only_mode: false,
+            listener_mode: false,
+            banned_patterns: Vec::new(),
+            auto_dj: false,
+            reject_duplicates: false,
+            first_song_jumps_queue: false,
+            shared_queue_sharding: default_shared_queue_sharding(),
+            cookies_path: None,
+            profiles: HashMap::new(),
+            active_profile: None,
         }
     }
 }
@@ -45,8 +235,12 @@ impl Settings {
     pub fn load(path: &PathBuf) -> Result<Self> {
         let content = std::fs::read_to_string(path)
             .with_context(|| format!("Failed to read settings file: {}", path.display()))?;
-        let settings = serde_json::from_str(&content)
+        let mut settings: Self = serde_json::from_str(&content)
             .context("Failed to parse settings JSON")?;
+        // Settings from before profiles existed become the default profile
+        if settings.profiles.is_empty() {
+            settings.profiles.insert(DEFAULT_PROFILE.to_string(), settings.current_profile());
+        }
         Ok(settings)
     }
 
@@ -60,6 +254,95 @@ impl Settings {
             .with_context(|| format!("Failed to write settings file: {}", path.display()))?;
         Ok(())
     }
+
+    pub fn active_profile_name(&self) -> &str {
+        self.active_profile.as_deref().unwrap_or(DEFAULT_PROFILE)
+    }
+
+    /// Profile names, sorted.
+    pub fn profile_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.profiles.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    fn current_profile(&self) -> ProfileSettings {
+        ProfileSettings {
+            livekit_url: self.livekit_url.clone(),
+            shared_queue_repo: self.shared_queue_repo.clone(),
+            shared_queue_file: self.shared_queue_file.clone(),
+            display_name: self.display_name.clone(),
+        }
+    }
+
+    /// Store the current connection fields as `name` and make it the active profile.
+    pub fn save_profile(&mut self, name: &str) -> Result<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            anyhow::bail!("Profile name must not be empty");
+        }
+        self.profiles.insert(name.to_string(), self.current_profile());
+        self.active_profile = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Keep edits to the active profile, then load `name` into the top-level fields.
+    pub fn switch_profile(&mut self, name: &str) -> Result<()> {
+        let profile = self
+            .profiles
+            .get(name)
+            .cloned()
+            .with_context(|| format!("No profile named {name}"))?;
+        self.profiles.insert(self.active_profile_name().to_string(), self.current_profile());
+        self.livekit_url = profile.livekit_url;
+        self.shared_queue_repo = profile.shared_queue_repo;
+        self.shared_queue_file = profile.shared_queue_file;
+        self.display_name = profile.display_name;
+        self.active_profile = Some(name.to_string());
+        Ok(())
+    }
+
+    /// The first banned pattern matching `url`, if any.
+    pub fn banned_pattern_for(&self, url: &str) -> Option<&str> {
+        self.banned_patterns
+            .iter()
+            .map(String::as_str)
+            .find(|pattern| url_matches_pattern(url, pattern))
+    }
+}
+
+/// Case-insensitive match: `*` matches any run of characters, otherwise a plain substring test.
+fn url_matches_pattern(url: &str, pattern: &str) -> bool {
+    let url = url.to_lowercase();
+    let pattern = pattern.trim().to_lowercase();
+    if pattern.is_empty() {
+        return false;
+    }
+    if !pattern.contains('*') {
+        return url.contains(&pattern);
+    }
+    let mut rest = url.as_str();
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let last = parts.len() - 1;
+    for (i, part) in parts.iter().enumerate() {
+        if part.is_empty() {
+            continue;
+        }
+        if i == 0 {
+            let Some(stripped) = rest.strip_prefix(part) else {
+                return false;
+            };
+            rest = stripped;
+        } else if i == last {
+            return rest.ends_with(part);
+        } else {
+            let Some(pos) = rest.find(part) else {
+                return false;
+            };
+            rest = &rest[pos + part.len()..];
+        }
+    }
+    true
 }
 
 #[cfg(test)]
@@ -71,9 +354,39 @@ mod tests {
     fn default_settings_have_reasonable_values() {
         let settings = Settings::default();
         assert_eq!(settings.livekit_url, "");
+        assert_eq!(settings.livekit_url_fallback, "");
         assert_eq!(settings.shared_queue_repo, "williammartin/gezellig-queue");
         assert_eq!(settings.shared_queue_file, "events.ndjson");
         assert_eq!(settings.gh_path, "gh");
+        assert_eq!(settings.display_name, "");
+        assert_eq!(settings.pipeline_backend, "youtube");
+        assert_eq!(settings.input_channel, None);
+        assert!(!settings.stereo_input);
+        assert_eq!(settings.queue_poll_interval_secs, 10);
+        assert_eq!(settings.skip_check_interval_secs, 2);
+        assert_eq!(settings.webhook_ping_interval_secs, 30);
+        assert_eq!(settings.dj_latency_ms, 100);
+        assert_eq!(settings.prefetch_depth, 2);
+        assert_eq!(settings.prefetch_concurrency, 2);
+        assert_eq!(settings.stream_timeout_secs, 30);
+        assert_eq!(settings.load_retries, 2);
+        assert_eq!(settings.idle_timeout_secs, 0);
+        assert_eq!(settings.pcm_channel_chunks, 1024);
+        assert_eq!(settings.audio_quality, "best");
+        assert_eq!(settings.duck_amount_pct, 0);
+        assert_eq!(settings.duck_attack_ms, 100);
+        assert_eq!(settings.duck_release_ms, 600);
+        assert!(!settings.duck_ignore_self);
+        assert!(!settings.dj_only_mode);
+        assert!(!settings.listener_mode);
+        assert!(settings.banned_patterns.is_empty());
+        assert!(!settings.auto_dj);
+        assert!(!settings.reject_duplicates);
+        assert!(!settings.first_song_jumps_queue);
+        assert_eq!(settings.shared_queue_sharding, "single");
+        assert_eq!(settings.cookies_path, None);
+        assert!(settings.profiles.is_empty());
+        assert_eq!(settings.active_profile_name(), DEFAULT_PROFILE);
     }
 
     #[test]
@@ -86,9 +399,47 @@ mod tests {
 
         let settings = Settings {
             livekit_url: "wss://example.livekit.cloud".to_string(),
+            livekit_url_fallback: "wss://backup.example.com".to_string(),
             shared_queue_repo: "owner/repo".to_string(),
             shared_queue_file: "events.ndjson".to_string(),
             gh_path: "/usr/local/bin/gh".to_string(),
+            display_name: "Alex".to_string(),
+            pipeline_backend: "spotify".to_string(),
+            input_channel: Some(2),
+            stereo_input: true,
+            queue_poll_interval_secs: 30,
+            skip_check_interval_secs: 5,
+            webhook_ping_interval_secs: 15,
+            dj_latency_ms: 60,
+            prefetch_depth: 0,
+            prefetch_concurrency: 3,
+            stream_timeout_secs: 45,
+            load_retries: 4,
+            idle_timeout_secs: 900,
+            pcm_channel_chunks: 256,
+            audio_quality: "low".to_string(),
+            duck_amount_pct: 60,
+            duck_attack_ms: 50,
+            duck_release_ms: 1_000,
+            duck_ignore_self: true,
+            dj_only_mode: true,
+            listener_mode: true,
+            banned_patterns: vec!["*.example.com/*".to_string()],
+            auto_dj: true,
+            reject_duplicates: true,
+            first_song_jumps_queue: true,
+            shared_queue_sharding: "buckets".to_string(),
+            cookies_path: Some("/home/alex/cookies.txt".to_string()),
+            profiles: HashMap::from([(
+                "work".to_string(),
+                ProfileSettings {
+                    livekit_url: "wss://work.livekit.cloud".to_string(),
+                    shared_queue_repo: "work/queue".to_string(),
+                    shared_queue_file: "events.ndjson".to_string(),
+                    display_name: String::new(),
+                },
+            )]),
+            active_profile: Some("work".to_string()),
         };
 
         assert!(settings.save(&path).is_ok());
@@ -99,6 +450,73 @@ mod tests {
         }
     }
 
+    #[test]
+    fn load_fills_missing_fields_with_defaults() {
+        let dir = match tempfile::tempdir() {
+            Ok(dir) => dir,
+            Err(err) => panic!("tempdir failed: {err}"),
+        };
+        let path = dir.path().join("settings.json");
+        assert!(fs::write(&path, r#"{ "livekit_url": "wss://example" }"#).is_ok());
+
+        match Settings::load(&path) {
+            Ok(loaded) => {
+                assert_eq!(loaded.livekit_url, "wss://example");
+                assert_eq!(loaded.display_name, "");
+                assert_eq!(loaded.gh_path, "gh");
+                assert_eq!(loaded.profile_names(), vec![DEFAULT_PROFILE.to_string()]);
+                assert_eq!(
+                    loaded.profiles.get(DEFAULT_PROFILE).map(|p| p.livekit_url.as_str()),
+                    Some("wss://example")
+                );
+            }
+            Err(err) => panic!("load failed: {err}"),
+        }
+    }
+
+    #[test]
+    fn switching_profiles_keeps_edits_to_the_previous_one() {
+        let mut settings = Settings {
+            livekit_url: "wss://home".to_string(),
+            shared_queue_repo: "me/home-queue".to_string(),
+            ..Settings::default()
+        };
+        settings.profiles.insert(DEFAULT_PROFILE.to_string(), settings.current_profile());
+        settings.livekit_url = "wss://work".to_string();
+        settings.shared_queue_repo = "me/work-queue".to_string();
+        assert!(settings.save_profile("work").is_ok());
+
+        assert!(settings.switch_profile(DEFAULT_PROFILE).is_ok());
+        assert_eq!(settings.livekit_url, "wss://home");
+        assert_eq!(settings.shared_queue_repo, "me/home-queue");
+
+        settings.livekit_url = "wss://home2".to_string();
+        assert!(settings.switch_profile("work").is_ok());
+        assert_eq!(settings.active_profile_name(), "work");
+        assert_eq!(settings.livekit_url, "wss://work");
+        assert_eq!(
+            settings.profiles.get(DEFAULT_PROFILE).map(|p| p.livekit_url.as_str()),
+            Some("wss://home2")
+        );
+        assert!(settings.switch_profile("missing").is_err());
+        assert!(settings.save_profile("  ").is_err());
+    }
+
+    #[test]
+    fn banned_patterns_match_substrings_and_globs() {
+        let settings = Settings {
+            banned_patterns: vec!["watch?v=abc123".to_string(), "https://*.spam.example/*".to_string()],
+            ..Settings::default()
+        };
+        assert_eq!(
+            settings.banned_pattern_for("https://youtube.com/watch?v=abc123&t=5"),
+            Some("watch?v=abc123")
+        );
+        assert!(settings.banned_pattern_for("https://www.SPAM.example/track").is_some());
+        assert!(settings.banned_pattern_for("https://spam.example.org/track").is_none());
+        assert!(settings.banned_pattern_for("https://youtube.com/watch?v=other").is_none());
+    }
+
     #[test]
     fn load_returns_default_when_file_missing() {
         let path = PathBuf::from("/tmp/nonexistent_gezellig_test/settings.json");
@@ -1,4 +1,7 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use base64::Engine;
 use futures_util::{SinkExt, StreamExt};
@@ -6,6 +9,8 @@ use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, Emitter};
 use tokio_tungstenite::tungstenite::{Message, client::IntoClientRequest};
 
+use crate::youtube_pipeline::QueueSyncMode;
+
 #[derive(Debug, Deserialize)]
 struct CreateHookResponse {
     id: u64,
@@ -21,6 +26,131 @@ struct WebhookDetails {
     url: String,
     #[serde(rename = "ws_url")]
     ws_url: Option<String>,
+    #[serde(default)]
+    active: bool,
+    updated_at: Option<String>,
+}
+
+/// `cli` hooks untouched for this long are assumed to belong to instances that exited uncleanly.
+const STALE_HOOK_AGE_SECS: u64 = 24 * 60 * 60;
+
+/// The hook this instance created, so it can be deleted on exit.
+#[derive(Debug, Clone)]
+pub struct WebhookRegistration {
+    pub repo: String,
+    pub gh_path: String,
+    pub hook_id: u64,
+}
+
+pub type ActiveWebhook = Arc<Mutex<Option<WebhookRegistration>>>;
+
+/// A connection with no traffic for this many ping intervals is treated as dead.
+const DEAD_CONNECTION_PINGS: u32 = 2;
+
+/// Whether the websocket has been silent long enough to force a reconnect.
+fn connection_dead(silent_for: Duration, ping_interval: Duration) -> bool {
+    silent_for > ping_interval * DEAD_CONNECTION_PINGS
+}
+
+/// Consecutive permission/not-found failures before giving up on the webhook.
+const MAX_PERMANENT_FAILURES: u32 = 3;
+
+/// Exponential backoff between retries, capped at a maximum delay.
+struct Backoff {
+    initial: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            current: initial,
+        }
+    }
+
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+
+    fn reset(&mut self) {
+        self.current = self.initial;
+    }
+}
+
+/// Decides whether and how long to wait after a failed attempt.
+struct RetryPolicy {
+    backoff: Backoff,
+    permanent_failures: u32,
+}
+
+impl RetryPolicy {
+    fn new() -> Self {
+        Self {
+            backoff: Backoff::new(Duration::from_secs(2), Duration::from_secs(60)),
+            permanent_failures: 0,
+        }
+    }
+
+    /// Returns the delay before retrying, or `None` to stop retrying.
+    fn on_failure(&mut self, err: &str) -> Option<Duration> {
+        if is_permanent_error(err) {
+            self.permanent_failures += 1;
+            if self.permanent_failures >= MAX_PERMANENT_FAILURES {
+                return None;
+            }
+        } else {
+            self.permanent_failures = 0;
+        }
+        Some(self.backoff.next_delay())
+    }
+
+    fn on_success(&mut self) {
+        self.backoff.reset();
+        self.permanent_failures = 0;
+    }
+}
+
+/// Errors that won't go away by retrying, such as missing admin rights or an unknown repo.
+fn is_permanent_error(err: &str) -> bool {
+    !crate::gh_throttle::is_rate_limited(err)
+        && ["HTTP 403", "HTTP 404", "403 Forbidden", "404 Not Found"]
+            .iter()
+            .any(|marker| err.contains(marker))
+}
+
+/// Record whether the webhook is live. The queue polls whenever it isn't, so no updates are
+/// lost while it is down or reconnecting.
+pub fn set_connected(connected: &AtomicBool, sync_mode: &QueueSyncMode, is_connected: bool) {
+    connected.store(is_connected, Ordering::Relaxed);
+    sync_mode.set_polling(!is_connected);
+}
+
+/// Wait before the next attempt, or tell the UI the webhook is unavailable and return false.
+/// The queue keeps polling either way.
+async fn back_off(
+    app: &AppHandle,
+    retry: &mut RetryPolicy,
+    sync_mode: &QueueSyncMode,
+    err: &str,
+) -> bool {
+    match retry.on_failure(err) {
+        Some(delay) => {
+            tracing::info!(event = "queue_webhook_retry", delay_secs = delay.as_secs());
+            tokio::time::sleep(delay).await;
+            true
+        }
+        None => {
+            tracing::warn!(event = "queue_webhook_unavailable", error = %err);
+            crate::dlog!("[Queue] Webhook unavailable, staying on polling: {err}");
+            let _ = app.emit("webhook-unavailable", err.to_string());
+            false
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -41,12 +171,32 @@ pub fn spawn_shared_queue_webhook(
     secret: String,
     hook_id: Option<u64>,
     updates_tx: Option<tokio::sync::broadcast::Sender<()>>,
-) {
+    state_path: Option<std::path::PathBuf>,
+    active_webhook: ActiveWebhook,
+    sync_mode: Arc<QueueSyncMode>,
+    connected: Arc<AtomicBool>,
+) -> tauri::async_runtime::JoinHandle<()> {
+    set_connected(&connected, &sync_mode, false);
     tauri::async_runtime::spawn(async move {
-        if let Err(err) = run_webhook_listener(app, repo, path, gh_path, secret, hook_id, updates_tx).await {
+        if let Err(err) = run_webhook_listener(
+            app,
+            repo,
+            path,
+            gh_path,
+            secret,
+            hook_id,
+            updates_tx,
+            state_path,
+            active_webhook,
+            sync_mode.clone(),
+            connected.clone(),
+        )
+        .await
+        {
             crate::dlog!("[Queue] Webhook listener error: {err}");
         }
-    });
+        set_connected(&connected, &sync_mode, false);
+    })
 }
 
 async fn run_webhook_listener(
@@ -57,6 +207,10 @@ async fn run_webhook_listener(
     secret: String,
     mut hook_id: Option<u64>,
     updates_tx: Option<tokio::sync::broadcast::Sender<()>>,
+    state_path: Option<std::path::PathBuf>,
+    active_webhook: ActiveWebhook,
+    sync_mode: Arc<QueueSyncMode>,
+    connected: Arc<AtomicBool>,
 ) -> Result<(), String> {
     let host = std::env::var("GH_HOST").unwrap_or_else(|_| "github.com".to_string());
     let token = gh_auth_token(&gh_path, &host).await?;
@@ -66,6 +220,8 @@ async fn run_webhook_listener(
         path = %path,
         secret_len = secret.len()
     );
+    prune_stale_webhooks(&gh_path, &repo, hook_id).await;
+    let mut retry = RetryPolicy::new();
 
     loop {
         tracing::info!(event = "queue_webhook_create", repo = %repo);
@@ -95,12 +251,21 @@ async fn run_webhook_listener(
                 Err(err) => {
                     tracing::warn!(event = "queue_webhook_create_failed", error = %err);
                     crate::dlog!("[Queue] Webhook create error: {err}");
-                    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-                    continue;
+                    if back_off(&app, &mut retry, &sync_mode, &err).await {
+                        continue;
+                    }
+                    return Ok(());
                 }
             },
         };
         hook_id = Some(hook.id);
+        if let Ok(mut active) = active_webhook.lock() {
+            *active = Some(WebhookRegistration {
+                repo: repo.clone(),
+                gh_path: gh_path.clone(),
+                hook_id: hook.id,
+            });
+        }
         tracing::info!(
             event = "queue_webhook_created",
             hook_url = %hook.url,
@@ -111,28 +276,42 @@ async fn run_webhook_listener(
             Err(err) => {
                 tracing::warn!(event = "queue_webhook_connect_failed", error = %err);
                 crate::dlog!("[Queue] Webhook connect error: {err}");
-                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-                continue;
+                if back_off(&app, &mut retry, &sync_mode, &err).await {
+                    continue;
+                }
+                return Ok(());
             }
         };
 
         if let Err(err) = activate_hook(&gh_path, &hook.url).await {
             tracing::warn!(event = "queue_webhook_activate_failed", error = %err);
             crate::dlog!("[Queue] Webhook activate error: {err}");
-            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-            continue;
+            if back_off(&app, &mut retry, &sync_mode, &err).await {
+                continue;
+            }
+            return Ok(());
         }
 
+        retry.on_success();
+        set_connected(&connected, &sync_mode, true);
         tracing::info!(event = "queue_webhook_connected");
         crate::dlog!("[Queue] Webhook listener connected");
         let _ = app.emit("shared-queue-updated", ());
         if let Some(tx) = updates_tx.as_ref() {
             let _ = tx.send(());
         }
-        let mut ping = tokio::time::interval(std::time::Duration::from_secs(30));
+        // The interval applies per connection, so a new setting takes effect on reconnect
+        let ping_interval = sync_mode.ping_interval();
+        let mut ping = tokio::time::interval(ping_interval);
+        let mut last_traffic = tokio::time::Instant::now();
         loop {
             tokio::select! {
                 _ = ping.tick() => {
+                    if connection_dead(last_traffic.elapsed(), ping_interval) {
+                        tracing::warn!(event = "queue_webhook_dead", silent_secs = last_traffic.elapsed().as_secs());
+                        crate::dlog!("[Queue] No webhook traffic for {}s, reconnecting", last_traffic.elapsed().as_secs());
+                        break;
+                    }
                     if let Err(err) = ws.send(Message::Ping(Vec::new().into())).await {
                         crate::dlog!("[Queue] Webhook ping error: {err}");
                         break;
@@ -150,6 +329,8 @@ async fn run_webhook_listener(
                             break;
                         }
                     };
+                    // Pongs count too, so an idle but healthy connection stays up
+                    last_traffic = tokio::time::Instant::now();
                     let text = match msg {
                         Message::Text(text) => text.to_string(),
                         Message::Binary(bytes) => String::from_utf8(bytes.to_vec())
@@ -180,9 +361,11 @@ async fn run_webhook_listener(
                     if queue_path_touched(&body_json, &repo, &path) {
                         tracing::info!(event = "queue_webhook_queue_touched", repo = %repo, path = %path);
                         crate::dlog!("[Queue] Webhook event: {}", body_json);
-                        let _ = app.emit("shared-queue-updated", ());
-                        if let Some(tx) = updates_tx.as_ref() {
-                            let _ = tx.send(());
+                        // A running pipeline re-reads the queue and reports what changed;
+                        // only read it here when nothing is listening
+                        let synced = updates_tx.as_ref().is_some_and(|tx| tx.send(()).is_ok());
+                        if !synced {
+                            emit_queue_updated(&app, &repo, &path, &gh_path, state_path.as_ref()).await;
                         }
                     }
                     let ack = WsEventAck {
@@ -199,10 +382,42 @@ async fn run_webhook_listener(
                 }
             }
         }
+        set_connected(&connected, &sync_mode, false);
+        crate::emit_backend_error("webhook", "Live queue updates disconnected, reconnecting");
         tokio::time::sleep(std::time::Duration::from_secs(2)).await;
     }
 }
 
+/// Emit `shared-queue-updated` with a summary of new events, or a bare event if it can't be computed.
+async fn emit_queue_updated(
+    app: &AppHandle,
+    repo: &str,
+    path: &str,
+    gh_path: &str,
+    state_path: Option<&std::path::PathBuf>,
+) {
+    let Some(state_path) = state_path.cloned() else {
+        let _ = app.emit("shared-queue-updated", ());
+        return;
+    };
+    let (repo, path, gh_path) = (repo.to_string(), path.to_string(), gh_path.to_string());
+    let update = tokio::task::spawn_blocking(move || {
+        crate::youtube_pipeline::queue_update_since_last_seen(repo, path, gh_path, state_path)
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result);
+    match update {
+        Ok(update) => {
+            let _ = app.emit("shared-queue-updated", update);
+        }
+        Err(err) => {
+            crate::dlog!("[Queue] Failed to diff queue update: {err}");
+            let _ = app.emit("shared-queue-updated", ());
+        }
+    }
+}
+
 async fn gh_auth_token(gh_path: &str, host: &str) -> Result<String, String> {
     let output = tokio::process::Command::new(gh_path)
         .args(["auth", "token", "--hostname", host])
@@ -239,11 +454,10 @@ async fn create_webhook(
         ];
         args.push("-f".to_string());
         args.push(format!("config[secret]={secret}"));
-        let output = tokio::process::Command::new(gh_path)
-            .args(args)
-            .output()
-            .await
-            .map_err(|e| format!("Failed to run gh api: {e}"))?;
+        let output = crate::gh_throttle::output_async(
+            tokio::process::Command::new(gh_path).args(args),
+        )
+        .await?;
         if output.status.success() {
             return serde_json::from_slice(&output.stdout)
                 .map_err(|e| format!("Invalid webhook response: {e}"));
@@ -254,23 +468,108 @@ async fn create_webhook(
 }
 
 async fn get_webhook(gh_path: &str, repo: &str, hook_id: u64) -> Result<WebhookDetails, String> {
-    let output = tokio::process::Command::new(gh_path)
-        .args(["api", &format!("repos/{repo}/hooks/{hook_id}")])
-        .output()
-        .await
-        .map_err(|e| format!("Failed to run gh api: {e}"))?;
+    let output = crate::gh_throttle::output_async(
+        tokio::process::Command::new(gh_path)
+            .args(["api", &format!("repos/{repo}/hooks/{hook_id}")]),
+    )
+    .await?;
     if !output.status.success() {
         return Err(String::from_utf8_lossy(&output.stderr).to_string());
     }
     serde_json::from_slice(&output.stdout).map_err(|e| format!("Invalid webhook response: {e}"))
 }
 
+async fn list_webhooks(gh_path: &str, repo: &str) -> Result<Vec<WebhookDetails>, String> {
+    let output = crate::gh_throttle::output_async(
+        tokio::process::Command::new(gh_path)
+            .args(["api", "--paginate", &format!("repos/{repo}/hooks")]),
+    )
+    .await?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).to_string());
+    }
+    serde_json::from_slice(&output.stdout).map_err(|e| format!("Invalid webhook list: {e}"))
+}
+
+pub async fn delete_webhook(gh_path: &str, repo: &str, hook_id: u64) -> Result<(), String> {
+    let output = crate::gh_throttle::output_async(
+        tokio::process::Command::new(gh_path)
+            .args(["api", "-X", "DELETE", &format!("repos/{repo}/hooks/{hook_id}")]),
+    )
+    .await?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).to_string());
+    }
+    Ok(())
+}
+
+/// Delete `cli` hooks left behind by earlier runs, leaving recently updated ones
+/// alone since they may belong to another running instance.
+async fn prune_stale_webhooks(gh_path: &str, repo: &str, keep: Option<u64>) {
+    let hooks = match list_webhooks(gh_path, repo).await {
+        Ok(hooks) => hooks,
+        Err(err) => {
+            tracing::warn!(event = "queue_webhook_list_failed", error = %err);
+            return;
+        }
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    for hook in hooks {
+        if Some(hook.id) == keep || !is_stale_cli_hook(&hook, now) {
+            continue;
+        }
+        match delete_webhook(gh_path, repo, hook.id).await {
+            Ok(()) => tracing::info!(event = "queue_webhook_pruned", hook_id = hook.id),
+            Err(err) => tracing::warn!(event = "queue_webhook_prune_failed", hook_id = hook.id, error = %err),
+        }
+    }
+}
+
+fn is_stale_cli_hook(hook: &WebhookDetails, now: u64) -> bool {
+    if hook.name != "cli" {
+        return false;
+    }
+    let age = hook
+        .updated_at
+        .as_deref()
+        .and_then(parse_github_timestamp)
+        .map(|updated| now.saturating_sub(updated));
+    match age {
+        Some(age) => age > STALE_HOOK_AGE_SECS || (!hook.active && age > 60 * 60),
+        None => false,
+    }
+}
+
+/// Parse a GitHub `YYYY-MM-DDTHH:MM:SSZ` timestamp into Unix seconds.
+fn parse_github_timestamp(value: &str) -> Option<u64> {
+    let (date, time) = value.trim_end_matches('Z').split_once('T')?;
+    let mut date_parts = date.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (date_parts.next()??, date_parts.next()??, date_parts.next()??);
+    let mut time_parts = time.splitn(3, ':').map(|p| p.get(..2).and_then(|p| p.parse::<i64>().ok()));
+    let (hour, minute, second) = (time_parts.next()??, time_parts.next()??, time_parts.next()??);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // Days from civil date (Howard Hinnant's algorithm)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    u64::try_from(days * 86_400 + hour * 3_600 + minute * 60 + second).ok()
+}
+
 async fn activate_hook(gh_path: &str, hook_url: &str) -> Result<(), String> {
-    let output = tokio::process::Command::new(gh_path)
-        .args(["api", "-X", "PATCH", hook_url, "-F", "active=true"])
-        .output()
-        .await
-        .map_err(|e| format!("Failed to run gh api: {e}"))?;
+    let output = crate::gh_throttle::output_async(
+        tokio::process::Command::new(gh_path)
+            .args(["api", "-X", "PATCH", hook_url, "-F", "active=true"]),
+    )
+    .await?;
     if !output.status.success() {
         return Err(String::from_utf8_lossy(&output.stderr).to_string());
     }
@@ -326,12 +625,19 @@ fn queue_path_touched(body: &serde_json::Value, repo: &str, path: &str) -> bool
     touched
 }
 
+/// True for the queue file itself or any of its shards.
 fn commit_paths_include(commit: &serde_json::Value, path: &str) -> bool {
+    let shard_prefix = format!("{}/", crate::youtube_pipeline::shard_dir(path));
     ["added", "modified", "removed"].iter().any(|key| {
         commit
             .get(*key)
             .and_then(|v| v.as_array())
-            .map(|paths| paths.iter().any(|p| p.as_str() == Some(path)))
+            .map(|paths| {
+                paths
+                    .iter()
+                    .filter_map(|p| p.as_str())
+                    .any(|p| p == path || p.starts_with(&shard_prefix))
+            })
             .unwrap_or(false)
     })
 }
@@ -356,6 +662,89 @@ mod tests {
         assert!(!queue_path_touched(&body, "owner/repo", "other.ndjson"));
     }
 
+    #[test]
+    fn detects_shard_paths_from_push() {
+        let body = serde_json::json!({
+            "repository": { "full_name": "owner/repo" },
+            "head_commit": { "modified": ["events/0002.ndjson"] }
+        });
+        assert!(queue_path_touched(&body, "owner/repo", "events.ndjson"));
+        assert!(!queue_path_touched(&body, "owner/repo", "other.ndjson"));
+    }
+
+    #[test]
+    fn silent_connection_is_dead_after_two_pings() {
+        let interval = Duration::from_secs(30);
+        assert!(!connection_dead(Duration::from_secs(30), interval));
+        assert!(!connection_dead(Duration::from_secs(60), interval));
+        assert!(connection_dead(Duration::from_secs(61), interval));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let mut backoff = Backoff::new(Duration::from_secs(2), Duration::from_secs(10));
+        let delays: Vec<u64> = (0..5).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, vec![2, 4, 8, 10, 10]);
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn queue_polls_whenever_the_webhook_is_down() {
+        let connected = AtomicBool::new(false);
+        let mode = QueueSyncMode::default();
+        set_connected(&connected, &mode, true);
+        assert!(connected.load(Ordering::Relaxed));
+        assert!(!mode.is_polling());
+        set_connected(&connected, &mode, false);
+        assert!(!connected.load(Ordering::Relaxed));
+        assert!(mode.is_polling());
+    }
+
+    #[test]
+    fn retry_gives_up_after_repeated_permanent_errors() {
+        let mut retry = RetryPolicy::new();
+        let forbidden = "gh: Resource not accessible by integration (HTTP 403)";
+        assert!(retry.on_failure(forbidden).is_some());
+        assert!(retry.on_failure(forbidden).is_some());
+        assert!(retry.on_failure(forbidden).is_none());
+    }
+
+    #[test]
+    fn transient_errors_keep_retrying() {
+        let mut retry = RetryPolicy::new();
+        for _ in 0..10 {
+            assert!(retry.on_failure("connection reset by peer").is_some());
+        }
+        assert!(!is_permanent_error("timed out"));
+        assert!(is_permanent_error("gh: Not Found (HTTP 404)"));
+        assert!(!is_permanent_error("gh: API rate limit exceeded for user ID 1. (HTTP 403)"));
+    }
+
+    #[test]
+    fn parses_github_timestamps() {
+        assert_eq!(parse_github_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_github_timestamp("2024-03-01T12:30:15Z"), Some(1_709_296_215));
+        assert_eq!(parse_github_timestamp("not a date"), None);
+    }
+
+    #[test]
+    fn only_old_cli_hooks_are_stale() {
+        let hook = |name: &str, active: bool, updated_at: &str| WebhookDetails {
+            id: 1,
+            name: name.to_string(),
+            url: String::new(),
+            ws_url: None,
+            active,
+            updated_at: Some(updated_at.to_string()),
+        };
+        let now = 1_709_296_215 + 2 * 60 * 60;
+        assert!(!is_stale_cli_hook(&hook("cli", true, "2024-03-01T12:30:15Z"), now));
+        assert!(is_stale_cli_hook(&hook("cli", false, "2024-03-01T12:30:15Z"), now));
+        assert!(is_stale_cli_hook(&hook("cli", true, "2024-02-27T12:30:15Z"), now));
+        assert!(!is_stale_cli_hook(&hook("web", false, "2024-02-27T12:30:15Z"), now));
+    }
+
     #[test]
     fn ignores_other_repo_events() {
         let body = serde_json::json!({
@@ -11,20 +11,74 @@ use std::sync::atomic::{AtomicU8, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{mpsc, oneshot};
+use crate::resampler::LinearResampler;
 
 const SAMPLE_RATE: u32 = 48_000;
 const SAMPLES_PER_CHANNEL: u32 = SAMPLE_RATE / 100; // 10ms
+const MIC_RETRY_INITIAL: Duration = Duration::from_millis(500);
+const MIC_RETRY_MAX: Duration = Duration::from_secs(5);
+/// Loopback frames queued in the output sink before new ones are dropped, keeping latency low.
+const LOOPBACK_MAX_QUEUED_FRAMES: usize = 5;
 
 pub struct VoiceChatHandle {
     pub shutdown_tx: std::sync::mpsc::Sender<()>,
     pub task_shutdown_tx: oneshot::Sender<()>,
     pub thread: std::thread::JoinHandle<()>,
     pub task: tokio::task::JoinHandle<()>,
+    /// Level of the mic input being published, zeroed once it stops.
+    /// Measured before LiveKit's noise suppression and gain control.
+    pub published_input_level: Arc<AtomicU8>,
 }
 
 pub struct MicTestHandle {
     pub shutdown_tx: std::sync::mpsc::Sender<()>,
     pub thread: std::thread::JoinHandle<()>,
+    /// Plays the mic back to the user when loopback is on.
+    pub loopback: Option<std::thread::JoinHandle<()>>,
+}
+
+/// User preferences for how the microphone is captured.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MicOptions {
+    /// Zero-based input channel to capture; `None` averages all channels.
+    pub input_channel: Option<usize>,
+    /// Keep L/R and publish a 2-channel track, for line-in sources like instruments.
+    pub stereo: bool,
+}
+
+impl MicOptions {
+    fn channels(&self) -> usize {
+        if self.stereo { 2 } else { 1 }
+    }
+}
+
+/// Reduce one interleaved frame to a mono sample, either from a single channel or the average.
+fn downmix<T: Copy>(frame: &[T], input_channel: Option<usize>, to_f32: impl Fn(T) -> f32) -> f32 {
+    match input_channel {
+        Some(channel) => frame.get(channel).map(|s| to_f32(*s)).unwrap_or(0.0),
+        None => frame.iter().map(|s| to_f32(*s)).sum::<f32>() / frame.len().max(1) as f32,
+    }
+}
+
+/// Append the samples sent for one interleaved device frame: a mono sample, or an
+/// L/R pair starting at the selected channel when capturing in stereo.
+fn extract_channels<T: Copy>(
+    frame: &[T],
+    input_channel: Option<usize>,
+    stereo: bool,
+    to_f32: impl Fn(T) -> f32,
+    out: &mut Vec<f32>,
+) {
+    if !stereo {
+        out.push(downmix(frame, input_channel, to_f32));
+        return;
+    }
+    let first = input_channel.unwrap_or(0);
+    let left = frame.get(first).map(|s| to_f32(*s)).unwrap_or(0.0);
+    // Single-channel devices feed the same signal to both sides
+    let right = frame.get(first + 1).map(|s| to_f32(*s)).unwrap_or(left);
+    out.push(left);
+    out.push(right);
 }
 
 fn update_level_from_f32(samples: &[f32], mic_level: &AtomicU8) {
@@ -37,28 +91,99 @@ fn update_level_from_f32(samples: &[f32], mic_level: &AtomicU8) {
     mic_level.store(level, Ordering::Relaxed);
 }
 
-fn update_level_from_i16(samples: &[i16], mic_level: &AtomicU8) {
-    if samples.is_empty() {
-        return;
+fn update_level_from_i16(samples: &[i16], level: &AtomicU8) {
+    let normalized: Vec<f32> = samples.iter().map(|s| *s as f32 / i16::MAX as f32).collect();
+    update_level_from_f32(&normalized, level);
+}
+
+/// Collects interleaved samples into 10ms 48kHz frames, resampling when the device runs at another rate.
+struct FrameAssembler {
+    resampler: Option<LinearResampler>,
+    buffer: Vec<i16>,
+    frame_size: usize,
+}
+
+impl FrameAssembler {
+    fn new(input_rate: u32, channels: usize) -> Self {
+        let frame_size = SAMPLES_PER_CHANNEL as usize * channels;
+        let resampler = (input_rate != SAMPLE_RATE)
+            .then(|| LinearResampler::new(f64::from(input_rate), f64::from(SAMPLE_RATE), channels));
+        Self {
+            resampler,
+            buffer: Vec::with_capacity(frame_size * 2),
+            frame_size,
+        }
     }
-    let sum = samples
-        .iter()
-        .map(|s| {
-            let v = *s as f32 / i16::MAX as f32;
-            v * v
-        })
-        .sum::<f32>()
-        / samples.len() as f32;
-    let rms = sum.sqrt();
-    let level = (rms * 100.0).clamp(0.0, 100.0) as u8;
-    mic_level.store(level, Ordering::Relaxed);
+
+    fn push(&mut self, interleaved: &[f32]) -> Vec<Vec<i16>> {
+        let resampled;
+        let samples = match self.resampler.as_mut() {
+            Some(resampler) => {
+                resampled = resampler.process(interleaved);
+                &resampled[..]
+            }
+            None => interleaved,
+        };
+        self.buffer.extend(
+            samples
+                .iter()
+                .map(|s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16),
+        );
+        let mut frames = Vec::new();
+        while self.buffer.len() >= self.frame_size {
+            frames.push(self.buffer.drain(..self.frame_size).collect());
+        }
+        frames
+    }
+}
+
+/// One input configuration range offered by the mic.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InputConfigInfo {
+    pub sample_format: String,
+    pub channels: u16,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+}
+
+/// What the default input device supports, for diagnosing capture failures.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InputDeviceInfo {
+    pub name: String,
+    pub configs: Vec<InputConfigInfo>,
 }
 
-fn select_input_config() -> Result<(cpal::Device, StreamConfig, SampleFormat)> {
-    let host = cpal::default_host();
-    let device = host
+fn default_input_device() -> Result<cpal::Device> {
+    cpal::default_host()
         .default_input_device()
-        .context("No default input device")?;
+        .context("No default input device")
+}
+
+/// Describe the default input device and every config it reports.
+pub fn input_device_info() -> Result<InputDeviceInfo> {
+    let device = default_input_device()?;
+    let configs = device
+        .supported_input_configs()
+        .context("Failed to query input configs")?
+        .map(|config| InputConfigInfo {
+            sample_format: config.sample_format().to_string(),
+            channels: config.channels(),
+            min_sample_rate: config.min_sample_rate().0,
+            max_sample_rate: config.max_sample_rate().0,
+        })
+        .collect();
+    Ok(InputDeviceInfo {
+        name: device.name().unwrap_or_else(|_| "Unknown device".to_string()),
+        configs,
+    })
+}
+
+fn select_input_config(
+    options: MicOptions,
+) -> Result<(cpal::Device, StreamConfig, SampleFormat, Option<usize>)> {
+    let device = default_input_device()?;
     let mut configs = device
         .supported_input_configs()
         .context("Failed to query input configs")?;
@@ -75,159 +200,227 @@ fn select_input_config() -> Result<(cpal::Device, StreamConfig, SampleFormat)> {
         }
     }
 
-    let (config, sample_format) = selected.context("No 48kHz input config available")?;
+    let (config, sample_format) = match selected {
+        Some(selected) => selected,
+        None => {
+            // No native 48kHz support; capture at the device default and resample
+            let default = device
+                .default_input_config()
+                .context("No usable input config available")?;
+            crate::dlog!(
+                "[VC] No 48kHz input config, resampling from {}Hz",
+                default.sample_rate().0
+            );
+            (default.config(), default.sample_format())
+        }
+    };
     if config.channels == 0 {
         return Err(anyhow::anyhow!("Input device reports 0 channels"));
     }
-    Ok((device, config, sample_format))
+    let max_channel = config.channels as usize - 1;
+    let input_channel = options.input_channel.map(|channel| {
+        if channel > max_channel {
+            crate::dlog!("[VC] Input channel {channel} not available, using {max_channel}");
+        }
+        channel.min(max_channel)
+    });
+    Ok((device, config, sample_format, input_channel))
+}
+
+/// Where captured mic audio goes, shared by every sample format.
+struct MicCapture {
+    mic_level: Arc<AtomicU8>,
+    /// Only set when the frames are published, so a mic test doesn't look like it's on air.
+    published_input_level: Option<Arc<AtomicU8>>,
+    frame_tx: Option<mpsc::Sender<Vec<i16>>>,
+    options: MicOptions,
+    input_channel: Option<usize>,
+}
+
+impl MicCapture {
+    /// Build the cpal data callback for samples of type `T`, converted with `to_f32`.
+    fn callback<T: Copy + 'static>(
+        self,
+        input_rate: u32,
+        input_channels: usize,
+        to_f32: fn(T) -> f32,
+    ) -> impl FnMut(&[T], &cpal::InputCallbackInfo) + Send + 'static {
+        let mut assembler = FrameAssembler::new(input_rate, self.options.channels());
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            let mut captured: Vec<f32> = Vec::with_capacity(data.len() / input_channels * 2);
+            for chunk in data.chunks(input_channels) {
+                extract_channels(chunk, self.input_channel, self.options.stereo, to_f32, &mut captured);
+            }
+            update_level_from_f32(&captured, &self.mic_level);
+            // Meter the frames handed to LiveKit; its own processing happens after this
+            for frame in assembler.push(&captured) {
+                if let Some(published_input_level) = self.published_input_level.as_ref() {
+                    update_level_from_i16(&frame, published_input_level);
+                }
+                if let Some(frame_tx) = self.frame_tx.as_ref() {
+                    let _ = frame_tx.try_send(frame);
+                }
+            }
+        }
+    }
+}
+
+/// Open and start a mic stream on the current default input device.
+/// Stream errors are reported through `failed_tx` so the caller can rebuild it.
+fn open_mic_stream(
+    mic_level: &Arc<AtomicU8>,
+    published_input_level: &Option<Arc<AtomicU8>>,
+    frame_tx: &Option<mpsc::Sender<Vec<i16>>>,
+    options: MicOptions,
+    failed_tx: std::sync::mpsc::Sender<()>,
+) -> Result<(cpal::Stream, String)> {
+    let (device, config, sample_format, input_channel) = select_input_config(options)?;
+    let device_name = device.name().unwrap_or_else(|_| "Unknown device".to_string());
+
+    let input_channels = config.channels as usize;
+    let input_rate = config.sample_rate.0;
+    let err_fn = move |err| {
+        crate::dlog!("[VC] Mic stream error: {err}");
+        let _ = failed_tx.send(());
+    };
+
+    let capture = MicCapture {
+        mic_level: mic_level.clone(),
+        published_input_level: published_input_level.clone(),
+        frame_tx: frame_tx.clone(),
+        options,
+        input_channel,
+    };
+    let stream = match sample_format {
+        SampleFormat::I16 => device.build_input_stream(
+            &config,
+            capture.callback(input_rate, input_channels, |s: i16| s as f32 / i16::MAX as f32),
+            err_fn,
+            None,
+        ),
+        SampleFormat::I32 => device.build_input_stream(
+            &config,
+            capture.callback(input_rate, input_channels, |s: i32| s as f32 / i32::MAX as f32),
+            err_fn,
+            None,
+        ),
+        SampleFormat::U16 => device.build_input_stream(
+            &config,
+            capture.callback(input_rate, input_channels, |s: u16| (s as f32 / u16::MAX as f32) * 2.0 - 1.0),
+            err_fn,
+            None,
+        ),
+        SampleFormat::F32 => device.build_input_stream(
+            &config,
+            capture.callback(input_rate, input_channels, |s: f32| s),
+            err_fn,
+            None,
+        ),
+        SampleFormat::F64 => device.build_input_stream(
+            &config,
+            capture.callback(input_rate, input_channels, |s: f64| s as f32),
+            err_fn,
+            None,
+        ),
+        other => return Err(anyhow::anyhow!("Unsupported mic sample format: {other:?}")),
+    }
+    .context("Failed to open mic stream")?;
+
+    stream.play().context("Failed to start mic stream")?;
+    Ok((stream, device_name))
+}
+
+/// Wait up to `timeout` for a shutdown signal. A dropped handle counts as shutdown.
+fn shutdown_requested(shutdown_rx: &std::sync::mpsc::Receiver<()>, timeout: Duration) -> bool {
+    !matches!(
+        shutdown_rx.recv_timeout(timeout),
+        Err(std::sync::mpsc::RecvTimeoutError::Timeout)
+    )
 }
 
 fn spawn_mic_thread(
     mic_level: Arc<AtomicU8>,
+    published_input_level: Option<Arc<AtomicU8>>,
     frame_tx: Option<mpsc::Sender<Vec<i16>>>,
     shutdown_rx: std::sync::mpsc::Receiver<()>,
+    options: MicOptions,
 ) -> std::thread::JoinHandle<()> {
     std::thread::spawn(move || {
-        let (device, config, sample_format) = match select_input_config() {
-            Ok(cfg) => cfg,
-            Err(err) => {
-                crate::dlog!("[VC] Mic config error: {err}");
-                return;
-            }
-        };
+        let mut retry_delay = MIC_RETRY_INITIAL;
+        let mut current_device: Option<String> = None;
+        loop {
+            let (failed_tx, failed_rx) = std::sync::mpsc::channel();
+            match open_mic_stream(&mic_level, &published_input_level, &frame_tx, options, failed_tx) {
+                Ok((stream, device_name)) => {
+                    retry_delay = MIC_RETRY_INITIAL;
+                    if current_device.is_some() {
+                        crate::dlog!("[VC] Mic reconnected on {device_name}");
+                        crate::emit_event("mic-device-changed", device_name.clone());
+                    }
+                    current_device = Some(device_name);
 
-        let input_channels = config.channels as usize;
-        let frame_size = SAMPLES_PER_CHANNEL as usize;
-        let err_fn = |err| crate::dlog!("[VC] Mic stream error: {err}");
-        let frame_tx = frame_tx.clone();
-
-        let stream_result = match sample_format {
-            SampleFormat::I16 => {
-                let mut buffer: Vec<i16> = Vec::with_capacity(frame_size * 2);
-                let mic_level = mic_level.clone();
-                device.build_input_stream(
-                    &config,
-                    move |data: &[i16], _| {
-                        let mut mono_samples: Vec<i16> = Vec::with_capacity(data.len() / input_channels);
-                        for chunk in data.chunks(input_channels) {
-                            let sum = chunk.iter().map(|s| *s as f32).sum::<f32>();
-                            let avg = sum / input_channels as f32;
-                            mono_samples.push(avg.clamp(i16::MIN as f32, i16::MAX as f32) as i16);
+                    // Run until shutdown, or rebuild the stream if it fails
+                    loop {
+                        if shutdown_requested(&shutdown_rx, Duration::from_millis(200)) {
+                            drop(stream);
+                            return;
                         }
-                        update_level_from_i16(&mono_samples, &mic_level);
-                        if let Some(frame_tx) = frame_tx.as_ref() {
-                            buffer.extend_from_slice(&mono_samples);
-                            while buffer.len() >= frame_size {
-                                let frame: Vec<i16> = buffer.drain(..frame_size).collect();
-                                let _ = frame_tx.try_send(frame);
-                            }
-                        }
-                    },
-                    err_fn,
-                    None,
-                )
-            }
-            SampleFormat::F32 => {
-                let mut buffer: Vec<i16> = Vec::with_capacity(frame_size * 2);
-                let mic_level = mic_level.clone();
-                device.build_input_stream(
-                    &config,
-                    move |data: &[f32], _| {
-                        let mut mono_f32: Vec<f32> = Vec::with_capacity(data.len() / input_channels);
-                        for chunk in data.chunks(input_channels) {
-                            let sum = chunk.iter().copied().sum::<f32>();
-                            let avg = sum / input_channels as f32;
-                            mono_f32.push(avg);
-                            buffer.push((avg.clamp(-1.0, 1.0) * i16::MAX as f32) as i16);
-                        }
-                        update_level_from_f32(&mono_f32, &mic_level);
-                        if let Some(frame_tx) = frame_tx.as_ref() {
-                            while buffer.len() >= frame_size {
-                                let frame: Vec<i16> = buffer.drain(..frame_size).collect();
-                                let _ = frame_tx.try_send(frame);
-                            }
-                        } else {
-                            buffer.clear();
-                        }
-                    },
-                    err_fn,
-                    None,
-                )
-            }
-            SampleFormat::U16 => {
-                let mut buffer: Vec<i16> = Vec::with_capacity(frame_size * 2);
-                let mic_level = mic_level.clone();
-                device.build_input_stream(
-                    &config,
-                    move |data: &[u16], _| {
-                        let mut mono_f32: Vec<f32> = Vec::with_capacity(data.len() / input_channels);
-                        for chunk in data.chunks(input_channels) {
-                            let sum = chunk
-                                .iter()
-                                .map(|s| (*s as f32 / u16::MAX as f32) * 2.0 - 1.0)
-                                .sum::<f32>();
-                            let avg = sum / input_channels as f32;
-                            mono_f32.push(avg);
-                            buffer.push((avg.clamp(-1.0, 1.0) * i16::MAX as f32) as i16);
+                        if failed_rx.try_recv().is_ok() {
+                            crate::dlog!("[VC] Mic stream failed, reopening input device");
+                            break;
                         }
-                        update_level_from_f32(&mono_f32, &mic_level);
-                        if let Some(frame_tx) = frame_tx.as_ref() {
-                            while buffer.len() >= frame_size {
-                                let frame: Vec<i16> = buffer.drain(..frame_size).collect();
-                                let _ = frame_tx.try_send(frame);
-                            }
-                        } else {
-                            buffer.clear();
-                        }
-                    },
-                    err_fn,
-                    None,
-                )
-            }
-            _ => {
-                crate::dlog!("[VC] Unsupported mic sample format");
-                return;
+                    }
+                    drop(stream);
+                    mic_level.store(0, Ordering::Relaxed);
+                    if let Some(published_input_level) = published_input_level.as_ref() {
+                        published_input_level.store(0, Ordering::Relaxed);
+                    }
+                }
+                Err(err) => {
+                    crate::dlog!("[VC] Mic unavailable: {err:#}");
+                    if current_device.take().is_some() {
+                        crate::emit_event("mic-device-changed", Option::<String>::None);
+                    } else if retry_delay == MIC_RETRY_INITIAL {
+                        crate::emit_backend_error("voice", format!("Microphone unavailable: {err:#}"));
+                    }
+                }
             }
-        };
 
-        let stream = match stream_result {
-            Ok(stream) => stream,
-            Err(err) => {
-                crate::dlog!("[VC] Failed to open mic stream: {err}");
+            if shutdown_requested(&shutdown_rx, retry_delay) {
                 return;
             }
-        };
-
-        if let Err(err) = stream.play() {
-            crate::dlog!("[VC] Failed to start mic stream: {err}");
-            return;
-        }
-
-        loop {
-            if shutdown_rx.recv_timeout(Duration::from_millis(200)).is_ok() {
-                break;
-            }
+            retry_delay = (retry_delay * 2).min(MIC_RETRY_MAX);
         }
-        drop(stream);
     })
 }
 
 pub async fn start_voice_chat(
     room: Arc<Room>,
     mic_level: Arc<AtomicU8>,
+    published_input_level: Arc<AtomicU8>,
+    options: MicOptions,
 ) -> Result<VoiceChatHandle> {
+    let num_channels = options.channels() as u32;
     let (frame_tx, mut frame_rx) = mpsc::channel::<Vec<i16>>(1024);
     let (shutdown_tx, shutdown_rx) = std::sync::mpsc::channel();
-    let thread = spawn_mic_thread(mic_level, Some(frame_tx), shutdown_rx);
+    let thread = spawn_mic_thread(
+        mic_level,
+        Some(published_input_level.clone()),
+        Some(frame_tx),
+        shutdown_rx,
+        options,
+    );
 
+    // Voice processing would mangle instruments, so stereo line-in is sent untouched
+    let voice_processing = !options.stereo;
     let source = NativeAudioSource::new(
         AudioSourceOptions {
-            echo_cancellation: true,
-            noise_suppression: true,
-            auto_gain_control: true,
+            echo_cancellation: voice_processing,
+            noise_suppression: voice_processing,
+            auto_gain_control: voice_processing,
         },
         SAMPLE_RATE,
-        1,
+        num_channels,
         100,
     );
 
@@ -255,7 +448,7 @@ pub async fn start_voice_chat(
                             let frame = AudioFrame {
                                 data: Cow::Owned(samples),
                                 sample_rate: SAMPLE_RATE,
-                                num_channels: 1,
+                                num_channels,
                                 samples_per_channel: SAMPLES_PER_CHANNEL,
                             };
                             if let Err(e) = source.capture_frame(&frame).await {
@@ -275,6 +468,7 @@ pub async fn start_voice_chat(
         task_shutdown_tx,
         thread,
         task,
+        published_input_level,
     })
 }
 
@@ -283,15 +477,118 @@ pub async fn stop_voice_chat(handle: VoiceChatHandle) {
     let _ = handle.task_shutdown_tx.send(());
     let _ = tokio::task::spawn_blocking(move || handle.thread.join()).await;
     let _ = handle.task.await;
+    handle.published_input_level.store(0, Ordering::Relaxed);
 }
 
-pub fn start_mic_test(mic_level: Arc<AtomicU8>) -> Result<MicTestHandle> {
+/// Play captured frames on the default output until the mic thread drops its sender.
+fn spawn_loopback_thread(mut frame_rx: mpsc::Receiver<Vec<i16>>, channels: u16) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        use rodio::{Sink, buffer::SamplesBuffer, stream::OutputStreamBuilder};
+        let stream = match OutputStreamBuilder::open_default_stream() {
+            Ok(s) => s,
+            Err(e) => {
+                crate::dlog!("[VC] Failed to open audio output for mic loopback: {e}");
+                return;
+            }
+        };
+        let sink = Sink::connect_new(stream.mixer());
+        while let Some(frame) = frame_rx.blocking_recv() {
+            if sink.len() > LOOPBACK_MAX_QUEUED_FRAMES {
+                continue;
+            }
+            let samples: Vec<f32> = frame.iter().map(|&s| s as f32 / 32768.0).collect();
+            sink.append(SamplesBuffer::new(channels, SAMPLE_RATE, samples));
+        }
+        crate::dlog!("[VC] Mic loopback ended");
+    })
+}
+
+/// Meter the mic without publishing it; with `loopback`, also play it back to the user.
+pub fn start_mic_test(
+    mic_level: Arc<AtomicU8>,
+    options: MicOptions,
+    loopback: bool,
+) -> Result<MicTestHandle> {
     let (shutdown_tx, shutdown_rx) = std::sync::mpsc::channel();
-    let thread = spawn_mic_thread(mic_level, None, shutdown_rx);
-    Ok(MicTestHandle { shutdown_tx, thread })
+    let (frame_tx, loopback) = if loopback {
+        let (frame_tx, frame_rx) = mpsc::channel::<Vec<i16>>(64);
+        (Some(frame_tx), Some(spawn_loopback_thread(frame_rx, options.channels() as u16)))
+    } else {
+        (None, None)
+    };
+    let thread = spawn_mic_thread(mic_level, None, frame_tx, shutdown_rx, options);
+    Ok(MicTestHandle { shutdown_tx, thread, loopback })
 }
 
 pub fn stop_mic_test(handle: MicTestHandle) {
     let _ = handle.shutdown_tx.send(());
     let _ = handle.thread.join();
+    if let Some(loopback) = handle.loopback {
+        let _ = loopback.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assembler_passes_48khz_through_in_10ms_frames() {
+        let mut assembler = FrameAssembler::new(SAMPLE_RATE, 1);
+        let frames = assembler.push(&vec![0.5; 1000]);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].len(), SAMPLES_PER_CHANNEL as usize);
+        assert_eq!(frames[0][0], (0.5 * i16::MAX as f32) as i16);
+        // The remainder stays buffered for the next callback
+        assert_eq!(assembler.push(&vec![0.5; 440]).len(), 1);
+    }
+
+    #[test]
+    fn assembler_resamples_44100_to_48000() {
+        let mut assembler = FrameAssembler::new(44_100, 1);
+        let mut frames = 0;
+        // One second of 44.1kHz audio in 10ms callbacks
+        for _ in 0..100 {
+            frames += assembler.push(&vec![0.25; 441]).len();
+        }
+        assert!((99..=100).contains(&frames), "got {frames} frames");
+    }
+
+    #[test]
+    fn stereo_assembler_builds_interleaved_frames() {
+        let mut assembler = FrameAssembler::new(SAMPLE_RATE, 2);
+        let interleaved: Vec<f32> = (0..480).flat_map(|_| [0.5, -0.5]).collect();
+        let frames = assembler.push(&interleaved);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].len(), 2 * SAMPLES_PER_CHANNEL as usize);
+        assert_eq!(frames[0][0], (0.5 * i16::MAX as f32) as i16);
+        assert_eq!(frames[0][1], (-0.5 * i16::MAX as f32) as i16);
+    }
+
+    #[test]
+    fn stereo_extraction_keeps_left_and_right() {
+        let mut out = Vec::new();
+        extract_channels(&[0.1_f32, 0.2, 0.3], None, true, |s| s, &mut out);
+        extract_channels(&[0.1_f32, 0.2, 0.3], Some(1), true, |s| s, &mut out);
+        extract_channels(&[0.4_f32], None, true, |s| s, &mut out);
+        assert_eq!(out, vec![0.1, 0.2, 0.2, 0.3, 0.4, 0.4]);
+    }
+
+    #[test]
+    fn level_from_i16_matches_f32_scale() {
+        let level = AtomicU8::new(0);
+        update_level_from_i16(&[i16::MAX / 2; 480], &level);
+        assert_eq!(level.load(Ordering::Relaxed), 49);
+    }
+
+    #[test]
+    fn downmix_averages_all_channels_by_default() {
+        assert_eq!(downmix(&[0.5_f32, 0.0, 0.0, 0.0], None, |s| s), 0.125);
+    }
+
+    #[test]
+    fn downmix_selects_a_single_channel() {
+        assert_eq!(downmix(&[0.0_f32, 0.8, 0.0, 0.0], Some(1), |s| s), 0.8);
+        assert_eq!(downmix(&[0.1_f32, 0.2], Some(5), |s| s), 0.0);
+    }
 }
@@ -4,16 +4,16 @@
 //! decodes to PCM with symphonia, and streams through a channel for
 //! LiveKit publishing. Queue supports multiple tracks with auto-advance.
 
-use std::collections::{HashMap, HashSet};
-use std::io::Cursor;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use std::sync::{
-    atomic::{AtomicU8, Ordering},
+    atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering},
     Arc, Mutex,
 };
 
+use crate::blacklist::Blacklist;
 use base64::Engine;
 use serde::{Deserialize, Serialize};
 use rusty_ytdl::{Video, VideoOptions, VideoQuality, VideoSearchOptions};
@@ -26,24 +26,102 @@ use symphonia::core::probe::Hint;
 use tokio::io::AsyncWrite;
 use tokio::sync::mpsc;
 
-use crate::audio::{AudioPipeline, DjStatus, NowPlaying, SharedNowPlaying, SharedQueueSnapshot};
+use crate::audio::{
+    clamp_pcm_channel_chunks, AudioPipeline, DjStatus, Ducking, NowPlaying, SharedNowPlaying, SharedQueueSnapshot,
+    DEFAULT_PCM_CHANNEL_CHUNKS, NO_HISTORY, NO_SHARED_QUEUE, SEEK_NEEDS_CACHE,
+};
+
+/// A track being written to the cache as it streams. Data goes to a `.part` file that only
+/// replaces the cache entry once the stream ends cleanly; dropped before then, it's deleted.
+pub struct CacheWriter {
+    file: tokio::fs::File,
+    part_path: std::path::PathBuf,
+    path: std::path::PathBuf,
+    /// Read but not yet accepted by the file.
+    pending: Vec<u8>,
+    /// A write failed, so the copy is incomplete and must not be kept.
+    failed: bool,
+    committed: bool,
+}
+
+impl CacheWriter {
+    async fn create(path: std::path::PathBuf) -> std::io::Result<Self> {
+        let part_path = partial_cache_path(&path);
+        let file = tokio::fs::File::create(&part_path).await?;
+        Ok(Self { file, part_path, path, pending: Vec::new(), failed: false, committed: false })
+    }
+
+    /// Write out as much pending data as the file takes without waiting, then flush.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        while !self.pending.is_empty() {
+            match Pin::new(&mut self.file).poll_write(cx, &self.pending) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(std::io::ErrorKind::WriteZero.into())),
+                Poll::Ready(Ok(n)) => {
+                    self.pending.drain(..n);
+                }
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Pin::new(&mut self.file).poll_flush(cx)
+    }
+
+    fn fail(&mut self, err: std::io::Error) {
+        crate::dlog!("[DJ] Cache write error: {err}");
+        self.failed = true;
+        self.pending = Vec::new();
+    }
+
+    /// Move the finished copy into place.
+    fn commit(&mut self) {
+        match std::fs::rename(&self.part_path, &self.path) {
+            Ok(()) => self.committed = true,
+            Err(err) => self.fail(err),
+        }
+    }
+}
 
-/// Async reader that tees all read data into an async writer (for caching while streaming).
-struct TeeReader<R, W> {
+impl Drop for CacheWriter {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.part_path);
+        }
+    }
+}
+
+/// Where a cache entry is written until it's complete.
+fn partial_cache_path(path: &std::path::Path) -> std::path::PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    part.into()
+}
+
+/// Write a whole cache entry, so a crash part way through never leaves a truncated one.
+fn write_cache_file(path: &std::path::Path, data: &[u8]) -> std::io::Result<()> {
+    let part_path = partial_cache_path(path);
+    std::fs::write(&part_path, data)?;
+    if let Err(err) = std::fs::rename(&part_path, path) {
+        let _ = std::fs::remove_file(&part_path);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Async reader that tees all read data into the cache while streaming.
+struct TeeReader<R> {
     reader: R,
-    writer: W,
+    cache: CacheWriter,
 }
 
-impl<R, W> TeeReader<R, W> {
-    fn new(reader: R, writer: W) -> Self {
-        Self { reader, writer }
+impl<R> TeeReader<R> {
+    fn new(reader: R, cache: CacheWriter) -> Self {
+        Self { reader, cache }
     }
 }
 
-impl<R, W> tokio::io::AsyncRead for TeeReader<R, W>
+impl<R> tokio::io::AsyncRead for TeeReader<R>
 where
     R: tokio::io::AsyncRead + Unpin,
-    W: AsyncWrite + Unpin,
 {
     fn poll_read(
         self: Pin<&mut Self>,
@@ -52,12 +130,27 @@ where
     ) -> Poll<std::io::Result<()>> {
         let this = self.get_mut();
         let before = buf.filled().len();
+        let had_room = buf.remaining() > 0;
         match Pin::new(&mut this.reader).poll_read(cx, buf) {
             Poll::Ready(Ok(())) => {
+                let cache = &mut this.cache;
+                if cache.failed || cache.committed {
+                    return Poll::Ready(Ok(()));
+                }
                 let new_data = &buf.filled()[before..];
                 if !new_data.is_empty() {
-                    // Best-effort write to cache; ignore errors
-                    let _ = Pin::new(&mut this.writer).poll_write(cx, new_data);
+                    // Playback never waits on the cache; whatever the file can't take yet waits here
+                    cache.pending.extend_from_slice(new_data);
+                    if let Poll::Ready(Err(err)) = cache.poll_drain(cx) {
+                        cache.fail(err);
+                    }
+                } else if had_room {
+                    // End of stream: everything must be on disk before the copy replaces the entry
+                    match cache.poll_drain(cx) {
+                        Poll::Pending => return Poll::Pending,
+                        Poll::Ready(Err(err)) => cache.fail(err),
+                        Poll::Ready(Ok(())) => cache.commit(),
+                    }
                 }
                 Poll::Ready(Ok(()))
             }
@@ -75,19 +168,193 @@ pub struct TrackInfo {
 
 /// A streaming audio source: provides PCM data as an async reader.
 pub enum StreamingAudioSource {
-    /// Reading from a cached PCM file.
-    Cached(tokio::fs::File),
+    /// Reading from a cached PCM file, limited to the end of a clip.
+    Cached(tokio::io::Take<tokio::fs::File>),
+    /// PCM decoded on the fly from a compressed cache file, limited to the end of a clip.
+    Decoded(tokio::io::Take<tokio::io::DuplexStream>),
     /// Reading from a live yt-dlp|ffmpeg child process stdout, optionally teeing to cache.
     Process {
         child: tokio::process::Child,
-        cache_writer: Option<tokio::fs::File>,
+        cache_writer: Option<CacheWriter>,
     },
 }
 
+/// Compressed files the cache may hold instead of raw PCM, decoded with symphonia.
+const COMPRESSED_CACHE_EXTENSIONS: &[&str] = &["webm", "m4a", "mp4", "opus", "ogg", "mp3", "flac", "wav"];
+
+/// Buffer between the decoding thread and the playback loop.
+const DECODE_PIPE_BYTES: usize = 256 * 1024;
+
+/// How often a track held by a room pause re-checks for skips, stops and handoffs.
+const ROOM_PAUSE_POLL: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// How long to wait for a killed yt-dlp|ffmpeg pipeline to exit before giving up on reaping it.
+const PIPELINE_REAP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Kill a streaming `sh -c` pipeline, including the yt-dlp and ffmpeg processes under it,
+/// and reap the shell so it doesn't linger as a zombie.
+async fn kill_pipeline(mut child: tokio::process::Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // The shell leads its own process group, so a negative pid signals every member.
+        // SAFETY: kill(2) has no memory-safety preconditions.
+        unsafe {
+            libc::kill(-(pid as libc::pid_t), libc::SIGTERM);
+        }
+    }
+    let _ = child.start_kill();
+    if tokio::time::timeout(PIPELINE_REAP_TIMEOUT, child.wait()).await.is_err() {
+        crate::dlog!("[DJ] yt-dlp|ffmpeg pipeline did not exit after kill");
+    }
+}
+
 /// Info for starting a streaming track.
 pub struct StreamingTrackInfo {
     pub title: String,
+    /// Full length of the track, when yt-dlp reports it or the cache file reveals it.
+    pub duration_secs: Option<u64>,
     pub source: StreamingAudioSource,
+    /// Layout of the s16le PCM `source` yields.
+    pub format: PcmFormat,
+    /// Which path `source` reads from.
+    pub kind: AudioSourceKind,
+}
+
+/// Where a track's audio was served from.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum AudioSourceKind {
+    /// Raw PCM from the cache.
+    Cache,
+    /// A compressed cache file decoded with symphonia.
+    Decoded,
+    /// A live yt-dlp|ffmpeg download.
+    Process,
+}
+
+/// How a track was fetched, for working out why it failed or was slow to start.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackDiagnostic {
+    pub url: String,
+    pub title: Option<String>,
+    /// `None` when the fetch failed.
+    pub source: Option<AudioSourceKind>,
+    pub fetch_ms: u64,
+    pub error: Option<String>,
+    /// Unix seconds when the fetch finished.
+    pub at: u64,
+}
+
+impl TrackDiagnostic {
+    fn from_fetch(
+        url: &str,
+        result: &Result<StreamingTrackInfo, String>,
+        elapsed: std::time::Duration,
+    ) -> Self {
+        let (title, source, error) = match result {
+            Ok(info) => (Some(info.title.clone()), Some(info.kind), None),
+            Err(err) => (None, None, Some(err.clone())),
+        };
+        Self {
+            url: url.to_string(),
+            title,
+            source,
+            fetch_ms: elapsed.as_millis() as u64,
+            error,
+            at: unix_now_secs(),
+        }
+    }
+}
+
+/// Fetches kept for `get_track_diagnostics`.
+const MAX_TRACK_DIAGNOSTICS: usize = 20;
+
+/// The most recent track fetches, newest first.
+#[derive(Default)]
+pub struct TrackDiagnosticLog(Mutex<VecDeque<TrackDiagnostic>>);
+
+impl TrackDiagnosticLog {
+    fn record(&self, entry: TrackDiagnostic) {
+        let mut entries = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        entries.push_front(entry);
+        entries.truncate(MAX_TRACK_DIAGNOSTICS);
+    }
+
+    pub fn recent(&self) -> Vec<TrackDiagnostic> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect()
+    }
+}
+
+/// What a URL would play, looked up without downloading it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackPreview {
+    pub title: String,
+    /// `None` for live streams.
+    pub duration_secs: Option<u64>,
+    pub uploader: Option<String>,
+    pub thumbnail: Option<String>,
+}
+
+/// How long a looked-up preview is reused.
+const PREVIEW_TTL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+const MAX_PREVIEWS: usize = 50;
+
+/// Recent previews by URL, so editing the URL field doesn't rerun yt-dlp for each keystroke.
+#[derive(Default)]
+pub struct TrackPreviewCache(Mutex<HashMap<String, (Instant, TrackPreview)>>);
+
+impl TrackPreviewCache {
+    pub fn get(&self, url: &str, now: Instant) -> Option<TrackPreview> {
+        let entries = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .get(url)
+            .filter(|(at, _)| now.duration_since(*at) < PREVIEW_TTL)
+            .map(|(_, preview)| preview.clone())
+    }
+
+    pub fn insert(&self, url: String, preview: TrackPreview, now: Instant) {
+        let mut entries = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|_, (at, _)| now.duration_since(*at) < PREVIEW_TTL);
+        if entries.len() >= MAX_PREVIEWS {
+            if let Some(oldest) = entries.iter().min_by_key(|(_, (at, _))| *at).map(|(url, _)| url.clone()) {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(url, (now, preview));
+    }
+}
+
+/// Sample rate and channel count of raw s16le PCM.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PcmFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl PcmFormat {
+    /// What the yt-dlp|ffmpeg pipeline produces and LiveKit publishes.
+    pub const STREAM: Self = Self { sample_rate: 48_000, channels: 2 };
+
+    pub fn bytes_per_sec(self) -> u64 {
+        self.sample_rate as u64 * self.channels as u64 * 2
+    }
+
+    /// Byte offset `secs` into a track, or `None` when it's past what a `u64` can address.
+    pub fn offset_bytes(self, secs: u64) -> Option<u64> {
+        secs.checked_mul(self.bytes_per_sec())
+    }
+
+    /// Bytes in a 10ms chunk.
+    fn chunk_bytes(self) -> usize {
+        self.sample_rate as usize / 100 * self.channels as usize * 2
+    }
+}
+
+impl Default for PcmFormat {
+    fn default() -> Self {
+        Self::STREAM
+    }
 }
 
 /// Trait for fetching audio from a URL. Abstraction allows swapping
@@ -159,10 +426,163 @@ impl AudioSource for RustyYtdlSource {
     }
 }
 
+/// Hosts accepted without a scheme, e.g. a pasted `youtu.be/abc`.
+const KNOWN_MEDIA_HOSTS: &[&str] = &[
+    "youtube.com",
+    "www.youtube.com",
+    "m.youtube.com",
+    "music.youtube.com",
+    "youtu.be",
+    "soundcloud.com",
+];
+
+/// Whether `url` looks like something yt-dlp could play: an http(s) URL with a
+/// dotted host, or a known media host without a scheme.
+pub fn is_plausible_media_url(url: &str) -> bool {
+    let url = url.trim();
+    if url.chars().any(char::is_whitespace) {
+        return false;
+    }
+    let lower = url.to_ascii_lowercase();
+    let (rest, has_scheme) = match lower.strip_prefix("https://").or_else(|| lower.strip_prefix("http://")) {
+        Some(rest) => (rest, true),
+        None => (lower.as_str(), false),
+    };
+    let host = rest.split(&['/', '?', '#'][..]).next().unwrap_or_default();
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    let host = host.split(':').next().unwrap_or_default();
+    if has_scheme {
+        host.contains('.') && !host.starts_with('.') && !host.ends_with('.')
+    } else {
+        KNOWN_MEDIA_HOSTS.contains(&host)
+    }
+}
+
+/// Furthest into a track a start offset or clip may reach; far longer than any video.
+const MAX_OFFSET_SECS: u64 = 7 * 24 * 3600;
+
+/// Parse a start offset from a URL's `t` parameter (`?t=90`, `&t=90s`, `#t=1m30s`).
+/// Returns the offset in seconds, or None when absent, malformed, zero or implausibly large.
+pub fn parse_start_offset(url: &str) -> Option<u64> {
+    let value = url
+        .split(&['?', '&', '#'][..])
+        .skip(1)
+        .find_map(|param| param.strip_prefix("t="))?;
+    let total = parse_offset_secs(value)?;
+    (total > 0 && total <= MAX_OFFSET_SECS).then_some(total)
+}
+
+/// Seconds in `90`, `90s` or `1h0m5s`; None when malformed or too big for a `u64`.
+fn parse_offset_secs(value: &str) -> Option<u64> {
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(secs);
+    }
+    let mut total = 0u64;
+    let mut digits = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        let n: u64 = digits.parse().ok()?;
+        total = n.checked_mul(unit).and_then(|secs| total.checked_add(secs))?;
+        digits.clear();
+    }
+    if !digits.is_empty() {
+        return None;
+    }
+    Some(total)
+}
+
+/// Which yt-dlp audio stream to download; lower settings save bandwidth and CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AudioQuality {
+    #[default]
+    Best,
+    Medium,
+    Low,
+}
+
+impl AudioQuality {
+    /// Parse the `audio_quality` setting (`best`, `medium` or `low`).
+    pub fn from_setting(value: &str) -> Option<Self> {
+        match value {
+            "best" => Some(Self::Best),
+            "medium" => Some(Self::Medium),
+            "low" => Some(Self::Low),
+            _ => None,
+        }
+    }
+
+    pub fn as_setting(self) -> &'static str {
+        match self {
+            Self::Best => "best",
+            Self::Medium => "medium",
+            Self::Low => "low",
+        }
+    }
+
+    /// yt-dlp `-f` selector, falling back to any audio if no stream is under the cap.
+    fn format_selector(self) -> &'static str {
+        match self {
+            Self::Best => "bestaudio",
+            Self::Medium => "bestaudio[abr<=128]/bestaudio",
+            Self::Low => "bestaudio[abr<=64]/bestaudio",
+        }
+    }
+
+    /// Appended to cache filenames so each quality is cached separately.
+    /// Empty for `Best` so caches written before this setting existed are still used.
+    fn cache_suffix(self) -> &'static str {
+        match self {
+            Self::Best => "",
+            Self::Medium => "-q128",
+            Self::Low => "-q64",
+        }
+    }
+}
+
+/// `--cookies <path>` for yt-dlp when a cookies file is configured.
+fn cookies_args(cookies: Option<&std::path::Path>) -> Vec<std::ffi::OsString> {
+    match cookies {
+        Some(path) => vec!["--cookies".into(), path.as_os_str().to_owned()],
+        None => Vec::new(),
+    }
+}
+
+/// Same as [`cookies_args`], quoted for the `sh -c` pipelines (with a leading space).
+fn cookies_shell_arg(cookies: Option<&std::path::Path>) -> String {
+    match cookies {
+        Some(path) => format!(" --cookies '{}'", path.to_string_lossy().replace('\'', "'\\''")),
+        None => String::new(),
+    }
+}
+
+/// The configured cookies file, or `None` (with a warning) if it doesn't exist.
+pub fn existing_cookies_path(path: Option<&str>) -> Option<std::path::PathBuf> {
+    let path = path.map(str::trim).filter(|p| !p.is_empty())?;
+    let path = std::path::PathBuf::from(path);
+    if path.is_file() {
+        Some(path)
+    } else {
+        crate::dlog!("WARNING: yt-dlp cookies file not found: {}", path.display());
+        None
+    }
+}
+
 /// YouTube audio source using yt-dlp CLI tool.
 /// Falls back to this when rusty_ytdl fails (e.g. 403 errors).
 pub struct YtDlpSource {
     pub(crate) cache_dir: Option<std::path::PathBuf>,
+    quality: AudioQuality,
+    /// Netscape cookies file passed to yt-dlp for age/region-restricted videos.
+    cookies: Option<std::path::PathBuf>,
 }
 
 impl YtDlpSource {
@@ -171,7 +591,25 @@ impl YtDlpSource {
             let _ = std::fs::create_dir_all(dir);
             crate::dlog!("[DJ] Audio cache dir: {}", dir.display());
         }
-        Self { cache_dir }
+        Self { cache_dir, quality: AudioQuality::default(), cookies: None }
+    }
+
+    pub fn with_quality(mut self, quality: AudioQuality) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    pub fn with_cookies(mut self, cookies: Option<std::path::PathBuf>) -> Self {
+        self.cookies = cookies;
+        self
+    }
+
+    fn set_quality(&mut self, quality: AudioQuality) {
+        self.quality = quality;
+    }
+
+    fn set_cookies(&mut self, cookies: Option<std::path::PathBuf>) {
+        self.cookies = cookies;
     }
 
     /// Extract video ID from YouTube URL for cache key.
@@ -191,14 +629,191 @@ impl YtDlpSource {
     fn cache_path(&self, url: &str) -> Option<std::path::PathBuf> {
         let dir = self.cache_dir.as_ref()?;
         let id = Self::video_id(url)?;
-        Some(dir.join(format!("{id}.pcm")))
+        Some(dir.join(format!("{id}{}.pcm", self.quality.cache_suffix())))
+    }
+
+    /// A compressed cache file for `url`, for caches not written by yt-dlp|ffmpeg.
+    fn compressed_cache_path(&self, url: &str) -> Option<std::path::PathBuf> {
+        let dir = self.cache_dir.as_ref()?;
+        let id = Self::video_id(url)?;
+        COMPRESSED_CACHE_EXTENSIONS.iter().find_map(|ext| {
+            let path = dir.join(format!("{id}{}.{ext}", self.quality.cache_suffix()));
+            path.exists().then_some(path)
+        })
     }
 
     fn title_cache_path(&self, url: &str) -> Option<std::path::PathBuf> {
         let dir = self.cache_dir.as_ref()?;
         let id = Self::video_id(url)?;
-        Some(dir.join(format!("{id}.title")))
+        Some(dir.join(format!("{id}{}.title", self.quality.cache_suffix())))
+    }
+
+    /// Cached PCM for `url`, preferring the current quality over the others.
+    fn cached_pcm_path(&self, url: &str) -> Option<std::path::PathBuf> {
+        let mut qualities = vec![self.quality];
+        qualities.extend(
+            [AudioQuality::Best, AudioQuality::Medium, AudioQuality::Low]
+                .into_iter()
+                .filter(|q| *q != self.quality),
+        );
+        qualities.into_iter().find_map(|quality| {
+            let source = YtDlpSource { cache_dir: self.cache_dir.clone(), quality, cookies: None };
+            source.cache_path(url).filter(|path| path.exists())
+        })
+    }
+
+    /// Export a cached track as WAV, or as FLAC via ffmpeg when `dest` ends in `.flac`.
+    /// Fails if the track isn't cached; nothing is downloaded.
+    pub async fn export_track(&self, url: &str, dest: &std::path::Path) -> Result<(), String> {
+        let pcm_path = self
+            .cached_pcm_path(url)
+            .ok_or_else(|| "Track is not cached; play it first".to_string())?;
+        let is_flac = dest
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("flac"));
+        if is_flac {
+            let output = tokio::process::Command::new("ffmpeg")
+                .args(["-y", "-f", "s16le", "-ar", "48000", "-ac", "2", "-i"])
+                .arg(&pcm_path)
+                .arg(dest)
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::piped())
+                .output()
+                .await
+                .map_err(|e| format!("ffmpeg spawn failed: {e}"))?;
+            if !output.status.success() {
+                return Err(format!(
+                    "ffmpeg failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+        } else {
+            let pcm = tokio::fs::read(&pcm_path)
+                .await
+                .map_err(|e| format!("Cache read error: {e}"))?;
+            let mut wav = wav_header(pcm.len() as u32).to_vec();
+            wav.extend_from_slice(&pcm);
+            tokio::fs::write(dest, wav)
+                .await
+                .map_err(|e| format!("Failed to write {}: {e}", dest.display()))?;
+        }
+        crate::dlog!("[DJ] Exported {} to {}", url, dest.display());
+        Ok(())
+    }
+}
+
+/// Contents of a `.title` cache sidecar. JSON survives newlines and odd characters in titles.
+#[derive(Debug, Serialize, Deserialize)]
+struct TitleSidecar {
+    title: String,
+    /// Layout of the cached PCM; sidecars written before this was recorded are 48kHz stereo.
+    #[serde(default)]
+    format: PcmFormat,
+}
+
+/// Length actually played for `clip`, stopping at the natural end when it runs past the track.
+fn clip_duration(clip: Option<(u64, u64)>, track_secs: Option<u64>) -> Option<u64> {
+    let Some((start, end)) = clip else {
+        return track_secs;
+    };
+    let end = track_secs.map_or(end, |secs| end.min(secs));
+    Some(end.saturating_sub(start))
+}
+
+/// Validate a `(start, end)` clip in seconds.
+pub fn check_clip(clip: (u64, u64)) -> Result<(), String> {
+    if clip.1 <= clip.0 {
+        return Err(format!("Clip end ({}s) must be after its start ({}s)", clip.1, clip.0));
+    }
+    if clip.1 > MAX_OFFSET_SECS {
+        return Err(format!("Clip end ({}s) is past the longest supported track", clip.1));
+    }
+    Ok(())
+}
+
+/// Split yt-dlp's `--print title --print duration` output; live streams print `NA` for the duration.
+fn parse_title_and_duration(stdout: &str) -> (String, Option<u64>) {
+    let mut lines = stdout.lines();
+    let title = lines.next().unwrap_or_default().trim().to_string();
+    let duration = lines
+        .next()
+        .and_then(|line| line.trim().parse::<f64>().ok())
+        .filter(|secs| *secs >= 0.0)
+        .map(|secs| secs.round() as u64);
+    (title, duration)
+}
+
+/// Parse `yt-dlp --print` output: title, duration, uploader and thumbnail lines in that order.
+fn parse_preview_output(stdout: &str) -> Option<TrackPreview> {
+    let mut lines = stdout.lines().map(str::trim);
+    let title = lines.next().filter(|t| !t.is_empty())?.to_string();
+    let duration_secs = lines
+        .next()
+        .and_then(|line| line.parse::<f64>().ok())
+        .filter(|secs| *secs >= 0.0)
+        .map(|secs| secs.round() as u64);
+    let mut optional = || {
+        lines
+            .next()
+            .filter(|v| !v.is_empty() && *v != "NA")
+            .map(str::to_string)
+    };
+    let uploader = optional();
+    let thumbnail = optional();
+    Some(TrackPreview { title, duration_secs, uploader, thumbnail })
+}
+
+/// Record the title of a track cached from the yt-dlp|ffmpeg pipeline.
+fn write_title_cache(path: &std::path::Path, title: &str) {
+    let sidecar = TitleSidecar { title: title.to_string(), format: PcmFormat::STREAM };
+    match serde_json::to_vec(&sidecar) {
+        Ok(content) => {
+            if let Err(e) = std::fs::write(path, content) {
+                crate::dlog!("[DJ] Title cache write error: {e}");
+            }
+        }
+        Err(e) => crate::dlog!("[DJ] Title cache serialize error: {e}"),
+    }
+}
+
+/// Read a cached title, migrating sidecars written as raw text to JSON.
+fn read_title_cache(path: &std::path::Path) -> Option<String> {
+    read_title_sidecar(path).map(|sidecar| sidecar.title)
+}
+
+fn read_title_sidecar(path: &std::path::Path) -> Option<TitleSidecar> {
+    let content = std::fs::read(path).ok()?;
+    if let Ok(sidecar) = serde_json::from_slice::<TitleSidecar>(&content) {
+        return Some(sidecar);
     }
+    let title = String::from_utf8_lossy(&content).trim().to_string();
+    write_title_cache(path, &title);
+    Some(TitleSidecar { title, format: PcmFormat::STREAM })
+}
+
+/// RIFF header for `data_len` bytes of 48kHz 16-bit stereo PCM, matching the cache format.
+fn wav_header(data_len: u32) -> [u8; 44] {
+    const CHANNELS: u16 = 2;
+    const SAMPLE_RATE: u32 = 48_000;
+    const BITS_PER_SAMPLE: u16 = 16;
+    let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+    let byte_rate = SAMPLE_RATE * block_align as u32;
+
+    let mut header = [0u8; 44];
+    header[0..4].copy_from_slice(b"RIFF");
+    header[4..8].copy_from_slice(&data_len.saturating_add(36).to_le_bytes());
+    header[8..12].copy_from_slice(b"WAVE");
+    header[12..16].copy_from_slice(b"fmt ");
+    header[16..20].copy_from_slice(&16u32.to_le_bytes());
+    header[20..22].copy_from_slice(&1u16.to_le_bytes());
+    header[22..24].copy_from_slice(&CHANNELS.to_le_bytes());
+    header[24..28].copy_from_slice(&SAMPLE_RATE.to_le_bytes());
+    header[28..32].copy_from_slice(&byte_rate.to_le_bytes());
+    header[32..34].copy_from_slice(&block_align.to_le_bytes());
+    header[34..36].copy_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+    header[36..40].copy_from_slice(b"data");
+    header[40..44].copy_from_slice(&data_len.to_le_bytes());
+    header
 }
 
 #[async_trait::async_trait]
@@ -209,7 +824,7 @@ impl AudioSource for YtDlpSource {
         // Check cache first
         if let (Some(pcm_path), Some(title_path)) = (self.cache_path(url), self.title_cache_path(url)) {
             if pcm_path.exists() && title_path.exists() {
-                let title = std::fs::read_to_string(&title_path).unwrap_or_else(|_| "Cached".into());
+                let title = read_title_cache(&title_path).unwrap_or_else(|| "Cached".into());
                 let audio_data = std::fs::read(&pcm_path).map_err(|e| format!("Cache read error: {e}"))?;
                 crate::dlog!("[DJ] Cache hit: '{}' ({} bytes)", title.trim(), audio_data.len());
                 return Ok(TrackInfo { title: title.trim().to_string(), audio_data });
@@ -218,6 +833,7 @@ impl AudioSource for YtDlpSource {
 
         // Get title
         let title_output = Command::new("yt-dlp")
+            .args(cookies_args(self.cookies.as_deref()))
             .args(["--get-title", "--no-warnings", url])
             .output()
             .await
@@ -236,7 +852,9 @@ impl AudioSource for YtDlpSource {
             .args([
                 "-c",
                 &format!(
-                    "yt-dlp -f bestaudio -o - --no-warnings --no-progress '{}' | ffmpeg -i pipe:0 -f s16le -acodec pcm_s16le -ar 48000 -ac 2 pipe:1 2>/dev/null",
+                    "yt-dlp{} -f '{}' -o - --no-warnings --no-progress '{}' | ffmpeg -i pipe:0 -f s16le -acodec pcm_s16le -ar 48000 -ac 2 pipe:1 2>/dev/null",
+                    cookies_shell_arg(self.cookies.as_deref()),
+                    self.quality.format_selector(),
                     url.replace('\'', "'\\''")
                 ),
             ])
@@ -254,10 +872,10 @@ impl AudioSource for YtDlpSource {
 
         // Write to cache
         if let (Some(pcm_path), Some(title_path)) = (self.cache_path(url), self.title_cache_path(url)) {
-            if let Err(e) = std::fs::write(&pcm_path, &audio_data) {
+            if let Err(e) = write_cache_file(&pcm_path, &audio_data) {
                 crate::dlog!("[DJ] Cache write error: {e}");
             } else {
-                let _ = std::fs::write(&title_path, &title);
+                write_title_cache(&title_path, &title);
                 crate::dlog!("[DJ] Cached {} bytes for '{}'", audio_data.len(), title);
             }
         }
@@ -268,69 +886,166 @@ impl AudioSource for YtDlpSource {
 
 impl YtDlpSource {
     /// Fetch title for a URL (used before starting streaming).
-    async fn fetch_title(&self, url: &str) -> String {
+    /// Errors only if yt-dlp hangs past `timeout`; other failures fall back to "Unknown".
+    pub async fn fetch_title(&self, url: &str, timeout: std::time::Duration) -> Result<(String, Option<u64>), String> {
         use tokio::process::Command;
-        let title_output = Command::new("yt-dlp")
-            .args(["--get-title", "--no-warnings", url])
-            .output()
-            .await;
-        match title_output {
+        let title_output = tokio::time::timeout(
+            timeout,
+            Command::new("yt-dlp")
+                .args(cookies_args(self.cookies.as_deref()))
+                .args(["--print", "title", "--print", "duration", "--no-warnings", url])
+                .kill_on_drop(true)
+                .output(),
+        )
+        .await
+        .map_err(|_| format!("yt-dlp title lookup timed out after {}s", timeout.as_secs()))?;
+        Ok(match title_output {
             Ok(output) if output.status.success() => {
-                String::from_utf8_lossy(&output.stdout).trim().to_string()
+                parse_title_and_duration(&String::from_utf8_lossy(&output.stdout))
             }
-            _ => "Unknown".to_string(),
+            _ => ("Unknown".to_string(), None),
+        })
+    }
+
+    /// Look up title, duration, uploader and thumbnail without downloading any audio.
+    pub async fn fetch_preview(&self, url: &str, timeout: std::time::Duration) -> Result<TrackPreview, String> {
+        use tokio::process::Command;
+        let output = tokio::time::timeout(
+            timeout,
+            Command::new("yt-dlp")
+                .args(cookies_args(self.cookies.as_deref()))
+                .args([
+                    "--skip-download",
+                    "--no-playlist",
+                    "--print", "%(title)s",
+                    "--print", "%(duration)s",
+                    "--print", "%(uploader)s",
+                    "--print", "%(thumbnail)s",
+                    "--no-warnings",
+                    url,
+                ])
+                .kill_on_drop(true)
+                .output(),
+        )
+        .await
+        .map_err(|_| format!("yt-dlp lookup timed out after {}s", timeout.as_secs()))?
+        .map_err(|e| format!("Failed to run yt-dlp: {e}"))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let reason = stderr.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("unknown error");
+            return Err(format!("Couldn't look up {url}: {}", reason.trim()));
         }
+        parse_preview_output(&String::from_utf8_lossy(&output.stdout))
+            .ok_or_else(|| format!("yt-dlp found nothing to play at {url}"))
     }
 
     /// Start streaming audio as PCM. Returns title + streaming source.
     /// If cached, streams from the cached file. Otherwise spawns yt-dlp|ffmpeg
-    /// and tees output to cache.
-    pub async fn fetch_audio_streaming(&self, url: &str) -> Result<StreamingTrackInfo, String> {
+    /// and tees output to cache. Honours a `clip`, or else a `t` start offset in the URL.
+    pub async fn fetch_audio_streaming(
+        &self,
+        url: &str,
+        clip: Option<(u64, u64)>,
+        timeout: std::time::Duration,
+    ) -> Result<StreamingTrackInfo, String> {
+        use tokio::io::AsyncReadExt;
         use tokio::process::Command;
 
+        let (start_offset, clip_end) = match clip {
+            Some((start, end)) => (start, Some(end)),
+            None => (parse_start_offset(url).unwrap_or(0), None),
+        };
+
         // Check cache first
         if let (Some(pcm_path), Some(title_path)) = (self.cache_path(url), self.title_cache_path(url)) {
             if pcm_path.exists() && title_path.exists() {
-                let title = std::fs::read_to_string(&title_path).unwrap_or_else(|_| "Cached".into());
-                let title = title.trim().to_string();
+                let (title, format) = match read_title_sidecar(&title_path) {
+                    Some(sidecar) => (sidecar.title.trim().to_string(), sidecar.format),
+                    None => ("Cached".to_string(), PcmFormat::STREAM),
+                };
                 crate::dlog!("[DJ] Cache hit (streaming): '{}'", title);
-                let file = tokio::fs::File::open(&pcm_path)
+                touch_cache_file(&pcm_path);
+                let duration_secs = tokio::fs::metadata(&pcm_path)
                     .await
-                    .map_err(|e| format!("Cache open error: {e}"))?;
+                    .ok()
+                    .map(|m| m.len() / format.bytes_per_sec());
+                let file = open_pcm_at(&pcm_path, start_offset, clip_end, format).await?;
                 return Ok(StreamingTrackInfo {
                     title,
+                    duration_secs: clip_duration(clip, duration_secs),
                     source: StreamingAudioSource::Cached(file),
+                    format,
+                    kind: AudioSourceKind::Cache,
+                });
+            }
+        }
+
+        // Raw PCM is the fast path; other cached formats are decoded as they play
+        if let (Some(path), Some(title_path)) = (self.compressed_cache_path(url), self.title_cache_path(url)) {
+            if title_path.exists() {
+                let title = read_title_sidecar(&title_path)
+                    .map(|sidecar| sidecar.title.trim().to_string())
+                    .unwrap_or_else(|| "Cached".to_string());
+                crate::dlog!("[DJ] Cache hit (decoding {}): '{}'", path.display(), title);
+                let (stream, format, duration_secs) = stream_decoded_file(&path, start_offset)?;
+                let limit = clip_end
+                    .and_then(|end| format.offset_bytes(end.saturating_sub(start_offset)))
+                    .unwrap_or(u64::MAX);
+                return Ok(StreamingTrackInfo {
+                    title,
+                    duration_secs: clip_duration(clip, duration_secs),
+                    source: StreamingAudioSource::Decoded(stream.take(limit)),
+                    format,
+                    kind: AudioSourceKind::Decoded,
                 });
             }
         }
 
         // Get title first
-        let title = self.fetch_title(url).await;
+        let (title, duration_secs) = self.fetch_title(url, timeout).await?;
         crate::dlog!("[DJ] yt-dlp streaming title: '{}'", title);
 
         // Save title to cache
         if let Some(title_path) = self.title_cache_path(url) {
-            let _ = std::fs::write(&title_path, &title);
+            write_title_cache(&title_path, &title);
         }
 
+        // Output-side `-t` is the clip length; ffmpeg stops early if the track ends first
+        let clip_length = clip_end
+            .map(|end| format!(" -t {}", end.saturating_sub(start_offset)))
+            .unwrap_or_default();
+
         // Spawn yt-dlp|ffmpeg process for streaming PCM
-        let child = Command::new("sh")
+        let mut command = Command::new("sh");
+        command
             .args([
                 "-c",
                 &format!(
-                    "yt-dlp -f bestaudio -o - --no-warnings --no-progress '{}' | ffmpeg -i pipe:0 -f s16le -acodec pcm_s16le -ar 48000 -ac 2 pipe:1 2>/dev/null",
-                    url.replace('\'', "'\\''")
+                    "yt-dlp{} -f '{}' -o - --no-warnings --no-progress '{}' | ffmpeg -i pipe:0 -ss {}{} -f s16le -acodec pcm_s16le -ar 48000 -ac 2 pipe:1 2>/dev/null",
+                    cookies_shell_arg(self.cookies.as_deref()),
+                    self.quality.format_selector(),
+                    url.replace('\'', "'\\''"),
+                    start_offset,
+                    clip_length
                 ),
             ])
             .stdout(std::process::Stdio::piped())
             .stderr(std::process::Stdio::null())
+            .kill_on_drop(true);
+        // Own process group so the whole pipeline can be killed, not just `sh`
+        #[cfg(unix)]
+        command.process_group(0);
+        let child = command
             .spawn()
             .map_err(|e| format!("yt-dlp|ffmpeg spawn failed: {e}"))?;
 
-        // Open cache file for writing if we have a cache path
-        let cache_writer = if let Some(pcm_path) = self.cache_path(url) {
-            match tokio::fs::File::create(&pcm_path).await {
-                Ok(f) => Some(f),
+        // Open cache file for writing if we have a cache path.
+        // An offset or clipped stream is missing part of the track, so it isn't cached.
+        let cache_writer = if start_offset > 0 || clip_end.is_some() {
+            None
+        } else if let Some(pcm_path) = self.cache_path(url) {
+            match CacheWriter::create(pcm_path).await {
+                Ok(writer) => Some(writer),
                 Err(e) => {
                     crate::dlog!("[DJ] Cache file create error: {e}");
                     None
@@ -342,94 +1057,266 @@ impl YtDlpSource {
 
         Ok(StreamingTrackInfo {
             title,
+            duration_secs: clip_duration(clip, duration_secs),
             source: StreamingAudioSource::Process { child, cache_writer },
+            format: PcmFormat::STREAM,
+            kind: AudioSourceKind::Process,
         })
     }
 }
 
-/// Decode raw audio bytes (webm/mp4/opus) to interleaved PCM i16 samples.
-/// Returns (samples, sample_rate, channels).
-/// Currently unused — yt-dlp|ffmpeg outputs PCM directly — kept for rusty_ytdl fallback.
-#[allow(dead_code)]
-pub fn decode_audio_to_pcm(
-    data: Vec<u8>,
-) -> Result<(Vec<i16>, u32, u16), String> {
-    let cursor = Cursor::new(data);
-    let mss = MediaSourceStream::new(Box::new(cursor), Default::default());
+/// Open cached PCM `secs` into the track, stopping at `clip_end` when set.
+async fn open_pcm_at(
+    path: &std::path::Path,
+    secs: u64,
+    clip_end: Option<u64>,
+    format: PcmFormat,
+) -> Result<tokio::io::Take<tokio::fs::File>, String> {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| format!("Cache open error: {e}"))?;
+    if secs > 0 {
+        let offset = format.offset_bytes(secs).ok_or_else(|| format!("Can't seek to {secs}s"))?;
+        file.seek(std::io::SeekFrom::Start(offset))
+            .await
+            .map_err(|e| format!("Cache seek error: {e}"))?;
+    }
+    // An end past the cached length just reads to the end of the file
+    let limit = clip_end
+        .and_then(|end| format.offset_bytes(end.saturating_sub(secs)))
+        .unwrap_or(u64::MAX);
+    Ok(file.take(limit))
+}
 
-    let hint = Hint::new();
-    let format_opts = FormatOptions::default();
-    let metadata_opts = MetadataOptions::default();
+/// Seek requests and position for the track being streamed.
+#[derive(Default)]
+struct SeekControl {
+    /// Only tracks streamed from the PCM cache can seek.
+    seekable: AtomicBool,
+    /// Requested position in seconds from the start of the track.
+    pending: Mutex<Option<u64>>,
+    /// Seconds into the current track that playback has reached.
+    position_secs: AtomicU64,
+    /// Length of the current track, when known; requests past it go to the end.
+    duration_secs: Mutex<Option<u64>>,
+    /// Furthest second the current track's PCM byte offsets can address.
+    max_secs: AtomicU64,
+}
 
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &format_opts, &metadata_opts)
-        .map_err(|e| format!("Failed to probe audio format: {e}"))?;
+impl SeekControl {
+    /// Reset for a new track starting `start_secs` in.
+    fn start_track(&self, seekable: bool, start_secs: u64, duration_secs: Option<u64>, format: PcmFormat) {
+        self.seekable.store(seekable, Ordering::Relaxed);
+        self.position_secs.store(start_secs, Ordering::Relaxed);
+        *self.duration_secs.lock().unwrap_or_else(|e| e.into_inner()) = duration_secs;
+        self.max_secs.store(u64::MAX / format.bytes_per_sec().max(1), Ordering::Relaxed);
+        *self.pending.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
 
-    let mut format = probed.format;
+    fn request(&self, secs: u64) -> Result<(), String> {
+        if !self.seekable.load(Ordering::Relaxed) {
+            return Err(SEEK_NEEDS_CACHE.to_string());
+        }
+        let duration = *self.duration_secs.lock().unwrap_or_else(|e| e.into_inner());
+        let secs = duration.map_or(secs, |duration| secs.min(duration));
+        if secs > self.max_secs.load(Ordering::Relaxed) {
+            return Err(format!("Can't seek to {secs}s"));
+        }
+        *self.pending.lock().unwrap_or_else(|e| e.into_inner()) = Some(secs);
+        Ok(())
+    }
 
-    let track = format
-        .tracks()
-        .iter()
-        .find(|t| t.codec_params.codec != symphonia::core::codecs::CODEC_TYPE_NULL)
-        .ok_or("No supported audio track found")?;
-
-    let track_id = track.id;
-    let sample_rate = track
-        .codec_params
-        .sample_rate
-        .ok_or("No sample rate in track")?;
-    let channels = track
-        .codec_params
-        .channels
-        .map(|c| c.count() as u16)
-        .unwrap_or(2);
-
-    let dec_opts = DecoderOptions::default();
-    let mut decoder = symphonia::default::get_codecs()
-        .make(&track.codec_params, &dec_opts)
-        .map_err(|e| format!("Failed to create decoder: {e}"))?;
-
-    let mut all_samples: Vec<i16> = Vec::new();
-    let mut sample_buf: Option<SampleBuffer<i16>> = None;
+    fn take_pending(&self) -> Option<u64> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+}
 
-    loop {
-        let packet = match format.next_packet() {
-            Ok(packet) => packet,
-            Err(symphonia::core::errors::Error::IoError(_)) => break,
-            Err(e) => return Err(format!("Packet read error: {e}")),
-        };
+/// Packet-by-packet symphonia decoder producing interleaved i16 PCM.
+struct PcmDecoder {
+    format: Box<dyn symphonia::core::formats::FormatReader>,
+    decoder: Box<dyn symphonia::core::codecs::Decoder>,
+    track_id: u32,
+    pcm_format: PcmFormat,
+    duration_secs: Option<u64>,
+    sample_buf: Option<SampleBuffer<i16>>,
+}
 
-        if packet.track_id() != track_id {
-            continue;
+impl PcmDecoder {
+    /// Probe `source`, using `extension` as a hint alongside the magic bytes.
+    fn open(
+        source: Box<dyn symphonia::core::io::MediaSource>,
+        extension: Option<&str>,
+    ) -> Result<Self, String> {
+        let mss = MediaSourceStream::new(source, Default::default());
+
+        let mut hint = Hint::new();
+        if let Some(extension) = extension {
+            hint.with_extension(extension);
         }
+        let format_opts = FormatOptions::default();
+        let metadata_opts = MetadataOptions::default();
+
+        let probed = symphonia::default::get_probe()
+            .format(&hint, mss, &format_opts, &metadata_opts)
+            .map_err(|e| format!("Failed to probe audio format: {e}"))?;
+
+        let format = probed.format;
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|t| t.codec_params.codec != symphonia::core::codecs::CODEC_TYPE_NULL)
+            .ok_or("No supported audio track found")?;
+
+        let track_id = track.id;
+        let sample_rate = track
+            .codec_params
+            .sample_rate
+            .ok_or("No sample rate in track")?;
+        let channels = track
+            .codec_params
+            .channels
+            .map(|c| c.count() as u16)
+            .unwrap_or(2);
+        let duration_secs = track.codec_params.n_frames.map(|frames| frames / u64::from(sample_rate));
+
+        let dec_opts = DecoderOptions::default();
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &dec_opts)
+            .map_err(|e| format!("Failed to create decoder: {e}"))?;
+
+        Ok(Self {
+            format,
+            decoder,
+            track_id,
+            pcm_format: PcmFormat { sample_rate, channels },
+            duration_secs,
+            sample_buf: None,
+        })
+    }
 
-        let decoded = match decoder.decode(&packet) {
-            Ok(decoded) => decoded,
-            Err(symphonia::core::errors::Error::DecodeError(_)) => continue,
-            Err(e) => return Err(format!("Decode error: {e}")),
-        };
+    /// Samples from the next decodable packet, or `None` at the end of the stream.
+    fn next_samples(&mut self) -> Result<Option<&[i16]>, String> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(symphonia::core::errors::Error::IoError(_)) => return Ok(None),
+                Err(e) => return Err(format!("Packet read error: {e}")),
+            };
 
-        if sample_buf.is_none() {
-            sample_buf = Some(SampleBuffer::<i16>::new(
-                decoded.capacity() as u64,
-                *decoded.spec(),
-            ));
-        }
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                Err(symphonia::core::errors::Error::DecodeError(_)) => continue,
+                Err(e) => return Err(format!("Decode error: {e}")),
+            };
 
-        if let Some(buf) = &mut sample_buf {
+            let buf = self.sample_buf.get_or_insert_with(|| {
+                SampleBuffer::<i16>::new(decoded.capacity() as u64, *decoded.spec())
+            });
             buf.copy_interleaved_ref(decoded);
-            all_samples.extend_from_slice(buf.samples());
+            return Ok(Some(buf.samples()));
         }
     }
+}
 
-    crate::dlog!(
-        "[DJ] Decoded {} PCM samples ({}Hz, {} channels)",
-        all_samples.len(),
-        sample_rate,
-        channels
-    );
+/// Converts interleaved PCM to `PcmFormat::STREAM`, which is all the publisher sends.
+/// Mono is duplicated, extra channels are dropped and other rates are resampled linearly,
+/// carrying the last frame across packets so there are no seams.
+struct StreamConverter {
+    from: PcmFormat,
+    /// Where the next output frame falls between `prev` (0.0) and the next input frame (1.0).
+    pos: f64,
+    prev: Option<[i16; 2]>,
+    out: Vec<i16>,
+}
+
+impl StreamConverter {
+    fn new(from: PcmFormat) -> Self {
+        Self { from, pos: 0.0, prev: None, out: Vec::new() }
+    }
+
+    fn convert(&mut self, samples: &[i16]) -> &[i16] {
+        self.out.clear();
+        let channels = usize::from(self.from.channels.max(1));
+        let frames = samples.chunks_exact(channels).map(|frame| match frame {
+            [mono] => [*mono, *mono],
+            [left, right, ..] => [*left, *right],
+            [] => [0, 0],
+        });
+        if self.from.sample_rate == PcmFormat::STREAM.sample_rate {
+            self.out.extend(frames.flatten());
+            return &self.out;
+        }
+        let step = f64::from(self.from.sample_rate) / f64::from(PcmFormat::STREAM.sample_rate);
+        for frame in frames {
+            let Some(prev) = self.prev.replace(frame) else {
+                continue;
+            };
+            while self.pos < 1.0 {
+                for (a, b) in prev.iter().zip(frame) {
+                    self.out.push((f64::from(*a) + (f64::from(b) - f64::from(*a)) * self.pos).round() as i16);
+                }
+                self.pos += step;
+            }
+            self.pos -= 1.0;
+        }
+        &self.out
+    }
+}
 
-    Ok((all_samples, sample_rate, channels))
+/// Decode a compressed file on a blocking thread, streaming `PcmFormat::STREAM` bytes from
+/// `start_offset` seconds. Returns the stream, its format and the track length when known.
+fn stream_decoded_file(
+    path: &std::path::Path,
+    start_offset: u64,
+) -> Result<(tokio::io::DuplexStream, PcmFormat, Option<u64>), String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("Cache open error: {e}"))?;
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    let mut decoder = PcmDecoder::open(Box::new(file), extension)?;
+    if decoder.pcm_format != PcmFormat::STREAM {
+        crate::dlog!(
+            "[DJ] Converting cached track from {}Hz/{}ch",
+            decoder.pcm_format.sample_rate,
+            decoder.pcm_format.channels
+        );
+    }
+    let mut converter = StreamConverter::new(decoder.pcm_format);
+    let format = PcmFormat::STREAM;
+    let duration_secs = decoder.duration_secs;
+
+    let (mut writer, reader) = tokio::io::duplex(DECODE_PIPE_BYTES);
+    let runtime = tokio::runtime::Handle::current();
+    let mut skip = start_offset.saturating_mul(format.bytes_per_sec());
+    tokio::task::spawn_blocking(move || {
+        use tokio::io::AsyncWriteExt;
+        loop {
+            let samples = match decoder.next_samples() {
+                Ok(Some(samples)) => samples,
+                Ok(None) => break,
+                Err(err) => {
+                    crate::dlog!("[DJ] Decoding cached track failed: {err}");
+                    break;
+                }
+            };
+            let mut bytes: Vec<u8> = converter.convert(samples).iter().flat_map(|s| s.to_le_bytes()).collect();
+            if skip > 0 {
+                let skipped = skip.min(bytes.len() as u64);
+                bytes.drain(..skipped as usize);
+                skip -= skipped;
+            }
+            // Fails once the playback loop drops the reader on skip or stop
+            if !bytes.is_empty() && runtime.block_on(writer.write_all(&bytes)).is_err() {
+                break;
+            }
+        }
+    });
+    Ok((reader, format, duration_secs))
 }
 
 /// A queued track.
@@ -439,15 +1326,197 @@ pub struct QueuedTrack {
     #[allow(dead_code)]
     pub title: String,
     pub queued_id: Option<u64>,
+    /// Id of a track in the local queue, which has no event id; stable across moves.
+    pub local_id: Option<u64>,
     pub queued_by: Option<String>,
+    pub artist: Option<String>,
+    pub thumbnail: Option<String>,
+    /// Unix seconds when the track was queued, if known.
+    pub queued_at: Option<u64>,
+    /// Start and end seconds to play instead of the whole track.
+    pub clip: Option<(u64, u64)>,
 }
 
-#[derive(Debug, Clone)]
-struct SharedQueueConfig {
-    repo: String,
-    path: String,
-    state_path: std::path::PathBuf,
+/// Track details recorded by `metadata` events.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct TrackMetadata {
+    title: String,
+    artist: Option<String>,
+    thumbnail: Option<String>,
+}
+
+/// Files the `buckets` shard strategy spreads events over.
+const SHARD_BUCKETS: u64 = 4;
+
+/// Where new shared queue events are written. Everyone in a room should use the same one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShardStrategy {
+    /// Every event is appended to the configured queue file.
+    #[default]
+    Single,
+    /// Each event goes to a random `<file stem>/NNNN.ndjson` bucket, so concurrent
+    /// writers rarely race on the same file.
+    Buckets,
+}
+
+impl ShardStrategy {
+    /// Parse the `shared_queue_sharding` setting (`single` or `buckets`).
+    pub fn from_setting(value: &str) -> Option<Self> {
+        match value {
+            "single" => Some(Self::Single),
+            "buckets" => Some(Self::Buckets),
+            _ => None,
+        }
+    }
+
+    pub fn as_setting(self) -> &'static str {
+        match self {
+            Self::Single => "single",
+            Self::Buckets => "buckets",
+        }
+    }
+}
+
+/// Directory holding the shards of the queue file at `path`: the path without its extension.
+pub(crate) fn shard_dir(path: &str) -> String {
+    match path.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && !ext.contains('/') => stem.to_string(),
+        _ => format!("{path}.shards"),
+    }
+}
+
+fn shard_path(path: &str, bucket: u64) -> String {
+    format!("{}/{bucket:04}.ndjson", shard_dir(path))
+}
+
+/// Smallest id above `max_id` owned by `bucket`, so two buckets never hand out the same id.
+fn next_bucket_id(max_id: u64, bucket: u64) -> u64 {
+    let next = max_id + 1;
+    next + (bucket + SHARD_BUCKETS - next % SHARD_BUCKETS) % SHARD_BUCKETS
+}
+
+fn random_bucket() -> u64 {
+    (uuid::Uuid::new_v4().as_u128() % u128::from(SHARD_BUCKETS)) as u64
+}
+
+/// How often a queue read as a single file looks for shards someone else started writing.
+const SHARD_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// What reads of the shared queue have turned up, shared by every copy of a config.
+#[derive(Debug, Default)]
+struct SharedQueueLog {
+    /// Highest event id in each shard, by path, along with the sha it was read at.
+    shard_max_ids: Mutex<HashMap<String, (String, u64)>>,
+    /// Set once shards turn up, whatever this client's own strategy is.
+    sharded: AtomicBool,
+    last_shard_check: Mutex<Option<Instant>>,
+}
+
+impl SharedQueueLog {
+    fn remember_shard(&self, path: &str, sha: &str, content: &str) {
+        let mut known = self.shard_max_ids.lock().unwrap_or_else(|e| e.into_inner());
+        known.insert(path.to_string(), (sha.to_string(), max_event_id(content)));
+    }
+
+    /// Highest event id across `listing` (shard path and sha). Shards whose sha changed
+    /// since they were last seen are fetched again with `read`; the rest come from memory.
+    fn shards_max_id<F>(&self, listing: &[(String, String)], mut read: F) -> Result<u64, String>
+    where
+        F: FnMut(&str) -> Result<String, String>,
+    {
+        let mut max_id = 0;
+        for (path, sha) in listing {
+            let known = self
+                .shard_max_ids
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(path)
+                .filter(|(seen_sha, _)| seen_sha == sha)
+                .map(|(_, id)| *id);
+            let shard_max = match known {
+                Some(id) => id,
+                None => {
+                    let content = read(path)?;
+                    self.remember_shard(path, sha, &content);
+                    max_event_id(&content)
+                }
+            };
+            max_id = max_id.max(shard_max);
+        }
+        Ok(max_id)
+    }
+
+    /// Whether a read should list the shard directory: always once shards are known,
+    /// otherwise every `SHARD_CHECK_INTERVAL`.
+    fn shard_check_due(&self, now: Instant) -> bool {
+        if self.sharded.load(Ordering::Relaxed) {
+            return true;
+        }
+        let mut last = self.last_shard_check.lock().unwrap_or_else(|e| e.into_inner());
+        if last.is_some_and(|at| now.duration_since(at) < SHARD_CHECK_INTERVAL) {
+            return false;
+        }
+        *last = Some(now);
+        true
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SharedQueueConfig {
+    repo: String,
+    path: String,
+    state_path: std::path::PathBuf,
     gh_path: String,
+    sharding: Arc<Mutex<ShardStrategy>>,
+    participants: RoomParticipants,
+    log: Arc<SharedQueueLog>,
+}
+
+impl SharedQueueConfig {
+    /// None when the repo or file is blank, which disables the shared queue.
+    fn new(
+        repo: String,
+        path: String,
+        gh_path: String,
+        state_path: std::path::PathBuf,
+        sharding: Arc<Mutex<ShardStrategy>>,
+        participants: RoomParticipants,
+    ) -> Option<Self> {
+        if repo.trim().is_empty() || path.trim().is_empty() {
+            return None;
+        }
+        Some(Self {
+            repo,
+            path,
+            state_path,
+            gh_path: if gh_path.trim().is_empty() {
+                "gh".to_string()
+            } else {
+                gh_path
+            },
+            sharding,
+            participants,
+            log: Arc::default(),
+        })
+    }
+
+    fn sharding(&self) -> ShardStrategy {
+        *self.sharding.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn participants(&self) -> Option<Vec<String>> {
+        self.participants.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// Identities in the LiveKit room; `None` while not connected, when presence is unknown.
+type RoomParticipants = Arc<Mutex<Option<Vec<String>>>>;
+
+/// The live shared queue config; swapped out when the user points at another repo.
+type SharedQueueHandle = Arc<Mutex<Option<SharedQueueConfig>>>;
+
+fn current_shared_queue(handle: &Mutex<Option<SharedQueueConfig>>) -> Option<SharedQueueConfig> {
+    handle.lock().unwrap_or_else(|e| e.into_inner()).clone()
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -455,6 +1524,14 @@ struct SharedQueueState {
     last_seen_id: u64,
 }
 
+/// Summary of shared queue events appended since this client last synced.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct QueueUpdate {
+    pub max_id: u64,
+    pub event_types: Vec<String>,
+}
+
 #[derive(Debug, Deserialize)]
 struct QueueEvent {
     id: u64,
@@ -466,6 +1543,17 @@ struct QueueEvent {
     #[serde(rename = "ref")]
     ref_id: Option<u64>,
     order: Option<Vec<u64>>,
+    dj_identity: Option<String>,
+    artist: Option<String>,
+    thumbnail: Option<String>,
+    /// Client-generated id on `queued` events so a retried append isn't counted twice.
+    uuid: Option<String>,
+    /// Unix seconds when the event was written; older logs don't have it.
+    at: Option<u64>,
+    /// Start and end seconds on `queued` events for a clip.
+    clip: Option<(u64, u64)>,
+    /// Track length in seconds on `playing` events, when the DJ knew it.
+    duration: Option<u64>,
 }
 
 #[derive(Debug, Clone)]
@@ -473,16 +1561,30 @@ struct SharedNowPlayingInternal {
     title: String,
     url: String,
     queued_id: Option<u64>,
+    dj_identity: Option<String>,
+    artist: Option<String>,
+    thumbnail: Option<String>,
+    clip: Option<(u64, u64)>,
+    queued_by: Option<String>,
+    /// Unix seconds from the `playing` event; missing in logs written before it was recorded.
+    started_at: Option<u64>,
+    duration_secs: Option<u64>,
 }
 
 #[derive(Debug, Clone)]
-struct SharedQueueData {
+pub(crate) struct SharedQueueData {
     items: Vec<QueuedTrack>,
     now_playing: Option<SharedNowPlayingInternal>,
     max_id: u64,
     skip_events: HashMap<u64, u64>,
     needs_metadata: Vec<(u64, String)>,
-    history: Vec<(String, Option<String>, Option<String>)>,
+    history: Vec<(String, Option<TrackMetadata>, Option<String>)>,
+    /// Identity of whoever most recently claimed DJ duty for the room.
+    active_dj: Option<String>,
+    /// Set by a `pause` event until the next `resume`.
+    paused: bool,
+    /// Event types in the log this version doesn't understand, written by newer clients.
+    unknown_event_types: BTreeSet<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -492,9 +1594,217 @@ struct RepoFileResponse {
     sha: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct RepoDirEntry {
+    path: String,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    sha: String,
+}
+
+/// Default seconds between shared queue refetches while in polling mode.
+const DEFAULT_QUEUE_POLL_INTERVAL_SECS: u64 = 10;
+const MIN_QUEUE_POLL_INTERVAL_SECS: u64 = 2;
+const MAX_QUEUE_POLL_INTERVAL_SECS: u64 = 300;
+/// Default seconds between checks for remote skips while streaming in polling mode.
+const DEFAULT_SKIP_CHECK_INTERVAL_SECS: u64 = 2;
+const MIN_SKIP_CHECK_INTERVAL_SECS: u64 = 1;
+const MAX_SKIP_CHECK_INTERVAL_SECS: u64 = 60;
+/// Default seconds between webhook websocket pings.
+const DEFAULT_WEBHOOK_PING_INTERVAL_SECS: u64 = 30;
+const MIN_WEBHOOK_PING_INTERVAL_SECS: u64 = 5;
+const MAX_WEBHOOK_PING_INTERVAL_SECS: u64 = 300;
+
+/// Maximum number of decoded tracks kept in the audio cache.
+pub const CACHE_LIMIT: usize = 10;
+/// Seconds yt-dlp may go without producing a title or audio before the track is failed.
+pub const DEFAULT_STREAM_TIMEOUT_SECS: u64 = 30;
+const MIN_STREAM_TIMEOUT_SECS: u64 = 5;
+const MAX_STREAM_TIMEOUT_SECS: u64 = 300;
+
+pub fn clamp_stream_timeout_secs(secs: u64) -> u64 {
+    secs.clamp(MIN_STREAM_TIMEOUT_SECS, MAX_STREAM_TIMEOUT_SECS)
+}
+
+/// Extra attempts at starting a track's stream before it is failed.
+pub const DEFAULT_LOAD_RETRIES: usize = 2;
+const MAX_LOAD_RETRIES: usize = 5;
+/// Wait before the first load retry; doubled for each one after.
+const LOAD_RETRY_BACKOFF: std::time::Duration = std::time::Duration::from_secs(1);
+
+pub fn clamp_load_retries(retries: usize) -> usize {
+    retries.min(MAX_LOAD_RETRIES)
+}
+
+/// How long the playback loop has been waiting on an empty queue.
+#[derive(Default)]
+struct IdleTimer {
+    since: Option<Instant>,
+}
+
+impl IdleTimer {
+    /// Note another idle check; true once idle for longer than `timeout_secs` (`0` never expires).
+    fn expired(&mut self, now: Instant, timeout_secs: u64) -> bool {
+        let since = *self.since.get_or_insert(now);
+        timeout_secs > 0 && now.duration_since(since) >= std::time::Duration::from_secs(timeout_secs)
+    }
+
+    fn reset(&mut self) {
+        self.since = None;
+    }
+}
+
+/// Result of trying to start a track's stream.
+enum LoadOutcome<T> {
+    Loaded(T),
+    Failed(String),
+    /// A skip or stop arrived while loading.
+    Cancelled,
+}
+
+/// Run `load` until it succeeds or `retries` more attempts have failed, backing off
+/// between attempts. A skip or stop on `skip_rx` gives up straight away.
+async fn load_with_retries<T, F, Fut>(
+    retries: usize,
+    backoff: std::time::Duration,
+    skip_rx: &mut tokio::sync::watch::Receiver<bool>,
+    mut load: F,
+) -> LoadOutcome<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, String>>,
+{
+    let mut attempt = 0;
+    loop {
+        let result = tokio::select! {
+            result = load() => result,
+            Ok(()) = skip_rx.changed() => return LoadOutcome::Cancelled,
+        };
+        let err = match result {
+            Ok(loaded) => return LoadOutcome::Loaded(loaded),
+            Err(err) => err,
+        };
+        if attempt >= retries {
+            return LoadOutcome::Failed(err);
+        }
+        let delay = backoff * 2u32.pow(attempt as u32);
+        crate::dlog!("[DJ] Load failed ({err}), retrying in {}ms", delay.as_millis());
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            Ok(()) = skip_rx.changed() => return LoadOutcome::Cancelled,
+        }
+        attempt += 1;
+    }
+}
+
+/// Upcoming tracks downloaded ahead of time by default.
+pub const DEFAULT_PREFETCH_DEPTH: usize = 2;
+
+/// Limit prefetching so the prefetched tracks and the one playing all fit in the cache.
+pub fn clamp_prefetch_depth(depth: usize) -> usize {
+    depth.min(CACHE_LIMIT - 1)
+}
+
+/// Prefetch downloads run in parallel by default.
+pub const DEFAULT_PREFETCH_CONCURRENCY: usize = 2;
+const MAX_PREFETCH_CONCURRENCY: usize = 4;
+
+/// Keep at least one prefetch running and cap parallel yt-dlp processes.
+pub fn clamp_prefetch_concurrency(concurrency: usize) -> usize {
+    concurrency.clamp(1, MAX_PREFETCH_CONCURRENCY)
+}
+
+/// What woke up the shared queue sync task.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SyncTrigger {
+    /// A webhook push reported a change to the queue file.
+    Push,
+    /// The periodic poll timer fired.
+    Tick,
+}
+
+/// Whether shared queue updates arrive via webhook pushes or periodic polling.
+/// Polls until a webhook connects, and again whenever it drops.
+#[derive(Debug)]
+pub struct QueueSyncMode {
+    polling: AtomicBool,
+    poll_interval_secs: AtomicU64,
+    skip_check_interval_secs: AtomicU64,
+    ping_interval_secs: AtomicU64,
+}
+
+impl Default for QueueSyncMode {
+    fn default() -> Self {
+        Self {
+            polling: AtomicBool::new(true),
+            poll_interval_secs: AtomicU64::new(DEFAULT_QUEUE_POLL_INTERVAL_SECS),
+            skip_check_interval_secs: AtomicU64::new(DEFAULT_SKIP_CHECK_INTERVAL_SECS),
+            ping_interval_secs: AtomicU64::new(DEFAULT_WEBHOOK_PING_INTERVAL_SECS),
+        }
+    }
+}
+
+impl QueueSyncMode {
+    /// Set the polling interval, clamped to a sane range. Returns the value applied.
+    pub fn set_poll_interval_secs(&self, secs: u64) -> u64 {
+        let secs = secs.clamp(MIN_QUEUE_POLL_INTERVAL_SECS, MAX_QUEUE_POLL_INTERVAL_SECS);
+        self.poll_interval_secs.store(secs, Ordering::Relaxed);
+        secs
+    }
+
+    fn poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.poll_interval_secs.load(Ordering::Relaxed))
+    }
+
+    /// Set how often a streaming DJ polls for remote skips, clamped. Returns the value applied.
+    pub fn set_skip_check_interval_secs(&self, secs: u64) -> u64 {
+        let secs = secs.clamp(MIN_SKIP_CHECK_INTERVAL_SECS, MAX_SKIP_CHECK_INTERVAL_SECS);
+        self.skip_check_interval_secs.store(secs, Ordering::Relaxed);
+        secs
+    }
+
+    fn skip_check_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.skip_check_interval_secs.load(Ordering::Relaxed))
+    }
+
+    /// Set how often the webhook websocket is pinged, clamped. Returns the value applied.
+    pub fn set_ping_interval_secs(&self, secs: u64) -> u64 {
+        let secs = secs.clamp(MIN_WEBHOOK_PING_INTERVAL_SECS, MAX_WEBHOOK_PING_INTERVAL_SECS);
+        self.ping_interval_secs.store(secs, Ordering::Relaxed);
+        secs
+    }
+
+    pub fn ping_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.ping_interval_secs.load(Ordering::Relaxed))
+    }
+
+    pub fn set_polling(&self, enabled: bool) {
+        if self.polling.swap(enabled, Ordering::SeqCst) != enabled {
+            crate::dlog!(
+                "[Queue] Switched to {} updates",
+                if enabled { "polling" } else { "webhook" }
+            );
+        }
+    }
+
+    pub fn is_polling(&self) -> bool {
+        self.polling.load(Ordering::SeqCst)
+    }
+
+    /// Webhook pushes always sync; poll ticks only sync in polling mode.
+    fn should_sync(&self, trigger: SyncTrigger) -> bool {
+        match trigger {
+            SyncTrigger::Push => true,
+            SyncTrigger::Tick => self.is_polling(),
+        }
+    }
+}
+
 /// Audio pipeline backed by YouTube audio via rusty_ytdl.
 pub struct YouTubePipeline {
-    status: Arc<Mutex<DjStatus>>,
+    /// Current status; subscribers are told about every transition.
+    status: Arc<tokio::sync::watch::Sender<DjStatus>>,
     volume: Arc<AtomicU8>,
     queue: Arc<Mutex<Vec<QueuedTrack>>>,
     active: Arc<Mutex<bool>>,
@@ -505,8 +1815,40 @@ pub struct YouTubePipeline {
     local_playback_disabled: Arc<std::sync::atomic::AtomicBool>,
     loop_running: Arc<std::sync::atomic::AtomicBool>,
     cache_dir: Option<std::path::PathBuf>,
-    shared_queue: Option<SharedQueueConfig>,
+    shared_queue: SharedQueueHandle,
+    shared_state_path: Option<std::path::PathBuf>,
     shared_queue_updates: Option<tokio::sync::broadcast::Sender<()>>,
+    /// LiveKit identity of the local participant, used for DJ claims.
+    local_identity: Arc<Mutex<Option<String>>>,
+    /// Most recent DJ claim seen in the shared queue.
+    active_dj: Arc<Mutex<Option<String>>>,
+    sync_mode: Arc<QueueSyncMode>,
+    /// Whether the webhook is live, so pushes can stand in for timed skip checks.
+    webhook_connected: Arc<AtomicBool>,
+    prefetch_depth: Arc<AtomicUsize>,
+    prefetch_concurrency: Arc<AtomicUsize>,
+    blacklist: Arc<Blacklist>,
+    stream_timeout_secs: Arc<AtomicU64>,
+    load_retries: Arc<AtomicUsize>,
+    idle_timeout_secs: Arc<AtomicU64>,
+    audio_quality: Arc<Mutex<AudioQuality>>,
+    /// Track being streamed, for the now-playing view when there is no shared queue.
+    current_track: Arc<Mutex<Option<QueuedTrack>>>,
+    seek: Arc<SeekControl>,
+    ducking: Arc<Ducking>,
+    /// Average source throughput in bytes/sec for the current track; `0` when idle.
+    stream_throughput: Arc<AtomicU64>,
+    track_diagnostics: Arc<TrackDiagnosticLog>,
+    cookies_path: Arc<Mutex<Option<std::path::PathBuf>>>,
+    shard_strategy: Arc<Mutex<ShardStrategy>>,
+    room_participants: RoomParticipants,
+    /// Last id handed out to a track in the local queue.
+    last_local_id: AtomicU64,
+    /// Set to the number of PCM chunks sent so far on every skip, so the publisher drops
+    /// the old track's buffered audio but keeps the faded tail that follows.
+    pcm_flush: Arc<tokio::sync::watch::Sender<u64>>,
+    /// PCM chunks handed to the publisher; counted before each send.
+    pcm_sent: Arc<AtomicU64>,
 }
 
 impl YouTubePipeline {
@@ -521,30 +1863,25 @@ impl YouTubePipeline {
         shared_queue_defaults: Option<(String, String, String)>,
         shared_queue_updates: Option<tokio::sync::broadcast::Sender<()>>,
     ) -> Self {
-        let (pcm_tx, pcm_rx) = mpsc::channel(1024);
+        let (pcm_tx, pcm_rx) = mpsc::channel(DEFAULT_PCM_CHANNEL_CHUNKS);
         let default_repo = shared_queue_defaults.as_ref().map(|(repo, _, _)| repo.clone());
         let default_path = shared_queue_defaults.as_ref().map(|(_, path, _)| path.clone());
         let default_gh = shared_queue_defaults.as_ref().map(|(_, _, gh)| gh.clone());
+        let shard_strategy = Arc::new(Mutex::new(ShardStrategy::default()));
+        let room_participants = RoomParticipants::default();
         let shared_queue = match (
             std::env::var("GEZELLIG_SHARED_QUEUE_REPO").ok().or(default_repo),
             std::env::var("GEZELLIG_SHARED_QUEUE_FILE").ok().or(default_path),
             std::env::var("GEZELLIG_GH_PATH").ok().or(default_gh),
-            shared_state_path,
+            shared_state_path.clone(),
         ) {
-            (Some(repo), Some(path), Some(gh_path), Some(state_path)) => Some(SharedQueueConfig {
-                repo,
-                path,
-                state_path,
-                gh_path: if gh_path.trim().is_empty() {
-                    "gh".to_string()
-                } else {
-                    gh_path
-                },
-            }),
+            (Some(repo), Some(path), Some(gh_path), Some(state_path)) => {
+                SharedQueueConfig::new(repo, path, gh_path, state_path, shard_strategy.clone(), room_participants.clone())
+            }
             _ => None,
         };
         Self {
-            status: Arc::new(Mutex::new(DjStatus::Idle)),
+            status: Arc::new(tokio::sync::watch::Sender::new(DjStatus::Idle)),
             volume: Arc::new(AtomicU8::new(50)),
             queue: Arc::new(Mutex::new(Vec::new())),
             active: Arc::new(Mutex::new(false)),
@@ -554,9 +1891,178 @@ impl YouTubePipeline {
             local_playback_disabled: Arc::new(std::sync::atomic::AtomicBool::new(false)),
             loop_running: Arc::new(std::sync::atomic::AtomicBool::new(false)),
             cache_dir,
-            shared_queue,
+            shared_queue: Arc::new(Mutex::new(shared_queue)),
+            shared_state_path,
             shared_queue_updates,
+            local_identity: Arc::new(Mutex::new(None)),
+            active_dj: Arc::new(Mutex::new(None)),
+            sync_mode: Arc::new(QueueSyncMode::default()),
+            webhook_connected: Arc::new(AtomicBool::new(false)),
+            prefetch_depth: Arc::new(AtomicUsize::new(DEFAULT_PREFETCH_DEPTH)),
+            prefetch_concurrency: Arc::new(AtomicUsize::new(DEFAULT_PREFETCH_CONCURRENCY)),
+            blacklist: Arc::new(Blacklist::default()),
+            stream_timeout_secs: Arc::new(AtomicU64::new(DEFAULT_STREAM_TIMEOUT_SECS)),
+            load_retries: Arc::new(AtomicUsize::new(DEFAULT_LOAD_RETRIES)),
+            idle_timeout_secs: Arc::new(AtomicU64::new(0)),
+            audio_quality: Arc::new(Mutex::new(AudioQuality::default())),
+            current_track: Arc::new(Mutex::new(None)),
+            seek: Arc::new(SeekControl::default()),
+            ducking: Arc::new(Ducking::default()),
+            stream_throughput: Arc::new(AtomicU64::new(0)),
+            track_diagnostics: Arc::new(TrackDiagnosticLog::default()),
+            cookies_path: Arc::new(Mutex::new(None)),
+            shard_strategy,
+            room_participants,
+            last_local_id: AtomicU64::new(0),
+            pcm_flush: Arc::new(tokio::sync::watch::Sender::new(0)),
+            pcm_sent: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Buffer `chunks` of PCM for the publisher instead of the default.
+    pub fn with_pcm_capacity(mut self, chunks: usize) -> Self {
+        let (pcm_tx, pcm_rx) = mpsc::channel(clamp_pcm_channel_chunks(chunks));
+        self.pcm_sender = pcm_tx;
+        self.pcm_receiver = Mutex::new(Some(pcm_rx));
+        self.pcm_sent = Arc::new(AtomicU64::new(0));
+        self
+    }
+
+    /// Share the skip signal the LiveKit publisher watches to flush stale audio.
+    pub fn with_pcm_flush(mut self, pcm_flush: Arc<tokio::sync::watch::Sender<u64>>) -> Self {
+        self.pcm_flush = pcm_flush;
+        self
+    }
+
+    /// Share the queue sync mode with the webhook listener.
+    pub fn with_sync_mode(mut self, sync_mode: Arc<QueueSyncMode>) -> Self {
+        self.sync_mode = sync_mode;
+        self
+    }
+
+    /// Share whether the shared queue webhook is connected.
+    pub fn with_webhook_connected(mut self, webhook_connected: Arc<AtomicBool>) -> Self {
+        self.webhook_connected = webhook_connected;
+        self
+    }
+
+    /// Share the number of upcoming tracks to prefetch; `0` disables prefetching.
+    pub fn with_prefetch_depth(mut self, prefetch_depth: Arc<AtomicUsize>) -> Self {
+        self.prefetch_depth = prefetch_depth;
+        self
+    }
+
+    /// Share how many prefetch downloads may run at once.
+    pub fn with_prefetch_concurrency(mut self, prefetch_concurrency: Arc<AtomicUsize>) -> Self {
+        self.prefetch_concurrency = prefetch_concurrency;
+        self
+    }
+
+    /// Share the list of URLs that are marked failed instead of streamed.
+    pub fn with_blacklist(mut self, blacklist: Arc<Blacklist>) -> Self {
+        self.blacklist = blacklist;
+        self
+    }
+
+    /// Share how long a stalled yt-dlp may hang before its track is failed.
+    pub fn with_stream_timeout(mut self, stream_timeout_secs: Arc<AtomicU64>) -> Self {
+        self.stream_timeout_secs = stream_timeout_secs;
+        self
+    }
+
+    /// Share how often a failed track load is retried so it can be changed at runtime.
+    pub fn with_load_retries(mut self, load_retries: Arc<AtomicUsize>) -> Self {
+        self.load_retries = load_retries;
+        self
+    }
+
+    /// Share how long an empty queue may idle before the loop stops itself.
+    pub fn with_idle_timeout(mut self, idle_timeout_secs: Arc<AtomicU64>) -> Self {
+        self.idle_timeout_secs = idle_timeout_secs;
+        self
+    }
+
+    /// Share the yt-dlp audio quality so it can be changed between tracks.
+    pub fn with_audio_quality(mut self, audio_quality: Arc<Mutex<AudioQuality>>) -> Self {
+        self.audio_quality = audio_quality;
+        self
+    }
+
+    /// Share the yt-dlp cookies file so it can be changed between tracks.
+    pub fn with_cookies_path(mut self, cookies_path: Arc<Mutex<Option<std::path::PathBuf>>>) -> Self {
+        self.cookies_path = cookies_path;
+        self
+    }
+
+    /// Share the measured stream throughput for diagnostics.
+    pub fn with_stream_throughput(mut self, stream_throughput: Arc<AtomicU64>) -> Self {
+        self.stream_throughput = stream_throughput;
+        self
+    }
+
+    /// Share the ducking state LiveKit updates while people talk.
+    pub fn with_ducking(mut self, ducking: Arc<Ducking>) -> Self {
+        self.ducking = ducking;
+        self
+    }
+
+    /// Share the log of recent track fetches for diagnostics.
+    pub fn with_track_diagnostics(mut self, track_diagnostics: Arc<TrackDiagnosticLog>) -> Self {
+        self.track_diagnostics = track_diagnostics;
+        self
+    }
+
+    /// Share where new shared queue events are written so it can be changed at runtime.
+    pub fn with_shard_strategy(mut self, shard_strategy: Arc<Mutex<ShardStrategy>>) -> Self {
+        if let Some(cfg) = self.shared_queue.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            cfg.sharding = shard_strategy.clone();
+        }
+        self.shard_strategy = shard_strategy;
+        self
+    }
+
+    fn shared_queue_config(&self) -> Option<SharedQueueConfig> {
+        current_shared_queue(&self.shared_queue)
+    }
+
+    /// Validate and queue a track, at the front when `next` is set.
+    fn enqueue(&self, url: String, queued_by: Option<String>, clip: Option<(u64, u64)>, next: bool) -> Result<(), String> {
+        let url = url.trim().to_string();
+        if url.is_empty() {
+            return Err("Enter a URL to queue".to_string());
+        }
+        if !is_plausible_media_url(&url) {
+            return Err(format!("Not a playable URL: {url}"));
+        }
+        if let Some(clip) = clip {
+            check_clip(clip)?;
+        }
+        if let Some(cfg) = self.shared_queue_config().as_ref() {
+            if next {
+                append_queued_next_events(cfg, &url, queued_by.as_deref(), clip)?;
+            } else {
+                append_queue_event(cfg, &url, queued_by.as_deref(), clip)?;
+            }
+            return Ok(());
+        }
+        let track = QueuedTrack {
+            url,
+            title: "Loading...".to_string(),
+            queued_id: None,
+            local_id: Some(self.last_local_id.fetch_add(1, Ordering::Relaxed) + 1),
+            queued_by,
+            artist: None,
+            thumbnail: None,
+            queued_at: Some(unix_now_secs()),
+            clip,
+        };
+        let mut queue = self.queue.lock().map_err(|e| e.to_string())?;
+        if next {
+            queue.insert(0, track);
+        } else {
+            queue.push(track);
         }
+        Ok(())
     }
 }
 
@@ -588,6 +2094,25 @@ impl AudioPipeline for YouTubePipeline {
             let volume = self.volume.clone();
             let shared_queue = self.shared_queue.clone();
             let shared_queue_updates = self.shared_queue_updates.clone();
+            let local_identity = self.local_identity.clone();
+            let active_dj = self.active_dj.clone();
+            let sync_mode = self.sync_mode.clone();
+            let webhook_connected = self.webhook_connected.clone();
+            let prefetch_depth = self.prefetch_depth.clone();
+            let prefetch_concurrency = self.prefetch_concurrency.clone();
+            let blacklist = self.blacklist.clone();
+            let stream_timeout_secs = self.stream_timeout_secs.clone();
+            let load_retries = self.load_retries.clone();
+            let idle_timeout_secs = self.idle_timeout_secs.clone();
+            let audio_quality = self.audio_quality.clone();
+            let current_track = self.current_track.clone();
+            let seek = self.seek.clone();
+            let ducking = self.ducking.clone();
+            let stream_throughput = self.stream_throughput.clone();
+            let track_diagnostics = self.track_diagnostics.clone();
+            let cookies_path = self.cookies_path.clone();
+            let pcm_flush = self.pcm_flush.clone();
+            let pcm_sent = self.pcm_sent.clone();
 
             tokio::spawn(async move {
                 run_playback_loop(
@@ -601,6 +2126,25 @@ impl AudioPipeline for YouTubePipeline {
                     volume,
                     shared_queue,
                     shared_queue_updates,
+                    local_identity,
+                    active_dj,
+                    sync_mode,
+                    webhook_connected,
+                    prefetch_depth,
+                    prefetch_concurrency,
+                    blacklist,
+                    stream_timeout_secs,
+                    load_retries,
+                    idle_timeout_secs,
+                    audio_quality,
+                    current_track,
+                    seek,
+                    ducking,
+                    stream_throughput,
+                    track_diagnostics,
+                    cookies_path,
+                    pcm_flush,
+                    pcm_sent,
                 )
                 .await;
                 crate::dlog!("[DJ] Playback loop ended");
@@ -617,16 +2161,14 @@ impl AudioPipeline for YouTubePipeline {
             let mut active = self.active.lock().map_err(|e| e.to_string())?;
             *active = false;
         }
-        // Signal skip to break out of any current playback
+        // Signal skip to break out of any current playback; the publisher keeps its
+        // buffered audio so a drain can still send it
         if let Ok(tx) = self.skip_tx.lock() {
             if let Some(tx) = tx.as_ref() {
                 let _ = tx.send(true);
             }
         }
-        {
-            let mut status = self.status.lock().map_err(|e| e.to_string())?;
-            *status = DjStatus::Idle;
-        }
+        self.status.send_replace(DjStatus::Idle);
         {
             let mut queue = self.queue.lock().map_err(|e| e.to_string())?;
             queue.clear();
@@ -635,7 +2177,11 @@ impl AudioPipeline for YouTubePipeline {
     }
 
     fn status(&self) -> DjStatus {
-        self.status.lock().unwrap_or_else(|e| e.into_inner()).clone()
+        self.status.borrow().clone()
+    }
+
+    fn subscribe_status(&self) -> Option<tokio::sync::watch::Receiver<DjStatus>> {
+        Some(self.status.subscribe())
     }
 
     fn set_volume(&self, volume: u8) -> Result<(), String> {
@@ -647,24 +2193,16 @@ impl AudioPipeline for YouTubePipeline {
         self.volume.load(Ordering::Relaxed)
     }
 
-    fn queue_track(&self, url: String, queued_by: Option<String>) -> Result<(), String> {
-        if let Some(cfg) = self.shared_queue.as_ref() {
-            let _ = append_queue_event(cfg, &url, queued_by.as_deref())?;
-            return Ok(());
-        }
-        let track = QueuedTrack {
-            url,
-            title: "Loading...".to_string(),
-            queued_id: None,
-            queued_by,
-        };
-        let mut queue = self.queue.lock().map_err(|e| e.to_string())?;
-        queue.push(track);
-        Ok(())
+    fn queue_track(&self, url: String, queued_by: Option<String>, clip: Option<(u64, u64)>) -> Result<(), String> {
+        self.enqueue(url, queued_by, clip, false)
+    }
+
+    fn queue_track_next(&self, url: String, queued_by: Option<String>, clip: Option<(u64, u64)>) -> Result<(), String> {
+        self.enqueue(url, queued_by, clip, true)
     }
 
     fn skip_track(&self) -> Result<(), String> {
-        if let Some(cfg) = self.shared_queue.as_ref() {
+        if let Some(cfg) = self.shared_queue_config().as_ref() {
             let data = fetch_shared_queue_data(cfg)?;
             if let Some(now) = data.now_playing {
                 if let Some(queued_id) = now.queued_id {
@@ -672,6 +2210,8 @@ impl AudioPipeline for YouTubePipeline {
                 }
             }
         }
+        // Flush before the playback loop notices, which may be stuck on a slow read
+        self.pcm_flush.send_replace(self.pcm_sent.load(Ordering::Relaxed));
         if let Ok(tx) = self.skip_tx.lock() {
             if let Some(tx) = tx.as_ref() {
                 let _ = tx.send(true);
@@ -680,25 +2220,115 @@ impl AudioPipeline for YouTubePipeline {
         Ok(())
     }
 
-    fn get_queue(&self) -> Vec<String> {
+    /// Only the shared queue keeps history; the local queue has nothing to go back to.
+    fn play_previous(&self) -> Result<(), String> {
+        let Some(cfg) = self.shared_queue_config() else {
+            return Err(NO_HISTORY.to_string());
+        };
+        let data = fetch_shared_queue_data(&cfg)?;
+        let Some((url, _, queued_by)) = data.history.first().cloned() else {
+            return Err(NO_HISTORY.to_string());
+        };
+        let requeued_id = append_queue_event(&cfg, &url, queued_by.as_deref(), None)?;
+        append_reorder_event(&cfg, order_with_first(requeued_id, &data.items))?;
+        self.skip_track()
+    }
+
+    /// With a shared queue configured the local queue is only a mirror kept by the
+    /// sync tasks, so the shared log is read instead. A failed read gives an empty
+    /// queue rather than a possibly stale mirror.
+    fn get_queue(&self) -> Result<Vec<String>, String> {
+        if let Some(cfg) = self.shared_queue_config() {
+            return Ok(fetch_shared_queue_data(&cfg)?.items.into_iter().map(|t| t.url).collect());
+        }
+        let queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(queue.iter().map(|t| t.url.clone()).collect())
+    }
+
+    fn shared_queue_snapshot(&self) -> Result<Option<SharedQueueSnapshot>, String> {
+        let Some(cfg) = self.shared_queue_config() else {
+            return Ok(None);
+        };
+        fetch_shared_queue_data(&cfg).map(|data| Some(shared_queue_snapshot_from_data(data)))
+    }
+
+    fn local_queue(&self) -> Result<Vec<crate::audio::SharedQueueItem>, String> {
         let queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
-        queue.iter().map(|t| t.url.clone()).collect()
+        Ok(queue
+            .iter()
+            .map(|t| crate::audio::SharedQueueItem {
+                url: t.url.clone(),
+                title: (t.title != "Loading...").then(|| t.title.clone()),
+                id: t.local_id.unwrap_or(0),
+                queued_by: t.queued_by.clone(),
+                artist: t.artist.clone(),
+                thumbnail: t.thumbnail.clone(),
+                queued_at: t.queued_at,
+                clip: t.clip,
+            })
+            .collect())
+    }
+
+    fn local_now_playing(&self) -> Option<SharedNowPlaying> {
+        let (DjStatus::Playing(now) | DjStatus::Paused(now)) = self.status() else {
+            return None;
+        };
+        let track = self.current_track.lock().unwrap_or_else(|e| e.into_inner()).clone()?;
+        Some(SharedNowPlaying {
+            title: now.track,
+            url: track.url,
+            artist: track.artist,
+            thumbnail: track.thumbnail,
+            queued_by: track.queued_by,
+        })
+    }
+
+    fn seek(&self, secs: u64) -> Result<(), String> {
+        if !matches!(self.status(), DjStatus::Playing(_) | DjStatus::Paused(_)) {
+            return Err("Nothing is playing".to_string());
+        }
+        self.seek.request(secs)
+    }
+
+    fn playback_position(&self) -> Option<u64> {
+        matches!(self.status(), DjStatus::Playing(_) | DjStatus::Paused(_))
+            .then(|| self.seek.position_secs.load(Ordering::Relaxed))
+    }
+
+    fn unknown_event_types(&self) -> Result<Vec<String>, String> {
+        let Some(cfg) = self.shared_queue_config() else {
+            return Ok(Vec::new());
+        };
+        Ok(fetch_shared_queue_data(&cfg)?.unknown_event_types.into_iter().collect())
     }
 
-    fn shared_queue(&self) -> Option<Vec<String>> {
-        let cfg = self.shared_queue.as_ref()?;
-        fetch_shared_queue_data(cfg)
-            .ok()
-            .map(|data| data.items.into_iter().map(|t| t.url).collect())
+    fn shared_queue_raw(&self) -> Result<Vec<String>, String> {
+        let Some(cfg) = self.shared_queue_config() else {
+            return Ok(Vec::new());
+        };
+        let content = read_queue_log(&cfg, cfg.sharding() == ShardStrategy::Buckets)?;
+        Ok(content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_string)
+            .collect())
     }
 
-    fn shared_queue_snapshot(&self) -> Option<SharedQueueSnapshot> {
-        let cfg = self.shared_queue.as_ref()?;
-        fetch_shared_queue_data(cfg).ok().map(shared_queue_snapshot_from_data)
+    fn initialize_shared_queue(&self) -> Result<bool, String> {
+        let cfg = self.shared_queue_config().ok_or_else(|| NO_SHARED_QUEUE.to_string())?;
+        match read_repo_file(&cfg, &cfg.path) {
+            Ok(_) => Ok(false),
+            Err(err) if is_not_found(&err) => {
+                write_repo_file(&cfg, &cfg.path, "", None)?;
+                crate::dlog!("[Queue] Created {} in {}", cfg.path, cfg.repo);
+                Ok(true)
+            }
+            Err(err) => Err(err),
+        }
     }
 
     fn clear_shared_queue(&self) -> Result<(), String> {
-        if let Some(cfg) = self.shared_queue.as_ref() {
+        if let Some(cfg) = self.shared_queue_config().as_ref() {
             if let Ok(data) = fetch_shared_queue_data(cfg) {
                 if let Some(now) = data.now_playing {
                     if let Some(queued_id) = now.queued_id {
@@ -706,11 +2336,8 @@ impl AudioPipeline for YouTubePipeline {
                     }
                 }
             }
-            append_cleared_event(cfg)?;
-        } else {
-            let mut queue = self.queue.lock().map_err(|e| e.to_string())?;
-            queue.clear();
         }
+        self.clear_upcoming()?;
         if let Ok(tx) = self.skip_tx.lock() {
             if let Some(tx) = tx.as_ref() {
                 let _ = tx.send(true);
@@ -719,148 +2346,725 @@ impl AudioPipeline for YouTubePipeline {
         Ok(())
     }
 
+    fn pause_room(&self) -> Result<(), String> {
+        let cfg = self.shared_queue_config().ok_or_else(|| NO_SHARED_QUEUE.to_string())?;
+        append_room_event(&cfg, "pause").map(|_| ())
+    }
+
+    fn resume_room(&self) -> Result<(), String> {
+        let cfg = self.shared_queue_config().ok_or_else(|| NO_SHARED_QUEUE.to_string())?;
+        append_room_event(&cfg, "resume").map(|_| ())
+    }
+
+    fn clear_upcoming(&self) -> Result<(), String> {
+        if let Some(cfg) = self.shared_queue_config().as_ref() {
+            append_cleared_event(cfg)?;
+        } else {
+            let mut queue = self.queue.lock().map_err(|e| e.to_string())?;
+            queue.clear();
+        }
+        Ok(())
+    }
+
     fn take_pcm_receiver(&self) -> Option<mpsc::Receiver<Vec<u8>>> {
         self.pcm_receiver.lock().ok()?.take()
     }
 
+    fn pcm_channels(&self) -> u16 {
+        PcmFormat::STREAM.channels
+    }
+
     fn set_local_playback(&self, enabled: bool) {
         self.local_playback_disabled.store(!enabled, Ordering::Relaxed);
     }
 
     fn reorder_queue(&self, order: Vec<u64>) -> Result<(), String> {
-        if let Some(cfg) = self.shared_queue.as_ref() {
+        if let Some(cfg) = self.shared_queue_config().as_ref() {
             append_reorder_event(cfg, order)?;
         }
         Ok(())
     }
-}
 
-/// The main playback loop: pops tracks from the queue, fetches, decodes, streams PCM.
-async fn run_playback_loop(
-    queue: Arc<Mutex<Vec<QueuedTrack>>>,
-    status: Arc<Mutex<DjStatus>>,
-    active: Arc<Mutex<bool>>,
-    pcm_sender: mpsc::Sender<Vec<u8>>,
-    mut skip_rx: tokio::sync::watch::Receiver<bool>,
-    local_playback_disabled: Arc<std::sync::atomic::AtomicBool>,
-    cache_dir: Option<std::path::PathBuf>,
-    volume: Arc<AtomicU8>,
-    shared_queue: Option<SharedQueueConfig>,
-    shared_queue_updates: Option<tokio::sync::broadcast::Sender<()>>,
-) {
-    let source = YtDlpSource::new(cache_dir);
-    crate::dlog!("[DJ] Playback loop started");
+    fn move_track(&self, queued_id: u64, new_index: usize) -> Result<(), String> {
+        if let Some(cfg) = self.shared_queue_config().as_ref() {
+            let data = fetch_shared_queue_data(cfg)?;
+            let order: Vec<u64> = data.items.iter().filter_map(|t| t.queued_id).collect();
+            let from = order
+                .iter()
+                .position(|id| *id == queued_id)
+                .ok_or_else(|| format!("Track {queued_id} is not in the queue"))?;
+            append_reorder_event(cfg, compute_move_order(order, from, new_index))?;
+            return Ok(());
+        }
+        let mut queue = self.queue.lock().map_err(|e| e.to_string())?;
+        let from = queue
+            .iter()
+            .position(|t| t.local_id == Some(queued_id))
+            .ok_or_else(|| format!("Track {queued_id} is not in the queue"))?;
+        *queue = compute_move_order(std::mem::take(&mut *queue), from, new_index);
+        Ok(())
+    }
+
+    fn set_local_identity(&self, identity: Option<String>) {
+        if let Ok(mut local) = self.local_identity.lock() {
+            *local = identity;
+        }
+    }
+
+    fn dj_claim_writer(&self, identity: &str) -> Option<Box<dyn FnOnce() -> Result<(), String> + Send>> {
+        let cfg = self.shared_queue_config()?;
+        let identity = identity.to_string();
+        Some(Box::new(move || append_dj_claim_event(&cfg, &identity).map(|_| ())))
+    }
+
+    fn claim_dj(&self, identity: &str) -> Result<(), String> {
+        let mut active_dj = self.active_dj.lock().map_err(|e| e.to_string())?;
+        *active_dj = Some(identity.to_string());
+        Ok(())
+    }
+
+    fn current_dj(&self) -> Option<String> {
+        self.active_dj.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn elected_dj(&self) -> Result<Option<String>, String> {
+        let Some(cfg) = self.shared_queue_config() else {
+            return Ok(self.current_dj());
+        };
+        let elected = fetch_shared_queue_data(&cfg)?.active_dj;
+        *self.active_dj.lock().map_err(|e| e.to_string())? = elected.clone();
+        Ok(elected)
+    }
+
+    fn set_room_participants(&self, identities: Option<Vec<String>>) {
+        *self.room_participants.lock().unwrap_or_else(|e| e.into_inner()) = identities;
+    }
+
+    fn reconfigure_shared_queue(&self, repo: String, path: String, gh_path: String) -> Result<(), String> {
+        let config = match self.shared_state_path.clone() {
+            Some(state_path) => {
+                SharedQueueConfig::new(
+                    repo,
+                    path,
+                    gh_path,
+                    state_path,
+                    self.shard_strategy.clone(),
+                    self.room_participants.clone(),
+                )
+            }
+            None => None,
+        };
+        if let Some(cfg) = config.as_ref() {
+            write_shared_state(cfg, SharedQueueState::default())?;
+        }
+        *self.shared_queue.lock().map_err(|e| e.to_string())? = config;
+        if let Ok(mut queue) = self.queue.lock() {
+            queue.clear();
+        }
+        if let Some(tx) = self.shared_queue_updates.as_ref() {
+            let _ = tx.send(());
+        }
+        Ok(())
+    }
+}
+
+/// Move the item at `from` to `new_index`, clamping the target to the end of the list.
+fn compute_move_order<T>(mut items: Vec<T>, from: usize, new_index: usize) -> Vec<T> {
+    let item = items.remove(from);
+    let to = new_index.min(items.len());
+    items.insert(to, item);
+    items
+}
+
+/// Apply a `reordered` event to the running queue order. Ids it lists go first, in its order;
+/// ids it doesn't know about (queued since, or left out) keep their relative order after them.
+/// Listed ids that aren't queued are ignored.
+fn apply_reorder(current: &[u64], reorder: &[u64]) -> Vec<u64> {
+    let known: HashSet<u64> = current.iter().copied().collect();
+    let mut placed = HashSet::new();
+    let mut next: Vec<u64> = reorder
+        .iter()
+        .copied()
+        .filter(|id| known.contains(id) && placed.insert(*id))
+        .collect();
+    next.extend(current.iter().copied().filter(|id| !placed.contains(id)));
+    next
+}
+
+/// Queue order with `first` ahead of the existing items.
+fn order_with_first(first: u64, items: &[QueuedTrack]) -> Vec<u64> {
+    std::iter::once(first)
+        .chain(items.iter().filter_map(|t| t.queued_id))
+        .collect()
+}
+
+/// Whether a streaming DJ should re-read the queue for skip and pause events. Pushes only
+/// replace the timer while the webhook is connected; otherwise nothing would ever arrive.
+fn skip_check_due(
+    push_rx: Option<&mut tokio::sync::broadcast::Receiver<()>>,
+    webhook_connected: bool,
+    since_last_check: std::time::Duration,
+    interval: std::time::Duration,
+) -> bool {
+    match push_rx {
+        Some(rx) if webhook_connected => push_received(rx),
+        _ => since_last_check >= interval,
+    }
+}
+
+/// Drain pending webhook pushes without waiting; true if any arrived.
+fn push_received(rx: &mut tokio::sync::broadcast::Receiver<()>) -> bool {
+    let mut received = false;
+    loop {
+        match rx.try_recv() {
+            Ok(()) | Err(tokio::sync::broadcast::error::TryRecvError::Lagged(_)) => received = true,
+            Err(_) => return received,
+        }
+    }
+}
+
+/// Whether the local client may stream audio given the room's latest DJ claim.
+/// With no claim on record anyone running the playback loop may stream.
+fn may_stream(local_identity: Option<&str>, active_dj: Option<&str>) -> bool {
+    match active_dj {
+        None => true,
+        Some(dj) => local_identity == Some(dj),
+    }
+}
+
+fn is_active_dj(local_identity: &Mutex<Option<String>>, active_dj: &Mutex<Option<String>>) -> bool {
+    let local = local_identity.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let active = active_dj.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    may_stream(local.as_deref(), active.as_deref())
+}
+
+/// Length of the fade applied at the start of a track and when it is cut short.
+const FADE_MS: usize = 50;
+/// Interleaved 48kHz stereo samples covered by [`FADE_MS`].
+const FADE_SAMPLES: usize = 48_000 * 2 * FADE_MS / 1000;
+
+/// Applies a linear fade-in to the start of a track and holds back the most
+/// recent samples so they can be faded out if the track is cut short.
+struct TrackFader {
+    fade_len: usize,
+    faded_in: usize,
+    pending: VecDeque<i16>,
+}
+
+impl TrackFader {
+    fn new(fade_len: usize) -> Self {
+        Self {
+            fade_len,
+            faded_in: 0,
+            pending: VecDeque::with_capacity(fade_len * 2),
+        }
+    }
+
+    /// Feed new samples and return those that are ready to be played.
+    fn push(&mut self, samples: &[i16]) -> Vec<i16> {
+        for &sample in samples {
+            let sample = if self.faded_in < self.fade_len {
+                // Ramp per stereo frame so both channels get the same gain
+                let frame = (self.faded_in / 2 * 2) as f32;
+                self.faded_in += 1;
+                (sample as f32 * frame / self.fade_len as f32) as i16
+            } else {
+                sample
+            };
+            self.pending.push_back(sample);
+        }
+        let ready = self.pending.len().saturating_sub(self.fade_len);
+        self.pending.drain(..ready).collect()
+    }
+
+    /// Release the held-back samples unchanged (track ended naturally).
+    fn finish(&mut self) -> Vec<i16> {
+        self.pending.drain(..).collect()
+    }
+
+    /// Release the held-back samples with a linear fade to silence.
+    fn fade_out(&mut self) -> Vec<i16> {
+        let frames = (self.pending.len() / 2).max(1) as f32;
+        self.pending
+            .drain(..)
+            .enumerate()
+            .map(|(i, sample)| {
+                let remaining = frames - (i / 2) as f32 - 1.0;
+                (sample as f32 * remaining.max(0.0) / frames) as i16
+            })
+            .collect()
+    }
+}
+
+/// Encode samples as little-endian bytes for the LiveKit publisher.
+fn samples_to_le_bytes(samples: &[i16]) -> Vec<u8> {
+    samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+}
+
+/// PCM bytes per second needed to keep up with 48kHz stereo s16 playback.
+pub const REALTIME_BYTES_PER_SEC: u64 = 48_000 * 2 * 2;
+/// How often the throughput average is updated.
+const THROUGHPUT_WINDOW: std::time::Duration = std::time::Duration::from_secs(1);
+/// Consecutive slow windows before a `stream-underrun` is reported.
+const UNDERRUN_WINDOWS: u32 = 5;
+
+/// Moving average of how fast the audio source delivers bytes, counting only
+/// time spent waiting on reads so LiveKit backpressure doesn't look like a slow download.
+struct ThroughputMeter {
+    average: Option<f64>,
+    window_bytes: u64,
+    window_read_time: std::time::Duration,
+    window_start: Instant,
+    slow_windows: u32,
+    underrun_reported: bool,
+}
+
+impl ThroughputMeter {
+    fn new() -> Self {
+        Self {
+            average: None,
+            window_bytes: 0,
+            window_read_time: std::time::Duration::ZERO,
+            window_start: Instant::now(),
+            slow_windows: 0,
+            underrun_reported: false,
+        }
+    }
+
+    /// Count a read; returns true when a sustained underrun is first detected.
+    fn record(&mut self, bytes: usize, read_time: std::time::Duration) -> bool {
+        self.window_bytes += bytes as u64;
+        self.window_read_time += read_time;
+        if self.window_start.elapsed() < THROUGHPUT_WINDOW {
+            return false;
+        }
+        let underrun = self.close_window();
+        self.window_start = Instant::now();
+        underrun
+    }
+
+    fn close_window(&mut self) -> bool {
+        let bytes = std::mem::take(&mut self.window_bytes);
+        let secs = std::mem::take(&mut self.window_read_time).as_secs_f64();
+        if secs <= 0.0 {
+            return false;
+        }
+        let rate = bytes as f64 / secs;
+        let average = match self.average {
+            Some(average) => average * 0.7 + rate * 0.3,
+            None => rate,
+        };
+        self.average = Some(average);
+        if average >= REALTIME_BYTES_PER_SEC as f64 {
+            self.slow_windows = 0;
+            self.underrun_reported = false;
+            return false;
+        }
+        self.slow_windows += 1;
+        if self.slow_windows >= UNDERRUN_WINDOWS && !self.underrun_reported {
+            self.underrun_reported = true;
+            return true;
+        }
+        false
+    }
+
+    fn bytes_per_sec(&self) -> u64 {
+        self.average.map(|average| average as u64).unwrap_or(0)
+    }
+}
+
+/// Time for the applied gain to travel the full 0–100% range after a volume change.
+const VOLUME_RAMP_MS: usize = 30;
+
+/// Moves the applied gain toward the volume target a little per frame,
+/// so volume changes don't cause an audible step.
+struct GainRamp {
+    current: f32,
+    step: f32,
+}
+
+impl GainRamp {
+    fn new(initial: f32) -> Self {
+        Self {
+            current: initial,
+            step: 1.0 / (48 * VOLUME_RAMP_MS) as f32,
+        }
+    }
+
+    /// Scale interleaved stereo samples, ramping toward `target` one frame at a time.
+    fn apply(&mut self, samples: &[i16], target: f32) -> Vec<i16> {
+        let mut out = Vec::with_capacity(samples.len());
+        for frame in samples.chunks(2) {
+            let delta = target - self.current;
+            self.current += delta.clamp(-self.step, self.step);
+            out.extend(frame.iter().map(|s| {
+                (*s as f32 * self.current).clamp(i16::MIN as f32, i16::MAX as f32) as i16
+            }));
+        }
+        out
+    }
+}
+
+/// Eases the ducking gain towards its target over the attack or release time.
+struct DuckRamp {
+    gain: f32,
+}
+
+impl DuckRamp {
+    fn new() -> Self {
+        Self { gain: 1.0 }
+    }
+
+    /// Gain for a chunk lasting `chunk_ms`, where a full 0–1 swing takes `ramp_ms`.
+    fn advance(&mut self, target: f32, ramp_ms: u64, chunk_ms: u64) -> f32 {
+        let step = if ramp_ms == 0 { 1.0 } else { chunk_ms as f32 / ramp_ms as f32 };
+        self.gain += (target - self.gain).clamp(-step, step);
+        self.gain
+    }
+}
+
+// Flow control between the playback loop and the LiveKit publisher is deliberate: when the
+// PCM channel is full, sending waits, which stops the loop reading from yt-dlp|ffmpeg until
+// the publisher (paced to real time) catches up. ffmpeg then blocks on its full pipe rather
+// than racing ahead. Only a publisher that stays behind is a problem, and `Backpressure`
+// reports that.
+
+/// How long the PCM channel may stay full before the publisher is reported as falling behind.
+const BACKPRESSURE_WARN_AFTER: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Tracks how long the publisher has left the PCM channel full.
+#[derive(Default)]
+struct Backpressure {
+    full_since: Option<Instant>,
+    warned: bool,
+}
+
+impl Backpressure {
+    /// Returns how long the channel has been full the first time that passes
+    /// [`BACKPRESSURE_WARN_AFTER`]; once per episode, until it drains again.
+    fn observe(&mut self, full: bool, now: Instant) -> Option<std::time::Duration> {
+        if !full {
+            self.full_since = None;
+            self.warned = false;
+            return None;
+        }
+        let stalled = now.duration_since(*self.full_since.get_or_insert(now));
+        if self.warned || stalled < BACKPRESSURE_WARN_AFTER {
+            return None;
+        }
+        self.warned = true;
+        Some(stalled)
+    }
+}
+
+/// Send a chunk to the publisher, waiting for room if its channel is full.
+/// Returns false once the publisher has gone away.
+async fn send_pcm(
+    sender: &mpsc::Sender<Vec<u8>>,
+    sent: &AtomicU64,
+    bytes: Vec<u8>,
+    backpressure: &mut Backpressure,
+) -> bool {
+    if let Some(stalled) = backpressure.observe(sender.capacity() == 0, Instant::now()) {
+        crate::dlog!("[DJ] Publisher falling behind: PCM channel full for {}ms", stalled.as_millis());
+        crate::emit_event("publisher-backpressure", stalled.as_millis() as u64);
+    }
+    // Counted before sending so a skip mid-send drops this chunk too
+    sent.fetch_add(1, Ordering::Relaxed);
+    sender.send(bytes).await.is_ok()
+}
+
+/// Ramp a local sink down to silence over [`FADE_MS`] before stopping it.
+fn fade_out_sink(sink: &rodio::Sink) {
+    const STEPS: usize = 5;
+    let start = sink.volume();
+    for step in (0..STEPS).rev() {
+        sink.set_volume(start * step as f32 / STEPS as f32);
+        std::thread::sleep(std::time::Duration::from_millis((FADE_MS / STEPS) as u64));
+    }
+}
+
+/// Audio queued locally before the monitor sink starts, so slow reads don't underrun rodio.
+const LOCAL_PREBUFFER_MS: usize = 200;
+
+/// Tracks how much audio the local sink has queued and when it may play.
+struct Prebuffer {
+    target_samples: usize,
+    buffered_samples: usize,
+    playing: bool,
+}
+
+impl Prebuffer {
+    fn new(ms: usize) -> Self {
+        Self {
+            target_samples: ms * 48 * 2,
+            buffered_samples: 0,
+            playing: false,
+        }
+    }
+
+    /// Record newly queued samples; returns true when playback should start.
+    fn push(&mut self, samples: usize) -> bool {
+        if self.playing {
+            return false;
+        }
+        self.buffered_samples += samples;
+        if self.buffered_samples >= self.target_samples {
+            self.playing = true;
+            return true;
+        }
+        false
+    }
+
+    /// The sink ran dry; hold playback until the buffer refills.
+    fn underrun(&mut self) {
+        self.playing = false;
+        self.buffered_samples = 0;
+    }
+}
+
+/// Set once opening the audio output fails, so later tracks don't keep retrying.
+static AUDIO_OUTPUT_UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+/// Local speaker playback for a single track, running rodio on its own thread.
+struct LocalPlayback {
+    stop_tx: std::sync::mpsc::Sender<()>,
+    samples_tx: std::sync::mpsc::Sender<Vec<i16>>,
+    handle: std::thread::JoinHandle<()>,
+}
+
+impl LocalPlayback {
+    /// Start local playback, or `None` if there is no usable audio output.
+    /// After the first failure local playback stays off for the rest of the session
+    /// and a `no-audio-output` event is emitted; LiveKit publishing is unaffected.
+    async fn start(volume: Arc<AtomicU8>, format: PcmFormat) -> Option<Self> {
+        if AUDIO_OUTPUT_UNAVAILABLE.load(Ordering::Relaxed) {
+            return None;
+        }
+        let (stop_tx, stop_rx) = std::sync::mpsc::channel::<()>();
+        let (samples_tx, samples_rx) = std::sync::mpsc::channel::<Vec<i16>>();
+        let (ready_tx, ready_rx) = tokio::sync::oneshot::channel::<bool>();
+        let handle = std::thread::spawn(move || {
+            use rodio::{Sink, buffer::SamplesBuffer, stream::OutputStreamBuilder};
+            let stream = match OutputStreamBuilder::open_default_stream() {
+                Ok(s) => {
+                    let _ = ready_tx.send(true);
+                    s
+                }
+                Err(e) => {
+                    crate::dlog!("[DJ] Failed to open audio output: {e}");
+                    let _ = ready_tx.send(false);
+                    return;
+                }
+            };
+            let sink = Sink::connect_new(stream.mixer());
+            sink.pause();
+            let mut prebuffer = Prebuffer::new(LOCAL_PREBUFFER_MS);
+            // Volume is applied to the samples so changes ramp instead of snapping the sink
+            let mut gain = GainRamp::new(volume.load(Ordering::Relaxed) as f32 / 100.0);
+
+            loop {
+                if stop_rx.try_recv().is_ok() {
+                    fade_out_sink(&sink);
+                    sink.stop();
+                    return;
+                }
+                if prebuffer.playing && sink.empty() {
+                    sink.pause();
+                    prebuffer.underrun();
+                }
+                match samples_rx.recv_timeout(std::time::Duration::from_millis(100)) {
+                    Ok(samples) => {
+                        let vol = volume.load(Ordering::Relaxed) as f32 / 100.0;
+                        let samples = gain.apply(&samples, vol);
+                        let sample_count = samples.len();
+                        let f32_samples: Vec<f32> = samples.iter().map(|&s| s as f32 / 32768.0).collect();
+                        let source = SamplesBuffer::new(format.channels, format.sample_rate, f32_samples);
+                        sink.append(source);
+                        if prebuffer.push(sample_count) {
+                            sink.play();
+                        }
+                    }
+                    Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                        // Check if sink is done and no more data coming
+                    }
+                    Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                        // Play out whatever is buffered, even if short of the target
+                        sink.play();
+                        while !sink.empty() {
+                            if stop_rx.try_recv().is_ok() {
+                                sink.stop();
+                                return;
+                            }
+                            std::thread::sleep(std::time::Duration::from_millis(50));
+                        }
+                        return;
+                    }
+                }
+            }
+        });
+        if !ready_rx.await.unwrap_or(false) {
+            // The thread returns right after reporting the failure; no need to block on it
+            drop(handle);
+            if !AUDIO_OUTPUT_UNAVAILABLE.swap(true, Ordering::Relaxed) {
+                crate::dlog!("[DJ] No audio output, local playback disabled for this session");
+                crate::emit_event("no-audio-output", ());
+            }
+            return None;
+        }
+        Some(Self {
+            stop_tx,
+            samples_tx,
+            handle,
+        })
+    }
+
+    fn send(&self, samples: Vec<i16>) {
+        let _ = self.samples_tx.send(samples);
+    }
+
+    /// Fade out and stop without waiting for buffered audio.
+    fn stop(self) {
+        let _ = self.stop_tx.send(());
+    }
+
+    /// Signal the end of the stream and wait for buffered audio to play out.
+    fn finish(self) {
+        drop(self.samples_tx);
+        let _ = self.handle.join();
+    }
+}
+
+/// The main playback loop: pops tracks from the queue, fetches, decodes, streams PCM.
+async fn run_playback_loop(
+    queue: Arc<Mutex<Vec<QueuedTrack>>>,
+    status: Arc<tokio::sync::watch::Sender<DjStatus>>,
+    active: Arc<Mutex<bool>>,
+    pcm_sender: mpsc::Sender<Vec<u8>>,
+    mut skip_rx: tokio::sync::watch::Receiver<bool>,
+    local_playback_disabled: Arc<std::sync::atomic::AtomicBool>,
+    cache_dir: Option<std::path::PathBuf>,
+    volume: Arc<AtomicU8>,
+    shared_queue_handle: SharedQueueHandle,
+    shared_queue_updates: Option<tokio::sync::broadcast::Sender<()>>,
+    local_identity: Arc<Mutex<Option<String>>>,
+    active_dj: Arc<Mutex<Option<String>>>,
+    sync_mode: Arc<QueueSyncMode>,
+    webhook_connected: Arc<AtomicBool>,
+    prefetch_depth: Arc<AtomicUsize>,
+    prefetch_concurrency: Arc<AtomicUsize>,
+    blacklist: Arc<Blacklist>,
+    stream_timeout_secs: Arc<AtomicU64>,
+    load_retries: Arc<AtomicUsize>,
+    idle_timeout_secs: Arc<AtomicU64>,
+    audio_quality: Arc<Mutex<AudioQuality>>,
+    current_track: Arc<Mutex<Option<QueuedTrack>>>,
+    seek: Arc<SeekControl>,
+    ducking: Arc<Ducking>,
+    stream_throughput: Arc<AtomicU64>,
+    track_diagnostics: Arc<TrackDiagnosticLog>,
+    cookies_path: Arc<Mutex<Option<std::path::PathBuf>>>,
+    pcm_flush: Arc<tokio::sync::watch::Sender<u64>>,
+    pcm_sent: Arc<AtomicU64>,
+) {
+    let mut source = YtDlpSource::new(cache_dir);
+    crate::dlog!("[DJ] Playback loop started");
 
-    if let (Some(cfg), Some(updates_tx)) = (shared_queue.clone(), shared_queue_updates.clone()) {
+    {
+        let shared_queue_sync = shared_queue_handle.clone();
         let queue_sync = queue.clone();
         let active_sync = active.clone();
         let status_sync = status.clone();
+        let local_identity_sync = local_identity.clone();
+        let active_dj_sync = active_dj.clone();
+        let audio_quality_sync = audio_quality.clone();
+        let cookies_sync = cookies_path.clone();
+        let current_track_sync = current_track.clone();
         let cache_dir = source.cache_dir.clone();
+        let mut rx = shared_queue_updates.as_ref().map(|tx| tx.subscribe());
+        if rx.is_none() {
+            // Without webhook pushes, polling is the only way to see new events
+            sync_mode.set_polling(true);
+        }
         tokio::spawn(async move {
-            let mut rx = updates_tx.subscribe();
             // Initial sync
-            if let Ok(data) = fetch_shared_queue_data(&cfg) {
-                let mut next_queue = data.items;
-                if next_queue.is_empty() {
-                    if let Some(now) = data.now_playing {
-                        let status_is_idle = matches!(
-                            *status_sync.lock().unwrap_or_else(|e| e.into_inner()),
-                            DjStatus::Idle
-                        );
-                        if status_is_idle {
-                            tracing::info!(
-                                event = "shared_queue_resume_now_playing",
-                                title = %now.title,
-                                url = %now.url,
-                                queued_id = ?now.queued_id
-                            );
-                            next_queue.push(QueuedTrack {
-                                url: now.url,
-                                title: now.title,
-                                queued_id: now.queued_id,
-                                queued_by: None,
-                            });
-                        }
-                    }
-                }
-                let prefetch_items: Vec<String> = next_queue.iter()
-                    .take(2)
-                    .map(|t| t.url.clone())
-                    .collect();
-                let source_for_prefetch = YtDlpSource::new(cache_dir.clone());
-                prefetch_tracks(&source_for_prefetch, prefetch_items).await;
-
-                if let Ok(mut q) = queue_sync.lock() {
-                    *q = next_queue;
-                }
-                if !data.needs_metadata.is_empty() {
-                    let cfg_clone = cfg.clone();
-                    let items = data.needs_metadata;
-                    tokio::spawn(async move {
-                        fetch_and_append_metadata(&cfg_clone, items).await;
-                    });
-                }
-                let _ = write_shared_state(&cfg, SharedQueueState { last_seen_id: data.max_id });
+            if let Some(cfg) = current_shared_queue(&shared_queue_sync) {
+                sync_shared_queue(
+                    &cfg,
+                    &queue_sync,
+                    &status_sync,
+                    &local_identity_sync,
+                    &active_dj_sync,
+                    cache_dir.clone(),
+                    prefetch_depth.load(Ordering::Relaxed),
+                    prefetch_concurrency.load(Ordering::Relaxed),
+                    current_quality(&audio_quality_sync),
+                    current_cookies(&cookies_sync),
+                    &current_track_sync,
+                )
+                .await;
             }
             loop {
                 if !*active_sync.lock().unwrap_or_else(|e| e.into_inner()) {
                     break;
                 }
-                if rx.recv().await.is_err() {
-                    break;
-                }
-                if let Ok(data) = fetch_shared_queue_data(&cfg) {
-                    let mut next_queue = data.items;
-                    if next_queue.is_empty() {
-                        if let Some(now) = data.now_playing {
-                            let status_is_idle = matches!(
-                                *status_sync.lock().unwrap_or_else(|e| e.into_inner()),
-                                DjStatus::Idle
-                            );
-                            if status_is_idle {
-                                tracing::info!(
-                                    event = "shared_queue_resume_now_playing",
-                                    title = %now.title,
-                                    url = %now.url,
-                                    queued_id = ?now.queued_id
-                                );
-                                next_queue.push(QueuedTrack {
-                                    url: now.url,
-                                    title: now.title,
-                                    queued_id: now.queued_id,
-                                    queued_by: None,
-                                });
+                let trigger = match rx.as_mut() {
+                    Some(rx) => tokio::select! {
+                        update = rx.recv() => match update {
+                            Ok(()) | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {
+                                SyncTrigger::Push
                             }
-                        }
-                    }
-                    let prefetch_items: Vec<String> = next_queue.iter()
-                        .take(2)
-                        .map(|t| t.url.clone())
-                        .collect();
-                    let source_for_prefetch = YtDlpSource::new(cache_dir.clone());
-                    prefetch_tracks(&source_for_prefetch, prefetch_items).await;
-
-                    if let Ok(mut q) = queue_sync.lock() {
-                        *q = next_queue;
-                    }
-                    if !data.needs_metadata.is_empty() {
-                        let cfg_clone = cfg.clone();
-                        let items = data.needs_metadata;
-                        tokio::spawn(async move {
-                            fetch_and_append_metadata(&cfg_clone, items).await;
-                        });
+                            Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                        },
+                        _ = tokio::time::sleep(sync_mode.poll_interval()) => SyncTrigger::Tick,
+                    },
+                    None => {
+                        tokio::time::sleep(sync_mode.poll_interval()).await;
+                        SyncTrigger::Tick
                     }
-                    let _ = write_shared_state(&cfg, SharedQueueState { last_seen_id: data.max_id });
+                };
+                if !sync_mode.should_sync(trigger) {
+                    continue;
                 }
+                // Re-read each time so a reconfigured repo takes effect on the next sync
+                let Some(cfg) = current_shared_queue(&shared_queue_sync) else {
+                    continue;
+                };
+                sync_shared_queue(
+                    &cfg,
+                    &queue_sync,
+                    &status_sync,
+                    &local_identity_sync,
+                    &active_dj_sync,
+                    cache_dir.clone(),
+                    prefetch_depth.load(Ordering::Relaxed),
+                    prefetch_concurrency.load(Ordering::Relaxed),
+                    current_quality(&audio_quality_sync),
+                    current_cookies(&cookies_sync),
+                    &current_track_sync,
+                )
+                .await;
             }
         });
     }
 
+    // Carried across tracks so the next track also waits for `resume`
+    let mut room_paused = false;
+    let mut idle = IdleTimer::default();
     loop {
         // Check if still active
         if !*active.lock().unwrap_or_else(|e| e.into_inner()) {
             break;
         }
+        // Events for a track go to the repo it was queued from, even if reconfigured mid-track
+        let shared_queue = current_shared_queue(&shared_queue_handle);
+
+        // Defer to whoever most recently claimed DJ duty
+        if !is_active_dj(&local_identity, &active_dj) {
+            status.send_replace(DjStatus::Idle);
+            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+            continue;
+        }
 
         // Pop next track from queue
         let track = {
@@ -875,31 +3079,78 @@ async fn run_playback_loop(
         let track = match track {
             Some(t) => {
                 crate::dlog!("[DJ] Popped track from queue: {}", t.url);
+                idle.reset();
                 t
             }
             None => {
+                let timeout_secs = idle_timeout_secs.load(Ordering::Relaxed);
+                if idle.expired(Instant::now(), timeout_secs) {
+                    crate::dlog!("[DJ] Queue empty for {timeout_secs}s, stopping");
+                    tracing::info!(event = "dj_auto_stopped_idle", idle_timeout_secs = timeout_secs);
+                    *active.lock().unwrap_or_else(|e| e.into_inner()) = false;
+                    status.send_replace(DjStatus::Idle);
+                    crate::emit_event("auto-stopped-idle", timeout_secs);
+                    break;
+                }
                 // No tracks in queue — wait a bit and check again
                 tokio::time::sleep(std::time::Duration::from_millis(500)).await;
                 continue;
             }
         };
 
+        if blacklist.contains(&track.url) {
+            crate::dlog!("[DJ] Skipping blacklisted track: {}", track.url);
+            if let (Some(cfg), Some(queued_id)) = (shared_queue.as_ref(), track.queued_id) {
+                if let Err(err) = append_failed_event(cfg, queued_id) {
+                    crate::dlog!("[DJ] Failed to append failed event: {err}");
+                }
+            }
+            continue;
+        }
+
         crate::dlog!("[DJ] Playing: {}", track.url);
 
         // Update status to Loading
-        if let Ok(mut s) = status.lock() {
-            *s = DjStatus::Loading;
-        }
+        status.send_replace(DjStatus::Loading);
 
         // Start streaming audio
         crate::dlog!("[DJ] Starting streaming audio...");
-        let streaming_info = match source.fetch_audio_streaming(&track.url).await {
-            Ok(info) => {
+        let stream_timeout = std::time::Duration::from_secs(stream_timeout_secs.load(Ordering::Relaxed));
+        source.set_quality(current_quality(&audio_quality));
+        source.set_cookies(current_cookies(&cookies_path));
+        let (source_ref, track_ref, diagnostics) = (&source, &track, &track_diagnostics);
+        let fetched = load_with_retries(
+            load_retries.load(Ordering::Relaxed),
+            LOAD_RETRY_BACKOFF,
+            &mut skip_rx,
+            || async move {
+                let fetch_started = Instant::now();
+                let fetched = source_ref
+                    .fetch_audio_streaming(&track_ref.url, track_ref.clip, stream_timeout)
+                    .await;
+                let elapsed = fetch_started.elapsed();
+                diagnostics.record(TrackDiagnostic::from_fetch(&track_ref.url, &fetched, elapsed));
+                fetched
+            },
+        )
+        .await;
+        let streaming_info = match fetched {
+            LoadOutcome::Loaded(info) => {
                 crate::dlog!("[DJ] Streaming: '{}'", info.title);
                 info
             }
-            Err(e) => {
+            LoadOutcome::Cancelled => {
+                crate::dlog!("[DJ] Skipped while loading: {}", track.url);
+                if let (Some(cfg), Some(queued_id)) = (shared_queue.as_ref(), track.queued_id) {
+                    if let Err(err) = append_played_event(cfg, queued_id) {
+                        crate::dlog!("[DJ] Failed to record end of track: {err}");
+                    }
+                }
+                continue;
+            }
+            LoadOutcome::Failed(e) => {
                 crate::dlog!("[DJ] Failed to start audio stream: {e}");
+                crate::emit_backend_error("playback", format!("Couldn't load {}: {e}", track.url));
                 if let (Some(cfg), Some(queued_id)) = (shared_queue.as_ref(), track.queued_id) {
                     if let Err(err) = append_failed_event(cfg, queued_id) {
                         crate::dlog!("[DJ] Failed to append failed event: {err}");
@@ -912,86 +3163,91 @@ async fn run_playback_loop(
         let title = streaming_info.title.clone();
 
         // Update status to Playing
-        if let Ok(mut s) = status.lock() {
-            *s = DjStatus::Playing(NowPlaying {
-                track: title.clone(),
-                artist: String::new(),
-            });
+        let now_playing = NowPlaying {
+            track: title.clone(),
+            artist: track.artist.clone().unwrap_or_default(),
+            url: Some(track.url.clone()),
+            thumbnail: track.thumbnail.clone(),
+            duration_secs: streaming_info.duration_secs,
+            queued_by: track.queued_by.clone(),
+        };
+        status.send_replace(if room_paused {
+            DjStatus::Paused(now_playing.clone())
+        } else {
+            DjStatus::Playing(now_playing.clone())
+        });
+        if let Ok(mut current) = current_track.lock() {
+            *current = Some(track.clone());
         }
         let mut playing_event_id = None;
         if let (Some(cfg), Some(queued_id)) = (shared_queue.as_ref(), track.queued_id) {
-            match append_playing_event(cfg, queued_id, &title, &track.url) {
+            let dj_identity = local_identity.lock().unwrap_or_else(|e| e.into_inner()).clone();
+            match append_playing_event(
+                cfg,
+                queued_id,
+                &title,
+                &track.url,
+                dj_identity.as_deref(),
+                streaming_info.duration_secs,
+            ) {
                 Ok(id) => playing_event_id = Some(id),
                 Err(err) => crate::dlog!("[DJ] Failed to append playing event: {err}"),
             }
         }
 
-        // Set up local playback via rodio with a channel for streaming samples
-        let use_local = !local_playback_disabled.load(Ordering::Relaxed);
-        let (stop_tx, stop_rx) = std::sync::mpsc::channel::<()>();
-        let (local_tx, local_rx) = std::sync::mpsc::channel::<Vec<i16>>();
-        let playback_handle = if use_local {
-            let volume = volume.clone();
-            Some(std::thread::spawn(move || {
-                use rodio::{Sink, buffer::SamplesBuffer, stream::OutputStreamBuilder};
-                let stream = match OutputStreamBuilder::open_default_stream() {
-                    Ok(s) => s,
-                    Err(e) => {
-                        crate::dlog!("[DJ] Failed to open audio output: {e}");
-                        return;
-                    }
-                };
-                let sink = Sink::connect_new(stream.mixer());
+        let format = streaming_info.format;
+        if format != PcmFormat::STREAM {
+            crate::dlog!(
+                "[DJ] Track is {}Hz/{}ch; LiveKit expects {}Hz/{}ch",
+                format.sample_rate,
+                format.channels,
+                PcmFormat::STREAM.sample_rate,
+                PcmFormat::STREAM.channels
+            );
+        }
 
-                loop {
-                    if stop_rx.try_recv().is_ok() {
-                        sink.stop();
-                        return;
-                    }
-                    match local_rx.recv_timeout(std::time::Duration::from_millis(100)) {
-                        Ok(samples) => {
-                            let vol = volume.load(Ordering::Relaxed) as f32 / 100.0;
-                            sink.set_volume(vol);
-                            let f32_samples: Vec<f32> = samples.iter().map(|&s| s as f32 / 32768.0).collect();
-                            let source = SamplesBuffer::new(2, 48000, f32_samples);
-                            sink.append(source);
-                        }
-                        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
-                            // Check if sink is done and no more data coming
-                        }
-                        Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
-                            // Wait for sink to drain
-                            while !sink.empty() {
-                                if stop_rx.try_recv().is_ok() {
-                                    sink.stop();
-                                    return;
-                                }
-                                std::thread::sleep(std::time::Duration::from_millis(50));
-                            }
-                            return;
-                        }
-                    }
-                }
-            }))
-        } else {
+        // Set up local playback via rodio with a channel for streaming samples
+        let mut local = if local_playback_disabled.load(Ordering::Relaxed) {
             crate::dlog!("[DJ] Local playback disabled, audio goes to LiveKit only");
-            drop(local_rx);
             None
+        } else {
+            LocalPlayback::start(volume.clone(), format).await
         };
 
         // Stream PCM from source in chunks
-        let chunk_bytes = 960 * 2; // 960 samples * 2 bytes/sample = 10ms at 48kHz stereo
+        let chunk_bytes = format.chunk_bytes();
         let mut skipped = false;
+        // Local skips flush in `skip_track`; skips from the shared queue flush here
+        let mut flush_publisher = false;
+        let mut handed_off = false;
+        let mut stalled = false;
         let mut last_skip_check = Instant::now();
-        let skip_check_interval = std::time::Duration::from_secs(2);
+        // With webhooks, skips are only checked after a push instead of on a timer
+        let mut skip_push_rx = shared_queue_updates.as_ref().map(|tx| tx.subscribe());
         let mut total_bytes = 0u64;
+        let mut throughput = ThroughputMeter::new();
 
+        let mut child: Option<tokio::process::Child> = None;
+        // Seeking reopens the cache file, so live streams can't seek; clips keep to their range
+        let start_secs = match track.clip {
+            Some((start, _)) => start,
+            None => parse_start_offset(&track.url).unwrap_or(0),
+        };
+        seek.start_track(
+            streaming_info.kind == AudioSourceKind::Cache && track.clip.is_none(),
+            start_secs,
+            streaming_info.duration_secs,
+            format,
+        );
+        let mut position_bytes = start_secs.saturating_mul(format.bytes_per_sec());
         let mut reader: Box<dyn tokio::io::AsyncRead + Unpin + Send> = match streaming_info.source {
             StreamingAudioSource::Cached(file) => Box::new(file),
-            StreamingAudioSource::Process { mut child, cache_writer } => {
-                let stdout = child.stdout.take()
+            StreamingAudioSource::Decoded(stream) => Box::new(stream),
+            StreamingAudioSource::Process { child: mut process, cache_writer } => {
+                let stdout = process.stdout.take()
                     .ok_or_else(|| "No stdout from yt-dlp process".to_string())
                     .unwrap();
+                child = Some(process);
                 if let Some(cw) = cache_writer {
                     // Tee: read from process, write to cache
                     Box::new(TeeReader::new(stdout, cw))
@@ -1003,12 +3259,15 @@ async fn run_playback_loop(
 
         use tokio::io::AsyncReadExt;
         let mut buf = vec![0u8; chunk_bytes];
+        let mut fader = TrackFader::new(FADE_SAMPLES);
+        let mut gain = GainRamp::new(volume.load(Ordering::Relaxed) as f32 / 100.0);
+        let mut duck = DuckRamp::new();
+        let mut backpressure = Backpressure::default();
 
         loop {
             // Check for skip signal
             if skip_rx.has_changed().unwrap_or(false) {
                 let _ = skip_rx.changed().await;
-                let _ = stop_tx.send(());
                 skipped = true;
                 break;
             }
@@ -1017,36 +3276,107 @@ async fn run_playback_loop(
             if let (Some(cfg), Some(queued_id), Some(event_id)) =
                 (shared_queue.as_ref(), track.queued_id, playing_event_id)
             {
-                if last_skip_check.elapsed() >= skip_check_interval {
-                    match shared_skip_requested(cfg, queued_id, event_id) {
-                        Ok(true) => {
-                            let _ = stop_tx.send(());
+                let check_due = skip_check_due(
+                    skip_push_rx.as_mut(),
+                    webhook_connected.load(Ordering::Relaxed),
+                    last_skip_check.elapsed(),
+                    sync_mode.skip_check_interval(),
+                );
+                if check_due {
+                    match fetch_playback_control(cfg, queued_id, event_id).await {
+                        Ok(control) if control.skip => {
                             skipped = true;
+                            flush_publisher = true;
                             break;
                         }
-                        Ok(false) => {}
+                        Ok(control) => {
+                            if control.paused != room_paused {
+                                room_paused = control.paused;
+                                crate::dlog!("[DJ] Room {}", if room_paused { "paused" } else { "resumed" });
+                                status.send_replace(if room_paused {
+                                    DjStatus::Paused(now_playing.clone())
+                                } else {
+                                    DjStatus::Playing(now_playing.clone())
+                                });
+                            }
+                        }
                         Err(err) => crate::dlog!("[DJ] Failed to check skip events: {err}"),
                     }
                     last_skip_check = Instant::now();
                 }
+            } else {
+                // Without a shared queue nothing could ever resume the room
+                room_paused = false;
             }
 
             if !*active.lock().unwrap_or_else(|e| e.into_inner()) {
-                let _ = stop_tx.send(());
                 skipped = true;
                 break;
             }
 
-            // Read next chunk from stream
-            let n = match reader.read(&mut buf).await {
-                Ok(0) => break, // EOF
-                Ok(n) => n,
-                Err(e) => {
-                    crate::dlog!("[DJ] Stream read error: {e}");
-                    break;
-                }
-            };
-            total_bytes += n as u64;
+            if !is_active_dj(&local_identity, &active_dj) {
+                crate::dlog!("[DJ] Another participant claimed DJ, handing off");
+                handed_off = true;
+                break;
+            }
+
+            if let Some(secs) = seek.take_pending() {
+                let reopened = match source.cache_path(&track.url) {
+                    Some(path) => open_pcm_at(&path, secs, None, format).await,
+                    None => Err(SEEK_NEEDS_CACHE.to_string()),
+                };
+                match reopened {
+                    Ok(file) => {
+                        // Fade out here and back in at the new position so the jump doesn't click
+                        let tail = fader.fade_out();
+                        if !tail.is_empty() {
+                            if let Some(playback) = local.as_ref() {
+                                playback.send(tail.clone());
+                            }
+                            let volume_val = volume.load(Ordering::Relaxed) as f32 / 100.0;
+                            let bytes = samples_to_le_bytes(&gain.apply(&tail, volume_val * duck.gain));
+                            send_pcm(&pcm_sender, &pcm_sent, bytes, &mut backpressure).await;
+                        }
+                        fader = TrackFader::new(FADE_SAMPLES);
+                        reader = Box::new(file);
+                        // Opening already checked the offset fits
+                        position_bytes = format.offset_bytes(secs).unwrap_or(position_bytes);
+                        seek.position_secs.store(secs, Ordering::Relaxed);
+                        crate::dlog!("[DJ] Seeked to {secs}s");
+                    }
+                    Err(err) => crate::dlog!("[DJ] Seek failed: {err}"),
+                }
+            }
+
+            // Hold the stream where it is; skips and stops are still honoured above
+            if room_paused {
+                tokio::time::sleep(ROOM_PAUSE_POLL).await;
+                continue;
+            }
+
+            // Read next chunk from stream, giving up if yt-dlp stalls
+            let read_started = Instant::now();
+            let n = match tokio::time::timeout(stream_timeout, reader.read(&mut buf)).await {
+                Ok(Ok(0)) => break, // EOF
+                Ok(Ok(n)) => n,
+                Ok(Err(e)) => {
+                    crate::dlog!("[DJ] Stream read error: {e}");
+                    break;
+                }
+                Err(_) => {
+                    crate::dlog!("[DJ] No audio for {}s, aborting stream", stream_timeout.as_secs());
+                    stalled = true;
+                    break;
+                }
+            };
+            total_bytes += n as u64;
+            position_bytes += n as u64;
+            seek.position_secs.store(position_bytes / format.bytes_per_sec(), Ordering::Relaxed);
+            if throughput.record(n, read_started.elapsed()) {
+                crate::dlog!("[DJ] Stream underrun: {} bytes/s", throughput.bytes_per_sec());
+                crate::emit_event("stream-underrun", throughput.bytes_per_sec());
+            }
+            stream_throughput.store(throughput.bytes_per_sec(), Ordering::Relaxed);
 
             // Convert bytes to i16 samples, apply volume, send to LiveKit
             let volume_val = volume.load(Ordering::Relaxed) as f32 / 100.0;
@@ -1054,73 +3384,293 @@ async fn run_playback_loop(
                 .chunks_exact(2)
                 .map(|b| i16::from_le_bytes([b[0], b[1]]))
                 .collect();
+            let samples = fader.push(&samples);
+            if samples.is_empty() {
+                continue;
+            }
+
+            // Follow local monitoring toggles mid-track
+            let want_local = !local_playback_disabled.load(Ordering::Relaxed);
+            if want_local && local.is_none() && !AUDIO_OUTPUT_UNAVAILABLE.load(Ordering::Relaxed) {
+                crate::dlog!("[DJ] Local monitoring enabled mid-track");
+                local = LocalPlayback::start(volume.clone(), format).await;
+            } else if !want_local {
+                if let Some(playback) = local.take() {
+                    crate::dlog!("[DJ] Local monitoring disabled mid-track");
+                    playback.stop();
+                }
+            }
 
             // Send to local playback
-            if use_local {
-                let _ = local_tx.send(samples.clone());
+            if let Some(playback) = local.as_ref() {
+                playback.send(samples.clone());
             }
 
-            let bytes: Vec<u8> = samples
-                .iter()
-                .map(|s| {
-                    let scaled = (*s as f32 * volume_val)
-                        .clamp(i16::MIN as f32, i16::MAX as f32) as i16;
-                    scaled.to_le_bytes()
-                })
-                .flatten()
-                .collect();
+            // Ducking only lowers what the room hears, not the DJ's local monitor
+            let (duck_target, duck_ramp_ms) = ducking.target();
+            let chunk_ms = n as u64 * 1000 / format.bytes_per_sec();
+            let duck_gain = duck.advance(duck_target, duck_ramp_ms, chunk_ms);
+            let bytes = samples_to_le_bytes(&gain.apply(&samples, volume_val * duck_gain));
 
-            if pcm_sender.is_closed() {
+            if !send_pcm(&pcm_sender, &pcm_sent, bytes, &mut backpressure).await {
                 break;
             }
+        }
 
-            if pcm_sender.send(bytes).await.is_err() {
-                break;
-            }
+        if flush_publisher {
+            // Listeners shouldn't have to sit through whatever the publisher still has buffered
+            pcm_flush.send_replace(pcm_sent.load(Ordering::Relaxed));
         }
 
-        drop(local_tx); // Signal local playback thread that stream is done
+        // Flush the held-back tail, fading it out if the track was cut short
+        let cut_short = skipped || handed_off || stalled;
+        let tail = if cut_short { fader.fade_out() } else { fader.finish() };
+        if !tail.is_empty() {
+            if let Some(playback) = local.as_ref() {
+                playback.send(tail.clone());
+            }
+            let volume_val = volume.load(Ordering::Relaxed) as f32 / 100.0;
+            // Stay at the current ducking level so a tail under speech doesn't jump back up
+            let bytes = samples_to_le_bytes(&gain.apply(&tail, volume_val * duck.gain));
+            // Sent after any flush, so the publisher keeps it and the skip fades out
+            send_pcm(&pcm_sender, &pcm_sent, bytes, &mut backpressure).await;
+        }
+        if cut_short {
+            if let Some(playback) = local.take() {
+                playback.stop();
+            }
+        }
         drop(reader);
+        if let Some(process) = child.take() {
+            kill_pipeline(process).await;
+        }
 
-        crate::dlog!("[DJ] Streamed {} bytes total ({:.1}s at 48kHz stereo)",
-            total_bytes, total_bytes as f64 / 48000.0 / 2.0 / 2.0);
+        crate::dlog!("[DJ] Streamed {} bytes total ({:.1}s at {}Hz/{}ch)",
+            total_bytes, total_bytes as f64 / format.bytes_per_sec() as f64,
+            format.sample_rate, format.channels);
 
-        if skipped {
+        if handed_off {
+            crate::dlog!("[DJ] Stopped streaming after DJ handoff: {}", title);
+        } else if stalled {
+            crate::dlog!("[DJ] Track stalled: {}", title);
+            crate::emit_backend_error("playback", format!("'{title}' stopped downloading, skipping it"));
+        } else if skipped {
             crate::dlog!("[DJ] Track skipped");
         } else {
             crate::dlog!("[DJ] Track finished: {}", title);
         }
 
-        if let (Some(cfg), Some(queued_id)) = (shared_queue.as_ref(), track.queued_id) {
-            if let Err(err) = append_played_event(cfg, queued_id) {
-                crate::dlog!("[DJ] Failed to append played event: {err}");
+        // After a handoff the new DJ resumes the track, so leave it marked as playing
+        if !handed_off {
+            if let (Some(cfg), Some(queued_id)) = (shared_queue.as_ref(), track.queued_id) {
+                let result = if stalled {
+                    append_failed_event(cfg, queued_id)
+                } else {
+                    append_played_event(cfg, queued_id)
+                };
+                if let Err(err) = result {
+                    crate::dlog!("[DJ] Failed to record end of track: {err}");
+                }
             }
         }
 
-        if let Some(handle) = playback_handle {
-            let _ = handle.join();
+        if let Ok(mut current) = current_track.lock() {
+            *current = None;
+        }
+        stream_throughput.store(0, Ordering::Relaxed);
+
+        // Let the local playback thread drain what it already has
+        if let Some(playback) = local {
+            playback.finish();
         }
     }
 
     // Loop ended — go idle
-    if let Ok(mut s) = status.lock() {
-        *s = DjStatus::Idle;
-    }
+    status.send_replace(DjStatus::Idle);
     crate::dlog!("[DJ] Playback loop ended");
 }
 
+/// The audio quality currently selected in settings.
+fn current_quality(audio_quality: &Mutex<AudioQuality>) -> AudioQuality {
+    *audio_quality.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn current_cookies(cookies_path: &Mutex<Option<std::path::PathBuf>>) -> Option<std::path::PathBuf> {
+    cookies_path.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Re-read the shared queue and mirror it into the local playback queue.
+async fn sync_shared_queue(
+    cfg: &SharedQueueConfig,
+    queue: &Mutex<Vec<QueuedTrack>>,
+    status: &tokio::sync::watch::Sender<DjStatus>,
+    local_identity: &Mutex<Option<String>>,
+    active_dj: &Mutex<Option<String>>,
+    cache_dir: Option<std::path::PathBuf>,
+    prefetch_depth: usize,
+    prefetch_concurrency: usize,
+    quality: AudioQuality,
+    cookies: Option<std::path::PathBuf>,
+    current_track: &Mutex<Option<QueuedTrack>>,
+) {
+    let Ok(content) = read_shared_queue_log(cfg) else {
+        return;
+    };
+    let data = shared_queue_data_from_log(cfg, &content);
+    if let Ok(mut dj) = active_dj.lock() {
+        *dj = data.active_dj.clone();
+    }
+    let local = local_identity.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let mut next_queue = data.items;
+    if let Some(now) = data.now_playing {
+        let status_is_idle = matches!(*status.borrow(), DjStatus::Idle);
+        // Pick up a track another DJ was streaming so playback continues after a handoff
+        let streamed_elsewhere = now.dj_identity.is_some() && now.dj_identity != local;
+        // A track this DJ was streaming while idle means the app restarted mid-track
+        let interrupted_here = now.dj_identity.is_some() && now.dj_identity == local;
+        if status_is_idle && (next_queue.is_empty() || streamed_elsewhere || interrupted_here) {
+            tracing::info!(
+                event = "shared_queue_resume_now_playing",
+                title = %now.title,
+                url = %now.url,
+                queued_id = ?now.queued_id
+            );
+            next_queue.insert(0, QueuedTrack {
+                url: now.url,
+                title: now.title,
+                queued_id: now.queued_id,
+                local_id: None,
+                queued_by: None,
+                artist: now.artist,
+                thumbnail: now.thumbnail,
+                queued_at: None,
+                clip: now.clip,
+            });
+        }
+    }
+    let prefetch_items: Vec<String> = next_queue.iter()
+        .take(clamp_prefetch_depth(prefetch_depth))
+        .map(|t| t.url.clone())
+        .collect();
+    if !prefetch_items.is_empty() {
+        let source_for_prefetch = YtDlpSource::new(cache_dir)
+            .with_quality(quality)
+            .with_cookies(cookies.clone());
+        let playing_url = current_track
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .map(|t| t.url.clone());
+        let keep: Vec<std::path::PathBuf> = playing_url
+            .and_then(|url| source_for_prefetch.cache_path(&url))
+            .into_iter()
+            .collect();
+        prefetch_tracks(&source_for_prefetch, prefetch_items, prefetch_concurrency, &keep).await;
+    }
+
+    if let Ok(mut q) = queue.lock() {
+        *q = next_queue;
+    }
+    if !data.needs_metadata.is_empty() {
+        let cfg_clone = cfg.clone();
+        let items = data.needs_metadata;
+        tokio::spawn(async move {
+            fetch_and_append_metadata(&cfg_clone, items, cookies).await;
+        });
+    }
+    // The webhook leaves reporting pushes to this sync, so it doesn't read the log twice
+    let update = summarize_events_since(&content, read_shared_state(&cfg.state_path).last_seen_id);
+    let _ = write_shared_state(cfg, SharedQueueState { last_seen_id: data.max_id });
+    if !update.event_types.is_empty() {
+        crate::emit_event("shared-queue-updated", update);
+    }
+}
+
+fn read_shared_queue_log(cfg: &SharedQueueConfig) -> Result<String, String> {
+    read_queue_log(cfg, cfg.sharding() == ShardStrategy::Buckets)
+}
+
 fn fetch_shared_queue_data(cfg: &SharedQueueConfig) -> Result<SharedQueueData, String> {
-    let (content, _) = read_repo_file(cfg)?;
+    let content = read_shared_queue_log(cfg)?;
+    Ok(shared_queue_data_from_log(cfg, &content))
+}
+
+/// Reduce an already-read log with the room's current participants.
+fn shared_queue_data_from_log(cfg: &SharedQueueConfig, content: &str) -> SharedQueueData {
+    let data = reduce_queue_events_at(content, unix_now_secs(), cfg.participants().as_deref());
+
+    tracing::info!(
+        event = "shared_queue_snapshot",
+        repo = %cfg.repo,
+        path = %cfg.path,
+        max_id = data.max_id,
+        queue_len = data.items.len(),
+        history_len = data.history.len(),
+        needs_metadata_len = data.needs_metadata.len(),
+        skip_events_len = data.skip_events.len(),
+        now_playing = data.now_playing.as_ref().map(|p| p.title.as_str()).unwrap_or(""),
+        unknown_event_types = ?data.unknown_event_types
+    );
+
+    data
+}
+
+/// A `playing` track with no `played`/`failed` after this long may belong to a DJ that
+/// quit mid-track; see `playing_is_orphaned`.
+const ORPHANED_PLAYING_SECS: u64 = 60 * 60;
+
+/// Whether a `playing` track was left behind by a DJ that quit mid-track, and should go
+/// back to the front of the queue. Long mixes are fine while their DJ is still around.
+fn playing_is_orphaned(
+    now: &SharedNowPlayingInternal,
+    active_dj: Option<&str>,
+    present: Option<&[String]>,
+    now_secs: u64,
+) -> bool {
+    let Some(started_at) = now.started_at else {
+        return false;
+    };
+    if now_secs.saturating_sub(started_at) <= ORPHANED_PLAYING_SECS {
+        return false;
+    }
+    if now.duration_secs.is_some_and(|duration| started_at.saturating_add(duration) >= now_secs) {
+        return false;
+    }
+    match active_dj {
+        None => true,
+        // Without the participant list there's no telling whether the DJ left
+        Some(dj) => present.is_some_and(|present| !present.iter().any(|identity| identity == dj)),
+    }
+}
+
+/// Fold the NDJSON event log into the current queue state. Pure, so it can be tested
+/// without `gh`; `fetch_shared_queue_data` is this plus the read.
+pub(crate) fn reduce_queue_events(content: &str) -> SharedQueueData {
+    reduce_queue_events_at(content, unix_now_secs(), None)
+}
+
+/// `reduce_queue_events` at `now_secs`, with the identities in the room when known.
+fn reduce_queue_events_at(content: &str, now_secs: u64, present: Option<&[String]>) -> SharedQueueData {
     let mut max_id = 0;
     let mut queued: Vec<(u64, String)> = Vec::new();
     let mut played: HashSet<u64> = HashSet::new();
     let mut failed: HashSet<u64> = HashSet::new();
+    // Id of the `played`/`failed` event for each finished track, which is its play order
+    let mut finished_at: HashMap<u64, u64> = HashMap::new();
     let mut skip_events: HashMap<u64, u64> = HashMap::new();
-    let mut metadata: HashMap<u64, String> = HashMap::new();
+    let mut metadata: HashMap<u64, TrackMetadata> = HashMap::new();
     let mut queued_by: HashMap<u64, String> = HashMap::new();
+    let mut queued_at: HashMap<u64, u64> = HashMap::new();
+    // Kept across clears: ids are never reused, and the playing track keeps its clip
+    let mut clips: HashMap<u64, (u64, u64)> = HashMap::new();
     let mut last_cleared_id = 0;
     let mut now_playing: Option<SharedNowPlayingInternal> = None;
-    let mut latest_reorder: Option<Vec<u64>> = None;
+    // Running queue order: each reorder applies to the ids queued before it
+    let mut order: Vec<u64> = Vec::new();
+    let mut active_dj: Option<String> = None;
+    let mut seen_uuids: HashSet<String> = HashSet::new();
+    let mut paused = false;
+    let mut unknown_event_types = BTreeSet::new();
 
     for line in content.lines() {
         let line = line.trim();
@@ -1131,22 +3681,37 @@ fn fetch_shared_queue_data(cfg: &SharedQueueConfig) -> Result<SharedQueueData, S
             Ok(event) => {
                 max_id = max_id.max(event.id);
                 match event.event_type.as_str() {
+                    // Events are appended in id order, so the first copy of a uuid has the lowest id
+                    "queued" if event.uuid.as_ref().is_some_and(|uuid| seen_uuids.contains(uuid)) => {}
                     "queued" => {
+                        if let Some(uuid) = event.uuid {
+                            seen_uuids.insert(uuid);
+                        }
                         if let Some(url) = event.url {
                             if let Some(by) = event.by {
                                 queued_by.insert(event.id, by);
                             }
+                            if let Some(at) = event.at {
+                                queued_at.insert(event.id, at);
+                            }
+                            // Other clients may not validate clips, so out-of-range ones play whole
+                            if let Some(clip) = event.clip.filter(|clip| check_clip(*clip).is_ok()) {
+                                clips.insert(event.id, clip);
+                            }
                             queued.push((event.id, url));
+                            order.push(event.id);
                         }
                     }
                     "played" => {
                         if let Some(ref_id) = event.ref_id {
                             played.insert(ref_id);
+                            finished_at.entry(ref_id).or_insert(event.id);
                         }
                     }
                     "failed" => {
                         if let Some(ref_id) = event.ref_id {
                             failed.insert(ref_id);
+                            finished_at.entry(ref_id).or_insert(event.id);
                         }
                     }
                     "playing" => {
@@ -1155,6 +3720,13 @@ fn fetch_shared_queue_data(cfg: &SharedQueueConfig) -> Result<SharedQueueData, S
                                 title,
                                 url,
                                 queued_id: event.ref_id,
+                                dj_identity: event.dj_identity,
+                                artist: None,
+                                thumbnail: None,
+                                clip: None,
+                                queued_by: event.ref_id.and_then(|id| queued_by.get(&id).cloned()),
+                                started_at: event.at,
+                                duration_secs: event.duration,
                             });
                         }
                     }
@@ -1165,26 +3737,52 @@ fn fetch_shared_queue_data(cfg: &SharedQueueConfig) -> Result<SharedQueueData, S
                     }
                     "metadata" => {
                         if let (Some(ref_id), Some(title)) = (event.ref_id, event.title) {
-                            metadata.insert(ref_id, title);
+                            metadata.insert(ref_id, TrackMetadata {
+                                title,
+                                artist: event.artist,
+                                thumbnail: event.thumbnail,
+                            });
                         }
                     }
                     "cleared" => {
                         last_cleared_id = last_cleared_id.max(event.id);
+                        // The current song survives a clear; only a `skip` stops it
+                        let playing_id = now_playing.as_ref().and_then(|now| now.queued_id);
+                        if playing_id.is_some_and(|id| played.contains(&id) || failed.contains(&id)) {
+                            now_playing = None;
+                        }
+                        let playing_id = now_playing.as_ref().and_then(|now| now.queued_id);
+                        let playing_skip = playing_id.and_then(|id| skip_events.get(&id).map(|skip| (id, *skip)));
+                        let playing_meta = playing_id.and_then(|id| metadata.remove(&id).map(|meta| (id, meta)));
                         queued.clear();
                         played.clear();
                         failed.clear();
+                        finished_at.clear();
                         skip_events.clear();
                         metadata.clear();
                         queued_by.clear();
-                        now_playing = None;
-                        latest_reorder = None;
+                        queued_at.clear();
+                        order.clear();
+                        skip_events.extend(playing_skip);
+                        metadata.extend(playing_meta);
                     }
                     "reordered" => {
-                        if let Some(order) = event.order {
-                            latest_reorder = Some(order);
+                        if let Some(reorder) = event.order {
+                            order = apply_reorder(&order, &reorder);
+                        }
+                    }
+                    // A clear leaves the pause alone; only `resume` lifts it
+                    "pause" => paused = true,
+                    "resume" => paused = false,
+                    // `dj_claimed` is the older name for the same event
+                    "dj_claim" | "dj_claimed" => {
+                        if let Some(identity) = event.dj_identity {
+                            active_dj = Some(identity);
                         }
                     }
-                    _ => {}
+                    other => {
+                        unknown_event_types.insert(other.to_string());
+                    }
                 }
             }
             Err(err) => {
@@ -1202,14 +3800,27 @@ fn fetch_shared_queue_data(cfg: &SharedQueueConfig) -> Result<SharedQueueData, S
 
     queued.sort_by_key(|(id, _)| *id);
 
-    // Build history from played items (most recent first)
-    let history: Vec<(String, Option<String>, Option<String>)> = queued
+    // Build history from played items, most recently finished first. Queued-id order
+    // isn't play order once the queue has been reordered.
+    let mut finished: Vec<&(u64, String)> = queued
         .iter()
         .filter(|(id, _)| *id > last_cleared_id && (played.contains(id) || failed.contains(id)))
-        .rev()
+        .collect();
+    finished.sort_by_key(|(id, _)| std::cmp::Reverse(finished_at.get(id).copied().unwrap_or(0)));
+    let history: Vec<(String, Option<TrackMetadata>, Option<String>)> = finished
+        .into_iter()
         .map(|(id, url)| (url.clone(), metadata.get(id).cloned(), queued_by.get(id).cloned()))
         .collect();
 
+    let mut orphaned_id = None;
+    let orphaned = now_playing
+        .as_ref()
+        .filter(|now| now.queued_id.is_none_or(|id| !played.contains(&id) && !failed.contains(&id)))
+        .is_some_and(|now| playing_is_orphaned(now, active_dj.as_deref(), present, now_secs));
+    if orphaned {
+        orphaned_id = now_playing.take().and_then(|now| now.queued_id);
+    }
+
     let playing_id = now_playing.as_ref().and_then(|now| now.queued_id);
     let mut items: Vec<QueuedTrack> = queued
         .into_iter()
@@ -1220,24 +3831,30 @@ fn fetch_shared_queue_data(cfg: &SharedQueueConfig) -> Result<SharedQueueData, S
                 && Some(*id) != playing_id
         })
         .map(|(id, url)| {
-            let title = metadata.get(&id).cloned();
+            let meta = metadata.get(&id).cloned();
             QueuedTrack {
                 url,
-                title: title.unwrap_or_else(|| "Loading...".to_string()),
+                title: meta.as_ref().map(|m| m.title.clone()).unwrap_or_else(|| "Loading...".to_string()),
                 queued_id: Some(id),
+                local_id: None,
                 queued_by: queued_by.get(&id).cloned(),
+                artist: meta.as_ref().and_then(|m| m.artist.clone()),
+                thumbnail: meta.and_then(|m| m.thumbnail),
+                queued_at: queued_at.get(&id).copied(),
+                clip: clips.get(&id).copied(),
             }
         })
         .collect();
 
-    // Apply latest reorder if present
-    if let Some(ref order) = latest_reorder {
-        let order_map: HashMap<u64, usize> = order.iter().enumerate().map(|(i, id)| (*id, i)).collect();
-        items.sort_by_key(|t| {
-            t.queued_id
-                .and_then(|id| order_map.get(&id).copied())
-                .unwrap_or(usize::MAX)
-        });
+    let order_map: HashMap<u64, usize> = order.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+    items.sort_by_key(|t| {
+        t.queued_id
+            .and_then(|id| order_map.get(&id).copied())
+            .unwrap_or(usize::MAX)
+    });
+    if let Some(pos) = orphaned_id.and_then(|id| items.iter().position(|t| t.queued_id == Some(id))) {
+        let track = items.remove(pos);
+        items.insert(0, track);
     }
 
     if let Some(ref_id) = now_playing.as_ref().and_then(|now| now.queued_id) {
@@ -1245,27 +3862,25 @@ fn fetch_shared_queue_data(cfg: &SharedQueueConfig) -> Result<SharedQueueData, S
             now_playing = None;
         }
     }
+    if let Some(now) = now_playing.as_mut() {
+        if let Some(meta) = now.queued_id.and_then(|id| metadata.get(&id)) {
+            now.artist = meta.artist.clone();
+            now.thumbnail = meta.thumbnail.clone();
+        }
+        now.clip = now.queued_id.and_then(|id| clips.get(&id).copied());
+    }
 
-    tracing::info!(
-        event = "shared_queue_snapshot",
-        repo = %cfg.repo,
-        path = %cfg.path,
-        max_id = max_id,
-        queue_len = items.len(),
-        history_len = history.len(),
-        needs_metadata_len = needs_metadata.len(),
-        skip_events_len = skip_events.len(),
-        now_playing = now_playing.as_ref().map(|p| p.title.as_str()).unwrap_or("")
-    );
-
-    Ok(SharedQueueData {
+    SharedQueueData {
         items,
         now_playing,
         max_id,
         skip_events,
         needs_metadata,
         history,
-    })
+        active_dj,
+        paused,
+        unknown_event_types,
+    }
 }
 
 fn shared_queue_snapshot_from_data(data: SharedQueueData) -> SharedQueueSnapshot {
@@ -1273,6 +3888,9 @@ fn shared_queue_snapshot_from_data(data: SharedQueueData) -> SharedQueueSnapshot
     let now_playing = data.now_playing.map(|now| SharedNowPlaying {
         title: now.title,
         url: now.url,
+        artist: now.artist,
+        thumbnail: now.thumbnail,
+        queued_by: now.queued_by,
     });
     SharedQueueSnapshot {
         queue: data.items.into_iter().map(|t| {
@@ -1281,32 +3899,152 @@ fn shared_queue_snapshot_from_data(data: SharedQueueData) -> SharedQueueSnapshot
                 title: if t.title == "Loading..." { None } else { Some(t.title) },
                 id: t.queued_id.unwrap_or(0),
                 queued_by: t.queued_by,
+                artist: t.artist,
+                thumbnail: t.thumbnail,
+                queued_at: t.queued_at,
+                clip: t.clip,
             }
         }).collect(),
         now_playing,
-        history: data.history.into_iter().map(|(url, title, queued_by)| {
-            SharedHistoryItem { url, title, queued_by }
+        history: data.history.into_iter().map(|(url, meta, queued_by)| {
+            let (title, artist, thumbnail) = match meta {
+                Some(meta) => (Some(meta.title), meta.artist, meta.thumbnail),
+                None => (None, None, None),
+            };
+            SharedHistoryItem { url, title, queued_by, artist, thumbnail }
         }).collect(),
+        paused: data.paused,
     }
 }
 
-fn shared_skip_requested(cfg: &SharedQueueConfig, queued_id: u64, since_id: u64) -> Result<bool, String> {
+/// What the room wants the current track to do, read in one fetch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PlaybackControl {
+    skip: bool,
+    paused: bool,
+}
+
+fn shared_playback_control(cfg: &SharedQueueConfig, queued_id: u64, since_id: u64) -> Result<PlaybackControl, String> {
     let data = fetch_shared_queue_data(cfg)?;
-    Ok(data
-        .skip_events
-        .get(&queued_id)
-        .map(|event_id| *event_id > since_id)
-        .unwrap_or(false))
-}
-
-fn read_repo_file(cfg: &SharedQueueConfig) -> Result<(String, Option<String>), String> {
-    let output = std::process::Command::new(&cfg.gh_path)
-        .args([
-            "api",
-            &format!("repos/{}/contents/{}", cfg.repo, cfg.path),
-        ])
-        .output()
-        .map_err(|e| format!("Failed to run gh api: {e}"))?;
+    Ok(PlaybackControl {
+        skip: data
+            .skip_events
+            .get(&queued_id)
+            .map(|event_id| *event_id > since_id)
+            .unwrap_or(false),
+        paused: data.paused,
+    })
+}
+
+/// `shared_playback_control` on a blocking thread, since it reads the queue through `gh`.
+async fn fetch_playback_control(cfg: &SharedQueueConfig, queued_id: u64, since_id: u64) -> Result<PlaybackControl, String> {
+    let cfg = cfg.clone();
+    tokio::task::spawn_blocking(move || shared_playback_control(&cfg, queued_id, since_id))
+        .await
+        .map_err(|e| format!("Playback control check failed: {e}"))?
+}
+
+/// Whether a `gh api` error means the file or directory isn't there.
+pub(crate) fn is_not_found(err: &str) -> bool {
+    err.contains("404") || err.contains("Not Found")
+}
+
+/// The event log: the queue file, merged with every shard when `include_shards` is set
+/// or other clients have been found writing shards.
+/// A queue file that doesn't exist yet reads as empty; the first append creates it.
+fn read_queue_log(cfg: &SharedQueueConfig, include_shards: bool) -> Result<String, String> {
+    let queue_file = match read_repo_file(cfg, &cfg.path) {
+        Ok((content, _)) => content,
+        Err(err) if is_not_found(&err) => String::new(),
+        Err(err) => return Err(err),
+    };
+    let shards = if include_shards || cfg.log.shard_check_due(Instant::now()) {
+        list_repo_dir(cfg, &shard_dir(&cfg.path))?
+    } else {
+        Vec::new()
+    };
+    if shards.is_empty() {
+        return Ok(queue_file);
+    }
+    cfg.log.sharded.store(true, Ordering::Relaxed);
+    let mut logs = vec![queue_file];
+    for path in shards {
+        let (content, sha) = read_repo_file(cfg, &path)?;
+        if let Some(sha) = sha {
+            cfg.log.remember_shard(&path, &sha, &content);
+        }
+        logs.push(content);
+    }
+    Ok(merge_shard_logs(&logs))
+}
+
+/// Highest event id in `queue_file` and every shard as they are now. Ids order the merged
+/// log, so new ones must sit above whatever other clients have written since the last sync.
+fn current_max_id(cfg: &SharedQueueConfig, queue_file: &str) -> Result<u64, String> {
+    let listing = list_repo_files(cfg, &shard_dir(&cfg.path))?;
+    if !listing.is_empty() {
+        cfg.log.sharded.store(true, Ordering::Relaxed);
+    }
+    let shards_max = cfg.log.shards_max_id(&listing, |path| read_repo_file(cfg, path).map(|(content, _)| content))?;
+    Ok(max_event_id(queue_file).max(shards_max))
+}
+
+/// Interleave several NDJSON logs into one ordered by event id.
+fn merge_shard_logs(logs: &[String]) -> String {
+    let mut lines: Vec<(u64, &str)> = logs
+        .iter()
+        .flat_map(|log| log.lines())
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let id = serde_json::from_str::<QueueEvent>(line).map(|e| e.id).unwrap_or(0);
+            (id, line)
+        })
+        .collect();
+    lines.sort_by_key(|(id, _)| *id);
+    lines.into_iter().map(|(_, line)| format!("{line}\n")).collect()
+}
+
+fn max_event_id(content: &str) -> u64 {
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<QueueEvent>(line).ok())
+        .map(|event| event.id)
+        .max()
+        .unwrap_or(0)
+}
+
+/// NDJSON files directly inside `dir`, sorted; empty when the directory doesn't exist.
+fn list_repo_dir(cfg: &SharedQueueConfig, dir: &str) -> Result<Vec<String>, String> {
+    Ok(list_repo_files(cfg, dir)?.into_iter().map(|(path, _)| path).collect())
+}
+
+/// `list_repo_dir`, with each file's blob sha.
+fn list_repo_files(cfg: &SharedQueueConfig, dir: &str) -> Result<Vec<(String, String)>, String> {
+    let output = crate::gh_throttle::output(
+        std::process::Command::new(&cfg.gh_path)
+            .args(["api", &format!("repos/{}/contents/{dir}", cfg.repo)]),
+    )?;
+    if !output.status.success() {
+        let err = String::from_utf8_lossy(&output.stderr).to_string();
+        return if is_not_found(&err) { Ok(Vec::new()) } else { Err(err) };
+    }
+    let entries: Vec<RepoDirEntry> = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("Failed to parse repo directory: {e}"))?;
+    let mut files: Vec<(String, String)> = entries
+        .into_iter()
+        .filter(|entry| entry.kind == "file" && entry.path.ends_with(".ndjson"))
+        .map(|entry| (entry.path, entry.sha))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+fn read_repo_file(cfg: &SharedQueueConfig, path: &str) -> Result<(String, Option<String>), String> {
+    let output = crate::gh_throttle::output(
+        std::process::Command::new(&cfg.gh_path)
+            .args(["api", &format!("repos/{}/contents/{path}", cfg.repo)]),
+    )?;
     if !output.status.success() {
         return Err(String::from_utf8_lossy(&output.stderr).to_string());
     }
@@ -1323,7 +4061,7 @@ fn read_repo_file(cfg: &SharedQueueConfig) -> Result<(String, Option<String>), S
     Ok((content, Some(response.sha)))
 }
 
-fn write_repo_file(cfg: &SharedQueueConfig, content: &str, sha: Option<String>) -> Result<(), String> {
+fn write_repo_file(cfg: &SharedQueueConfig, path: &str, content: &str, sha: Option<String>) -> Result<(), String> {
     let mut tmp_path = std::env::temp_dir();
     let suffix = SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -1338,7 +4076,7 @@ fn write_repo_file(cfg: &SharedQueueConfig, content: &str, sha: Option<String>)
         "api".to_string(),
         "-X".to_string(),
         "PUT".to_string(),
-        format!("repos/{}/contents/{}", cfg.repo, cfg.path),
+        format!("repos/{}/contents/{path}", cfg.repo),
         "-f".to_string(),
         "message=Update shared queue".to_string(),
         "-f".to_string(),
@@ -1348,10 +4086,7 @@ fn write_repo_file(cfg: &SharedQueueConfig, content: &str, sha: Option<String>)
         args.push("-f".to_string());
         args.push(format!("sha={sha}"));
     }
-    let output = std::process::Command::new(&cfg.gh_path)
-        .args(args)
-        .output()
-        .map_err(|e| format!("Failed to run gh api: {e}"))?;
+    let output = crate::gh_throttle::output(std::process::Command::new(&cfg.gh_path).args(args))?;
 
     let _ = std::fs::remove_file(&tmp_path);
     if !output.status.success() {
@@ -1360,6 +4095,53 @@ fn write_repo_file(cfg: &SharedQueueConfig, content: &str, sha: Option<String>)
     Ok(())
 }
 
+fn read_shared_state(state_path: &std::path::Path) -> SharedQueueState {
+    std::fs::read_to_string(state_path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Collect the distinct event types appended after `last_seen_id`.
+fn summarize_events_since(content: &str, last_seen_id: u64) -> QueueUpdate {
+    let mut update = QueueUpdate {
+        max_id: last_seen_id,
+        event_types: Vec::new(),
+    };
+    for line in content.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let Ok(event) = serde_json::from_str::<QueueEvent>(line) else {
+            continue;
+        };
+        update.max_id = update.max_id.max(event.id);
+        if event.id > last_seen_id && !update.event_types.contains(&event.event_type) {
+            update.event_types.push(event.event_type);
+        }
+    }
+    update
+}
+
+/// Describe what changed in the shared queue since the stored `last_seen_id`.
+pub(crate) fn queue_update_since_last_seen(
+    repo: String,
+    path: String,
+    gh_path: String,
+    state_path: std::path::PathBuf,
+) -> Result<QueueUpdate, String> {
+    let last_seen_id = read_shared_state(&state_path).last_seen_id;
+    let cfg = SharedQueueConfig {
+        repo,
+        path,
+        state_path,
+        gh_path,
+        sharding: Arc::default(),
+        participants: Arc::default(),
+        log: Arc::default(),
+    };
+    // Webhooks only fire on pushes, so always checking the shards here is cheap
+    let content = read_queue_log(&cfg, true)?;
+    Ok(summarize_events_since(&content, last_seen_id))
+}
+
 fn write_shared_state(cfg: &SharedQueueConfig, state: SharedQueueState) -> Result<(), String> {
     if let Some(parent) = cfg.state_path.parent() {
         std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create state dir: {e}"))?;
@@ -1369,20 +4151,69 @@ fn write_shared_state(cfg: &SharedQueueConfig, state: SharedQueueState) -> Resul
     std::fs::write(&cfg.state_path, content).map_err(|e| format!("Failed to write state: {e}"))
 }
 
-fn append_queue_event(cfg: &SharedQueueConfig, url: &str, queued_by: Option<&str>) -> Result<u64, String> {
-    let queued_by = queued_by.map(|s| s.to_string());
-    let event_builder = move |next_id| {
+fn unix_now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn queued_event_builder<'a>(
+    url: &'a str,
+    queued_by: Option<&'a str>,
+    clip: Option<(u64, u64)>,
+) -> impl Fn(u64) -> serde_json::Value + 'a {
+    // Shared across retries so a write that landed despite an error isn't queued twice
+    let uuid = uuid::Uuid::new_v4().to_string();
+    let at = unix_now_secs();
+    move |next_id| {
         let mut event = serde_json::json!({
             "id": next_id,
             "type": "queued",
             "url": url,
+            "uuid": uuid,
+            "at": at,
         });
-        if let Some(by) = queued_by.clone() {
-            event["by"] = serde_json::Value::String(by);
+        if let Some(by) = queued_by {
+            event["by"] = serde_json::Value::String(by.to_string());
+        }
+        if let Some((start, end)) = clip {
+            event["clip"] = serde_json::json!([start, end]);
         }
         event
-    };
-    append_event_with_retry(cfg, event_builder)
+    }
+}
+
+fn append_queue_event(
+    cfg: &SharedQueueConfig,
+    url: &str,
+    queued_by: Option<&str>,
+    clip: Option<(u64, u64)>,
+) -> Result<u64, String> {
+    append_event_with_retry(cfg, queued_event_builder(url, queued_by, clip))
+}
+
+/// Queue a track and move it to the front in one write, so the reorder names exactly the
+/// track just queued. Returns the `queued` event id.
+fn append_queued_next_events(
+    cfg: &SharedQueueConfig,
+    url: &str,
+    queued_by: Option<&str>,
+    clip: Option<(u64, u64)>,
+) -> Result<u64, String> {
+    let queued = queued_event_builder(url, queued_by, clip);
+    let ids = append_events_with_retry(cfg, 2, |ids| {
+        let (queued_id, reorder_id) = (ids[0], ids[1]);
+        vec![
+            queued(queued_id),
+            serde_json::json!({
+                "id": reorder_id,
+                "type": "reordered",
+                "order": [queued_id],
+            }),
+        ]
+    })?;
+    Ok(ids[0])
 }
 
 fn append_played_event(cfg: &SharedQueueConfig, queued_id: u64) -> Result<u64, String> {
@@ -1398,16 +4229,42 @@ fn append_playing_event(
     queued_id: u64,
     title: &str,
     url: &str,
+    dj_identity: Option<&str>,
+    duration_secs: Option<u64>,
 ) -> Result<u64, String> {
     let title = title.to_string();
     let url = url.to_string();
+    let dj_identity = dj_identity.map(|s| s.to_string());
+    let at = unix_now_secs();
     let event_builder = move |next_id| {
-        serde_json::json!({
+        let mut event = serde_json::json!({
             "id": next_id,
             "type": "playing",
             "ref": queued_id,
             "title": title,
             "url": url,
+            "at": at,
+        });
+        if let Some(dj) = dj_identity.clone() {
+            event["dj_identity"] = serde_json::Value::String(dj);
+        }
+        if let Some(duration) = duration_secs {
+            event["duration"] = serde_json::Value::from(duration);
+        }
+        event
+    };
+    append_event_with_retry(cfg, event_builder)
+}
+
+fn append_dj_claim_event(cfg: &SharedQueueConfig, identity: &str) -> Result<u64, String> {
+    let identity = identity.to_string();
+    let at = unix_now_secs();
+    let event_builder = move |next_id| {
+        serde_json::json!({
+            "id": next_id,
+            "type": "dj_claim",
+            "dj_identity": identity,
+            "at": at,
         })
     };
     append_event_with_retry(cfg, event_builder)
@@ -1418,10 +4275,15 @@ fn append_skip_event(cfg: &SharedQueueConfig, queued_id: u64) -> Result<u64, Str
 }
 
 fn append_cleared_event(cfg: &SharedQueueConfig) -> Result<u64, String> {
+    append_room_event(cfg, "cleared")
+}
+
+/// Append an event that applies to the whole room rather than one track.
+fn append_room_event(cfg: &SharedQueueConfig, event_type: &str) -> Result<u64, String> {
     let event_builder = |next_id| {
         serde_json::json!({
             "id": next_id,
-            "type": "cleared",
+            "type": event_type,
         })
     };
     append_event_with_retry(cfg, event_builder)
@@ -1441,91 +4303,247 @@ fn append_reorder_event(cfg: &SharedQueueConfig, order: Vec<u64>) -> Result<u64,
 fn append_metadata_event(
     cfg: &SharedQueueConfig,
     queued_id: u64,
-    title: &str,
+    meta: &TrackMetadata,
     url: &str,
 ) -> Result<u64, String> {
-    let title = title.to_string();
+    let meta = meta.clone();
     let url = url.to_string();
     let event_builder = move |next_id| {
-        serde_json::json!({
+        let mut event = serde_json::json!({
             "id": next_id,
             "type": "metadata",
             "ref": queued_id,
-            "title": title,
+            "title": meta.title,
             "url": url,
-        })
+        });
+        if let Some(artist) = meta.artist.clone() {
+            event["artist"] = serde_json::Value::String(artist);
+        }
+        if let Some(thumbnail) = meta.thumbnail.clone() {
+            event["thumbnail"] = serde_json::Value::String(thumbnail);
+        }
+        event
     };
     append_event_with_retry(cfg, event_builder)
 }
 
-/// Fetch metadata (title) for queued items that don't have it yet, and append metadata events.
-async fn fetch_and_append_metadata(cfg: &SharedQueueConfig, items: Vec<(u64, String)>) {
+/// Parse `yt-dlp --print` output: title, uploader and thumbnail lines in that order.
+fn parse_metadata_output(stdout: &str) -> Option<TrackMetadata> {
+    let mut lines = stdout.lines().map(str::trim);
+    let title = lines.next().filter(|t| !t.is_empty())?.to_string();
+    // yt-dlp prints "NA" for fields the extractor doesn't provide
+    let mut optional = || {
+        lines
+            .next()
+            .filter(|v| !v.is_empty() && *v != "NA")
+            .map(str::to_string)
+    };
+    let artist = optional();
+    let thumbnail = optional();
+    Some(TrackMetadata { title, artist, thumbnail })
+}
+
+/// Fetch metadata (title, uploader, thumbnail) for queued items that don't have it yet, and append metadata events.
+async fn fetch_and_append_metadata(
+    cfg: &SharedQueueConfig,
+    items: Vec<(u64, String)>,
+    cookies: Option<std::path::PathBuf>,
+) {
     for (queued_id, url) in items {
-        let title_output = tokio::process::Command::new("yt-dlp")
-            .args(["--get-title", "--no-warnings", &url])
+        let output = tokio::process::Command::new("yt-dlp")
+            .args(cookies_args(cookies.as_deref()))
+            .args([
+                "--print", "%(title)s",
+                "--print", "%(uploader)s",
+                "--print", "%(thumbnail)s",
+                "--no-warnings",
+                &url,
+            ])
             .output()
             .await;
-        let title = match title_output {
+        let meta = match output {
             Ok(output) if output.status.success() => {
-                String::from_utf8_lossy(&output.stdout).trim().to_string()
+                match parse_metadata_output(&String::from_utf8_lossy(&output.stdout)) {
+                    Some(meta) => meta,
+                    None => continue,
+                }
             }
             _ => continue,
         };
-        crate::dlog!("[DJ] Fetched metadata for queued {}: '{}'", queued_id, title);
-        if let Err(e) = append_metadata_event(cfg, queued_id, &title, &url) {
+        crate::dlog!("[DJ] Fetched metadata for queued {}: '{}' by {:?}", queued_id, meta.title, meta.artist);
+        if let Err(e) = append_metadata_event(cfg, queued_id, &meta, &url) {
             crate::dlog!("[DJ] Failed to append metadata event: {e}");
         }
     }
 }
 
-/// Prefetch upcoming tracks by downloading them to cache.
-/// Also enforces a max of 10 cached items (LRU eviction).
-async fn prefetch_tracks(source: &YtDlpSource, urls: Vec<String>) {
+/// Prefetch upcoming tracks by downloading them to cache, up to `concurrency` at a time.
+/// Once all downloads finish, enforces a max of [`CACHE_LIMIT`] cached items (LRU
+/// eviction), never evicting the files in `keep`.
+async fn prefetch_tracks(
+    source: &YtDlpSource,
+    urls: Vec<String>,
+    concurrency: usize,
+    keep: &[std::path::PathBuf],
+) {
+    use futures_util::StreamExt;
+
     let cache_dir = match source.cache_dir.as_ref() {
         Some(d) => d,
         None => return,
     };
 
-    for url in &urls {
-        if let Some(pcm_path) = source.cache_path(url) {
-            if pcm_path.exists() {
-                continue; // Already cached
+    futures_util::stream::iter(urls)
+        .for_each_concurrent(clamp_prefetch_concurrency(concurrency), |url| async move {
+            if let Some(pcm_path) = source.cache_path(&url) {
+                if pcm_path.exists() {
+                    return; // Already cached
+                }
             }
-        }
-        crate::dlog!("[DJ] Prefetching: {}", url);
-        // Use the non-streaming fetch to download fully to cache
-        match source.fetch_audio(url).await {
-            Ok(info) => crate::dlog!("[DJ] Prefetched: '{}' ({} bytes)", info.title, info.audio_data.len()),
-            Err(e) => crate::dlog!("[DJ] Prefetch failed for {}: {}", url, e),
-        }
-    }
+            crate::dlog!("[DJ] Prefetching: {}", url);
+            // Use the non-streaming fetch to download fully to cache
+            match source.fetch_audio(&url).await {
+                Ok(info) => crate::dlog!("[DJ] Prefetched: '{}' ({} bytes)", info.title, info.audio_data.len()),
+                Err(e) => crate::dlog!("[DJ] Prefetch failed for {}: {}", url, e),
+            }
+        })
+        .await;
 
     // Enforce cache limit: keep only the 10 most recently modified .pcm files
-    enforce_cache_limit(cache_dir, 10);
+    enforce_cache_limit(cache_dir, CACHE_LIMIT, keep);
 }
 
-/// Remove oldest cached .pcm (and matching .title) files if count exceeds limit.
-fn enforce_cache_limit(cache_dir: &std::path::Path, max_items: usize) {
-    let mut pcm_files: Vec<(std::path::PathBuf, std::time::SystemTime)> = Vec::new();
+/// Payload of the `prefetch-progress` event.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PrefetchProgress {
+    pub done: usize,
+    pub total: usize,
+}
+
+/// Download every URL into the cache, up to `concurrency` at a time, reporting each
+/// finished item. Stops starting new downloads once `cancel` is set. The cache limit
+/// is not enforced here, so a long queue stays cached until the next automatic prefetch.
+/// Returns how many items were handled before finishing or being cancelled.
+pub async fn prefetch_all(
+    source: &YtDlpSource,
+    urls: Vec<String>,
+    concurrency: usize,
+    cancel: &AtomicBool,
+    on_progress: impl Fn(PrefetchProgress),
+) -> usize {
+    use futures_util::StreamExt;
+
+    let total = urls.len();
+    let done = AtomicUsize::new(0);
+    let (done_ref, on_progress) = (&done, &on_progress);
+    futures_util::stream::iter(urls)
+        .for_each_concurrent(clamp_prefetch_concurrency(concurrency), |url| async move {
+            if cancel.load(Ordering::Relaxed) {
+                return;
+            }
+            let cached = source.cache_path(&url).is_some_and(|path| path.exists());
+            if !cached {
+                if let Err(e) = source.fetch_audio(&url).await {
+                    crate::dlog!("[DJ] Prefetch failed for {}: {}", url, e);
+                }
+            }
+            let done = done_ref.fetch_add(1, Ordering::Relaxed) + 1;
+            on_progress(PrefetchProgress { done, total });
+        })
+        .await;
+    done.load(Ordering::Relaxed)
+}
+
+/// A track in the audio cache, for replaying without downloading.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedTrack {
+    /// YouTube video ID.
+    pub id: String,
+    pub title: String,
+    pub size_bytes: u64,
+    /// Unix seconds the track was cached or last played from the cache.
+    pub cached_at: u64,
+}
+
+/// Mark a cached file as just used, so the cache evicts least recently played tracks.
+fn touch_cache_file(path: &std::path::Path) {
+    let touched = std::fs::File::options()
+        .append(true)
+        .open(path)
+        .and_then(|file| file.set_modified(std::time::SystemTime::now()));
+    if let Err(e) = touched {
+        crate::dlog!("[DJ] Couldn't update cache mtime for {}: {e}", path.display());
+    }
+}
+
+/// Every cached .pcm file with its size and last-used time.
+fn cached_pcm_files(cache_dir: &std::path::Path) -> Vec<(std::path::PathBuf, u64, std::time::SystemTime)> {
+    let mut pcm_files = Vec::new();
     if let Ok(entries) = std::fs::read_dir(cache_dir) {
         for entry in entries.flatten() {
             let path = entry.path();
             if path.extension().map(|e| e == "pcm").unwrap_or(false) {
-                let mtime = entry.metadata()
-                    .and_then(|m| m.modified())
+                let metadata = entry.metadata().ok();
+                let size = metadata.as_ref().map_or(0, |m| m.len());
+                let mtime = metadata
+                    .and_then(|m| m.modified().ok())
                     .unwrap_or(std::time::UNIX_EPOCH);
-                pcm_files.push((path, mtime));
+                pcm_files.push((path, size, mtime));
             }
         }
     }
+    pcm_files
+}
+
+/// Cached tracks with their titles, most recently used first. One entry per video,
+/// even when it is cached at more than one quality.
+pub fn list_cached_tracks(cache_dir: &std::path::Path) -> Vec<CachedTrack> {
+    let mut pcm_files = cached_pcm_files(cache_dir);
+    pcm_files.sort_by_key(|(_, _, mtime)| std::cmp::Reverse(*mtime));
+    let mut tracks: Vec<CachedTrack> = Vec::new();
+    for (path, size_bytes, mtime) in pcm_files {
+        let Some(stem) = path.file_stem().map(|s| s.to_string_lossy().into_owned()) else {
+            continue;
+        };
+        let id = [AudioQuality::Medium, AudioQuality::Low]
+            .iter()
+            .find_map(|q| stem.strip_suffix(q.cache_suffix()))
+            .unwrap_or(&stem)
+            .to_string();
+        if tracks.iter().any(|t| t.id == id) {
+            continue;
+        }
+        let title = read_title_cache(&path.with_extension("title"))
+            .map(|title| title.trim().to_string())
+            .unwrap_or_else(|| id.clone());
+        let cached_at = mtime
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        tracks.push(CachedTrack { id, title, size_bytes, cached_at });
+    }
+    tracks
+}
 
-    if pcm_files.len() <= max_items {
+/// Remove oldest cached .pcm (and matching .title) files if count exceeds limit.
+/// Files in `keep`, such as the track being streamed, are never removed.
+fn enforce_cache_limit(cache_dir: &std::path::Path, max_items: usize, keep: &[std::path::PathBuf]) {
+    let all = cached_pcm_files(cache_dir);
+    let total = all.len();
+    let mut pcm_files: Vec<(std::path::PathBuf, std::time::SystemTime)> = all
+        .into_iter()
+        .filter(|(path, _, _)| !keep.contains(path))
+        .map(|(path, _, mtime)| (path, mtime))
+        .collect();
+
+    if total <= max_items {
         return;
     }
 
     // Sort by mtime ascending (oldest first)
     pcm_files.sort_by_key(|(_, mtime)| *mtime);
-    let to_remove = pcm_files.len() - max_items;
+    let to_remove = total - max_items;
     for (path, _) in pcm_files.iter().take(to_remove) {
         crate::dlog!("[DJ] Evicting cached: {}", path.display());
         let _ = std::fs::remove_file(path);
@@ -1549,27 +4567,62 @@ fn append_event_with_ref(cfg: &SharedQueueConfig, event_type: &str, queued_id: u
 fn append_event_with_retry<F>(cfg: &SharedQueueConfig, build_event: F) -> Result<u64, String>
 where
     F: Fn(u64) -> serde_json::Value,
+{
+    let ids = append_events_with_retry(cfg, 1, |ids| vec![build_event(ids[0])])?;
+    Ok(ids[0])
+}
+
+/// Append `count` events in a single write; `build_events` gets their ids in order.
+fn append_events_with_retry<F>(cfg: &SharedQueueConfig, count: usize, build_events: F) -> Result<Vec<u64>, String>
+where
+    F: Fn(&[u64]) -> Vec<serde_json::Value>,
 {
     for attempt in 0..2 {
-        let (content, sha) = read_repo_file(cfg).unwrap_or((String::new(), None));
-        let mut max_id = 0;
-        for line in content.lines() {
-            if let Ok(event) = serde_json::from_str::<QueueEvent>(line) {
-                max_id = max_id.max(event.id);
+        let (path, ids, content, sha) = match cfg.sharding() {
+            ShardStrategy::Single => {
+                let (content, sha) = read_repo_file(cfg, &cfg.path).unwrap_or((String::new(), None));
+                // Shards other clients write count too, so ids keep increasing across files
+                let max_id = if cfg.log.sharded.load(Ordering::Relaxed) {
+                    current_max_id(cfg, &content)?
+                } else {
+                    max_event_id(&content)
+                };
+                let first = max_id + 1;
+                let ids: Vec<u64> = (first..).take(count).collect();
+                (cfg.path.clone(), ids, content, sha)
             }
-        }
-        let next_id = max_id + 1;
-        let event = build_event(next_id);
+            ShardStrategy::Buckets => {
+                let bucket = random_bucket();
+                let path = shard_path(&cfg.path, bucket);
+                let queue_file = match read_repo_file(cfg, &cfg.path) {
+                    Ok((content, _)) => content,
+                    Err(err) if is_not_found(&err) => String::new(),
+                    Err(err) => return Err(err),
+                };
+                let (content, sha) = read_repo_file(cfg, &path).unwrap_or((String::new(), None));
+                // The bucket may have grown since it was listed
+                let max_id = current_max_id(cfg, &queue_file)?.max(max_event_id(&content));
+                let ids: Vec<u64> = std::iter::successors(Some(next_bucket_id(max_id, bucket)), |id| {
+                    Some(next_bucket_id(*id, bucket))
+                })
+                .take(count)
+                .collect();
+                (path, ids, content, sha)
+            }
+        };
         let mut new_content = content;
         if !new_content.ends_with('\n') && !new_content.is_empty() {
             new_content.push('\n');
         }
-        new_content.push_str(&event.to_string());
-        new_content.push('\n');
-        match write_repo_file(cfg, &new_content, sha) {
+        for event in build_events(&ids) {
+            new_content.push_str(&event.to_string());
+            new_content.push('\n');
+        }
+        match write_repo_file(cfg, &path, &new_content, sha) {
             Ok(()) => {
-                write_shared_state(cfg, SharedQueueState { last_seen_id: next_id })?;
-                return Ok(next_id);
+                let last_seen_id = ids.last().copied().unwrap_or(0);
+                write_shared_state(cfg, SharedQueueState { last_seen_id })?;
+                return Ok(ids);
             }
             Err(err) => {
                 if attempt == 0 && err.contains("409") {
@@ -1586,6 +4639,365 @@ where
 mod tests {
     use super::*;
 
+    #[test]
+    fn missing_repo_files_are_recognised() {
+        assert!(is_not_found("gh: Not Found (HTTP 404)"));
+        assert!(is_not_found("HTTP 404"));
+        assert!(!is_not_found("gh: Bad credentials (HTTP 401)"));
+    }
+
+    #[test]
+    fn cookies_are_passed_only_when_configured() {
+        assert!(cookies_args(None).is_empty());
+        assert_eq!(cookies_shell_arg(None), "");
+        let path = std::path::Path::new("/tmp/it's/cookies.txt");
+        assert_eq!(cookies_args(Some(path)), vec!["--cookies", "/tmp/it's/cookies.txt"]);
+        assert_eq!(cookies_shell_arg(Some(path)), " --cookies '/tmp/it'\\''s/cookies.txt'");
+    }
+
+    #[test]
+    fn missing_cookies_file_is_ignored() {
+        assert_eq!(existing_cookies_path(None), None);
+        assert_eq!(existing_cookies_path(Some("  ")), None);
+        assert_eq!(existing_cookies_path(Some("/nonexistent/gezellig/cookies.txt")), None);
+    }
+
+    #[test]
+    fn webhook_mode_ignores_poll_ticks() {
+        let mode = QueueSyncMode::default();
+        mode.set_polling(false);
+        assert!(!mode.is_polling());
+        assert!(mode.should_sync(SyncTrigger::Push));
+        assert!(!mode.should_sync(SyncTrigger::Tick));
+    }
+
+    #[test]
+    fn poll_interval_is_clamped() {
+        let mode = QueueSyncMode::default();
+        assert_eq!(mode.poll_interval(), std::time::Duration::from_secs(10));
+        assert_eq!(mode.set_poll_interval_secs(0), 2);
+        assert_eq!(mode.set_poll_interval_secs(30), 30);
+        assert_eq!(mode.poll_interval(), std::time::Duration::from_secs(30));
+        assert_eq!(mode.set_poll_interval_secs(10_000), 300);
+    }
+
+    #[test]
+    fn skip_check_interval_is_clamped() {
+        let mode = QueueSyncMode::default();
+        assert_eq!(mode.skip_check_interval(), std::time::Duration::from_secs(2));
+        assert_eq!(mode.set_skip_check_interval_secs(0), 1);
+        assert_eq!(mode.set_skip_check_interval_secs(5), 5);
+        assert_eq!(mode.skip_check_interval(), std::time::Duration::from_secs(5));
+        assert_eq!(mode.set_skip_check_interval_secs(1_000), 60);
+    }
+
+    #[test]
+    fn ping_interval_is_clamped() {
+        let mode = QueueSyncMode::default();
+        assert_eq!(mode.ping_interval(), std::time::Duration::from_secs(30));
+        assert_eq!(mode.set_ping_interval_secs(1), 5);
+        assert_eq!(mode.set_ping_interval_secs(1_000), 300);
+    }
+
+    #[test]
+    fn push_received_drains_pending_pushes() {
+        let (tx, mut rx) = tokio::sync::broadcast::channel(4);
+        assert!(!push_received(&mut rx));
+        let _ = tx.send(());
+        let _ = tx.send(());
+        assert!(push_received(&mut rx));
+        assert!(!push_received(&mut rx));
+    }
+
+    #[test]
+    fn skips_are_checked_on_a_timer_until_the_webhook_connects() {
+        let (tx, mut rx) = tokio::sync::broadcast::channel(4);
+        let interval = std::time::Duration::from_secs(2);
+        let recent = std::time::Duration::from_millis(500);
+        assert!(!skip_check_due(Some(&mut rx), false, recent, interval));
+        assert!(skip_check_due(Some(&mut rx), false, interval, interval));
+        assert!(skip_check_due(None, true, interval, interval));
+
+        assert!(!skip_check_due(Some(&mut rx), true, interval, interval));
+        let _ = tx.send(());
+        assert!(skip_check_due(Some(&mut rx), true, recent, interval));
+    }
+
+    #[test]
+    fn switching_to_polling_syncs_on_ticks() {
+        let mode = QueueSyncMode::default();
+        assert!(mode.is_polling());
+        mode.set_polling(false);
+        mode.set_polling(true);
+        assert!(mode.should_sync(SyncTrigger::Tick));
+        assert!(mode.should_sync(SyncTrigger::Push));
+        mode.set_polling(false);
+        assert!(!mode.should_sync(SyncTrigger::Tick));
+    }
+
+    #[test]
+    fn summarize_lists_new_event_types_once() {
+        let content = concat!(
+            r#"{"id":1,"type":"queued","url":"https://a"}"#, "\n",
+            r#"{"id":2,"type":"metadata","ref":1,"title":"A"}"#, "\n",
+            r#"{"id":3,"type":"queued","url":"https://b"}"#, "\n",
+            r#"{"id":4,"type":"metadata","ref":3,"title":"B"}"#, "\n",
+        );
+        let update = summarize_events_since(content, 2);
+        assert_eq!(update.max_id, 4);
+        assert_eq!(update.event_types, vec!["queued".to_string(), "metadata".to_string()]);
+    }
+
+    #[test]
+    fn summarize_is_empty_when_nothing_new() {
+        let content = r#"{"id":1,"type":"queued","url":"https://a"}"#;
+        let update = summarize_events_since(content, 1);
+        assert_eq!(update.max_id, 1);
+        assert!(update.event_types.is_empty());
+    }
+
+    #[test]
+    fn parses_title_and_duration_from_yt_dlp() {
+        assert_eq!(parse_title_and_duration("Song\n213\n"), ("Song".to_string(), Some(213)));
+        assert_eq!(parse_title_and_duration("Song\n212.6\n"), ("Song".to_string(), Some(213)));
+        assert_eq!(parse_title_and_duration("Live\nNA\n"), ("Live".to_string(), None));
+        assert_eq!(parse_title_and_duration("Song"), ("Song".to_string(), None));
+    }
+
+    #[test]
+    fn plausible_media_urls() {
+        assert!(is_plausible_media_url("https://www.youtube.com/watch?v=abc"));
+        assert!(is_plausible_media_url("http://example.com/song.mp3"));
+        assert!(is_plausible_media_url("youtu.be/abc"));
+        assert!(is_plausible_media_url("  HTTPS://YouTu.be/abc  "));
+        assert!(!is_plausible_media_url(""));
+        assert!(!is_plausible_media_url("   "));
+        assert!(!is_plausible_media_url("never gonna give you up"));
+        assert!(!is_plausible_media_url("ftp://example.com/song.mp3"));
+        assert!(!is_plausible_media_url("https://localhost/song"));
+        assert!(!is_plausible_media_url("example.com/song"));
+    }
+
+    #[test]
+    fn queue_track_rejects_blank_urls() {
+        let pipeline = YouTubePipeline::new();
+        assert!(pipeline.queue_track("   ".to_string(), None, None).is_err());
+        assert!(pipeline.queue_track("not a url".to_string(), None, None).is_err());
+        assert_eq!(pipeline.get_queue(), Ok(Vec::new()));
+        pipeline
+            .queue_track(" https://youtu.be/abc ".to_string(), None, None)
+            .unwrap_or_else(|e| panic!("queue_track failed: {e}"));
+        assert_eq!(pipeline.get_queue(), Ok(vec!["https://youtu.be/abc".to_string()]));
+    }
+
+    #[test]
+    fn queue_track_next_goes_ahead_of_waiting_tracks() {
+        let pipeline = YouTubePipeline::new();
+        for url in ["https://youtu.be/a", "https://youtu.be/b"] {
+            pipeline
+                .queue_track(url.to_string(), None, None)
+                .unwrap_or_else(|e| panic!("queue_track failed: {e}"));
+        }
+        pipeline
+            .queue_track_next("https://youtu.be/c".to_string(), Some("robin".to_string()), None)
+            .unwrap_or_else(|e| panic!("queue_track_next failed: {e}"));
+        assert_eq!(pipeline.get_queue().unwrap_or_default(), vec!["https://youtu.be/c", "https://youtu.be/a", "https://youtu.be/b"]);
+        assert!(pipeline.queue_track_next("not a url".to_string(), None, None).is_err());
+    }
+
+    #[test]
+    fn clips_stop_at_the_natural_end() {
+        assert_eq!(clip_duration(None, Some(200)), Some(200));
+        assert_eq!(clip_duration(Some((60, 120)), Some(200)), Some(60));
+        assert_eq!(clip_duration(Some((60, 500)), Some(200)), Some(140));
+        assert_eq!(clip_duration(Some((60, 120)), None), Some(60));
+        assert!(check_clip((60, 120)).is_ok());
+        assert!(check_clip((120, 60)).is_err());
+        assert!(check_clip((60, 60)).is_err());
+        assert!(check_clip((0, MAX_OFFSET_SECS)).is_ok());
+        assert!(check_clip((0, u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn pause_holds_until_resume() {
+        let content = concat!(
+            r#"{"id":1,"type":"queued","url":"https://a"}"#, "\n",
+            r#"{"id":2,"type":"pause"}"#, "\n",
+            r#"{"id":3,"type":"cleared"}"#, "\n",
+        );
+        assert!(reduce_queue_events(content).paused);
+        let resumed = format!("{content}{}\n", r#"{"id":4,"type":"resume"}"#);
+        let data = reduce_queue_events(&resumed);
+        assert!(!data.paused);
+        assert!(!shared_queue_snapshot_from_data(data).paused);
+    }
+
+    #[test]
+    fn room_controls_need_a_shared_queue() {
+        let pipeline = YouTubePipeline::new();
+        assert_eq!(pipeline.pause_room(), Err(NO_SHARED_QUEUE.to_string()));
+        assert_eq!(pipeline.resume_room(), Err(NO_SHARED_QUEUE.to_string()));
+    }
+
+    #[test]
+    fn queued_clip_survives_reduce() {
+        let content = concat!(
+            r#"{"id":1,"type":"queued","url":"https://a","clip":[60,120]}"#, "\n",
+            r#"{"id":2,"type":"queued","url":"https://b"}"#, "\n",
+        );
+        let data = reduce_queue_events(content);
+        assert_eq!(data.items[0].clip, Some((60, 120)));
+        assert_eq!(data.items[1].clip, None);
+    }
+
+    #[test]
+    fn out_of_range_clips_from_the_log_are_ignored() {
+        let content = concat!(
+            r#"{"id":1,"type":"queued","url":"https://a","clip":[60,18446744073709551615]}"#, "\n",
+            r#"{"id":2,"type":"queued","url":"https://b","clip":[120,60]}"#, "\n",
+        );
+        let data = reduce_queue_events(content);
+        assert_eq!(data.items.len(), 2);
+        assert!(data.items.iter().all(|item| item.clip.is_none()));
+    }
+
+    #[test]
+    fn shards_merge_in_id_order() {
+        let logs = vec![
+            concat!(
+                r#"{"id":1,"type":"queued","url":"https://a"}"#, "\n",
+                r#"{"id":6,"type":"played","ref":1}"#, "\n",
+            ).to_string(),
+            r#"{"id":3,"type":"queued","url":"https://b"}"#.to_string(),
+            concat!(r#"{"id":2,"type":"queued","url":"https://c"}"#, "\n\n").to_string(),
+        ];
+        let merged = merge_shard_logs(&logs);
+        let ids: Vec<u64> = merged
+            .lines()
+            .map(|line| serde_json::from_str::<QueueEvent>(line).unwrap().id)
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3, 6]);
+        let data = reduce_queue_events(&merged);
+        let urls: Vec<&str> = data.items.iter().map(|t| t.url.as_str()).collect();
+        assert_eq!(urls, vec!["https://c", "https://b"]);
+        assert_eq!(data.max_id, 6);
+    }
+
+    #[test]
+    fn bucket_ids_never_collide() {
+        assert_eq!(next_bucket_id(5, 2), 6);
+        assert_eq!(next_bucket_id(5, 0), 8);
+        assert_eq!(next_bucket_id(0, 1), 1);
+        let ids: HashSet<u64> = (0..SHARD_BUCKETS).map(|bucket| next_bucket_id(9, bucket)).collect();
+        assert_eq!(ids.len(), SHARD_BUCKETS as usize);
+        assert!(ids.iter().all(|id| *id > 9));
+    }
+
+    #[test]
+    fn single_file_queues_look_for_shards_now_and_then() {
+        let log = SharedQueueLog::default();
+        let start = Instant::now();
+        assert!(log.shard_check_due(start));
+        assert!(!log.shard_check_due(start + std::time::Duration::from_secs(1)));
+        assert!(log.shard_check_due(start + SHARD_CHECK_INTERVAL));
+        // Once shards turn up every read merges them
+        log.sharded.store(true, Ordering::Relaxed);
+        assert!(log.shard_check_due(start + SHARD_CHECK_INTERVAL));
+    }
+
+    #[test]
+    fn shards_changed_since_the_last_sync_are_reread_for_ids() {
+        let log = SharedQueueLog::default();
+        log.remember_shard("queue/0001.ndjson", "a1", r#"{"id":5,"type":"queued","url":"https://a"}"#);
+        log.remember_shard("queue/0002.ndjson", "b1", r#"{"id":6,"type":"queued","url":"https://b"}"#);
+        // Another client has since written a `cleared` to shard 1
+        let listing = vec![
+            ("queue/0001.ndjson".to_string(), "a2".to_string()),
+            ("queue/0002.ndjson".to_string(), "b1".to_string()),
+        ];
+        let mut reads = Vec::new();
+        let max_id = log.shards_max_id(&listing, |path| {
+            reads.push(path.to_string());
+            Ok(concat!(
+                r#"{"id":5,"type":"queued","url":"https://a"}"#, "\n",
+                r#"{"id":9,"type":"cleared"}"#, "\n",
+            ).to_string())
+        });
+        assert_eq!(max_id, Ok(9));
+        assert_eq!(reads, vec!["queue/0001.ndjson"]);
+        // A new id lands after the clear, so the track it queues survives
+        assert!(next_bucket_id(9, 2) > 9);
+    }
+
+    #[test]
+    fn shard_paths_sit_beside_the_queue_file() {
+        assert_eq!(shard_path("queue.ndjson", 1), "queue/0001.ndjson");
+        assert_eq!(shard_path("rooms/events.ndjson", 3), "rooms/events/0003.ndjson");
+        assert_eq!(shard_dir("events"), "events.shards");
+        assert_eq!(ShardStrategy::from_setting("buckets"), Some(ShardStrategy::Buckets));
+        assert_eq!(ShardStrategy::from_setting("daily"), None);
+    }
+
+    #[test]
+    fn fader_ramps_in_from_silence() {
+        let mut fader = TrackFader::new(8);
+        let out = fader.push(&[1000; 16]);
+        assert_eq!(out.len(), 8);
+        assert_eq!(out[0], 0);
+        assert_eq!(out[1], 0);
+        assert!(out[2] > 0 && out[2] < 1000);
+        assert_eq!(out[2], out[3]);
+    }
+
+    #[test]
+    fn fader_holds_back_tail_until_finished() {
+        let mut fader = TrackFader::new(4);
+        assert!(fader.push(&[1, 2]).is_empty());
+        let out = fader.push(&[500; 8]);
+        assert_eq!(out.len(), 6);
+        assert_eq!(fader.finish(), vec![500; 4]);
+    }
+
+    #[test]
+    fn fader_fades_tail_to_silence() {
+        let mut fader = TrackFader::new(4);
+        let _ = fader.push(&[1000; 12]);
+        let tail = fader.fade_out();
+        assert_eq!(tail.len(), 4);
+        assert_eq!(tail[0], 500);
+        assert_eq!(tail[1], 500);
+        assert_eq!(tail[2], 0);
+        assert_eq!(tail[3], 0);
+        assert!(fader.finish().is_empty());
+    }
+
+    #[test]
+    fn reconfiguring_the_shared_queue_takes_effect() {
+        let dir = match tempfile::tempdir() {
+            Ok(dir) => dir,
+            Err(err) => panic!("tempdir failed: {err}"),
+        };
+        let state_path = dir.path().join("state.json");
+        let pipeline = YouTubePipeline::with_cache_dir_and_state(
+            None,
+            Some(state_path.clone()),
+            Some(("old/queue".to_string(), "events.ndjson".to_string(), "gh".to_string())),
+            None,
+        );
+        assert!(std::fs::write(&state_path, r#"{"last_seen_id":42}"#).is_ok());
+
+        let result = pipeline.reconfigure_shared_queue("new/queue".to_string(), "dj.ndjson".to_string(), String::new());
+        assert!(result.is_ok());
+        let cfg = pipeline
+            .shared_queue_config()
+            .unwrap_or_else(|| panic!("shared queue should stay configured"));
+        assert_eq!((cfg.repo.as_str(), cfg.path.as_str(), cfg.gh_path.as_str()), ("new/queue", "dj.ndjson", "gh"));
+        assert_eq!(read_shared_state(&state_path).last_seen_id, 0);
+
+        assert!(pipeline.reconfigure_shared_queue(String::new(), String::new(), String::new()).is_ok());
+        assert!(pipeline.shared_queue_config().is_none());
+    }
+
     #[test]
     fn pipeline_starts_in_idle() {
         let pipeline = YouTubePipeline::new();
@@ -1608,12 +5020,41 @@ mod tests {
         let pipeline = YouTubePipeline::new();
         assert!(pipeline.start().is_ok());
         pipeline
-            .queue_track("https://youtube.com/watch?v=test".to_string(), None)
+            .queue_track("https://youtube.com/watch?v=test".to_string(), None, None)
             .unwrap_or_else(|e| panic!("queue_track failed: {e}"));
-        assert_eq!(pipeline.get_queue().len(), 1);
+        assert_eq!(pipeline.get_queue().unwrap_or_default().len(), 1);
         assert!(pipeline.stop().is_ok());
         assert_eq!(pipeline.status(), DjStatus::Idle);
-        assert_eq!(pipeline.get_queue().len(), 0);
+        assert_eq!(pipeline.get_queue().unwrap_or_default().len(), 0);
+    }
+
+    #[test]
+    fn stopping_leaves_buffered_audio_for_the_publisher_to_drain() {
+        let flush = Arc::new(tokio::sync::watch::Sender::new(0));
+        let mut flush_rx = flush.subscribe();
+        let pipeline = YouTubePipeline::new().with_pcm_flush(flush);
+        let mut pcm_rx = pipeline
+            .take_pcm_receiver()
+            .unwrap_or_else(|| panic!("pcm receiver should be available"));
+        assert!(pipeline.pcm_sender.try_send(vec![1, 2]).is_ok());
+
+        assert!(pipeline.stop().is_ok());
+        // Nothing told the publisher to drop its buffer, so a drain still sends it
+        assert!(!flush_rx.has_changed().unwrap_or(true));
+        assert_eq!(pcm_rx.try_recv().ok(), Some(vec![1, 2]));
+    }
+
+    #[test]
+    fn skipping_flushes_only_audio_sent_before_the_skip() {
+        let flush = Arc::new(tokio::sync::watch::Sender::new(0));
+        let mut flush_rx = flush.subscribe();
+        let pipeline = YouTubePipeline::new().with_pcm_flush(flush);
+        pipeline.pcm_sent.store(3, Ordering::Relaxed);
+
+        // Signalled straight away, not once the playback loop gets round to it
+        assert!(pipeline.skip_track().is_ok());
+        assert!(flush_rx.has_changed().unwrap_or(false));
+        assert_eq!(*flush_rx.borrow_and_update(), 3);
     }
 
     #[test]
@@ -1640,12 +5081,12 @@ mod tests {
     fn queue_track_adds_to_queue() {
         let pipeline = YouTubePipeline::new();
         pipeline
-            .queue_track("https://youtube.com/watch?v=abc".to_string(), None)
+            .queue_track("https://youtube.com/watch?v=abc".to_string(), None, None)
             .unwrap_or_else(|e| panic!("queue_track failed: {e}"));
         pipeline
-            .queue_track("https://youtube.com/watch?v=def".to_string(), None)
+            .queue_track("https://youtube.com/watch?v=def".to_string(), None, None)
             .unwrap_or_else(|e| panic!("queue_track failed: {e}"));
-        let queue = pipeline.get_queue();
+        let queue = pipeline.get_queue().unwrap_or_default();
         assert_eq!(queue.len(), 2);
         assert_eq!(queue[0], "https://youtube.com/watch?v=abc");
         assert_eq!(queue[1], "https://youtube.com/watch?v=def");
@@ -1654,15 +5095,574 @@ mod tests {
     #[test]
     fn get_queue_empty_initially() {
         let pipeline = YouTubePipeline::new();
-        assert!(pipeline.get_queue().is_empty());
+        assert_eq!(pipeline.get_queue(), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn get_queue_ignores_the_local_mirror_when_shared() {
+        let dir = match tempfile::tempdir() {
+            Ok(dir) => dir,
+            Err(err) => panic!("tempdir failed: {err}"),
+        };
+        let pipeline = YouTubePipeline::with_cache_dir_and_state(
+            None,
+            Some(dir.path().join("state.json")),
+            Some((
+                "owner/queue".to_string(),
+                "events.ndjson".to_string(),
+                dir.path().join("missing-gh").to_string_lossy().into_owned(),
+            )),
+            None,
+        );
+        pipeline.queue.lock().unwrap().push(QueuedTrack {
+            url: "https://youtube.com/watch?v=stale".to_string(),
+            title: "Loading...".to_string(),
+            queued_id: Some(1),
+            local_id: None,
+            queued_by: None,
+            artist: None,
+            thumbnail: None,
+            queued_at: None,
+            clip: None,
+        });
+        // An unreadable shared queue is an error, not the stale local mirror
+        assert!(pipeline.get_queue().is_err());
+
+        assert!(pipeline.reconfigure_shared_queue(String::new(), String::new(), String::new()).is_ok());
+        assert_eq!(pipeline.get_queue(), Ok(vec!["https://youtube.com/watch?v=stale".to_string()]));
+    }
+
+    #[test]
+    fn local_now_playing_follows_playing_status() {
+        let pipeline = YouTubePipeline::new();
+        assert_eq!(pipeline.local_now_playing(), None);
+        *pipeline.current_track.lock().unwrap() = Some(QueuedTrack {
+            url: "https://youtube.com/watch?v=abc".to_string(),
+            title: "Loading...".to_string(),
+            queued_id: None,
+            local_id: None,
+            queued_by: None,
+            artist: Some("Channel".to_string()),
+            thumbnail: None,
+            queued_at: None,
+            clip: None,
+        });
+        pipeline.status.send_replace(DjStatus::Playing(NowPlaying {
+            track: "Song".to_string(),
+            artist: "Channel".to_string(),
+            ..Default::default()
+        }));
+        let now = pipeline.local_now_playing().unwrap_or_else(|| panic!("expected now playing"));
+        assert_eq!(now.title, "Song");
+        assert_eq!(now.url, "https://youtube.com/watch?v=abc");
+        assert_eq!(now.artist.as_deref(), Some("Channel"));
+    }
+
+    #[test]
+    fn play_previous_without_shared_queue_has_no_history() {
+        let pipeline = YouTubePipeline::new();
+        assert_eq!(pipeline.play_previous(), Err(NO_HISTORY.to_string()));
+    }
+
+    #[test]
+    fn play_previous_requeues_most_recent_history_first() {
+        let content = [
+            r#"{"id":1,"type":"queued","url":"https://a"}"#,
+            r#"{"id":2,"type":"queued","url":"https://b"}"#,
+            r#"{"id":3,"type":"queued","url":"https://c"}"#,
+            r#"{"id":4,"type":"played","ref":1}"#,
+            r#"{"id":5,"type":"failed","ref":2}"#,
+        ]
+        .join("\n");
+        let data = reduce_queue_events(&content);
+        assert_eq!(data.history.first().map(|(url, _, _)| url.as_str()), Some("https://b"));
+        assert_eq!(order_with_first(6, &data.items), vec![6, 3]);
+    }
+
+    #[test]
+    fn history_follows_play_order_after_a_reorder() {
+        let content = [
+            r#"{"id":1,"type":"queued","url":"https://a"}"#,
+            r#"{"id":2,"type":"queued","url":"https://b"}"#,
+            r#"{"id":3,"type":"reordered","order":[2,1]}"#,
+            r#"{"id":4,"type":"played","ref":2}"#,
+            r#"{"id":5,"type":"played","ref":1}"#,
+        ]
+        .join("\n");
+        let data = reduce_queue_events(&content);
+        let urls: Vec<&str> = data.history.iter().map(|(url, _, _)| url.as_str()).collect();
+        assert_eq!(urls, vec!["https://a", "https://b"]);
+    }
+
+    #[test]
+    fn move_order_moves_a_single_item() {
+        assert_eq!(compute_move_order(vec![1, 2, 3, 4], 3, 1), vec![1, 4, 2, 3]);
+        assert_eq!(compute_move_order(vec![1, 2, 3, 4], 0, 2), vec![2, 3, 1, 4]);
+    }
+
+    #[test]
+    fn move_order_clamps_past_the_end() {
+        assert_eq!(compute_move_order(vec![1, 2, 3], 0, 99), vec![2, 3, 1]);
+    }
+
+    #[test]
+    fn local_move_track_reorders_queue() {
+        let pipeline = YouTubePipeline::new();
+        for id in ["a", "b", "c"] {
+            assert!(pipeline.queue_track(format!("https://youtu.be/{id}"), None, None).is_ok());
+        }
+        let ids: Vec<u64> = pipeline.local_queue().unwrap_or_default().iter().map(|t| t.id).collect();
+        assert!(pipeline.move_track(ids[2], 0).is_ok());
+        assert_eq!(
+            pipeline.get_queue().unwrap_or_default(),
+            vec!["https://youtu.be/c", "https://youtu.be/a", "https://youtu.be/b"]
+        );
+        // Ids follow the track, not its position
+        let moved: Vec<u64> = pipeline.local_queue().unwrap_or_default().iter().map(|t| t.id).collect();
+        assert_eq!(moved, vec![ids[2], ids[0], ids[1]]);
+        assert!(pipeline.move_track(ids[0], 2).is_ok());
+        assert_eq!(
+            pipeline.get_queue().unwrap_or_default(),
+            vec!["https://youtu.be/c", "https://youtu.be/b", "https://youtu.be/a"]
+        );
+        assert!(pipeline.move_track(99, 0).is_err());
+    }
+
+    #[test]
+    fn metadata_output_includes_uploader() {
+        assert_eq!(
+            parse_metadata_output("Song Title\nSome Channel\nhttps://i.ytimg.com/vi/x/hq.jpg\n"),
+            Some(TrackMetadata {
+                title: "Song Title".to_string(),
+                artist: Some("Some Channel".to_string()),
+                thumbnail: Some("https://i.ytimg.com/vi/x/hq.jpg".to_string()),
+            })
+        );
+        let meta = parse_metadata_output("Song Title\nNA\n");
+        assert_eq!(meta.as_ref().and_then(|m| m.artist.clone()), None);
+        assert_eq!(meta.and_then(|m| m.thumbnail), None);
+        assert_eq!(parse_metadata_output(""), None);
+    }
+
+    #[test]
+    fn preview_output_parses_duration_and_skips_missing_fields() {
+        assert_eq!(
+            parse_preview_output("Song\n212.4\nChannel\nNA\n"),
+            Some(TrackPreview {
+                title: "Song".to_string(),
+                duration_secs: Some(212),
+                uploader: Some("Channel".to_string()),
+                thumbnail: None,
+            })
+        );
+        assert_eq!(parse_preview_output("Live\nNA\n").and_then(|p| p.duration_secs), None);
+        assert_eq!(parse_preview_output(""), None);
+    }
+
+    #[test]
+    fn preview_cache_expires_entries() {
+        let cache = TrackPreviewCache::default();
+        let preview = TrackPreview { title: "Song".to_string(), duration_secs: None, uploader: None, thumbnail: None };
+        let now = Instant::now();
+        cache.insert("https://a".to_string(), preview.clone(), now);
+        assert_eq!(cache.get("https://a", now + std::time::Duration::from_secs(60)), Some(preview));
+        assert_eq!(cache.get("https://a", now + PREVIEW_TTL), None);
+        assert_eq!(cache.get("https://b", now), None);
+    }
+
+    #[test]
+    fn metadata_events_carry_artist_when_present() {
+        let content = r#"{"id":1,"type":"queued","url":"https://a"}
+{"id":2,"type":"queued","url":"https://b"}
+{"id":3,"type":"metadata","ref":1,"title":"A","url":"https://a"}
+{"id":4,"type":"metadata","ref":2,"title":"B","url":"https://b","artist":"Band","thumbnail":"https://t/b.jpg"}
+"#;
+        let data = reduce_queue_events(content);
+        let artists: Vec<Option<String>> = data.items.iter().map(|t| t.artist.clone()).collect();
+        assert_eq!(artists, vec![None, Some("Band".to_string())]);
+        assert_eq!(data.items[0].thumbnail, None);
+        assert_eq!(data.items[1].thumbnail.as_deref(), Some("https://t/b.jpg"));
+        assert!(data.needs_metadata.is_empty());
+    }
+
+    #[test]
+    fn clearing_keeps_the_current_song() {
+        let content = r#"{"id":1,"type":"queued","url":"https://a"}
+{"id":2,"type":"queued","url":"https://b"}
+{"id":3,"type":"metadata","ref":1,"title":"A","url":"https://a","artist":"Band"}
+{"id":4,"type":"playing","ref":1,"title":"A","url":"https://a"}
+{"id":5,"type":"cleared"}
+"#;
+        let data = reduce_queue_events(content);
+        assert!(data.items.is_empty());
+        let now = data.now_playing.expect("current song should survive the clear");
+        assert_eq!(now.queued_id, Some(1));
+        assert_eq!(now.artist.as_deref(), Some("Band"));
+
+        let finished = format!("{content}{{\"id\":6,\"type\":\"played\",\"ref\":1}}\n");
+        assert!(reduce_queue_events(&finished).now_playing.is_none());
+    }
+
+    #[test]
+    fn skip_before_clear_still_stops_the_current_song() {
+        let content = r#"{"id":1,"type":"queued","url":"https://a"}
+{"id":2,"type":"playing","ref":1,"title":"A","url":"https://a"}
+{"id":3,"type":"skip","ref":1}
+{"id":4,"type":"cleared"}
+"#;
+        assert_eq!(reduce_queue_events(content).skip_events.get(&1), Some(&3));
+    }
+
+    #[test]
+    fn unknown_event_types_are_reported_not_applied() {
+        let content = r#"{"id":1,"type":"queued","url":"https://a"}
+{"id":2,"type":"reaction","ref":1,"emoji":"🔥"}
+{"id":3,"type":"vote_skip","ref":1}
+{"id":4,"type":"reaction","ref":1,"emoji":"💤"}
+"#;
+        let data = reduce_queue_events(content);
+        assert_eq!(data.items.len(), 1);
+        assert_eq!(
+            data.unknown_event_types.into_iter().collect::<Vec<_>>(),
+            vec!["reaction".to_string(), "vote_skip".to_string()]
+        );
+    }
+
+    #[test]
+    fn orphaned_playing_event_is_requeued_at_the_front() {
+        let content = r#"{"id":1,"type":"queued","url":"https://a"}
+{"id":2,"type":"queued","url":"https://b"}
+{"id":3,"type":"playing","ref":2,"title":"B","url":"https://b","at":1700000000}
+"#;
+        let fresh = reduce_queue_events_at(content, 1_700_000_000 + 60, None);
+        assert_eq!(fresh.now_playing.and_then(|now| now.queued_id), Some(2));
+        assert_eq!(fresh.items.len(), 1);
+
+        let stale = reduce_queue_events_at(content, 1_700_000_000 + ORPHANED_PLAYING_SECS + 1, None);
+        assert!(stale.now_playing.is_none());
+        let ids: Vec<_> = stale.items.iter().map(|t| t.queued_id).collect();
+        assert_eq!(ids, vec![Some(2), Some(1)]);
+
+        let finished = format!("{content}{{\"id\":4,\"type\":\"played\",\"ref\":2}}\n");
+        let stale_finished = reduce_queue_events_at(&finished, 1_700_000_000 + ORPHANED_PLAYING_SECS + 1, None);
+        assert_eq!(stale_finished.items.len(), 1);
+        assert_eq!(stale_finished.history.len(), 1);
+    }
+
+    #[test]
+    fn long_track_with_an_active_dj_keeps_playing() {
+        let content = r#"{"id":1,"type":"dj_claim","dj_identity":"alice"}
+{"id":2,"type":"queued","url":"https://mix"}
+{"id":3,"type":"playing","ref":2,"title":"Mix","url":"https://mix","at":1700000000,"duration":10800,"dj_identity":"alice"}
+"#;
+        let later = 1_700_000_000 + ORPHANED_PLAYING_SECS * 2;
+        let present = vec!["alice".to_string(), "bob".to_string()];
+        let playing = reduce_queue_events_at(content, later, Some(&present));
+        assert_eq!(playing.now_playing.and_then(|now| now.queued_id), Some(2));
+        assert!(playing.items.is_empty());
+        // Still within its length, so even a departed DJ's mix is left alone
+        let left = vec!["bob".to_string()];
+        assert!(reduce_queue_events_at(content, later, Some(&left)).now_playing.is_some());
+        let overdue = 1_700_000_000 + 10_800 + 1;
+        assert!(reduce_queue_events_at(content, overdue, None).now_playing.is_some());
+        assert!(reduce_queue_events_at(content, overdue, Some(&left)).now_playing.is_none());
     }
 
     #[test]
-    fn decode_audio_returns_error_for_invalid_data() {
-        let result = decode_audio_to_pcm(vec![0, 1, 2, 3]);
+    fn clearing_after_the_song_finished_leaves_nothing_playing() {
+        let content = r#"{"id":1,"type":"queued","url":"https://a"}
+{"id":2,"type":"playing","ref":1,"title":"A","url":"https://a"}
+{"id":3,"type":"played","ref":1}
+{"id":4,"type":"cleared"}
+"#;
+        assert!(reduce_queue_events(content).now_playing.is_none());
+    }
+
+    #[test]
+    fn played_failed_and_playing_tracks_leave_the_queue() {
+        let content = r#"{"id":1,"type":"queued","url":"https://a"}
+{"id":2,"type":"queued","url":"https://b"}
+{"id":3,"type":"queued","url":"https://c"}
+{"id":4,"type":"queued","url":"https://d"}
+{"id":5,"type":"played","ref":1}
+{"id":6,"type":"failed","ref":2}
+{"id":7,"type":"playing","ref":3,"title":"C","url":"https://c"}
+"#;
+        let data = reduce_queue_events(content);
+        let ids: Vec<Option<u64>> = data.items.iter().map(|t| t.queued_id).collect();
+        assert_eq!(ids, vec![Some(4)]);
+        assert_eq!(data.now_playing.and_then(|now| now.queued_id), Some(3));
+        let history: Vec<&str> = data.history.iter().map(|(url, _, _)| url.as_str()).collect();
+        assert_eq!(history, vec!["https://b", "https://a"]);
+        assert_eq!(data.max_id, 7);
+    }
+
+    #[test]
+    fn reorder_puts_tracks_it_does_not_mention_last() {
+        let content = r#"{"id":1,"type":"queued","url":"https://a"}
+{"id":2,"type":"queued","url":"https://b"}
+{"id":3,"type":"queued","url":"https://c"}
+{"id":4,"type":"reordered","order":[1,2]}
+{"id":5,"type":"reordered","order":[3,2,1]}
+{"id":6,"type":"queued","url":"https://d"}
+"#;
+        let data = reduce_queue_events(content);
+        let ids: Vec<Option<u64>> = data.items.iter().map(|t| t.queued_id).collect();
+        assert_eq!(ids, vec![Some(3), Some(2), Some(1), Some(6)]);
+    }
+
+    #[test]
+    fn reorders_apply_in_sequence_to_the_tracks_queued_before_them() {
+        let content = r#"{"id":1,"type":"queued","url":"https://a"}
+{"id":2,"type":"queued","url":"https://b"}
+{"id":3,"type":"queued","url":"https://c"}
+{"id":4,"type":"reordered","order":[3,2,1]}
+{"id":5,"type":"queued","url":"https://d"}
+{"id":6,"type":"reordered","order":[5]}
+{"id":7,"type":"queued","url":"https://e"}
+{"id":8,"type":"queued","url":"https://f"}
+"#;
+        let data = reduce_queue_events(content);
+        let ids: Vec<Option<u64>> = data.items.iter().map(|t| t.queued_id).collect();
+        assert_eq!(ids, vec![Some(5), Some(3), Some(2), Some(1), Some(7), Some(8)]);
+    }
+
+    #[test]
+    fn apply_reorder_ignores_unknown_and_repeated_ids() {
+        assert_eq!(apply_reorder(&[1, 2, 3], &[9, 3, 3, 1]), vec![3, 1, 2]);
+        assert_eq!(apply_reorder(&[1, 2], &[]), vec![1, 2]);
+        assert_eq!(apply_reorder(&[], &[1, 2]), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn clear_forgets_the_queue_history_and_pending_metadata() {
+        let content = r#"{"id":1,"type":"queued","url":"https://a"}
+{"id":2,"type":"played","ref":1}
+{"id":3,"type":"queued","url":"https://b"}
+{"id":4,"type":"reordered","order":[3]}
+{"id":5,"type":"cleared"}
+{"id":6,"type":"queued","url":"https://c"}
+{"id":7,"type":"queued","url":"https://d"}
+{"id":8,"type":"metadata","ref":7,"title":"D","url":"https://d"}
+"#;
+        let data = reduce_queue_events(content);
+        let titles: Vec<&str> = data.items.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(titles, vec!["Loading...", "D"]);
+        assert!(data.history.is_empty());
+        assert_eq!(data.needs_metadata, vec![(6, "https://c".to_string())]);
+    }
+
+    #[test]
+    fn malformed_lines_are_skipped() {
+        let content = "{\"id\":1,\"type\":\"queued\",\"url\":\"https://a\"}\nnot json\n\n{\"id\":2,\"type\":\"queued\"}\n";
+        let data = reduce_queue_events(content);
+        assert_eq!(data.items.len(), 1);
+        assert_eq!(data.max_id, 2);
+        assert!(data.unknown_event_types.is_empty());
+    }
+
+    #[test]
+    fn duplicate_queued_uuids_are_counted_once() {
+        let content = r#"{"id":1,"type":"queued","url":"https://a","uuid":"u-1"}
+{"id":2,"type":"queued","url":"https://b"}
+{"id":3,"type":"queued","url":"https://a","uuid":"u-1"}
+"#;
+        let data = reduce_queue_events(content);
+        let ids: Vec<Option<u64>> = data.items.iter().map(|t| t.queued_id).collect();
+        assert_eq!(ids, vec![Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn start_offset_parses_seconds_and_units() {
+        assert_eq!(parse_start_offset("https://www.youtube.com/watch?v=abc&t=90"), Some(90));
+        assert_eq!(parse_start_offset("https://www.youtube.com/watch?v=abc&t=90s"), Some(90));
+        assert_eq!(parse_start_offset("https://youtu.be/abc#t=1m30s"), Some(90));
+        assert_eq!(parse_start_offset("https://youtu.be/abc?t=1h0m5s"), Some(3605));
+    }
+
+    #[test]
+    fn start_offset_ignores_missing_or_malformed_values() {
+        assert_eq!(parse_start_offset("https://www.youtube.com/watch?v=abc"), None);
+        assert_eq!(parse_start_offset("https://www.youtube.com/watch?v=abc&t=0"), None);
+        assert_eq!(parse_start_offset("https://www.youtube.com/watch?v=abc&t=1m30"), None);
+        assert_eq!(parse_start_offset("https://www.youtube.com/watch?v=abc&t=soon"), None);
+        assert_eq!(parse_start_offset("https://www.youtube.com/watch?v=abc&list=t=5"), None);
+    }
+
+    #[test]
+    fn start_offset_rejects_huge_values_without_overflowing() {
+        assert_eq!(parse_start_offset("https://youtu.be/abc?t=18446744073709551615"), None);
+        assert_eq!(parse_start_offset("https://youtu.be/abc?t=18446744073709551615h"), None);
+        assert_eq!(parse_start_offset("https://youtu.be/abc?t=9999999999999999h9999999999999999h"), None);
+        assert_eq!(parse_start_offset("https://youtu.be/abc?t=200h"), None);
+        assert_eq!(parse_start_offset("https://youtu.be/abc?t=100h"), Some(360_000));
+    }
+
+    #[test]
+    fn audio_quality_keeps_separate_cache_files() {
+        let url = "https://www.youtube.com/watch?v=abc123";
+        let dir = std::path::PathBuf::from("/cache");
+        let best = YtDlpSource { cache_dir: Some(dir.clone()), quality: AudioQuality::Best };
+        let low = YtDlpSource { cache_dir: Some(dir.clone()), quality: AudioQuality::Low };
+        assert_eq!(best.cache_path(url), Some(dir.join("abc123.pcm")));
+        assert_eq!(low.cache_path(url), Some(dir.join("abc123-q64.pcm")));
+        assert_eq!(AudioQuality::from_setting("medium"), Some(AudioQuality::Medium));
+        assert_eq!(AudioQuality::from_setting("lossless"), None);
+    }
+
+    #[test]
+    fn prebuffer_waits_for_target_and_refills_after_underrun() {
+        let mut prebuffer = Prebuffer::new(10);
+        assert!(!prebuffer.push(480));
+        assert!(prebuffer.push(480));
+        assert!(!prebuffer.push(480));
+        prebuffer.underrun();
+        assert!(!prebuffer.push(500));
+        assert!(prebuffer.push(460));
+    }
+
+    #[test]
+    fn idle_timer_expires_after_timeout_unless_reset() {
+        let mut idle = IdleTimer::default();
+        let start = Instant::now();
+        assert!(!idle.expired(start, 60));
+        assert!(!idle.expired(start + std::time::Duration::from_secs(59), 60));
+        assert!(!idle.expired(start + std::time::Duration::from_secs(59), 0));
+        assert!(idle.expired(start + std::time::Duration::from_secs(60), 60));
+
+        idle.reset();
+        assert!(!idle.expired(start + std::time::Duration::from_secs(90), 60));
+        assert!(idle.expired(start + std::time::Duration::from_secs(150), 60));
+    }
+
+    #[test]
+    fn stream_timeout_is_clamped() {
+        assert_eq!(clamp_stream_timeout_secs(0), 5);
+        assert_eq!(clamp_stream_timeout_secs(DEFAULT_STREAM_TIMEOUT_SECS), 30);
+        assert_eq!(clamp_stream_timeout_secs(10_000), 300);
+    }
+
+    #[test]
+    fn load_retries_until_the_source_succeeds() {
+        let rt = match tokio::runtime::Runtime::new() {
+            Ok(rt) => rt,
+            Err(err) => panic!("failed to create runtime: {err}"),
+        };
+        let (_skip_tx, mut skip_rx) = tokio::sync::watch::channel(false);
+        let attempts = std::cell::Cell::new(0);
+        let flaky_source = || {
+            attempts.set(attempts.get() + 1);
+            let attempt = attempts.get();
+            async move {
+                if attempt < 3 {
+                    Err(format!("network error {attempt}"))
+                } else {
+                    Ok(attempt)
+                }
+            }
+        };
+
+        let outcome = rt.block_on(load_with_retries(2, std::time::Duration::ZERO, &mut skip_rx, flaky_source));
+        assert!(matches!(outcome, LoadOutcome::Loaded(3)));
+
+        attempts.set(0);
+        let outcome = rt.block_on(load_with_retries(1, std::time::Duration::ZERO, &mut skip_rx, flaky_source));
+        assert!(matches!(outcome, LoadOutcome::Failed(ref err) if err == "network error 2"));
+        assert_eq!(clamp_load_retries(DEFAULT_LOAD_RETRIES), 2);
+        assert_eq!(clamp_load_retries(50), MAX_LOAD_RETRIES);
+    }
+
+    #[test]
+    fn skip_cancels_a_track_still_loading() {
+        let rt = match tokio::runtime::Runtime::new() {
+            Ok(rt) => rt,
+            Err(err) => panic!("failed to create runtime: {err}"),
+        };
+        let (skip_tx, mut skip_rx) = tokio::sync::watch::channel(false);
+        let _ = skip_tx.send(true);
+        let outcome = rt.block_on(load_with_retries(
+            DEFAULT_LOAD_RETRIES,
+            LOAD_RETRY_BACKOFF,
+            &mut skip_rx,
+            std::future::pending::<Result<(), String>>,
+        ));
+        assert!(matches!(outcome, LoadOutcome::Cancelled));
+    }
+
+    #[test]
+    fn prefetch_depth_never_exceeds_cache() {
+        assert_eq!(clamp_prefetch_depth(0), 0);
+        assert_eq!(clamp_prefetch_depth(DEFAULT_PREFETCH_DEPTH), 2);
+        assert_eq!(clamp_prefetch_depth(50), CACHE_LIMIT - 1);
+    }
+
+    #[test]
+    fn latest_dj_claim_wins() {
+        let content = r#"{"id":1,"type":"dj_claimed","dj_identity":"alice"}
+{"id":2,"type":"dj_claim","dj_identity":"bob","at":1700000000}
+"#;
+        assert_eq!(reduce_queue_events(content).active_dj.as_deref(), Some("bob"));
+    }
+
+    #[test]
+    fn sustained_slow_reads_report_one_underrun() {
+        let mut meter = ThroughputMeter::new();
+        let slow = REALTIME_BYTES_PER_SEC as usize / 2;
+        let reports: Vec<bool> = (0..UNDERRUN_WINDOWS + 2)
+            .map(|_| {
+                meter.window_bytes = slow as u64;
+                meter.window_read_time = std::time::Duration::from_secs(1);
+                meter.close_window()
+            })
+            .collect();
+        assert_eq!(reports.iter().filter(|r| **r).count(), 1);
+        assert!(reports[UNDERRUN_WINDOWS as usize - 1]);
+        assert_eq!(meter.bytes_per_sec(), slow as u64);
+    }
+
+    #[test]
+    fn queued_at_is_optional_on_queue_items() {
+        let content = r#"{"id":1,"type":"queued","url":"https://a","at":1700000000}
+{"id":2,"type":"queued","url":"https://b"}
+"#;
+        let snapshot = shared_queue_snapshot_from_data(reduce_queue_events(content));
+        let at: Vec<Option<u64>> = snapshot.queue.iter().map(|item| item.queued_at).collect();
+        assert_eq!(at, vec![Some(1700000000), None]);
+    }
+
+    #[test]
+    fn anyone_may_stream_without_a_dj_claim() {
+        assert!(may_stream(None, None));
+        assert!(may_stream(Some("alice"), None));
+    }
+
+    #[test]
+    fn only_the_claimed_dj_may_stream() {
+        assert!(may_stream(Some("alice"), Some("alice")));
+        assert!(!may_stream(Some("bob"), Some("alice")));
+        assert!(!may_stream(None, Some("alice")));
+    }
+
+    #[test]
+    fn decoder_rejects_invalid_data() {
+        let result = PcmDecoder::open(Box::new(std::io::Cursor::new(vec![0u8, 1, 2, 3])), None);
         assert!(result.is_err());
     }
 
+    #[test]
+    fn converter_upmixes_and_resamples_to_the_stream_format() {
+        let mut stereo = StreamConverter::new(PcmFormat::STREAM);
+        assert_eq!(stereo.convert(&[1, 2, 3, 4]), &[1, 2, 3, 4]);
+
+        let mut mono = StreamConverter::new(PcmFormat { sample_rate: 48_000, channels: 1 });
+        assert_eq!(mono.convert(&[5, 6]), &[5, 5, 6, 6]);
+
+        // Half the rate: every input frame becomes two, interpolated across packet boundaries
+        let mut half = StreamConverter::new(PcmFormat { sample_rate: 24_000, channels: 1 });
+        assert_eq!(half.convert(&[0, 100]), &[0, 0, 50, 50]);
+        assert_eq!(half.convert(&[200]), &[100, 100, 150, 150]);
+    }
+
     #[test]
     fn enforce_cache_limit_removes_oldest() {
         let dir = std::env::temp_dir().join("gezellig-cache-test");
@@ -1679,7 +5679,7 @@ mod tests {
             std::thread::sleep(std::time::Duration::from_millis(10));
         }
 
-        enforce_cache_limit(&dir, 10);
+        enforce_cache_limit(&dir, 10, &[]);
 
         let remaining: Vec<_> = std::fs::read_dir(&dir)
             .unwrap()
@@ -1699,6 +5699,123 @@ mod tests {
         let _ = std::fs::remove_dir_all(&dir);
     }
 
+    #[test]
+    fn enforce_cache_limit_keeps_protected_files() {
+        let dir = match tempfile::tempdir() {
+            Ok(dir) => dir,
+            Err(err) => panic!("tempdir failed: {err}"),
+        };
+        for i in 0..4 {
+            std::fs::write(dir.path().join(format!("v{i}.pcm")), "data").unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        let playing = dir.path().join("v0.pcm");
+        enforce_cache_limit(dir.path(), 2, std::slice::from_ref(&playing));
+
+        assert!(playing.exists());
+        assert!(!dir.path().join("v1.pcm").exists());
+        assert!(!dir.path().join("v2.pcm").exists());
+        assert!(dir.path().join("v3.pcm").exists());
+    }
+
+    #[test]
+    fn prefetch_all_reports_progress_and_honours_cancel() {
+        let dir = match tempfile::tempdir() {
+            Ok(dir) => dir,
+            Err(err) => panic!("tempdir failed: {err}"),
+        };
+        std::fs::write(dir.path().join("a.pcm"), "data").unwrap();
+        std::fs::write(dir.path().join("b.pcm"), "data").unwrap();
+        let source = YtDlpSource::new(Some(dir.path().to_path_buf()));
+        let urls = vec![
+            "https://youtube.com/watch?v=a".to_string(),
+            "https://youtube.com/watch?v=b".to_string(),
+        ];
+        let rt = match tokio::runtime::Runtime::new() {
+            Ok(rt) => rt,
+            Err(err) => panic!("failed to create runtime: {err}"),
+        };
+
+        let progress = Mutex::new(Vec::new());
+        let cancel = AtomicBool::new(false);
+        let done = rt.block_on(prefetch_all(&source, urls.clone(), 2, &cancel, |p| {
+            progress.lock().unwrap().push(p);
+        }));
+        assert_eq!(done, 2);
+        assert_eq!(progress.lock().unwrap().last(), Some(&PrefetchProgress { done: 2, total: 2 }));
+
+        cancel.store(true, Ordering::Relaxed);
+        assert_eq!(rt.block_on(prefetch_all(&source, urls, 2, &cancel, |_| {})), 0);
+    }
+
+    #[test]
+    fn prefetch_concurrency_is_clamped() {
+        assert_eq!(clamp_prefetch_concurrency(0), 1);
+        assert_eq!(clamp_prefetch_concurrency(DEFAULT_PREFETCH_CONCURRENCY), 2);
+        assert_eq!(clamp_prefetch_concurrency(50), MAX_PREFETCH_CONCURRENCY);
+    }
+
+    #[test]
+    fn streamed_tracks_are_cached_only_once_they_finish() {
+        use tokio::io::AsyncReadExt;
+
+        let dir = match tempfile::tempdir() {
+            Ok(dir) => dir,
+            Err(err) => panic!("tempdir failed: {err}"),
+        };
+        let rt = match tokio::runtime::Runtime::new() {
+            Ok(rt) => rt,
+            Err(err) => panic!("failed to create runtime: {err}"),
+        };
+        let pcm = vec![7u8; 64 * 1024];
+
+        let finished = dir.path().join("done.pcm");
+        rt.block_on(async {
+            let cache = CacheWriter::create(finished.clone()).await.unwrap();
+            let mut tee = TeeReader::new(&pcm[..], cache);
+            let mut out = Vec::new();
+            tee.read_to_end(&mut out).await.unwrap();
+            assert_eq!(out, pcm);
+        });
+        assert_eq!(std::fs::read(&finished).unwrap_or_default(), pcm);
+        assert!(!partial_cache_path(&finished).exists());
+
+        // A skip drops the reader part way through
+        let skipped = dir.path().join("skipped.pcm");
+        rt.block_on(async {
+            let cache = CacheWriter::create(skipped.clone()).await.unwrap();
+            let mut tee = TeeReader::new(&pcm[..], cache);
+            let mut chunk = [0u8; 1024];
+            tee.read_exact(&mut chunk).await.unwrap();
+        });
+        assert!(!skipped.exists());
+        assert!(!partial_cache_path(&skipped).exists());
+    }
+
+    #[test]
+    fn list_cached_tracks_pairs_titles_newest_first() {
+        let dir = match tempfile::tempdir() {
+            Ok(dir) => dir,
+            Err(err) => panic!("tempdir failed: {err}"),
+        };
+        std::fs::write(dir.path().join("old.pcm"), "data").unwrap();
+        write_title_cache(&dir.path().join("old.title"), "Old Song");
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        std::fs::write(dir.path().join("new-q64.pcm"), "longer data").unwrap();
+        std::fs::write(dir.path().join("new-q64.title"), "New Song").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        std::fs::write(dir.path().join("untitled.pcm"), "").unwrap();
+        std::fs::write(dir.path().join("ignored.webm"), "").unwrap();
+
+        let tracks = list_cached_tracks(dir.path());
+        let summary: Vec<_> = tracks.iter().map(|t| (t.id.as_str(), t.title.as_str(), t.size_bytes)).collect();
+        assert_eq!(
+            summary,
+            vec![("untitled", "untitled", 0), ("new", "New Song", 11), ("old", "Old Song", 4)]
+        );
+    }
+
     #[test]
     fn enforce_cache_limit_noop_under_limit() {
         let dir = std::env::temp_dir().join("gezellig-cache-test-noop");
@@ -1709,7 +5826,7 @@ mod tests {
             std::fs::write(dir.join(format!("v{i}.pcm")), "data").unwrap();
         }
 
-        enforce_cache_limit(&dir, 10);
+        enforce_cache_limit(&dir, 10, &[]);
 
         let remaining: Vec<_> = std::fs::read_dir(&dir)
             .unwrap()
@@ -1720,4 +5837,270 @@ mod tests {
 
         let _ = std::fs::remove_dir_all(&dir);
     }
+
+    #[test]
+    fn gain_ramp_moves_gradually_toward_target() {
+        let mut gain = GainRamp::new(0.0);
+        let frames = vec![10_000i16; 48 * VOLUME_RAMP_MS * 2 * 2];
+        let out = gain.apply(&frames, 1.0);
+        assert!(out[0] > 0 && out[0] < 100);
+        assert_eq!(out[0], out[1]);
+        assert!(out[frames.len() / 4] > out[0]);
+        assert_eq!(*out.last().unwrap(), 10_000);
+    }
+
+    #[test]
+    fn gain_ramp_holds_steady_at_target() {
+        let mut gain = GainRamp::new(0.5);
+        assert_eq!(gain.apply(&[1000, -1000], 0.5), vec![500, -500]);
+    }
+
+    #[test]
+    fn title_cache_round_trips_newlines() {
+        let dir = match tempfile::tempdir() {
+            Ok(dir) => dir,
+            Err(err) => panic!("tempdir failed: {err}"),
+        };
+        let path = dir.path().join("abc.title");
+        write_title_cache(&path, "Line one\nLine two");
+        assert_eq!(read_title_cache(&path).as_deref(), Some("Line one\nLine two"));
+    }
+
+    #[test]
+    fn title_sidecar_records_pcm_format() {
+        let dir = match tempfile::tempdir() {
+            Ok(dir) => dir,
+            Err(err) => panic!("tempdir failed: {err}"),
+        };
+        let path = dir.path().join("abc.title");
+        std::fs::write(&path, r#"{"title":"Old"}"#).unwrap();
+        assert_eq!(read_title_sidecar(&path).map(|s| s.format), Some(PcmFormat::STREAM));
+        std::fs::write(&path, r#"{"title":"Mono","format":{"sample_rate":44100,"channels":1}}"#).unwrap();
+        let format = read_title_sidecar(&path).map(|s| s.format);
+        assert_eq!(format, Some(PcmFormat { sample_rate: 44_100, channels: 1 }));
+        assert_eq!(format.map(PcmFormat::chunk_bytes), Some(882));
+        assert_eq!(PcmFormat::STREAM.chunk_bytes(), 1920);
+    }
+
+    #[test]
+    fn legacy_title_cache_is_migrated_to_json() {
+        let dir = match tempfile::tempdir() {
+            Ok(dir) => dir,
+            Err(err) => panic!("tempdir failed: {err}"),
+        };
+        let path = dir.path().join("abc.title");
+        std::fs::write(&path, b"Caf\xe9 Song\n").unwrap();
+        assert_eq!(read_title_cache(&path).as_deref(), Some("Caf\u{fffd} Song"));
+        let migrated = std::fs::read_to_string(&path).unwrap();
+        assert!(migrated.starts_with('{'));
+        assert_eq!(read_title_cache(&path).as_deref(), Some("Caf\u{fffd} Song"));
+    }
+
+    #[test]
+    fn wav_header_describes_cache_pcm() {
+        let header = wav_header(PcmFormat::STREAM.bytes_per_sec() as u32);
+        assert_eq!(&header[0..4], b"RIFF");
+        assert_eq!(&header[8..16], b"WAVEfmt ");
+        assert_eq!(u16::from_le_bytes([header[22], header[23]]), 2);
+        assert_eq!(u32::from_le_bytes([header[24], header[25], header[26], header[27]]), 48_000);
+        assert_eq!(
+            u32::from_le_bytes([header[28], header[29], header[30], header[31]]) as u64,
+            PcmFormat::STREAM.bytes_per_sec()
+        );
+        assert_eq!(&header[36..40], b"data");
+        assert_eq!(
+            u32::from_le_bytes([header[40], header[41], header[42], header[43]]) as u64,
+            PcmFormat::STREAM.bytes_per_sec()
+        );
+    }
+
+    #[test]
+    fn export_track_writes_wav_from_any_cached_quality() {
+        let tmp = match tempfile::tempdir() {
+            Ok(dir) => dir,
+            Err(err) => panic!("tempdir failed: {err}"),
+        };
+        let dir = tmp.path().to_path_buf();
+        std::fs::write(dir.join("abc-q64.pcm"), [1u8, 2, 3, 4]).unwrap();
+
+        let source = YtDlpSource::new(Some(dir.clone()));
+        let dest = dir.join("out.wav");
+        let rt = match tokio::runtime::Runtime::new() {
+            Ok(rt) => rt,
+            Err(err) => panic!("failed to create runtime: {err}"),
+        };
+        rt.block_on(source.export_track("https://youtube.com/watch?v=abc", &dest))
+            .unwrap_or_else(|e| panic!("export failed: {e}"));
+        let wav = std::fs::read(&dest).unwrap();
+        assert_eq!(wav.len(), 48);
+        assert_eq!(&wav[44..], &[1, 2, 3, 4]);
+
+        let missing = rt.block_on(source.export_track("https://youtube.com/watch?v=zzz", &dest));
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn backpressure_warns_once_per_full_stretch() {
+        let mut backpressure = Backpressure::default();
+        let start = Instant::now();
+        assert_eq!(backpressure.observe(true, start), None);
+        assert_eq!(backpressure.observe(true, start + std::time::Duration::from_secs(1)), None);
+        assert_eq!(
+            backpressure.observe(true, start + BACKPRESSURE_WARN_AFTER),
+            Some(BACKPRESSURE_WARN_AFTER)
+        );
+        assert_eq!(backpressure.observe(true, start + std::time::Duration::from_secs(5)), None);
+        assert_eq!(backpressure.observe(false, start + std::time::Duration::from_secs(6)), None);
+        let later = start + std::time::Duration::from_secs(7);
+        assert_eq!(backpressure.observe(true, later), None);
+        assert_eq!(backpressure.observe(true, later + BACKPRESSURE_WARN_AFTER), Some(BACKPRESSURE_WARN_AFTER));
+    }
+
+    #[test]
+    fn send_pcm_stops_once_the_publisher_is_gone() {
+        let rt = match tokio::runtime::Runtime::new() {
+            Ok(rt) => rt,
+            Err(err) => panic!("failed to create runtime: {err}"),
+        };
+        let (tx, mut rx) = mpsc::channel(1);
+        let mut backpressure = Backpressure::default();
+        let sent = AtomicU64::new(0);
+        assert!(rt.block_on(send_pcm(&tx, &sent, vec![1, 2], &mut backpressure)));
+        assert_eq!(rx.try_recv().ok(), Some(vec![1, 2]));
+        assert_eq!(sent.load(Ordering::Relaxed), 1);
+        drop(rx);
+        assert!(!rt.block_on(send_pcm(&tx, &sent, vec![3, 4], &mut backpressure)));
+    }
+
+    #[test]
+    fn duck_ramp_moves_at_the_attack_and_release_rate() {
+        let mut duck = DuckRamp::new();
+        assert!((duck.advance(0.5, 100, 10) - 0.9).abs() < 1e-6);
+        for _ in 0..10 {
+            duck.advance(0.5, 100, 10);
+        }
+        assert_eq!(duck.advance(0.5, 100, 10), 0.5);
+        assert_eq!(duck.advance(1.0, 0, 10), 1.0);
+    }
+
+    #[test]
+    fn seeking_is_only_accepted_for_cached_tracks() {
+        let seek = SeekControl::default();
+        seek.start_track(false, 0, None, PcmFormat::STREAM);
+        assert_eq!(seek.request(30), Err(SEEK_NEEDS_CACHE.to_string()));
+        assert_eq!(seek.take_pending(), None);
+
+        seek.start_track(true, 10, Some(200), PcmFormat::STREAM);
+        assert_eq!(seek.position_secs.load(Ordering::Relaxed), 10);
+        assert!(seek.request(30).is_ok());
+        assert_eq!(seek.take_pending(), Some(30));
+        assert_eq!(seek.take_pending(), None);
+    }
+
+    #[test]
+    fn seeks_are_clamped_to_the_track_and_never_overflow() {
+        let seek = SeekControl::default();
+        seek.start_track(true, 0, Some(200), PcmFormat::STREAM);
+        assert!(seek.request(u64::MAX).is_ok());
+        assert_eq!(seek.take_pending(), Some(200));
+
+        seek.start_track(true, 0, None, PcmFormat::STREAM);
+        assert!(seek.request(u64::MAX).is_err());
+        assert_eq!(seek.take_pending(), None);
+    }
+
+    #[test]
+    fn open_pcm_at_starts_at_the_requested_second() {
+        use tokio::io::AsyncReadExt;
+
+        let dir = match tempfile::tempdir() {
+            Ok(dir) => dir,
+            Err(err) => panic!("tempdir failed: {err}"),
+        };
+        let format = PcmFormat { sample_rate: 2, channels: 1 };
+        let path = dir.path().join("abc.pcm");
+        std::fs::write(&path, [0u8, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2]).unwrap();
+        let rt = match tokio::runtime::Runtime::new() {
+            Ok(rt) => rt,
+            Err(err) => panic!("failed to create runtime: {err}"),
+        };
+        let read = |secs, clip_end| {
+            rt.block_on(async {
+                let mut file = open_pcm_at(&path, secs, clip_end, format)
+                    .await
+                    .unwrap_or_else(|e| panic!("open failed: {e}"));
+                let mut bytes = Vec::new();
+                file.read_to_end(&mut bytes).await.unwrap();
+                bytes
+            })
+        };
+        assert_eq!(read(1, None), vec![1, 1, 1, 1, 2, 2, 2, 2]);
+        assert_eq!(read(1, Some(2)), vec![1, 1, 1, 1]);
+        assert_eq!(read(1, Some(u64::MAX)), vec![1, 1, 1, 1, 2, 2, 2, 2]);
+        let overflowing = rt.block_on(open_pcm_at(&path, u64::MAX, None, format));
+        assert!(overflowing.is_err());
+    }
+
+    #[test]
+    fn track_diagnostics_keep_recent_fetches_newest_first() {
+        let log = TrackDiagnosticLog::default();
+        for i in 0..MAX_TRACK_DIAGNOSTICS + 2 {
+            let failed: Result<StreamingTrackInfo, String> = Err(format!("timed out {i}"));
+            log.record(TrackDiagnostic::from_fetch(
+                &format!("https://youtu.be/{i}"),
+                &failed,
+                std::time::Duration::from_millis(1500),
+            ));
+        }
+        let recent = log.recent();
+        assert_eq!(recent.len(), MAX_TRACK_DIAGNOSTICS);
+        let newest = &recent[0];
+        assert_eq!(newest.url, format!("https://youtu.be/{}", MAX_TRACK_DIAGNOSTICS + 1));
+        assert_eq!(newest.source, None);
+        assert_eq!(newest.fetch_ms, 1500);
+        assert_eq!(newest.error.as_deref(), Some(format!("timed out {}", MAX_TRACK_DIAGNOSTICS + 1).as_str()));
+    }
+
+    #[test]
+    fn streams_decoded_clip_from_non_pcm_cache() {
+        use tokio::io::AsyncReadExt;
+
+        let dir = match tempfile::tempdir() {
+            Ok(dir) => dir,
+            Err(err) => panic!("tempdir failed: {err}"),
+        };
+        let second = PcmFormat::STREAM.bytes_per_sec() as usize;
+        let pcm: Vec<u8> = (0..second * 2).map(|i| (i / second) as u8 + 1).collect();
+        let mut wav = wav_header(pcm.len() as u32).to_vec();
+        wav.extend_from_slice(&pcm);
+        std::fs::write(dir.path().join("abc.wav"), wav).unwrap();
+        write_title_cache(&dir.path().join("abc.title"), "Song");
+
+        let source = YtDlpSource::new(Some(dir.path().to_path_buf()));
+        let rt = match tokio::runtime::Runtime::new() {
+            Ok(rt) => rt,
+            Err(err) => panic!("failed to create runtime: {err}"),
+        };
+        let bytes = rt.block_on(async {
+            let info = source
+                .fetch_audio_streaming(
+                    "https://youtube.com/watch?v=abc",
+                    Some((1, 5)),
+                    std::time::Duration::from_secs(5),
+                )
+                .await
+                .unwrap_or_else(|e| panic!("streaming failed: {e}"));
+            assert_eq!(info.title, "Song");
+            assert_eq!(info.format, PcmFormat::STREAM);
+            assert_eq!(info.kind, AudioSourceKind::Decoded);
+            let StreamingAudioSource::Decoded(mut stream) = info.source else {
+                panic!("expected a decoded stream");
+            };
+            let mut bytes = Vec::new();
+            stream.read_to_end(&mut bytes).await.unwrap();
+            bytes
+        });
+        assert_eq!(bytes.len(), second);
+        assert!(bytes.iter().all(|&b| b == 2));
+    }
 }